@@ -0,0 +1,90 @@
+use std::{
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    sync::Mutex,
+};
+
+use chrono::Utc;
+use log::warn;
+use nu_plugin::{EngineInterface, EvaluatedCall};
+use nu_protocol::ShellError;
+use serde_json::json;
+
+use crate::config::Config;
+
+/// Destructive operations recorded in the audit log
+#[derive(Debug, Clone, Copy)]
+pub enum AuditAction {
+    Delete,
+    Overwrite,
+}
+
+impl Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AuditAction::Delete => "delete",
+            AuditAction::Overwrite => "overwrite",
+        })
+    }
+}
+
+/// The configured `audit_log` file for a command run, which every removed or overwritten
+/// object is appended to as a line of JSON
+#[derive(Debug)]
+pub struct AuditLog {
+    command: String,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /// Open the audit log for the call, if one is configured. The file is opened before the
+    /// command changes anything, so a log that can't be written fails the command.
+    pub fn open(
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+    ) -> Result<Option<AuditLog>, Box<ShellError>> {
+        let Some(path) = Config::load(engine)?.audit_log else {
+            return Ok(None);
+        };
+        let file = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| OpenOptions::new().create(true).append(true).open(&path))
+            .map_err(|e| ShellError::GenericError {
+                error: format!("Could not open the audit log {}: {e}", path.display()),
+                msg: "".into(),
+                span: Some(call.head),
+                help: Some("Check the audit_log setting of the plugin config".into()),
+                inner: vec![],
+            })?;
+        let command = String::from_utf8_lossy(&engine.get_span_contents(call.head)?).into_owned();
+        Ok(Some(AuditLog {
+            command,
+            file: Mutex::new(file),
+        }))
+    }
+
+    /// Record an operation on the object at `url` with its outcome
+    pub fn record<E: Display>(
+        &self,
+        action: AuditAction,
+        url: &impl Display,
+        result: Result<(), E>,
+    ) {
+        let mut line = json!({
+            "time": Utc::now().to_rfc3339(),
+            "command": self.command,
+            "action": action.to_string(),
+            "url": url.to_string(),
+        });
+        match result {
+            Ok(()) => line["result"] = "ok".into(),
+            Err(e) => line["result"] = e.to_string().into(),
+        }
+        let mut file = self.file.lock().expect("not poisoned");
+        if let Err(e) = writeln!(file, "{line}") {
+            warn!("Could not write to the audit log: {e}");
+        }
+    }
+}
@@ -1,11 +1,26 @@
-use crate::providers::{NuObjectStore, parse_url};
+#[cfg(feature = "fixtures")]
+use crate::{config::FixturesConfig, providers::fixtures::Fixtures};
+use crate::{
+    config::{Config, DebugLogConfig, FaultInjectionConfig},
+    credentials::CloudCredentials,
+    disk_cache::DiskCache,
+    error::{Action, store_error},
+    jobs::Jobs,
+    providers::{
+        NuObjectStore, aws::AwsProfile, debug_log::DebugLog, faults::FaultInjector,
+        limit::RateLimiter, metrics::Metrics, parse_url,
+    },
+};
 use async_lock::{Mutex, MutexGuard};
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use nu_plugin::EngineInterface;
 use nu_protocol::{ShellError, Span, Spanned};
 use object_store::{GetOptions, path::Path};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
     time::{Duration, Instant},
 };
 use url::Url;
@@ -25,23 +40,78 @@ pub struct CacheEntry {
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ObjectStoreCacheKey {
-    Memory,
+    Memory {
+        bucket: Option<String>,
+    },
     Local,
-    AmazonS3 { bucket: String, region: String },
+    AmazonS3 {
+        bucket: String,
+        region: String,
+        profile: Option<String>,
+        /// Access key of static credentials, or the AWS profile of MFA sessions. Other
+        /// temporary credentials are refreshed in the store, which is keyed by `profile`.
+        credentials: Option<String>,
+    },
+    Http {
+        origin: String,
+        authorization: Option<String>,
+        profile: Option<String>,
+    },
+    #[cfg(feature = "sftp")]
+    Sftp {
+        host: String,
+        port: u16,
+        user: String,
+    },
+    WebHdfs {
+        endpoint: String,
+        user: Option<String>,
+    },
 }
 
 impl From<&NuObjectStore> for ObjectStoreCacheKey {
     fn from(value: &NuObjectStore) -> Self {
         match value {
-            NuObjectStore::Memory(_) => ObjectStoreCacheKey::Memory,
+            NuObjectStore::Memory { bucket, .. } => ObjectStoreCacheKey::Memory {
+                bucket: bucket.to_owned(),
+            },
             NuObjectStore::Local(_) => ObjectStoreCacheKey::Local,
-            NuObjectStore::AmazonS3 { bucket, region, .. } => ObjectStoreCacheKey::AmazonS3 {
+            NuObjectStore::AmazonS3 {
+                bucket,
+                region,
+                profile,
+                credentials,
+                ..
+            } => ObjectStoreCacheKey::AmazonS3 {
                 bucket: bucket.to_owned(),
                 region: region.to_owned(),
+                profile: profile.to_owned(),
+                credentials: credentials.to_owned(),
+            },
+            #[cfg(feature = "sftp")]
+            NuObjectStore::Sftp {
+                host, port, user, ..
+            } => ObjectStoreCacheKey::Sftp {
+                host: host.to_owned(),
+                port: *port,
+                user: user.to_owned(),
+            },
+            NuObjectStore::WebHdfs { endpoint, user, .. } => ObjectStoreCacheKey::WebHdfs {
+                endpoint: endpoint.to_owned(),
+                user: user.to_owned(),
             },
             NuObjectStore::GoogleCloudStorage(_) => unimplemented!(),
             NuObjectStore::MicrosoftAzure(_) => unimplemented!(),
-            NuObjectStore::Http(_) => unimplemented!(),
+            NuObjectStore::Http {
+                origin,
+                authorization,
+                profile,
+                ..
+            } => ObjectStoreCacheKey::Http {
+                origin: origin.to_owned(),
+                authorization: authorization.to_owned(),
+                profile: profile.to_owned(),
+            },
         }
     }
 }
@@ -51,8 +121,85 @@ impl From<&NuObjectStore> for ObjectStoreCacheKey {
 pub struct Cache {
     entries: Mutex<HashMap<Url, CacheEntry>>,
     stores: Mutex<HashMap<ObjectStoreCacheKey, NuObjectStore>>,
+    /// Credentials of closures, MFA sessions, and the keychain, shared with the credential
+    /// providers of cached stores
+    credentials: Arc<CredentialCache>,
+    /// AWS configuration and credential providers, keyed by AWS profile name
+    aws_profiles: Mutex<HashMap<Option<String>, AwsProfile>>,
+    /// Limiter for the configured `requests_per_second`
+    rate_limiter: Mutex<Option<Arc<RateLimiter>>>,
+    /// Log file for the configured `debug_log`
+    debug_log: Mutex<Option<Arc<DebugLog>>>,
+    /// Injector for the configured `fault_injection`
+    faults: Mutex<Option<Arc<FaultInjector>>>,
+    /// Responses recorded or replayed for the configured `fixtures`
+    #[cfg(feature = "fixtures")]
+    fixtures: Mutex<Option<Arc<Fixtures>>>,
+    /// Totals of all requests, kept when the cache is cleared
+    metrics: Arc<Metrics>,
+    /// Transfers running in the background, kept when the cache is cleared
+    jobs: Arc<Jobs>,
+    /// Delimiter listings of prefixes for completions, with the instant they were listed
+    listings: Mutex<HashMap<Url, (Instant, Vec<ListingEntry>)>>,
+    /// SHA-256 digests of local files compared by `cloud sync --checksum`, keyed by path so a
+    /// changed file replaces its digest rather than adding another
+    digests: Mutex<HashMap<PathBuf, CachedDigest>>,
+}
+
+/// Credentials returned by credentials closures keyed by configured bucket prefix, MFA sessions
+/// keyed by AWS profile, and credentials read from the keychain
+#[derive(Default)]
+pub struct CredentialCache {
+    credentials: Mutex<HashMap<String, CloudCredentials>>,
+    /// Keys known to have no credentials, e.g. profiles without a keychain entry, so they aren't
+    /// looked up again until `cloud login` or cache-clear
+    missing: Mutex<HashSet<String>>,
 }
 
+impl CredentialCache {
+    pub async fn put(&self, key: &str, credentials: CloudCredentials) {
+        self.missing.lock().await.remove(key);
+        self.credentials
+            .lock()
+            .await
+            .insert(key.to_string(), credentials);
+    }
+
+    /// Cached credentials for the key, unless they are about to expire
+    pub async fn get(&self, key: &str) -> Option<CloudCredentials> {
+        let lock = self.credentials.lock().await;
+        lock.get(key)
+            .filter(|credentials| !credentials.needs_refresh())
+            .cloned()
+    }
+
+    pub async fn put_missing(&self, key: &str) {
+        self.missing.lock().await.insert(key.to_string());
+    }
+
+    pub async fn is_missing(&self, key: &str) -> bool {
+        self.missing.lock().await.contains(key)
+    }
+
+    async fn clear(&self) {
+        self.credentials.lock().await.clear();
+        self.missing.lock().await.clear();
+    }
+}
+
+/// Name under a listed prefix, and whether it is a prefix itself
+pub type ListingEntry = (String, bool);
+
+/// Path, size, and modification time of a local file, which its digest is reused while they
+/// are the same
+pub type DigestKey = (PathBuf, u64, DateTime<Utc>);
+
+/// Size and modification time of a local file when its digest was computed, and the digest
+type CachedDigest = (u64, DateTime<Utc>, String);
+
+/// Listings are reused for completions typed in quick succession
+const LISTING_TTL: Duration = Duration::from_secs(5);
+
 impl Cache {
     pub async fn get(
         &self,
@@ -60,6 +207,7 @@ impl Cache {
         url: &Spanned<Url>,
         span: Span,
     ) -> Result<Bytes, ShellError> {
+        let cache_get_error = |e| store_error(Action::Read, &url.item, url.span, e);
         let mut lock = self.entries_cache_lock().await;
         Ok(match lock.get_mut(&url.item) {
             Some(e) => match e.refreshed_at.elapsed() < Duration::from_secs(10) {
@@ -82,6 +230,13 @@ impl Cache {
             None => {
                 // Not cached, fetch data
                 let (store, path) = parse_url(engine, self, url, span).await?;
+                if let Some(disk_cache) = disk_cache(engine, span).map_err(|e| *e)? {
+                    // Revalidated by the disk cache on every read
+                    return disk_cache
+                        .get(&store, &path, &url.item)
+                        .await
+                        .map_err(cache_get_error);
+                }
                 let get = store
                     .object_store()
                     .get(&path)
@@ -104,6 +259,16 @@ impl Cache {
         })
     }
 
+    /// Drop cached objects of the store at or under `prefix`, as a command is about to change them.
+    /// Changes made elsewhere are picked up when the entries are revalidated.
+    pub async fn invalidate(&self, store: &NuObjectStore, prefix: &Path) {
+        let key = ObjectStoreCacheKey::from(store);
+        let mut lock = self.entries_cache_lock().await;
+        lock.retain(|_, entry| {
+            !(entry.path.prefix_matches(prefix) && ObjectStoreCacheKey::from(&entry.store) == key)
+        });
+    }
+
     pub async fn put_store(
         &self,
         engine: &EngineInterface,
@@ -112,7 +277,7 @@ impl Cache {
     ) -> Result<(), ShellError> {
         let mut lock = self.stores_cache_lock().await;
         lock.insert(key, store);
-        engine.set_gc_disabled(true)
+        update_gc(engine, &lock, &self.jobs).map_err(|e| *e)
     }
 
     pub async fn get_store(&self, key: &ObjectStoreCacheKey) -> Option<NuObjectStore> {
@@ -120,29 +285,162 @@ impl Cache {
         lock.get(key).cloned()
     }
 
-    pub async fn clear(&self, engine: &EngineInterface) -> Result<(), ShellError> {
+    pub fn credentials(&self) -> Arc<CredentialCache> {
+        self.credentials.clone()
+    }
+
+    pub async fn put_aws_profile(&self, profile: Option<String>, aws_profile: AwsProfile) {
+        let mut lock = self.aws_profiles.lock().await;
+        lock.insert(profile, aws_profile);
+    }
+
+    pub async fn get_aws_profile(&self, profile: &Option<String>) -> Option<AwsProfile> {
+        let lock = self.aws_profiles.lock().await;
+        lock.get(profile).cloned()
+    }
+
+    /// The limiter shared by all stores, replaced when the configured rate changes
+    pub async fn rate_limiter(&self, per_second: f64) -> Arc<RateLimiter> {
+        let mut lock = self.rate_limiter.lock().await;
+        match lock.as_ref() {
+            Some(limiter) if limiter.per_second() == per_second => limiter.clone(),
+            _ => lock.insert(Arc::new(RateLimiter::new(per_second))).clone(),
+        }
+    }
+
+    /// The request log shared by all stores, replaced when its settings change
+    pub async fn debug_log(&self, config: &DebugLogConfig) -> Arc<DebugLog> {
+        let mut lock = self.debug_log.lock().await;
+        match lock.as_ref() {
+            Some(log) if log.matches(config) => log.clone(),
+            _ => lock.insert(Arc::new(DebugLog::new(config))).clone(),
+        }
+    }
+
+    /// The fault injector shared by all stores, replaced when its settings change
+    pub async fn fault_injector(&self, config: &FaultInjectionConfig) -> Arc<FaultInjector> {
+        let mut lock = self.faults.lock().await;
+        match lock.as_ref() {
+            Some(injector) if injector.matches(config) => injector.clone(),
+            _ => lock.insert(Arc::new(FaultInjector::new(config))).clone(),
+        }
+    }
+
+    /// The fixtures shared by all stores, loaded again when their settings change, or when
+    /// replaying after cache-clear
+    #[cfg(feature = "fixtures")]
+    pub async fn fixtures(&self, config: &FixturesConfig) -> std::io::Result<Arc<Fixtures>> {
+        let mut lock = self.fixtures.lock().await;
+        match lock.as_ref() {
+            Some(fixtures) if fixtures.matches(config) => Ok(fixtures.clone()),
+            _ => Ok(lock.insert(Arc::new(Fixtures::new(config)?)).clone()),
+        }
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    pub fn jobs(&self) -> Arc<Jobs> {
+        self.jobs.clone()
+    }
+
+    pub async fn get_listing(&self, url: &Url) -> Option<Vec<ListingEntry>> {
+        let lock = self.listings.lock().await;
+        lock.get(url)
+            .filter(|(listed_at, _)| listed_at.elapsed() < LISTING_TTL)
+            .map(|(_, entries)| entries.clone())
+    }
+
+    pub async fn put_listing(&self, url: Url, entries: Vec<ListingEntry>) {
+        let mut lock = self.listings.lock().await;
+        lock.retain(|_, (listed_at, _)| listed_at.elapsed() < LISTING_TTL);
+        lock.insert(url, (Instant::now(), entries));
+    }
+
+    pub async fn get_digest(&self, key: &DigestKey) -> Option<String> {
+        let (path, size, modified) = key;
+        let lock = self.digests.lock().await;
+        lock.get(path)
+            .filter(|(digest_size, digest_modified, _)| {
+                digest_size == size && digest_modified == modified
+            })
+            .map(|(_, _, digest)| digest.clone())
+    }
+
+    pub async fn put_digest(&self, key: DigestKey, digest: String) {
+        let (path, size, modified) = key;
+        self.digests
+            .lock()
+            .await
+            .insert(path, (size, modified, digest));
+    }
+
+    pub async fn clear(&self, engine: &EngineInterface, span: Span) -> Result<(), ShellError> {
+        if let Some(disk_cache) = disk_cache(engine, span).map_err(|e| *e)? {
+            disk_cache.clear().map_err(|e| ShellError::GenericError {
+                error: format!("Could not clear disk cache: {e}"),
+                msg: "".into(),
+                span: Some(span),
+                help: None,
+                inner: vec![],
+            })?;
+        }
         let mut lock = self.entries_cache_lock().await;
         lock.clear();
+        self.credentials.clear().await;
+        self.aws_profiles.lock().await.clear();
+        self.listings.lock().await.clear();
+        self.digests.lock().await.clear();
+        #[cfg(feature = "fixtures")]
+        self.fixtures.lock().await.take();
         let mut lock = self.stores_cache_lock().await;
-        lock.clear();
-        engine.set_gc_disabled(false)
+        // In-memory stores hold the only copy of their data, keep them for the plugin's lifetime
+        lock.retain(|key, _| matches!(key, ObjectStoreCacheKey::Memory { .. }));
+        update_gc(engine, &lock, &self.jobs).map_err(|e| *e)
     }
 
-    async fn entries_cache_lock(&self) -> MutexGuard<HashMap<Url, CacheEntry>> {
+    async fn entries_cache_lock(&self) -> MutexGuard<'_, HashMap<Url, CacheEntry>> {
         self.entries.lock().await
     }
 
-    async fn stores_cache_lock(&self) -> MutexGuard<HashMap<ObjectStoreCacheKey, NuObjectStore>> {
+    async fn stores_cache_lock(
+        &self,
+    ) -> MutexGuard<'_, HashMap<ObjectStoreCacheKey, NuObjectStore>> {
         self.stores.lock().await
     }
 }
 
-fn cache_get_error(e: impl std::error::Error) -> ShellError {
-    ShellError::GenericError {
-        error: format!("Error fetching data from obect store: {}", e),
-        msg: "".into(),
-        span: None,
-        help: None,
-        inner: vec![],
-    }
+/// Keep the plugin running while stores are cached, so plugin GC doesn't throw away their
+/// connections along with the credentials and data cached with them. With `keep_resident`
+/// disabled, `$env.config.plugin_gc` applies unless in-memory stores or background jobs are
+/// in use.
+fn update_gc(
+    engine: &EngineInterface,
+    stores: &HashMap<ObjectStoreCacheKey, NuObjectStore>,
+    jobs: &Jobs,
+) -> Result<(), Box<ShellError>> {
+    let memory = stores
+        .keys()
+        .any(|key| matches!(key, ObjectStoreCacheKey::Memory { .. }));
+    let keep_resident = Config::load(engine)?.keep_resident.unwrap_or(true);
+    jobs.set_stores_resident(memory || (keep_resident && !stores.is_empty()));
+    jobs.update_gc(engine)
+}
+
+/// The configured disk cache, if any
+fn disk_cache(engine: &EngineInterface, span: Span) -> Result<Option<DiskCache>, Box<ShellError>> {
+    let config = Config::load(engine)?;
+    let Some(disk_cache) = &config.disk_cache else {
+        return Ok(None);
+    };
+    DiskCache::new(disk_cache).map(Some).map_err(|e| {
+        Box::new(ShellError::GenericError {
+            error: format!("Could not open disk cache: {e}"),
+            msg: "".into(),
+            span: Some(span),
+            help: None,
+            inner: vec![],
+        })
+    })
 }
@@ -0,0 +1,661 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{Read, Write},
+};
+
+use chrono::DateTime;
+use flate2::{Compression, read::DeflateDecoder, write::DeflateEncoder};
+use nu_protocol::{Span, Value};
+
+/// Nesting beyond this is rejected, so malformed data can't exhaust the stack
+const MAX_DEPTH: usize = 64;
+const MAGIC: &[u8] = b"Obj\x01";
+const SYNC_SIZE: usize = 16;
+/// Rows written per block, so readers can process a file without holding all of it
+const BLOCK_ROWS: usize = 4096;
+
+/// A value decoded from an Avro object container file, which Iceberg uses for its manifests.
+///
+/// Unions decode to the value of their branch, enums to their symbol, and timestamps to
+/// microseconds since the epoch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Avro {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Double(f64),
+    Bytes(Vec<u8>),
+    String(String),
+    Array(Vec<Avro>),
+    Map(Vec<(String, Avro)>),
+    Record(Record),
+    Timestamp(i64),
+}
+
+/// Fields of a record in schema order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Record(pub Vec<(String, Avro)>);
+
+impl Record {
+    pub fn get(&self, name: &str) -> Option<&Avro> {
+        self.0
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn int(&self, name: &str) -> Option<i64> {
+        match self.get(name)? {
+            Avro::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            Avro::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Record> {
+        match self.get(name)? {
+            Avro::Record(child) => Some(child),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Schema {
+    Null,
+    Boolean,
+    Int,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Record(Vec<(String, Schema)>),
+    Enum(Vec<String>),
+    Array(Box<Schema>),
+    Map(Box<Schema>),
+    Union(Vec<Schema>),
+    Fixed(usize),
+    /// A long counting this many microseconds per unit since the epoch
+    Timestamp(i64),
+    /// Reference to a record, enum, or fixed defined elsewhere in the schema
+    Named(String),
+}
+
+/// Decode the records of an object container file
+pub fn read_container(data: &[u8]) -> Result<Vec<Avro>, Box<dyn Error + Send + Sync>> {
+    let no_names = HashMap::new();
+    let mut header = Decoder {
+        data,
+        pos: 0,
+        names: &no_names,
+    };
+    if header.bytes(MAGIC.len())? != MAGIC {
+        return Err("not an Avro file".into());
+    }
+    let mut metadata = HashMap::new();
+    header.blocks(|decoder| {
+        let key = decoder.string()?;
+        let len = decoder.long()? as usize;
+        metadata.insert(key, decoder.bytes(len)?.to_vec());
+        Ok(())
+    })?;
+    let sync = header.bytes(SYNC_SIZE)?.to_vec();
+
+    let schema = metadata.get("avro.schema").ok_or("the schema is missing")?;
+    let schema: serde_json::Value = serde_json::from_slice(schema)?;
+    let mut names = HashMap::new();
+    let schema = parse_schema(&schema, None, &mut names, 0)?;
+    let codec = metadata
+        .get("avro.codec")
+        .map(|codec| String::from_utf8_lossy(codec).into_owned());
+
+    let mut decoder = Decoder {
+        data,
+        pos: header.pos,
+        names: &names,
+    };
+    let mut values = vec![];
+    while decoder.remaining() > 0 {
+        let count = decoder.long()?;
+        let len = decoder.long()? as usize;
+        let block = decoder.bytes(len)?;
+        let block = match codec.as_deref() {
+            None | Some("null") => block.to_vec(),
+            Some("deflate") => {
+                let mut inflated = vec![];
+                DeflateDecoder::new(block).read_to_end(&mut inflated)?;
+                inflated
+            }
+            Some(codec) => return Err(format!("the {codec} codec isn't supported").into()),
+        };
+        if decoder.bytes(SYNC_SIZE)? != sync {
+            return Err("invalid sync marker".into());
+        }
+
+        let mut block = Decoder {
+            data: &block,
+            pos: 0,
+            names: &names,
+        };
+        for _ in 0..count {
+            values.push(block.value(&schema, 0)?);
+        }
+    }
+    Ok(values)
+}
+
+/// Encode rows into an object container file, with a schema inferred from their values.
+///
+/// Columns missing from some rows or holding nulls become unions with null, and integers mixed
+/// with floats become doubles.
+pub fn write_container(rows: &[Value]) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let rows: Vec<&Value> = rows.iter().collect();
+    let mut records = 0;
+    let (json, schema) = infer(&rows, &mut records, 0)?;
+    if !matches!(schema, Schema::Record(_)) {
+        return Err("only tables and records can be written as Avro".into());
+    }
+
+    let mut file = MAGIC.to_vec();
+    let sync = fastrand::u128(..).to_le_bytes();
+    let metadata = [
+        ("avro.schema", json.to_string()),
+        ("avro.codec", "deflate".to_string()),
+    ];
+    write_long(metadata.len() as i64, &mut file);
+    for (key, value) in metadata {
+        write_bytes(key.as_bytes(), &mut file);
+        write_bytes(value.as_bytes(), &mut file);
+    }
+    write_long(0, &mut file);
+    file.extend_from_slice(&sync);
+
+    for block in rows.chunks(BLOCK_ROWS) {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        let mut data = vec![];
+        for row in block {
+            encode(row, &schema, &mut data)?;
+        }
+        encoder.write_all(&data)?;
+        let data = encoder.finish()?;
+        write_long(block.len() as i64, &mut file);
+        write_long(data.len() as i64, &mut file);
+        file.extend_from_slice(&data);
+        file.extend_from_slice(&sync);
+    }
+    Ok(file)
+}
+
+/// Convert a decoded value, with maps and records becoming records
+pub fn to_value(value: Avro, span: Span) -> Value {
+    match value {
+        Avro::Null => Value::nothing(span),
+        Avro::Bool(b) => Value::bool(b, span),
+        Avro::Int(n) => Value::int(n, span),
+        Avro::Double(f) => Value::float(f, span),
+        Avro::Bytes(data) => Value::binary(data, span),
+        Avro::String(s) => Value::string(s, span),
+        Avro::Array(items) => Value::list(
+            items.into_iter().map(|item| to_value(item, span)).collect(),
+            span,
+        ),
+        Avro::Map(entries) | Avro::Record(Record(entries)) => Value::record(
+            entries
+                .into_iter()
+                .map(|(name, value)| (name, to_value(value, span)))
+                .collect(),
+            span,
+        ),
+        Avro::Timestamp(micros) => DateTime::from_timestamp_micros(micros)
+            .map_or(Value::int(micros, span), |date| {
+                Value::date(date.fixed_offset(), span)
+            }),
+    }
+}
+
+/// Schema of the values of a column, which are nothing where the column is missing
+fn infer(
+    values: &[&Value],
+    records: &mut usize,
+    depth: usize,
+) -> Result<(serde_json::Value, Schema), Box<dyn Error + Send + Sync>> {
+    use serde_json::json;
+
+    if depth > MAX_DEPTH {
+        return Err("values are nested too deep".into());
+    }
+    let present: Vec<&Value> = values
+        .iter()
+        .copied()
+        .filter(|value| !value.is_nothing())
+        .collect();
+    let Some(first) = present.first() else {
+        return Ok((json!("null"), Schema::Null));
+    };
+    let all = |kind: fn(&Value) -> bool| present.iter().all(|value| kind(value));
+
+    let (json, schema) = match first {
+        Value::Bool { .. } if all(|v| matches!(v, Value::Bool { .. })) => {
+            (json!("boolean"), Schema::Boolean)
+        }
+        Value::Int { .. } | Value::Filesize { .. } | Value::Duration { .. }
+            if all(|v| {
+                matches!(
+                    v,
+                    Value::Int { .. } | Value::Filesize { .. } | Value::Duration { .. }
+                )
+            }) =>
+        {
+            (json!("long"), Schema::Int)
+        }
+        Value::Int { .. } | Value::Float { .. }
+            if all(|v| matches!(v, Value::Int { .. } | Value::Float { .. })) =>
+        {
+            (json!("double"), Schema::Double)
+        }
+        Value::String { .. } if all(|v| matches!(v, Value::String { .. })) => {
+            (json!("string"), Schema::String)
+        }
+        Value::Binary { .. } if all(|v| matches!(v, Value::Binary { .. })) => {
+            (json!("bytes"), Schema::Bytes)
+        }
+        Value::Date { .. } if all(|v| matches!(v, Value::Date { .. })) => (
+            json!({"type": "long", "logicalType": "timestamp-micros"}),
+            Schema::Timestamp(1),
+        ),
+        Value::Record { .. } if all(|v| matches!(v, Value::Record { .. })) => {
+            let mut columns: Vec<&str> = vec![];
+            for value in &present {
+                for column in value.as_record()?.columns() {
+                    if !columns.contains(&column.as_str()) {
+                        columns.push(column);
+                    }
+                }
+            }
+            let nothing = Value::nothing(Span::unknown());
+            let mut json_fields = vec![];
+            let mut fields = vec![];
+            for column in columns {
+                let column_values: Vec<&Value> = values
+                    .iter()
+                    .map(|value| {
+                        value
+                            .as_record()
+                            .ok()
+                            .and_then(|record| record.get(column))
+                            .unwrap_or(&nothing)
+                    })
+                    .collect();
+                let (json, schema) = infer(&column_values, records, depth + 1)?;
+                json_fields.push(json!({"name": column, "type": json}));
+                fields.push((column.to_string(), schema));
+            }
+            // Every record type needs a unique name
+            *records += 1;
+            let name = if *records == 1 {
+                "row".to_string()
+            } else {
+                format!("record{records}")
+            };
+            (
+                json!({"type": "record", "name": name, "fields": json_fields}),
+                Schema::Record(fields),
+            )
+        }
+        Value::List { .. } if all(|v| matches!(v, Value::List { .. })) => {
+            let mut items = vec![];
+            for value in &present {
+                items.extend(value.as_list()?);
+            }
+            let (json, schema) = infer(&items, records, depth + 1)?;
+            (
+                json!({"type": "array", "items": json}),
+                Schema::Array(Box::new(schema)),
+            )
+        }
+        _ => {
+            let types = present
+                .iter()
+                .map(|value| value.get_type().to_string())
+                .collect::<Vec<_>>();
+            return Err(format!("can't write values of types {} as Avro", types.join(", ")).into());
+        }
+    };
+    Ok(if present.len() < values.len() {
+        (
+            json!(["null", json]),
+            Schema::Union(vec![Schema::Null, schema]),
+        )
+    } else {
+        (json, schema)
+    })
+}
+
+/// Encode a value with a schema from [`infer`]
+fn encode(
+    value: &Value,
+    schema: &Schema,
+    out: &mut Vec<u8>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match (schema, value) {
+        (Schema::Null, _) => {}
+        (Schema::Union(branches), Value::Nothing { .. }) if matches!(branches[0], Schema::Null) => {
+            write_long(0, out);
+        }
+        (Schema::Union(branches), value) => {
+            write_long(1, out);
+            encode(value, &branches[1], out)?;
+        }
+        (Schema::Boolean, Value::Bool { val, .. }) => out.push(*val as u8),
+        (Schema::Int, Value::Int { val, .. }) => write_long(*val, out),
+        (Schema::Int, Value::Filesize { val, .. }) => write_long(val.get(), out),
+        (Schema::Int, Value::Duration { val, .. }) => write_long(*val, out),
+        (Schema::Double, Value::Int { val, .. }) => {
+            out.extend_from_slice(&(*val as f64).to_le_bytes())
+        }
+        (Schema::Double, Value::Float { val, .. }) => out.extend_from_slice(&val.to_le_bytes()),
+        (Schema::String, Value::String { val, .. }) => write_bytes(val.as_bytes(), out),
+        (Schema::Bytes, Value::Binary { val, .. }) => write_bytes(val, out),
+        (Schema::Timestamp(_), Value::Date { val, .. }) => write_long(val.timestamp_micros(), out),
+        (Schema::Record(fields), Value::Record { val, .. }) => {
+            let nothing = Value::nothing(Span::unknown());
+            for (name, schema) in fields {
+                encode(val.get(name).unwrap_or(&nothing), schema, out)?;
+            }
+        }
+        (Schema::Array(items), Value::List { vals, .. }) => {
+            if !vals.is_empty() {
+                write_long(vals.len() as i64, out);
+                for value in vals {
+                    encode(value, items, out)?;
+                }
+            }
+            write_long(0, out);
+        }
+        (_, value) => {
+            return Err(format!("unexpected {} value", value.get_type()).into());
+        }
+    }
+    Ok(())
+}
+
+fn write_long(n: i64, out: &mut Vec<u8>) {
+    let mut n = ((n << 1) ^ (n >> 63)) as u64;
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn write_bytes(data: &[u8], out: &mut Vec<u8>) {
+    write_long(data.len() as i64, out);
+    out.extend_from_slice(data);
+}
+
+fn parse_schema(
+    schema: &serde_json::Value,
+    namespace: Option<&str>,
+    names: &mut HashMap<String, Schema>,
+    depth: usize,
+) -> Result<Schema, Box<dyn Error + Send + Sync>> {
+    use serde_json::Value;
+
+    if depth > MAX_DEPTH {
+        return Err("the schema is nested too deep".into());
+    }
+    let object = match schema {
+        Value::Array(branches) => {
+            return branches
+                .iter()
+                .map(|branch| parse_schema(branch, namespace, names, depth + 1))
+                .collect::<Result<_, _>>()
+                .map(Schema::Union);
+        }
+        Value::String(name) => {
+            return Ok(match name.as_str() {
+                "null" => Schema::Null,
+                "boolean" => Schema::Boolean,
+                "int" | "long" => Schema::Int,
+                "float" => Schema::Float,
+                "double" => Schema::Double,
+                "bytes" => Schema::Bytes,
+                "string" => Schema::String,
+                name => Schema::Named(name.to_string()),
+            });
+        }
+        Value::Object(object) => object,
+        _ => return Err(format!("invalid schema {schema}").into()),
+    };
+
+    let Some(kind) = object.get("type") else {
+        return Err("a schema has no type".into());
+    };
+    let name = object.get("name").and_then(Value::as_str);
+    let namespace = object
+        .get("namespace")
+        .and_then(Value::as_str)
+        .or(namespace);
+    let parsed = match kind.as_str() {
+        Some("record" | "error") => {
+            let fields = object
+                .get("fields")
+                .and_then(Value::as_array)
+                .ok_or("a record has no fields")?;
+            let fields = fields
+                .iter()
+                .map(|field| {
+                    let name = field
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .ok_or("a field has no name")?;
+                    let schema = field.get("type").ok_or("a field has no type")?;
+                    Ok((
+                        name.to_string(),
+                        parse_schema(schema, namespace, names, depth + 1)?,
+                    ))
+                })
+                .collect::<Result<_, Box<dyn Error + Send + Sync>>>()?;
+            Schema::Record(fields)
+        }
+        Some("enum") => {
+            let symbols = object
+                .get("symbols")
+                .and_then(Value::as_array)
+                .ok_or("an enum has no symbols")?;
+            Schema::Enum(
+                symbols
+                    .iter()
+                    .map(|symbol| symbol.as_str().unwrap_or_default().to_string())
+                    .collect(),
+            )
+        }
+        Some("array") => Schema::Array(Box::new(parse_schema(
+            object.get("items").ok_or("an array has no items")?,
+            namespace,
+            names,
+            depth + 1,
+        )?)),
+        Some("map") => Schema::Map(Box::new(parse_schema(
+            object.get("values").ok_or("a map has no values")?,
+            namespace,
+            names,
+            depth + 1,
+        )?)),
+        Some("fixed") => Schema::Fixed(
+            object
+                .get("size")
+                .and_then(Value::as_u64)
+                .ok_or("a fixed has no size")? as usize,
+        ),
+        Some("long") => match object.get("logicalType").and_then(Value::as_str) {
+            Some("timestamp-millis" | "local-timestamp-millis") => Schema::Timestamp(1000),
+            Some("timestamp-micros" | "local-timestamp-micros") => Schema::Timestamp(1),
+            _ => Schema::Int,
+        },
+        // A primitive with attributes, such as another logical type
+        _ => return parse_schema(kind, namespace, names, depth + 1),
+    };
+    if let Some(name) = name {
+        // References may use the short or the full name
+        if let Some(namespace) = namespace.filter(|_| !name.contains('.')) {
+            names.insert(format!("{namespace}.{name}"), parsed.clone());
+        }
+        names.insert(name.to_string(), parsed.clone());
+    }
+    Ok(parsed)
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// Named types of the schema
+    names: &'a HashMap<String, Schema>,
+}
+
+impl Decoder<'_> {
+    fn value(
+        &mut self,
+        schema: &Schema,
+        depth: usize,
+    ) -> Result<Avro, Box<dyn Error + Send + Sync>> {
+        if depth > MAX_DEPTH {
+            return Err("nesting is too deep".into());
+        }
+        Ok(match schema {
+            Schema::Null => Avro::Null,
+            Schema::Boolean => Avro::Bool(self.byte()? == 1),
+            Schema::Int => Avro::Int(self.long()?),
+            Schema::Float => {
+                let bytes = self.bytes(4)?.try_into().expect("4 bytes were read");
+                Avro::Double(f32::from_le_bytes(bytes) as f64)
+            }
+            Schema::Double => {
+                let bytes = self.bytes(8)?.try_into().expect("8 bytes were read");
+                Avro::Double(f64::from_le_bytes(bytes))
+            }
+            Schema::Bytes => {
+                let len = self.long()? as usize;
+                Avro::Bytes(self.bytes(len)?.to_vec())
+            }
+            Schema::String => Avro::String(self.string()?),
+            Schema::Record(fields) => {
+                let mut values = Vec::with_capacity(fields.len());
+                for (name, schema) in fields {
+                    values.push((name.clone(), self.value(schema, depth + 1)?));
+                }
+                Avro::Record(Record(values))
+            }
+            Schema::Enum(symbols) => {
+                let idx = self.long()?;
+                let symbol = usize::try_from(idx)
+                    .ok()
+                    .and_then(|idx| symbols.get(idx))
+                    .ok_or_else(|| format!("invalid enum index {idx}"))?;
+                Avro::String(symbol.clone())
+            }
+            Schema::Array(items) => {
+                let mut values = vec![];
+                self.blocks(|decoder| {
+                    values.push(decoder.value(items, depth + 1)?);
+                    Ok(())
+                })?;
+                Avro::Array(values)
+            }
+            Schema::Map(items) => {
+                let mut entries = vec![];
+                self.blocks(|decoder| {
+                    let key = decoder.string()?;
+                    entries.push((key, decoder.value(items, depth + 1)?));
+                    Ok(())
+                })?;
+                Avro::Map(entries)
+            }
+            Schema::Union(branches) => {
+                let idx = self.long()?;
+                let branch = usize::try_from(idx)
+                    .ok()
+                    .and_then(|idx| branches.get(idx))
+                    .ok_or_else(|| format!("invalid union index {idx}"))?;
+                self.value(branch, depth + 1)?
+            }
+            Schema::Fixed(size) => Avro::Bytes(self.bytes(*size)?.to_vec()),
+            Schema::Timestamp(unit) => Avro::Timestamp(self.long()?.saturating_mul(*unit)),
+            Schema::Named(name) => {
+                let names = self.names;
+                let schema = names
+                    .get(name)
+                    .ok_or_else(|| format!("unknown type {name}"))?;
+                self.value(schema, depth + 1)?
+            }
+        })
+    }
+
+    /// Read the items of an array or map, which are written in blocks ending with an empty one
+    fn blocks(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<(), Box<dyn Error + Send + Sync>>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        loop {
+            let count = match self.long()? {
+                0 => return Ok(()),
+                // A negative count is followed by the size of the block in bytes
+                count if count < 0 => {
+                    self.long()?;
+                    count.unsigned_abs()
+                }
+                count => count as u64,
+            };
+            // Items take at least a byte unless they are null, which no schema read here has
+            // arrays of, so this bounds the loop on malformed counts
+            if count > self.remaining() as u64 {
+                return Err("unexpected end of data".into());
+            }
+            for _ in 0..count {
+                item(self)?;
+            }
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn byte(&mut self) -> Result<u8, Box<dyn Error + Send + Sync>> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&[u8], Box<dyn Error + Send + Sync>> {
+        if len > self.remaining() {
+            return Err("unexpected end of data".into());
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let len = self.long()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    /// A zigzag encoded varint, used for ints and longs
+    fn long(&mut self) -> Result<i64, Box<dyn Error + Send + Sync>> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((n >> 1) as i64 ^ -((n & 1) as i64));
+            }
+        }
+        Err("varint is too long".into())
+    }
+}
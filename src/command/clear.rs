@@ -1,6 +1,8 @@
 use crate::CloudPlugin;
 use nu_plugin::{EngineInterface, PluginCommand};
-use nu_protocol::{Category, Example, LabeledError, PipelineData, ShellError, Signature, Type};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Span, Type,
+};
 
 pub struct Clear;
 
@@ -18,10 +20,10 @@ impl PluginCommand for Clear {
     }
 
     fn description(&self) -> &str {
-        "Clears plugin internal caches. This will also re-enable plugin GC."
+        "Clears plugin internal caches. This will also re-enable plugin GC unless in-memory stores are in use."
     }
 
-    fn examples(&self) -> Vec<Example> {
+    fn examples(&self) -> Vec<Example<'_>> {
         vec![Example {
             description: "Clear plugin cache",
             example: "cloud cache-clear",
@@ -33,12 +35,13 @@ impl PluginCommand for Clear {
         &self,
         plugin: &Self::Plugin,
         engine: &EngineInterface,
-        _call: &nu_plugin::EvaluatedCall,
+        call: &nu_plugin::EvaluatedCall,
         _input: PipelineData,
     ) -> Result<PipelineData, LabeledError> {
         plugin
-            .rt
-            .block_on(command(plugin, engine))
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(command(plugin, engine, call.head))
             .map_err(LabeledError::from)
     }
 }
@@ -46,7 +49,8 @@ impl PluginCommand for Clear {
 async fn command(
     plugin: &CloudPlugin,
     engine: &EngineInterface,
+    span: Span,
 ) -> Result<PipelineData, ShellError> {
-    plugin.cache.clear(engine).await?;
+    plugin.cache.clear(engine, span).await?;
     Ok(PipelineData::empty())
 }
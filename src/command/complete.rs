@@ -0,0 +1,145 @@
+use std::vec;
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
+    Type, Value,
+};
+
+use super::{run_flags, with_rate_limit, with_request_log, with_timeout};
+use crate::{
+    CloudPlugin,
+    error::{Action, store_error},
+    remote::RemotePath,
+};
+
+pub struct Complete;
+
+impl PluginCommand for Complete {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud complete"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            Signature::build("cloud complete")
+                .input_output_types(vec![(Type::Nothing, Type::List(Box::new(Type::String)))])
+                .required(
+                    "context",
+                    SyntaxShape::String,
+                    "The command line being completed, ending with a partial url.",
+                )
+                .optional(
+                    "position",
+                    SyntaxShape::Int,
+                    "The cursor position, passed by completers and not used.",
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Complete a partial url with the objects and prefixes next to it, for use in custom completers."
+    }
+
+    fn extra_description(&self) -> &str {
+        "The last word of the context is completed from a delimiter listing of its parent prefix. Listings are cached for a few seconds, so completing while typing doesn't list the prefix again."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Complete urls for the argument of a custom command.",
+            example: "def copen [uri: string@'cloud complete'] { cloud open $uri }",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let context: Spanned<String> = call.req(0)?;
+    let word = context
+        .item
+        .split_whitespace()
+        .next_back()
+        .unwrap_or_default()
+        .trim_start_matches(['\'', '"', '`']);
+
+    // Only paths within a bucket or host are completed, as stores can't list those
+    let Some((dir, _)) = word.rsplit_once('/') else {
+        return Ok(PipelineData::Value(Value::list(vec![], call_span), None));
+    };
+    if !dir.contains("://") || dir.ends_with(":/") {
+        return Ok(PipelineData::Value(Value::list(vec![], call_span), None));
+    }
+    let Ok(remote) = RemotePath::parse(&format!("{dir}/"), context.span, call_span) else {
+        return Ok(PipelineData::Value(Value::list(vec![], call_span), None));
+    };
+    let url = remote.url;
+
+    let entries = match plugin.cache.get_listing(&url.item).await {
+        Some(entries) => entries,
+        None => {
+            let (store, path) = plugin.parse_url(engine, &url, call_span).await?;
+            let prefix = (!path.as_ref().is_empty()).then_some(&path);
+            let listing = store
+                .object_store()
+                .list_with_delimiter(prefix)
+                .await
+                .map_err(|e| store_error(Action::List, &url.item, url.span, e))?;
+            let prefixes = listing
+                .common_prefixes
+                .iter()
+                .filter_map(|prefix| Some((prefix.filename()?.to_string(), true)));
+            let objects = listing
+                .objects
+                .iter()
+                .filter_map(|meta| Some((meta.location.filename()?.to_string(), false)));
+            let entries: Vec<_> = prefixes.chain(objects).collect();
+            plugin
+                .cache
+                .put_listing(url.item.clone(), entries.clone())
+                .await;
+            entries
+        }
+    };
+
+    let completions = entries
+        .into_iter()
+        .map(|(name, is_prefix)| {
+            let slash = if is_prefix { "/" } else { "" };
+            format!("{dir}/{name}{slash}")
+        })
+        .filter(|completion| completion.starts_with(word))
+        .map(|completion| Value::string(completion, call_span))
+        .collect();
+    Ok(PipelineData::Value(
+        Value::list(completions, call_span),
+        None,
+    ))
+}
@@ -0,0 +1,232 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Span, SyntaxShape, Type,
+};
+use object_store::{PutPayload, path::Path};
+
+use super::{
+    concurrency, confirm_overwrite, glob_objects, object_url, run_flags, with_rate_limit,
+    with_request_log, with_timeout, yes_flag,
+};
+use crate::{
+    CloudPlugin,
+    audit::{AuditAction, AuditLog},
+    cache::ObjectStoreCacheKey,
+    error::{Action, store_error},
+    providers::concat::{ConcatClient, MAX_PARTS, MIN_PART_SIZE},
+    remote::RemotePath,
+};
+
+pub struct Concat;
+
+impl PluginCommand for Concat {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud concat"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            yes_flag(Signature::build("cloud concat"))
+                .input_output_types(vec![(Type::Nothing, Type::Nothing)])
+                .required(
+                    "destination",
+                    SyntaxShape::String,
+                    "The url of the object to write.",
+                )
+                .rest(
+                    "sources",
+                    SyntaxShape::String,
+                    "The urls of the objects to join, in order, which may have glob patterns in their paths.",
+                )
+                .named(
+                    "concurrency",
+                    SyntaxShape::Int,
+                    "Number of parts copied at once (default: concurrency of the config, or 8)",
+                    Some('j'),
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Join objects of an S3 bucket into one, without downloading them."
+    }
+
+    fn extra_description(&self) -> &str {
+        "The sources are copied by the store as the parts of a multipart upload to the destination, so they must be in its bucket, and all but the last must be at least 5 MiB. Globs are expanded in name order. The destination may be one of the sources, to append to it."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Merge the part files of a day into one.",
+                example: "cloud concat s3://mybucket/logs/2025-01-01.log s3://mybucket/logs/2025-01-01/part-*",
+                result: None,
+            },
+            Example {
+                description: "Append an object to another.",
+                example: "cloud concat s3://mybucket/all.csv s3://mybucket/all.csv s3://mybucket/new.csv",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+/// An object to join, with its url for errors
+struct Source {
+    location: Path,
+    size: u64,
+    url: String,
+    span: Span,
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let destination = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let concurrency = concurrency(engine, call).map_err(|e| *e)?;
+    if call.positional.len() < 2 {
+        return Err(ShellError::MissingParameter {
+            param_name: "sources".into(),
+            span: call_span,
+        });
+    }
+    let (object_store, dest_path) = plugin.parse_url(engine, &destination, call_span).await?;
+    let client = object_store
+        .concat_client()
+        .ok_or_else(|| ShellError::GenericError {
+            error: format!(
+                "Objects can't be joined in {} urls",
+                destination.item.scheme()
+            ),
+            msg: "".into(),
+            span: Some(destination.span),
+            help: Some("Objects are only joined by the store in S3 buckets".into()),
+            inner: vec![],
+        })?;
+
+    let mut sources = vec![];
+    for pos in 1..call.positional.len() {
+        let remote = RemotePath::from_call(engine, call, pos).map_err(|e| *e)?;
+        let glob = remote.glob().map_err(|e| *e)?;
+        let url = remote.url;
+        // Globs are expanded by listing the prefix before the pattern
+        let base = glob.as_ref().map_or(&url, |glob| &glob.url);
+        let (store, path) = plugin.parse_url(engine, base, call_span).await?;
+        if ObjectStoreCacheKey::from(&store) != ObjectStoreCacheKey::from(&object_store) {
+            return Err(ShellError::GenericError {
+                error: format!("{} isn't in the bucket of {}", url.item, destination.item),
+                msg: "".into(),
+                span: Some(url.span),
+                help: Some("The store only copies parts from objects of the same bucket".into()),
+                inner: vec![],
+            });
+        }
+        match &glob {
+            Some(glob) => {
+                let mut objects = glob_objects(&store, glob, &path, false).await?;
+                objects.sort_unstable_by(|a, b| a.location.cmp(&b.location));
+                sources.extend(objects.into_iter().map(|meta| Source {
+                    url: object_url(&glob.url.item, &path, &meta.location),
+                    location: meta.location,
+                    size: meta.size,
+                    span: url.span,
+                }));
+            }
+            None => {
+                let meta = store
+                    .object_store()
+                    .head(&path)
+                    .await
+                    .map_err(|e| store_error(Action::Read, &url.item, url.span, e))?;
+                sources.push(Source {
+                    location: path,
+                    size: meta.size,
+                    url: url.item.to_string(),
+                    span: url.span,
+                });
+            }
+        }
+    }
+    // Parts but the last have a minimum size, and empty sources are left out of the parts
+    sources.retain(|source| source.size > 0);
+    if let Some((_, parts)) = sources.split_last()
+        && let Some(small) = parts.iter().find(|source| source.size < MIN_PART_SIZE)
+    {
+        return Err(ShellError::GenericError {
+            error: format!("{} is smaller than 5 MiB", small.url),
+            msg: "".into(),
+            span: Some(small.span),
+            help: Some("Only the last object joined may be smaller than 5 MiB. Join small objects by downloading them instead, e.g. with cloud open and cloud save".into()),
+            inner: vec![],
+        });
+    }
+    if ConcatClient::parts(sources.iter().map(|source| source.size)) > MAX_PARTS {
+        return Err(ShellError::GenericError {
+            error: format!("Too many objects to join, at most {MAX_PARTS} parts are copied"),
+            msg: "".into(),
+            span: Some(call_span),
+            help: None,
+            inner: vec![],
+        });
+    }
+
+    let audit = AuditLog::open(engine, call).map_err(|e| *e)?;
+    let overwrite = confirm_overwrite(
+        engine,
+        call,
+        &object_store,
+        &dest_path,
+        &destination.item,
+        audit.as_ref(),
+    )
+    .await
+    .map_err(|e| *e)?;
+    plugin.cache.invalidate(&object_store, &dest_path).await;
+    let sources: Vec<(Path, u64)> = sources
+        .into_iter()
+        .map(|source| (source.location, source.size))
+        .collect();
+    let result = match sources.is_empty() {
+        // There are no parts to copy for empty objects
+        true => object_store
+            .object_store()
+            .put(&dest_path, PutPayload::new())
+            .await
+            .map(|_| ()),
+        false => client.concat(&dest_path, &sources, concurrency).await,
+    };
+    if let Some(audit) = audit.as_ref().filter(|_| overwrite) {
+        let outcome = result.as_ref().map(|_| ());
+        audit.record(AuditAction::Overwrite, &destination.item, outcome);
+    }
+    result.map_err(|e| {
+        let target = format!("{} objects into {}", sources.len(), destination.item);
+        store_error(Action::Copy, target, destination.span, e)
+    })?;
+    Ok(PipelineData::empty())
+}
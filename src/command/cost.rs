@@ -0,0 +1,264 @@
+use futures::TryStreamExt;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Span, SyntaxShape, Type,
+    Value, record,
+};
+
+use super::{
+    glob_objects, is_dir, metrics::counts, run_flags, with_rate_limit, with_request_log,
+    with_timeout,
+};
+use crate::{
+    CloudPlugin,
+    config::Config,
+    error::{Action, store_error},
+    providers::metrics::Usage,
+    remote::RemotePath,
+};
+
+pub struct CostEstimate;
+
+impl PluginCommand for CostEstimate {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud cost estimate"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            Signature::build("cloud cost estimate")
+                .input_output_types(vec![
+                    (Type::Nothing, Type::table()),
+                    (Type::table(), Type::table()),
+                ])
+                .optional(
+                    "uri",
+                    SyntaxShape::String,
+                    "The url of the objects to price downloading, which may have a glob pattern in its path, or the url the objects of the input are transferred from or to.",
+                )
+                .switch(
+                    "upload",
+                    "Price uploading the objects of the input instead of downloading them",
+                    Some('u'),
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Estimate the cost of the requests and transfers of a download, an upload, or the session so far."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Without a url, the requests and bytes counted by cloud metrics are priced for each provider. With a url, downloading the objects under it is priced, with a GET request for each object and for each page of 1000 listed. Given records with a size column as input, like those of cloud ls or ls, transferring those objects is priced instead, with a request each. Prices come from the pricing config by provider, the scheme of the url, and costs are empty for providers without any. Retries and the parts of multipart uploads make more requests than estimated."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Estimate the egress cost of downloading a prefix.",
+                example: "cloud cost estimate s3://mybucket/exports/",
+                result: None,
+            },
+            Example {
+                description: "Price uploading the files of a local directory.",
+                example: "ls **/* | where type == file | cloud cost estimate --upload s3://mybucket/backup/",
+                result: None,
+            },
+            Example {
+                description: "Price the requests and transfers of the session so far.",
+                example: "cloud cost estimate",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(
+                    call,
+                    with_timeout(call, command(engine, plugin, call, input)),
+                ),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+/// Objects listed by a request for a page of a listing, as for S3
+const LIST_PAGE_SIZE: u64 = 1000;
+
+const GIB: f64 = (1u64 << 30) as f64;
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+    input: PipelineData,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let config = Config::load(engine).map_err(|e| *e)?;
+    if call.positional.is_empty() {
+        let rows = plugin
+            .cache
+            .metrics()
+            .totals()
+            .providers
+            .into_iter()
+            .map(|(provider, usage)| row(&config, &provider, usage, call_span))
+            .collect();
+        return Ok(PipelineData::Value(Value::list(rows, call_span), None));
+    }
+
+    let remote = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?;
+    let provider = remote.url.item.scheme().to_string();
+    let upload = call.has_flag("upload")?;
+    let usage = match input_sizes(input, call_span).map_err(|e| *e)? {
+        Some(sizes) => transfer_usage(&sizes, upload),
+        None if upload => {
+            return Err(ShellError::GenericError {
+                error: "--upload prices the objects of the input".into(),
+                msg: "".into(),
+                span: call.get_flag_span("upload"),
+                help: Some("Pipe the files to upload into the command, e.g. from ls".into()),
+                inner: vec![],
+            });
+        }
+        None => {
+            let glob = remote.glob().map_err(|e| *e)?;
+            let url = remote.url;
+            // Globs are expanded by listing the prefix before the pattern
+            let base = glob.as_ref().map_or(&url, |glob| &glob.url);
+            let (object_store, path) = plugin.parse_url(engine, base, call_span).await?;
+            let objects = match &glob {
+                Some(glob) => glob_objects(&object_store, glob, &path, false).await?,
+                None => object_store
+                    .object_store()
+                    .list(Some(&path))
+                    .try_collect()
+                    .await
+                    .map_err(|e| store_error(Action::List, &url.item, url.span, e))?,
+            };
+            let sizes: Vec<u64> = objects.iter().map(|meta| meta.size).collect();
+            let mut usage = transfer_usage(&sizes, false);
+            let pages = (sizes.len() as u64).div_ceil(LIST_PAGE_SIZE).max(1);
+            *usage.requests.entry("GET".into()).or_default() += pages;
+            usage
+        }
+    };
+    let rows = vec![row(&config, &provider, usage, call_span)];
+    Ok(PipelineData::Value(Value::list(rows, call_span), None))
+}
+
+/// Sizes of the objects of the input, records with a size column like those of `cloud ls` or
+/// `ls`, leaving out their directories. Input that isn't a list is `None`, for estimates given a
+/// url alone.
+fn input_sizes(input: PipelineData, span: Span) -> Result<Option<Vec<u64>>, Box<ShellError>> {
+    let values: Vec<Value> = match input {
+        PipelineData::ListStream(stream, _) => stream.into_iter().collect(),
+        PipelineData::Value(Value::List { vals, .. }, _) => vals,
+        _ => return Ok(None),
+    };
+    let sizes = values
+        .into_iter()
+        .filter(|value| !is_dir(value))
+        .map(|value| {
+            let value_span = value.span();
+            match value {
+                Value::Record { val, .. } => match val.get("size") {
+                    Some(Value::Filesize { val, .. }) => Ok(val.get().max(0) as u64),
+                    Some(Value::Int { val, .. }) => Ok((*val).max(0) as u64),
+                    _ => Err(Box::new(ShellError::CantFindColumn {
+                        col_name: "size".into(),
+                        span: None,
+                        src_span: value_span,
+                    })),
+                },
+                Value::Error { error, .. } => Err(error),
+                other => Err(Box::new(ShellError::UnsupportedInput {
+                    msg: "Objects are records with a size column, like those of cloud ls".into(),
+                    input: format!("{} input", other.get_type()),
+                    msg_span: span,
+                    input_span: value_span,
+                })),
+            }
+        });
+    Ok(Some(sizes.collect::<Result<_, Box<ShellError>>>()?))
+}
+
+/// Requests and bytes of transferring objects of the sizes, with a request each
+fn transfer_usage(sizes: &[u64], upload: bool) -> Usage {
+    let bytes = sizes.iter().sum();
+    let (method, mut usage) = match upload {
+        true => (
+            "PUT",
+            Usage {
+                bytes_uploaded: bytes,
+                ..Usage::default()
+            },
+        ),
+        false => (
+            "GET",
+            Usage {
+                bytes_downloaded: bytes,
+                ..Usage::default()
+            },
+        ),
+    };
+    usage.requests.insert(method.into(), sizes.len() as u64);
+    usage
+}
+
+/// Row of the estimate for a provider, with empty costs if it has no prices
+fn row(config: &Config, provider: &str, usage: Usage, span: Span) -> Value {
+    let pricing = config.pricing(provider);
+    let request_cost = pricing.as_ref().map(|pricing| {
+        let prices = pricing.per_1000_requests.as_ref();
+        usage
+            .requests
+            .iter()
+            .map(|(method, count)| {
+                let price = prices
+                    .and_then(|prices| {
+                        prices
+                            .iter()
+                            .find(|(priced, _)| priced.eq_ignore_ascii_case(method))
+                    })
+                    .map_or(0.0, |(_, price)| *price);
+                price * *count as f64 / 1000.0
+            })
+            .sum::<f64>()
+    });
+    let transfer_cost = pricing.as_ref().map(|pricing| {
+        let egress = pricing.egress_per_gib.unwrap_or(0.0) * usage.bytes_downloaded as f64;
+        let ingress = pricing.ingress_per_gib.unwrap_or(0.0) * usage.bytes_uploaded as f64;
+        (egress + ingress) / GIB
+    });
+    let cost =
+        |cost: Option<f64>| cost.map_or(Value::nothing(span), |cost| Value::float(cost, span));
+    Value::record(
+        record!(
+            "provider" => Value::string(provider, span),
+            "requests" => counts(usage.requests, span),
+            "bytes_uploaded" => Value::filesize(usage.bytes_uploaded as i64, span),
+            "bytes_downloaded" => Value::filesize(usage.bytes_downloaded as i64, span),
+            "request_cost" => cost(request_cost),
+            "transfer_cost" => cost(transfer_cost),
+            "cost" => cost(request_cost.zip(transfer_cost).map(|(requests, transfer)| requests + transfer)),
+        ),
+        span,
+    )
+}
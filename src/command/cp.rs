@@ -0,0 +1,809 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    time::Instant,
+    vec,
+};
+
+use futures::{StreamExt, TryStreamExt, stream};
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, Filesize, LabeledError, PipelineData, ShellError, Signature, Span, Spanned,
+    SyntaxShape, Type, Value,
+};
+use object_store::{GetOptions, GetRange, ObjectMeta, PutPayload, path::Path};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use url::Url;
+
+use super::{
+    BatchObject, OutputFormat, bandwidth_limit, batch_objects, batch_row, bwlimit_flag,
+    concurrency, confirm, confirm_overwrite, confirming, glob_objects,
+    multipart::{MultipartWriter, PartSize},
+    object_url, output_format_flag,
+    read_ahead::RANGE_SIZE,
+    run_flags, with_rate_limit, with_request_log, with_timeout, yes_flag,
+};
+use crate::{
+    CloudPlugin,
+    audit::{AuditAction, AuditLog},
+    cache::ObjectStoreCacheKey,
+    config::Config,
+    error::{Action, store_error},
+    providers::{
+        NuObjectStore,
+        concat::MAX_PART_SIZE,
+        limit::{self, RateLimiter},
+    },
+    remote::RemotePath,
+};
+
+pub struct Copy;
+
+impl PluginCommand for Copy {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud cp"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            transfer_flags(
+                output_format_flag(yes_flag(bwlimit_flag(Signature::build("cloud cp")))),
+                "with --recursive, a glob, or a batch ",
+            )
+            .input_output_types(vec![(Type::Any, Type::Any)])
+            .required(
+                "source",
+                SyntaxShape::String,
+                "The url of the file to copy, which may have a glob pattern in its path, or the url the objects of a batch are under.",
+            )
+            .required(
+                "destination",
+                SyntaxShape::String,
+                "The url to copy the file to.",
+            )
+            .switch(
+                "recursive",
+                "Copy every object under the source url, or under prefixes matching its pattern",
+                Some('r'),
+            )
+            .switch(
+                "update",
+                "Skip objects whose copy at the destination is at least as new, or has the same etag",
+                Some('u'),
+            )
+            .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Copy a file within or between cloud storage locations"
+    }
+
+    fn extra_description(&self) -> &str {
+        "Given a list of names as input, like the output of cloud ls, the objects with those paths within the bucket of the source url are copied instead, keeping their paths relative to it. They must be under the source url. Returns a row per object with its url, the url of the copy, and the error if it couldn't be copied.
+
+With --update, objects are only copied when they are missing at the destination, or were modified at the source after their copy there and have a different etag, so repeated copies only transfer what changed. Objects copied by --recursive or a glob are compared with a listing of the destination, and a single file by requesting both."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Copy a file within a s3 bucket.",
+                example: "cloud cp s3://mybucket/file.txt s3://mybucket/backup/file.txt",
+                result: None,
+            },
+            Example {
+                description: "Copy a file from HDFS to s3.",
+                example: "cloud cp webhdfs://namenode:9870/data/file.csv s3://mybucket/file.csv",
+                result: None,
+            },
+            Example {
+                description: "Archive the compressed logs, keeping their names.",
+                example: "cloud cp 's3://mybucket/logs/*.gz' s3://mybucket/archive/",
+                result: None,
+            },
+            Example {
+                description: "Upload the csv files of a local directory.",
+                example: "cloud cp 'file:///tmp/exports/*.csv' s3://mybucket/exports/",
+                result: None,
+            },
+            Example {
+                description: "Archive the logs not modified for a year.",
+                example: "cloud ls --recursive s3://mybucket/logs/ | where modified < ((date now) - 365day) | cloud cp s3://mybucket/logs/ s3://archive/logs/",
+                result: None,
+            },
+            Example {
+                description: "Upload the files of a local directory changed since the last run.",
+                example: "cloud cp -r --update file:///srv/reports/ s3://mybucket/reports/",
+                result: None,
+            },
+            Example {
+                description: "Download a prefix to a local directory, 16 objects at a time.",
+                example: "cloud cp -r -j 16 --progress s3://mybucket/logs/ file:///tmp/logs/",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(
+                    call,
+                    with_timeout(call, command(engine, plugin, call, input)),
+                ),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+    input: PipelineData,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let source = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?;
+    let glob = source.glob().map_err(|e| *e)?;
+    // Globs are expanded by listing the prefix before the pattern
+    let source = glob.as_ref().map_or(source.url, |glob| glob.url.clone());
+    let destination = RemotePath::from_call(engine, call, 1).map_err(|e| *e)?.url;
+
+    let (source_store, source_path) = plugin.parse_url(engine, &source, call_span).await?;
+    let (dest_store, dest_path) = plugin.parse_url(engine, &destination, call_span).await?;
+    let bwlimit = bandwidth_limit(engine, call).map_err(|e| *e)?;
+    let format = OutputFormat::from_call(call).map_err(|e| *e)?;
+    let audit = AuditLog::open(engine, call).map_err(|e| *e)?;
+    let config = Config::load(engine).map_err(|e| *e)?;
+    let mut copy = Transfer {
+        source: &source_store,
+        destination: &dest_store,
+        same_store: ObjectStoreCacheKey::from(&source_store)
+            == ObjectStoreCacheKey::from(&dest_store),
+        bwlimit: bwlimit.as_deref(),
+        config: &config,
+        overwrites: None,
+        removals: None,
+    };
+
+    let recursive = call.has_flag("recursive")?;
+    let update = call.has_flag("update")?;
+    let batch = match glob {
+        Some(_) => None,
+        None => batch_objects(input, &source, &source_path, call_span).map_err(|e| *e)?,
+    };
+    plugin.cache.invalidate(&dest_store, &dest_path).await;
+    if let Some(objects) = batch {
+        if update {
+            return Err(ShellError::GenericError {
+                error: "--update only skips objects listed with --recursive or a glob, or a single file".into(),
+                msg: "".into(),
+                span: call.get_flag_span("update"),
+                help: Some("Filter a batch on its modified column instead, e.g. with where".into()),
+                inner: vec![],
+            });
+        }
+        if confirming(engine, call).map_err(|e| *e)? {
+            let question = format!(
+                "Copy the {} objects of the input into {}, overwriting objects with the same names?",
+                objects.len(),
+                destination.item
+            );
+            confirm(engine, &question, call_span).map_err(|e| *e)?;
+        }
+        if let Some(audit) = &audit {
+            copy.overwrites = Some(Overwrites {
+                audit,
+                existing: existing_objects(&dest_store, &dest_path, &destination).await?,
+                url: &destination.item,
+                path: &dest_path,
+            });
+        }
+        let limits = Limits::from_call(engine, call).map_err(|e| *e)?;
+        let rows = copy_batch(
+            engine,
+            &copy,
+            &limits,
+            objects,
+            (&source, &source_path),
+            (&destination.item, &dest_path),
+            false,
+        )
+        .await?;
+        return Ok(PipelineData::Value(Value::list(rows, call_span), None));
+    }
+    let copied = if glob.is_some() || recursive {
+        if confirming(engine, call).map_err(|e| *e)?
+            && let Some(Ok(_)) = dest_store
+                .object_store()
+                .list(Some(&dest_path))
+                .next()
+                .await
+        {
+            let question = format!(
+                "Copy into {}, overwriting the objects already there with the same names?",
+                destination.item
+            );
+            confirm(engine, &question, call_span).map_err(|e| *e)?;
+        }
+
+        let limits = Limits::from_call(engine, call).map_err(|e| *e)?;
+        if let Some(audit) = &audit {
+            copy.overwrites = Some(Overwrites {
+                audit,
+                existing: existing_objects(&dest_store, &dest_path, &destination).await?,
+                url: &destination.item,
+                path: &dest_path,
+            });
+        }
+        let mut objects: Vec<ObjectMeta> = match &glob {
+            Some(glob) => glob_objects(&source_store, glob, &source_path, recursive).await?,
+            None => source_store
+                .object_store()
+                .list(Some(&source_path))
+                .try_collect()
+                .await
+                .map_err(|e| store_error(Action::List, &source.item, source.span, e))?,
+        };
+        let progress = call.has_flag("progress")? && !call.has_flag("quiet")?;
+        if update {
+            let existing: HashMap<Path, ObjectMeta> = dest_store
+                .object_store()
+                .list(Some(&dest_path))
+                .map_ok(|meta| (meta.location.clone(), meta))
+                .try_collect()
+                .await
+                .map_err(|e| store_error(Action::List, &destination.item, destination.span, e))?;
+            let listed = objects.len();
+            objects.retain(|meta| {
+                existing
+                    .get(&relocated(&meta.location, &source_path, &dest_path))
+                    .is_none_or(|copy| !up_to_date(meta, copy))
+            });
+            if progress && objects.len() < listed {
+                eprintln!(
+                    "Skipping {} objects already up to date",
+                    listed - objects.len()
+                );
+            }
+        }
+        let copied = copy_recursive(
+            engine,
+            &copy,
+            &limits,
+            objects,
+            &source_path,
+            &dest_path,
+            progress,
+            source.span,
+        )
+        .await?;
+        copied
+            .into_iter()
+            .map(|(to, size)| (object_url(&destination.item, &dest_path, &to), Some(size)))
+            .collect()
+    } else if update
+        && copy
+            .up_to_date(&source_path, &dest_path)
+            .await
+            .map_err(|e| copy_error(&source.item, &destination.item, e, source.span))?
+    {
+        vec![]
+    } else {
+        let exists = confirm_overwrite(
+            engine,
+            call,
+            &dest_store,
+            &dest_path,
+            &destination.item,
+            audit.as_ref(),
+        )
+        .await
+        .map_err(|e| *e)?;
+        if let Some(audit) = &audit {
+            copy.overwrites = Some(Overwrites {
+                audit,
+                existing: HashSet::from_iter(exists.then(|| dest_path.clone())),
+                url: &destination.item,
+                path: &dest_path,
+            });
+        }
+        let size = copy
+            .object(&source_path, &dest_path, None)
+            .await
+            .map_err(|e| copy_error(&source.item, &destination.item, e, source.span))?;
+        vec![(destination.item.to_string(), size)]
+    };
+
+    Ok(match format {
+        Some(format) => format.output(&destination.item, copied, call_span),
+        None => PipelineData::empty(),
+    })
+}
+
+const DEFAULT_MAX_IN_FLIGHT: u64 = 256 * 1000 * 1000;
+
+/// Parts copied at once for objects copied in parts
+const PART_CONCURRENCY: usize = 8;
+
+/// Objects transferred between stores are streamed from this size, in ranges read ahead of a
+/// multipart upload, instead of being held in memory whole
+const STREAMED_SIZE: u64 = 2 * RANGE_SIZE;
+/// Ranges read ahead of the upload of a streamed object
+const READ_AHEAD: usize = 2;
+
+/// Add the `--concurrency`, `--max-in-flight`, and `--progress` flags of copying many objects,
+/// which apply `when` as described
+pub(super) fn transfer_flags(signature: Signature, when: &str) -> Signature {
+    signature
+        .named(
+            "concurrency",
+            SyntaxShape::Int,
+            format!(
+                "Number of objects copied at once {when}(default: concurrency of the config, or 8)"
+            ),
+            Some('j'),
+        )
+        .named(
+            "max-in-flight",
+            SyntaxShape::Filesize,
+            format!("Total size of objects transferred at once {when}(default: 256MB)"),
+            None,
+        )
+        .switch(
+            "progress",
+            "Print each copied object and the overall throughput",
+            Some('p'),
+        )
+}
+
+/// Bounds on the work of a recursive copy
+pub(super) struct Limits {
+    /// Objects transferred at once
+    pub(super) concurrency: usize,
+    budget: ByteBudget,
+}
+
+impl Limits {
+    /// The limits of the `--concurrency` and `--max-in-flight` flags
+    pub(super) fn from_call(
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+    ) -> Result<Self, Box<ShellError>> {
+        let call_span = call.head;
+        let concurrency = concurrency(engine, call)?;
+        let max_in_flight = match call.get_flag::<Value>("max-in-flight")? {
+            Some(value) => {
+                let span = value.span();
+                let size = value.as_filesize()?.get();
+                if size < 1 {
+                    return Err(Box::new(ShellError::IncorrectValue {
+                        msg: "The in-flight budget must be positive".into(),
+                        val_span: span,
+                        call_span,
+                    }));
+                }
+                size as u64
+            }
+            None => DEFAULT_MAX_IN_FLIGHT,
+        };
+        Ok(Limits {
+            concurrency,
+            budget: ByteBudget::new(max_in_flight),
+        })
+    }
+}
+
+/// Limits the bytes held in memory by concurrent transfers, so many small objects are
+/// transferred concurrently while large ones wait for capacity
+struct ByteBudget {
+    semaphore: Semaphore,
+    /// Permits are KiB, as a semaphore can't count every byte of a large budget
+    permits: u32,
+}
+
+impl ByteBudget {
+    fn new(bytes: u64) -> Self {
+        let permits = bytes.div_ceil(1024).min(Semaphore::MAX_PERMITS as u64) as u32;
+        ByteBudget {
+            semaphore: Semaphore::new(permits as usize),
+            permits,
+        }
+    }
+
+    /// Wait until `bytes` fit in the budget. Objects larger than the whole budget take all of it.
+    async fn reserve(&self, bytes: u64) -> SemaphorePermit<'_> {
+        let permits = bytes.div_ceil(1024).clamp(1, self.permits as u64) as u32;
+        self.semaphore
+            .acquire_many(permits)
+            .await
+            .expect("The semaphore is never closed")
+    }
+}
+
+/// Copies objects from one store to another
+pub(super) struct Transfer<'a> {
+    pub(super) source: &'a NuObjectStore,
+    pub(super) destination: &'a NuObjectStore,
+    pub(super) same_store: bool,
+    /// Bytes per second transferred through the plugin, counting downloads and uploads
+    pub(super) bwlimit: Option<&'a RateLimiter>,
+    /// Part sizes and concurrency of uploads of streamed objects
+    pub(super) config: &'a Config,
+    /// Objects at the destination that are audited when copies replace them
+    pub(super) overwrites: Option<Overwrites<'a>>,
+    /// Log the sources removed by moves are audited in
+    pub(super) removals: Option<&'a AuditLog>,
+}
+
+pub(super) struct Overwrites<'a> {
+    pub(super) audit: &'a AuditLog,
+    pub(super) existing: HashSet<Path>,
+    /// Destination url and its path, for the urls of the replaced objects
+    pub(super) url: &'a Url,
+    pub(super) path: &'a Path,
+}
+
+impl Transfer<'_> {
+    /// Copy an object, of `size` if it is known, returning its size if it passed through the
+    /// plugin
+    pub(super) async fn object(
+        &self,
+        from: &Path,
+        to: &Path,
+        size: Option<u64>,
+    ) -> object_store::Result<Option<u64>> {
+        let result = self.transfer(from, to, size).await;
+        if let Some(overwrites) = &self.overwrites
+            && overwrites.existing.contains(to)
+        {
+            let url = object_url(overwrites.url, overwrites.path, to);
+            let outcome = result.as_ref().map(|_| ());
+            overwrites
+                .audit
+                .record(AuditAction::Overwrite, &url, outcome);
+        }
+        result
+    }
+
+    /// Remove the source of a move once it is copied, the object at `url`
+    pub(super) async fn remove(&self, from: &Path, url: &impl Display) -> object_store::Result<()> {
+        let result = self.source.object_store().delete(from).await;
+        if let Some(audit) = self.removals {
+            audit.record(AuditAction::Delete, url, result.as_ref().map(|_| ()));
+        }
+        result
+    }
+
+    async fn transfer(
+        &self,
+        from: &Path,
+        to: &Path,
+        size: Option<u64>,
+    ) -> object_store::Result<Option<u64>> {
+        if self.same_store {
+            // S3 copies objects of up to 5 GiB in one request, and larger ones in parts
+            if let Some(concat) = self.source.concat_client()
+                && size.is_none_or(|size| size > MAX_PART_SIZE)
+            {
+                let options = GetOptions {
+                    head: true,
+                    ..GetOptions::default()
+                };
+                let head = self.source.object_store().get_opts(from, options).await?;
+                if head.meta.size > MAX_PART_SIZE {
+                    concat
+                        .copy(from, to, head.meta.size, &head.attributes, PART_CONCURRENCY)
+                        .await?;
+                    return Ok(None);
+                }
+            }
+            // Let the store copy the object without transferring the data through the plugin
+            self.source.object_store().copy(from, to).await?;
+            Ok(None)
+        } else {
+            if size.is_none_or(|size| size >= STREAMED_SIZE) {
+                let meta = self.source.object_store().head(from).await?;
+                if meta.size >= STREAMED_SIZE
+                    && let Some(upload) = self.start_upload(to).await?
+                {
+                    return self.stream(from, meta, upload).await.map(Some);
+                }
+            }
+            let result = self.source.object_store().get(from).await?;
+            let bytes = match self.bwlimit {
+                Some(bwlimit) => {
+                    let bytes = limit::read_throttled(result, bwlimit).await?;
+                    bwlimit.acquire_many(bytes.len() as u64).await;
+                    bytes
+                }
+                None => result.bytes().await?,
+            };
+            let size = bytes.len() as u64;
+            self.destination
+                .object_store()
+                .put(to, PutPayload::from(bytes))
+                .await?;
+            Ok(Some(size))
+        }
+    }
+
+    /// Check that the copy of an object moved between stores matches its source before the
+    /// source is removed, by size, and by ETag when both are S3 digests of the content
+    pub(super) async fn verify(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        let (source, copied) = futures::try_join!(
+            self.source.object_store().head(from),
+            self.destination.object_store().head(to),
+        )?;
+        let mismatch = if source.size != copied.size {
+            format!("{} bytes were copied of {}", copied.size, source.size)
+        } else if let (Some(source_etag), Some(copied_etag)) = (
+            content_etag(self.source, &source),
+            content_etag(self.destination, &copied),
+        ) && source_etag != copied_etag
+        {
+            format!("the copy has ETag {copied_etag} instead of {source_etag}")
+        } else {
+            return Ok(());
+        };
+        Err(object_store::Error::Precondition {
+            path: to.to_string(),
+            source: format!("The copy doesn't match the source, which is kept: {mismatch}").into(),
+        })
+    }
+
+    /// Whether the object at `to` is at least as new as its source at `from`, or has the same
+    /// ETag, so `--update` skips copying it
+    async fn up_to_date(&self, from: &Path, to: &Path) -> object_store::Result<bool> {
+        let (source, copy) = futures::join!(
+            self.source.object_store().head(from),
+            self.destination.object_store().head(to),
+        );
+        match copy {
+            Ok(copy) => Ok(up_to_date(&source?, &copy)),
+            Err(object_store::Error::NotFound { .. }) => source.map(|_| false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Start a multipart upload to `to`, if the destination supports them
+    async fn start_upload(
+        &self,
+        to: &Path,
+    ) -> object_store::Result<Option<Box<dyn object_store::MultipartUpload>>> {
+        match self.destination.object_store().put_multipart(to).await {
+            Ok(upload) => Ok(Some(upload)),
+            Err(object_store::Error::NotImplemented) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Copy an object in ranges, uploading them as parts while the next ones are read. Ranges
+    /// are read for the ETag of `meta`, so an object replaced during the copy fails it.
+    async fn stream(
+        &self,
+        from: &Path,
+        meta: ObjectMeta,
+        upload: Box<dyn object_store::MultipartUpload>,
+    ) -> object_store::Result<u64> {
+        let part_size = self
+            .config
+            .upload_part_size
+            .map(|size| size.get().max(0) as u64);
+        let mut write = MultipartWriter::new(upload, PartSize::new(Some(meta.size), part_size));
+        let ranges = (0..meta.size)
+            .step_by(RANGE_SIZE as usize)
+            .map(|start| start..(start + RANGE_SIZE).min(meta.size));
+        let mut reads = stream::iter(ranges)
+            .map(|range| {
+                let options = GetOptions {
+                    range: Some(GetRange::Bounded(range)),
+                    if_match: meta.e_tag.clone(),
+                    ..GetOptions::default()
+                };
+                async move {
+                    let result = self.source.object_store().get_opts(from, options).await?;
+                    result.bytes().await
+                }
+            })
+            .buffered(READ_AHEAD);
+        let copied = async {
+            while let Some(bytes) = reads.next().await {
+                let bytes = bytes?;
+                if let Some(bwlimit) = self.bwlimit {
+                    // Counting the download and the upload
+                    bwlimit.acquire_many(2 * bytes.len() as u64).await;
+                }
+                write
+                    .wait_for_capacity(self.config.upload_concurrency())
+                    .await?;
+                write.put(bytes);
+            }
+            Ok(())
+        }
+        .await;
+        if let Err(e) = copied {
+            let _ = write.abort().await;
+            return Err(e);
+        }
+        write.finish().await?;
+        Ok(meta.size)
+    }
+}
+
+/// ETag of an S3 object, when it is the MD5 of its content rather than of the parts of a
+/// multipart upload
+fn content_etag<'a>(store: &NuObjectStore, meta: &'a ObjectMeta) -> Option<&'a str> {
+    let etag = meta.e_tag.as_deref()?;
+    (matches!(store, NuObjectStore::AmazonS3 { .. }) && !etag.contains('-')).then_some(etag)
+}
+
+/// Copy `objects` from under `source_path`, keeping their paths relative to it, returning the
+/// paths and sizes of the copies. Errors point at the source url, at `span`.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn copy_recursive(
+    engine: &EngineInterface,
+    copy: &Transfer<'_>,
+    limits: &Limits,
+    objects: Vec<ObjectMeta>,
+    source_path: &Path,
+    dest_path: &Path,
+    progress: bool,
+    span: Span,
+) -> Result<Vec<(Path, u64)>, ShellError> {
+    let total = objects.len();
+    let started = Instant::now();
+    let mut transfers = stream::iter(objects)
+        .map(|meta| async move {
+            let to = relocated(&meta.location, source_path, dest_path);
+            // Server side copies don't pass the data through the plugin
+            let _reserved = match copy.same_store {
+                true => None,
+                false => Some(limits.budget.reserve(meta.size).await),
+            };
+            let started = Instant::now();
+            match copy.object(&meta.location, &to, Some(meta.size)).await {
+                Ok(_) => Ok((meta, to, started.elapsed())),
+                Err(e) => Err(copy_error(&meta.location, &to, e, span)),
+            }
+        })
+        .buffer_unordered(limits.concurrency);
+
+    let mut copied = 0;
+    let mut bytes = 0;
+    let mut copied_objects = Vec::with_capacity(total);
+    while let Some(result) = transfers.next().await {
+        engine.signals().check(&span)?;
+        let (meta, to, elapsed) = result?;
+        copied += 1;
+        bytes += meta.size;
+        copied_objects.push((to, meta.size));
+        if progress {
+            eprintln!(
+                "[{copied}/{total}] {} ({} in {elapsed:.1?})",
+                meta.location,
+                Filesize::new(meta.size as i64)
+            );
+        }
+    }
+
+    if progress {
+        let elapsed = started.elapsed().as_secs_f64();
+        eprintln!(
+            "Copied {copied} objects, {} in {elapsed:.1}s ({}/s)",
+            Filesize::new(bytes as i64),
+            Filesize::new((bytes as f64 / elapsed.max(0.001)) as i64)
+        );
+    }
+    Ok(copied_objects)
+}
+
+/// Whether `copy` is at least as new as the object it would be copied from, or has its ETag
+pub(super) fn up_to_date(source: &ObjectMeta, copy: &ObjectMeta) -> bool {
+    copy.last_modified >= source.last_modified
+        || (source.e_tag.is_some() && source.e_tag == copy.e_tag)
+}
+
+/// Path `location` under `source_path` is copied to under `dest_path`
+fn relocated(location: &Path, source_path: &Path, dest_path: &Path) -> Path {
+    location
+        .prefix_match(source_path)
+        .into_iter()
+        .flatten()
+        .fold(dest_path.clone(), |path, part| path.child(part))
+}
+
+/// Paths of the objects under the destination, listed before a copy to audit the ones it
+/// replaces, as the copies are among them once they are made
+pub(super) async fn existing_objects(
+    store: &NuObjectStore,
+    path: &Path,
+    url: &Spanned<Url>,
+) -> Result<HashSet<Path>, ShellError> {
+    store
+        .object_store()
+        .list(Some(path))
+        .map_ok(|meta| meta.location)
+        .try_collect()
+        .await
+        .map_err(|e| store_error(Action::List, &url.item, url.span, e))
+}
+
+/// Copy the objects of a batch from under the source path to the same paths under the
+/// destination, removing the sources of the copies with `remove`. Failed objects are reported
+/// in their rows rather than stopping the batch.
+pub(super) async fn copy_batch(
+    engine: &EngineInterface,
+    copy: &Transfer<'_>,
+    limits: &Limits,
+    objects: Vec<BatchObject>,
+    (source, source_path): (&Spanned<Url>, &Path),
+    (destination, dest_path): (&Url, &Path),
+    remove: bool,
+) -> Result<Vec<Value>, ShellError> {
+    let span = source.span;
+    let mut transfers = stream::iter(objects)
+        .map(|object| async move {
+            let to = relocated(&object.location, source_path, dest_path);
+            let _reserved = match copy.same_store {
+                true => None,
+                false => Some(limits.budget.reserve(object.size.unwrap_or(0)).await),
+            };
+            let mut result = copy.object(&object.location, &to, object.size).await;
+            // Moving an object onto itself leaves it as it is
+            if remove && result.is_ok() && !(copy.same_store && object.location == to) {
+                if !copy.same_store
+                    && let Err(e) = copy.verify(&object.location, &to).await
+                {
+                    result = Err(e);
+                } else {
+                    let url = object_url(&source.item, source_path, &object.location);
+                    let removed = copy.remove(&object.location, &url).await;
+                    result = removed.and(result);
+                }
+            }
+            let result = result.map_err(|e| copy_error(&object.location, &to, e, span));
+            (object, to, result)
+        })
+        .buffered(limits.concurrency);
+
+    let mut rows = vec![];
+    while let Some((object, to, result)) = transfers.next().await {
+        engine.signals().check(&span)?;
+        let (size, error) = match result {
+            Ok(size) => (size.or(object.size), None),
+            Err(e) => (object.size, Some(e)),
+        };
+        rows.push(batch_row(
+            object_url(&source.item, source_path, &object.location),
+            Some(object_url(destination, dest_path, &to)),
+            size,
+            error,
+            span,
+        ));
+    }
+    Ok(rows)
+}
+
+fn copy_error(
+    source: &impl Display,
+    destination: &impl Display,
+    e: object_store::Error,
+    span: Span,
+) -> ShellError {
+    store_error(Action::Copy, format!("{source} to {destination}"), span, e)
+}
@@ -0,0 +1,103 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{Example, LabeledError, PipelineData, ShellError, Signature, Spanned};
+
+use crate::CloudPlugin;
+
+/// A command whose flags default to the settings of its name in the config, e.g.
+/// `$env.config.plugins.cloud.ls.limit` for `cloud ls --limit`.
+///
+/// Settings are named by the flag, with dashes or underscores. Flags given to the command take
+/// precedence.
+pub struct WithDefaults(pub Box<dyn PluginCommand<Plugin = CloudPlugin>>);
+
+impl PluginCommand for WithDefaults {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn signature(&self) -> Signature {
+        self.0.signature()
+    }
+
+    fn description(&self) -> &str {
+        self.0.description()
+    }
+
+    fn extra_description(&self) -> &str {
+        self.0.extra_description()
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        self.0.search_terms()
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        self.0.examples()
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let call = with_defaults(engine, call, self.name(), &self.signature()).map_err(|e| *e)?;
+        self.0.run(plugin, engine, &call, input)
+    }
+}
+
+/// `call` with the defaults of the command `name` configured for the flags it wasn't given
+fn with_defaults(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+    name: &str,
+    signature: &Signature,
+) -> Result<EvaluatedCall, Box<ShellError>> {
+    let mut call = call.clone();
+    // The config is named like the command without its `cloud` prefix
+    let Some(key) = name.strip_prefix("cloud ") else {
+        return Ok(call);
+    };
+    let Some(config) = engine.get_plugin_config()? else {
+        return Ok(call);
+    };
+    let Some(defaults) = config.as_record()?.get(key) else {
+        return Ok(call);
+    };
+    let defaults = defaults.as_record().map_err(|_| {
+        Box::new(ShellError::GenericError {
+            error: format!("The defaults of {name} must be a record of its flags"),
+            msg: "".into(),
+            span: Some(defaults.span()),
+            help: Some(format!(
+                "Set them like `$env.config.plugins.cloud.{key} = {{...}}`"
+            )),
+            inner: vec![],
+        })
+    })?;
+    for (setting, value) in defaults {
+        let flag = setting.replace('_', "-");
+        if !signature.named.iter().any(|named| named.long == flag) {
+            return Err(Box::new(ShellError::GenericError {
+                error: format!("{name} has no --{flag} flag to default"),
+                msg: "".into(),
+                span: Some(value.span()),
+                help: Some(format!("See `help {name}` for its flags")),
+                inner: vec![],
+            }));
+        }
+        if call.named.iter().all(|(named, _)| named.item != flag) {
+            call.named.push((
+                Spanned {
+                    item: flag,
+                    span: value.span(),
+                },
+                Some(value.clone()),
+            ));
+        }
+    }
+    Ok(call)
+}
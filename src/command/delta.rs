@@ -0,0 +1,432 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    vec,
+};
+
+use chrono::DateTime;
+use futures::TryStreamExt;
+use log::debug;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, Record, ShellError, Signature, Span, Spanned,
+    SyntaxShape, Type, Value, record,
+};
+use object_store::path::Path;
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use url::Url;
+
+use super::{from_parquet, run_flags, with_rate_limit, with_request_log, with_timeout};
+use crate::{CloudPlugin, providers::NuObjectStore, remote::RemotePath};
+
+/// Reader features that don't change how data files are read
+const SUPPORTED_READER_FEATURES: &[&str] = &["timestampNtz", "typeWidening", "vacuumProtocolCheck"];
+
+pub struct DeltaOpen;
+
+impl PluginCommand for DeltaOpen {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud delta open"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            Signature::build("cloud delta open")
+                .input_output_types(vec![(Type::Nothing, Type::table())])
+                .required("uri", SyntaxShape::String, "The url of the Delta table.")
+                .named(
+                    "version",
+                    SyntaxShape::Int,
+                    "Read the table as of this version instead of the latest one",
+                    Some('v'),
+                )
+                .switch(
+                    "files",
+                    "List the data files of the snapshot instead of reading them",
+                    None,
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Read a Delta Lake table, resolving the data files of its current or an earlier version from the transaction log."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Data files are converted with a `from parquet` command, which must be in scope. Tables using column mapping, deletion vectors, or checkpoints without the preceding commits are not supported."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Read the latest version of a Delta table.",
+                example: "cloud delta open s3://mybucket/tables/events",
+                result: None,
+            },
+            Example {
+                description: "List the data files of version 3 of a Delta table.",
+                example: "cloud delta open s3://mybucket/tables/events --version 3 --files",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let version = match call.get_flag::<i64>("version")? {
+        Some(version) if version < 0 => {
+            return Err(ShellError::IncorrectValue {
+                msg: "The version can't be negative".into(),
+                val_span: call.get_flag_span("version").unwrap_or(call_span),
+                call_span,
+            });
+        }
+        version => version.map(|v| v as u64),
+    };
+    let (store, root) = plugin.parse_url(engine, &url, call_span).await?;
+    let snapshot = Snapshot::load(&store, &root, version, &url).await?;
+
+    if call.has_flag("files")? {
+        let files = snapshot
+            .files
+            .values()
+            .map(|add| add.to_value(&snapshot, call_span))
+            .collect();
+        return Ok(PipelineData::Value(Value::list(files, call_span), None));
+    }
+
+    let from_parquet = from_parquet::find_decl(engine, "Delta", call_span).map_err(|e| *e)?;
+    let mut rows = vec![];
+    for add in snapshot.files.values() {
+        engine.signals().check(&call_span)?;
+        let (file_store, file_path) =
+            data_file(plugin, engine, &store, &root, &add.path, url.span).await?;
+        debug!("Reading Delta data file {file_path}");
+        let values = from_parquet::read(
+            engine,
+            from_parquet,
+            &file_store,
+            &file_path,
+            call_span,
+            url.span,
+        )
+        .await?;
+        // Partition columns are only recorded in the log, not in the data files
+        let partition_values = add.partition_values(&snapshot, call_span);
+        for mut row in values {
+            if let Value::Record { val, .. } = &mut row {
+                let record = val.to_mut();
+                for (column, value) in partition_values.iter() {
+                    if !record.contains(column) {
+                        record.push(column, value.clone());
+                    }
+                }
+            }
+            rows.push(row);
+        }
+    }
+    Ok(PipelineData::Value(Value::list(rows, call_span), None))
+}
+
+/// Store and path of a data file, which the log records relative to the table root or as an
+/// absolute url
+async fn data_file(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    store: &NuObjectStore,
+    root: &Path,
+    path: &str,
+    span: Span,
+) -> Result<(NuObjectStore, Path), ShellError> {
+    match Url::parse(path) {
+        Ok(url) => {
+            plugin
+                .parse_url(engine, &Spanned { item: url, span }, span)
+                .await
+        }
+        Err(_) => {
+            let relative = percent_decode_str(path).decode_utf8_lossy();
+            Ok((store.clone(), Path::from(format!("{root}/{relative}"))))
+        }
+    }
+}
+
+/// One line of a commit file, holding a single action
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Action {
+    protocol: Option<Protocol>,
+    meta_data: Option<MetaData>,
+    add: Option<Add>,
+    remove: Option<Remove>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Protocol {
+    min_reader_version: u32,
+    reader_features: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetaData {
+    #[serde(default)]
+    partition_columns: Vec<String>,
+    schema_string: String,
+    #[serde(default)]
+    configuration: HashMap<String, Option<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Add {
+    path: String,
+    #[serde(default)]
+    partition_values: HashMap<String, Option<String>>,
+    size: i64,
+    modification_time: i64,
+    stats: Option<String>,
+    deletion_vector: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Remove {
+    path: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Stats {
+    #[serde(rename = "numRecords")]
+    num_records: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Schema {
+    fields: Vec<SchemaField>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SchemaField {
+    name: String,
+    /// A primitive type name, or an object for nested types
+    #[serde(rename = "type")]
+    data_type: serde_json::Value,
+}
+
+/// The state of a table at a version, from replaying its commits
+struct Snapshot {
+    partition_columns: Vec<String>,
+    /// Primitive types of the columns, for typing partition values
+    column_types: HashMap<String, String>,
+    /// Data files by path
+    files: BTreeMap<String, Add>,
+}
+
+impl Snapshot {
+    async fn load(
+        store: &NuObjectStore,
+        root: &Path,
+        version: Option<u64>,
+        url: &Spanned<Url>,
+    ) -> Result<Self, ShellError> {
+        let error = |msg: String| ShellError::GenericError {
+            error: format!("Could not read Delta table {}: {msg}", url.item),
+            msg: "".into(),
+            span: Some(url.span),
+            help: None,
+            inner: vec![],
+        };
+
+        let log = root.child("_delta_log");
+        let mut commits: Vec<(u64, Path)> = store
+            .object_store()
+            .list(Some(&log))
+            .try_filter_map(|meta| async move {
+                let name = meta.location.filename().unwrap_or_default();
+                let version = name
+                    .strip_suffix(".json")
+                    .filter(|v| v.len() == 20)
+                    .and_then(|v| v.parse().ok());
+                Ok(version.map(|version| (version, meta.location)))
+            })
+            .try_collect()
+            .await
+            .map_err(|e| error(format!("could not list the log: {e}")))?;
+        commits.sort_by_key(|(version, _)| *version);
+
+        let Some((latest, _)) = commits.last() else {
+            return Err(error("the transaction log has no commits".into()));
+        };
+        let version = version.unwrap_or(*latest);
+        if version > *latest {
+            return Err(error(format!(
+                "version {version} doesn't exist, the latest is {latest}"
+            )));
+        }
+        // Commits before a checkpoint may be cleaned up, which would need the checkpoint's
+        // Parquet data to reconstruct the snapshot
+        if commits
+            .iter()
+            .take_while(|(v, _)| *v <= version)
+            .enumerate()
+            .any(|(idx, (v, _))| idx as u64 != *v)
+        {
+            return Err(error(
+                "commits before a checkpoint were removed, reading checkpoints isn't supported"
+                    .into(),
+            ));
+        }
+
+        let mut snapshot = Snapshot {
+            partition_columns: vec![],
+            column_types: HashMap::new(),
+            files: BTreeMap::new(),
+        };
+        for (_, path) in commits.iter().take_while(|(v, _)| *v <= version) {
+            let data = async { store.object_store().get(path).await?.bytes().await }
+                .await
+                .map_err(|e| error(format!("could not read {path}: {e}")))?;
+            for line in data.split(|b| *b == b'\n') {
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let action: Action = serde_json::from_slice(line)
+                    .map_err(|e| error(format!("invalid action in {path}: {e}")))?;
+                snapshot.apply(action).map_err(error)?;
+            }
+        }
+        Ok(snapshot)
+    }
+
+    fn apply(&mut self, action: Action) -> Result<(), String> {
+        if let Some(protocol) = action.protocol {
+            let unsupported = protocol
+                .reader_features
+                .iter()
+                .flatten()
+                .find(|feature| !SUPPORTED_READER_FEATURES.contains(&feature.as_str()));
+            if let Some(feature) = unsupported {
+                return Err(format!("the {feature} reader feature isn't supported"));
+            }
+            if protocol.min_reader_version == 2 {
+                return Err("column mapping isn't supported".into());
+            }
+            if protocol.min_reader_version > 3 {
+                return Err(format!(
+                    "reader version {} isn't supported",
+                    protocol.min_reader_version
+                ));
+            }
+        }
+        if let Some(meta_data) = action.meta_data {
+            let column_mapping = meta_data
+                .configuration
+                .get("delta.columnMapping.mode")
+                .cloned()
+                .flatten();
+            if column_mapping.is_some_and(|mode| mode != "none") {
+                return Err("column mapping isn't supported".into());
+            }
+            let schema: Schema = serde_json::from_str(&meta_data.schema_string)
+                .map_err(|e| format!("invalid schema: {e}"))?;
+            self.column_types = schema
+                .fields
+                .into_iter()
+                .filter_map(|field| Some((field.name, field.data_type.as_str()?.to_string())))
+                .collect();
+            self.partition_columns = meta_data.partition_columns;
+        }
+        if let Some(add) = action.add {
+            if add.deletion_vector.is_some() {
+                return Err("deletion vectors aren't supported".into());
+            }
+            self.files.insert(add.path.clone(), add);
+        }
+        if let Some(remove) = action.remove {
+            self.files.remove(&remove.path);
+        }
+        Ok(())
+    }
+}
+
+impl Add {
+    /// Partition values typed by the column types of the schema
+    fn partition_values(&self, snapshot: &Snapshot, span: Span) -> Record {
+        snapshot
+            .partition_columns
+            .iter()
+            .map(|column| {
+                let value = self.partition_values.get(column).cloned().flatten();
+                let data_type = snapshot.column_types.get(column).map(String::as_str);
+                (column.clone(), partition_value(value, data_type, span))
+            })
+            .collect()
+    }
+
+    fn to_value(&self, snapshot: &Snapshot, span: Span) -> Value {
+        let num_records = self
+            .stats
+            .as_deref()
+            .and_then(|stats| serde_json::from_str::<Stats>(stats).ok())
+            .and_then(|stats| stats.num_records);
+        let modified = DateTime::from_timestamp_millis(self.modification_time)
+            .map_or(Value::nothing(span), |date| {
+                Value::date(date.fixed_offset(), span)
+            });
+        Value::record(
+            record!(
+                "path" => Value::string(percent_decode_str(&self.path).decode_utf8_lossy(), span),
+                "size" => Value::filesize(self.size, span),
+                "modified" => modified,
+                "partition_values" => Value::record(self.partition_values(snapshot, span), span),
+                "num_records" => num_records.map_or(Value::nothing(span), |n| Value::int(n, span)),
+            ),
+            span,
+        )
+    }
+}
+
+/// A partition value, which the log records as a string
+fn partition_value(value: Option<String>, data_type: Option<&str>, span: Span) -> Value {
+    let Some(value) = value else {
+        return Value::nothing(span);
+    };
+    let parsed = match data_type {
+        Some("byte" | "short" | "integer" | "long") => {
+            value.parse().ok().map(|n| Value::int(n, span))
+        }
+        Some("float" | "double") => value.parse().ok().map(|n| Value::float(n, span)),
+        Some("boolean") => value.parse().ok().map(|b| Value::bool(b, span)),
+        _ => None,
+    };
+    parsed.unwrap_or_else(|| Value::string(value, span))
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use futures::StreamExt;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, SyntaxShape, Type, Value,
+    record,
+};
+use object_store::path::Path;
+
+use super::{run_flags, with_rate_limit, with_request_log, with_timeout};
+use crate::{
+    CloudPlugin,
+    error::{Action, store_error},
+    remote::RemotePath,
+};
+
+pub struct Du;
+
+impl PluginCommand for Du {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud du"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            Signature::build("cloud du")
+                .input_output_types(vec![(Type::Nothing, Type::table())])
+                .required(
+                    "uri",
+                    SyntaxShape::String,
+                    "The url of the prefix to measure.",
+                )
+                .named(
+                    "depth",
+                    SyntaxShape::Int,
+                    "Number of path segments under the url to group objects by (default: 1)",
+                    Some('d'),
+                )
+                .switch(
+                    "bytes",
+                    "Show sizes as exact numbers of bytes instead of filesizes",
+                    Some('b'),
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Total the sizes of the objects under a prefix, grouped by the prefixes at a depth under it."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Every object under the url is listed, and counted towards the prefix of its first --depth path segments under the url, as a dir row. Objects less deep are rows of their own, of type file. Returns a row per prefix and object, by name, with the number of objects and their total size. A depth of 0 gives the total of the url."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Find the folders of a bucket taking the most storage.",
+                example: "cloud du s3://mybucket/ | sort-by size --reverse",
+                result: None,
+            },
+            Example {
+                description: "Total the logs of each year and month.",
+                example: "cloud du --depth 2 s3://mybucket/logs/",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+/// What the objects counted towards a row add up to
+#[derive(Default)]
+struct Usage {
+    dir: bool,
+    objects: i64,
+    size: u64,
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let depth = match call.get_flag::<i64>("depth")? {
+        Some(depth) if depth < 0 => {
+            return Err(ShellError::IncorrectValue {
+                msg: "The depth can't be negative".into(),
+                val_span: call.get_flag_span("depth").unwrap_or(call_span),
+                call_span,
+            });
+        }
+        Some(depth) => depth as usize,
+        None => DEFAULT_DEPTH,
+    };
+    let bytes = call.has_flag("bytes")?;
+
+    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
+    let mut usage: BTreeMap<Path, Usage> = BTreeMap::new();
+    let mut objects = object_store.object_store().list(Some(&path));
+    while let Some(meta) = objects.next().await {
+        engine.signals().check(&call_span)?;
+        let meta = meta.map_err(|e| store_error(Action::List, &url.item, url.span, e))?;
+        let parts: Vec<_> = meta
+            .location
+            .prefix_match(&path)
+            .into_iter()
+            .flatten()
+            .collect();
+        let dir = parts.len() > depth;
+        let group = parts
+            .into_iter()
+            .take(depth)
+            .fold(path.clone(), |group, part| group.child(part));
+        let group = usage.entry(group).or_default();
+        group.dir |= dir;
+        group.objects += 1;
+        group.size += meta.size;
+    }
+
+    let rows = usage
+        .into_iter()
+        .map(|(name, usage)| {
+            Value::record(
+                record!(
+                    "name" => Value::string(name.to_string(), call_span),
+                    "type" => Value::string(if usage.dir { "dir" } else { "file" }, call_span),
+                    "objects" => Value::int(usage.objects, call_span),
+                    "size" => match bytes {
+                        true => Value::int(usage.size as i64, call_span),
+                        false => Value::filesize(usage.size as i64, call_span),
+                    },
+                ),
+                call_span,
+            )
+        })
+        .collect();
+    Ok(PipelineData::Value(Value::list(rows, call_span), None))
+}
+
+const DEFAULT_DEPTH: usize = 1;
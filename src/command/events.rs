@@ -0,0 +1,374 @@
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, ListStream, PipelineData, ShellError, Signature, Span,
+    Spanned, SyntaxShape, Type, Value, record,
+};
+use object_store::path::Path;
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use url::Url;
+
+use super::{
+    duration_flag, object_url, run_flags, with_rate_limit, with_request_log, with_timeout,
+};
+use crate::{
+    CloudPlugin,
+    error::{Action, store_error},
+    providers::{
+        limit,
+        sqs::{MAX_WAIT_SECONDS, Message, SqsClient},
+        verbose,
+    },
+    remote::RemotePath,
+};
+
+pub struct Events;
+
+impl PluginCommand for Events {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud events"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            Signature::build("cloud events")
+                .input_output_types(vec![(Type::Nothing, Type::list(Type::record()))])
+                .required(
+                    "uri",
+                    SyntaxShape::String,
+                    "The url of the bucket or prefix to emit the events of.",
+                )
+                .named(
+                    "queue",
+                    SyntaxShape::String,
+                    "Url of the SQS queue the bucket sends its event notifications to",
+                    None,
+                )
+                .named(
+                    "idle",
+                    SyntaxShape::Duration,
+                    "Stop once no events arrive for this long, instead of waiting for more",
+                    None,
+                )
+                .switch(
+                    "keep",
+                    "Leave the messages in the queue instead of deleting them once their events are emitted",
+                    Some('k'),
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Stream the objects created and deleted in an S3 bucket, from the event notifications it sends to an SQS queue."
+    }
+
+    fn extra_description(&self) -> &str {
+        "The queue is long polled, so events are emitted as they arrive instead of by listing the bucket again. Each event is a record with whether the object was created or deleted, its name, url, size, etag, version id, and time. Notifications sent directly or through SNS are both understood. Messages are deleted from the queue as their events are emitted, including those without any event under the url, unless --keep is given. The stream ends when interrupted, or after --idle without events."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Print the uploads to a prefix as they happen.",
+                example: "cloud events s3://mybucket/uploads/ --queue https://sqs.us-east-1.amazonaws.com/123456789012/uploads | where event == created | each { print $in.url }",
+                result: None,
+            },
+            Example {
+                description: "Collect the events already waiting in the queue.",
+                example: "cloud events s3://mybucket --queue https://sqs.us-east-1.amazonaws.com/123456789012/uploads --idle 0sec",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let queue: Spanned<String> =
+        call.get_flag("queue")?
+            .ok_or_else(|| ShellError::MissingParameter {
+                param_name: "--queue".into(),
+                span: call_span,
+            })?;
+    let queue_url = Url::parse(&queue.item).map_err(|e| ShellError::IncorrectValue {
+        msg: format!("Invalid queue url: {e}"),
+        val_span: queue.span,
+        call_span,
+    })?;
+    let idle = duration_flag(call, "idle", true).map_err(|e| *e)?;
+    let keep = call.has_flag("keep")?;
+
+    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
+    let signed = object_store
+        .signed_client()
+        .ok_or_else(|| ShellError::GenericError {
+            error: format!("Events aren't supported for {} urls", url.item.scheme()),
+            msg: "".into(),
+            span: Some(url.span),
+            help: Some("Event notifications are only supported for S3 buckets".into()),
+            inner: vec![],
+        })?;
+    let bucket = url.item.host_str().unwrap_or_default().to_string();
+    let mut events = EventStream {
+        client: SqsClient::new(Arc::clone(signed), queue_url),
+        bucket,
+        prefix: path,
+        url: url.item.clone(),
+        keep,
+        idle,
+        last_event: Instant::now(),
+        pending: VecDeque::new(),
+        failed: false,
+        span: call_span,
+    };
+    let queue_error = move |e| store_error(Action::Read, &queue.item, queue.span, e);
+
+    let runtime = Arc::clone(plugin.runtime(engine, call_span).map_err(|e| *e)?);
+    let run_limit = limit::current();
+    let verbose = verbose::enabled();
+    let values = std::iter::from_fn(move || {
+        let next = limit::scoped(run_limit.clone(), events.next());
+        runtime
+            .block_on(verbose::scoped(verbose, next))
+            .map(|result| result.unwrap_or_else(|e| Value::error(queue_error(e), call_span)))
+    });
+    Ok(PipelineData::ListStream(
+        ListStream::new(values, call_span, engine.signals().clone()),
+        None,
+    ))
+}
+
+/// Events of the objects under a prefix, received from the queue as they are consumed
+struct EventStream {
+    client: SqsClient,
+    bucket: String,
+    prefix: Path,
+    url: Url,
+    keep: bool,
+    idle: Option<Duration>,
+    last_event: Instant,
+    /// Events received and not yet emitted, with the message to delete after the last event of
+    /// each
+    pending: VecDeque<(Value, Option<String>)>,
+    /// Whether the queue failed, which ends the stream after the error
+    failed: bool,
+    span: Span,
+}
+
+impl EventStream {
+    async fn next(&mut self) -> Option<object_store::Result<Value>> {
+        loop {
+            if self.failed {
+                return None;
+            }
+            if let Some((event, receipt_handle)) = self.pending.pop_front() {
+                if let Some(receipt_handle) = receipt_handle
+                    && let Err(e) = self.client.delete(&receipt_handle).await
+                {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+                self.last_event = Instant::now();
+                return Some(Ok(event));
+            }
+
+            let wait = match self.idle {
+                Some(idle) => idle.saturating_sub(self.last_event.elapsed()).as_secs(),
+                None => MAX_WAIT_SECONDS,
+            };
+            let messages = match self.client.receive(wait).await {
+                Ok(messages) => messages,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            };
+            if messages.is_empty()
+                && let Some(idle) = self.idle
+                && self.last_event.elapsed() >= idle
+            {
+                return None;
+            }
+            for message in messages {
+                if let Err(e) = self.queue_events(message).await {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+
+    /// Queue the events of a message under the prefix, deleting messages without any right away
+    async fn queue_events(&mut self, message: Message) -> object_store::Result<()> {
+        let events: Vec<Value> = parse_events(&message.body)
+            .into_iter()
+            .filter(|event| event.bucket == self.bucket)
+            .filter(|event| event.location.prefix_match(&self.prefix).is_some())
+            .map(|event| event.value(&self.url, &self.prefix, self.span))
+            .collect();
+        let mut receipt_handle = (!self.keep).then_some(message.receipt_handle);
+        if events.is_empty() {
+            if let Some(receipt_handle) = receipt_handle {
+                self.client.delete(&receipt_handle).await?;
+            }
+            return Ok(());
+        }
+        let count = events.len();
+        for (i, event) in events.into_iter().enumerate() {
+            let delete = if i + 1 == count {
+                receipt_handle.take()
+            } else {
+                None
+            };
+            self.pending.push_back((event, delete));
+        }
+        Ok(())
+    }
+}
+
+/// An object created or deleted, from a record of an S3 event notification
+struct ObjectEvent {
+    event: &'static str,
+    event_name: String,
+    bucket: String,
+    location: Path,
+    size: Option<u64>,
+    etag: Option<String>,
+    version_id: Option<String>,
+    time: Option<String>,
+}
+
+impl ObjectEvent {
+    fn value(self, url: &Url, prefix: &Path, span: Span) -> Value {
+        let string =
+            |value: Option<String>| value.map_or(Value::nothing(span), |s| Value::string(s, span));
+        let time = self
+            .time
+            .and_then(|time| chrono::DateTime::parse_from_rfc3339(&time).ok())
+            .map_or(Value::nothing(span), |time| Value::date(time, span));
+        Value::record(
+            record!(
+                "event" => Value::string(self.event, span),
+                "name" => Value::string(self.location.to_string(), span),
+                "url" => Value::string(object_url(url, prefix, &self.location), span),
+                "size" => self.size.map_or(Value::nothing(span), |size| Value::filesize(size as i64, span)),
+                "etag" => string(self.etag),
+                "version_id" => string(self.version_id),
+                "time" => time,
+                "event_name" => Value::string(self.event_name, span),
+            ),
+            span,
+        )
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Notification {
+    #[serde(default)]
+    records: Vec<NotificationRecord>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NotificationRecord {
+    event_name: String,
+    event_time: Option<String>,
+    s3: S3Entity,
+}
+
+#[derive(Deserialize)]
+struct S3Entity {
+    bucket: S3Bucket,
+    object: S3Object,
+}
+
+#[derive(Deserialize)]
+struct S3Bucket {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct S3Object {
+    key: String,
+    size: Option<u64>,
+    e_tag: Option<String>,
+    version_id: Option<String>,
+}
+
+/// Object events of the body of a message, a notification of the bucket or an SNS notification
+/// wrapping one. Test events and events of other kinds are left out.
+fn parse_events(body: &str) -> Vec<ObjectEvent> {
+    let Ok(mut message) = serde_json::from_str::<serde_json::Value>(body) else {
+        return vec![];
+    };
+    if message["Type"] == "Notification"
+        && let Some(inner) = message["Message"].as_str()
+    {
+        match serde_json::from_str(inner) {
+            Ok(inner) => message = inner,
+            Err(_) => return vec![],
+        }
+    }
+    let Ok(notification) = serde_json::from_value::<Notification>(message) else {
+        return vec![];
+    };
+    notification
+        .records
+        .into_iter()
+        .filter_map(|record| {
+            let event = match record.event_name.split(':').next() {
+                Some("ObjectCreated") => "created",
+                Some("ObjectRemoved" | "LifecycleExpiration") => "deleted",
+                _ => return None,
+            };
+            // Keys are form encoded, with spaces as `+`
+            let key = record.s3.object.key.replace('+', " ");
+            let key = percent_decode_str(&key).decode_utf8().ok()?;
+            Some(ObjectEvent {
+                event,
+                event_name: record.event_name,
+                bucket: record.s3.bucket.name,
+                location: Path::parse(key).ok()?,
+                size: record.s3.object.size,
+                etag: record.s3.object.e_tag,
+                version_id: record.s3.object.version_id,
+                time: record.event_time,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,265 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, TryStreamExt};
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, ListStream, PipelineData, ShellError, Signature, SyntaxShape,
+    Type, Value, record,
+};
+use object_store::ObjectMeta;
+use regex::Regex;
+
+use super::{listing_metadata, run_flags, with_rate_limit, with_request_log, with_timeout};
+use crate::{
+    CloudPlugin,
+    error::{Action, store_error},
+    providers::{limit, verbose},
+    remote::RemotePath,
+};
+
+pub struct Find;
+
+impl PluginCommand for Find {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud find"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            Signature::build("cloud find")
+                .input_output_types(vec![(Type::Nothing, Type::table())])
+                .required(
+                    "uri",
+                    SyntaxShape::String,
+                    "The url of the prefix to search.",
+                )
+                .named(
+                    "regex",
+                    SyntaxShape::String,
+                    "Only objects whose names match this regular expression",
+                    Some('e'),
+                )
+                .named(
+                    "min-size",
+                    SyntaxShape::Filesize,
+                    "Only objects of at least this size",
+                    None,
+                )
+                .named(
+                    "max-size",
+                    SyntaxShape::Filesize,
+                    "Only objects of at most this size",
+                    None,
+                )
+                .named(
+                    "before",
+                    SyntaxShape::DateTime,
+                    "Only objects last modified before this time",
+                    None,
+                )
+                .named(
+                    "after",
+                    SyntaxShape::DateTime,
+                    "Only objects last modified at or after this time",
+                    None,
+                )
+                .named(
+                    "storage-class",
+                    SyntaxShape::String,
+                    "Only objects of an S3 bucket in this storage class",
+                    None,
+                )
+                .switch(
+                    "bytes",
+                    "Show sizes as exact numbers of bytes instead of filesizes",
+                    Some('b'),
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Search the objects under a url by name, size, modification time, and storage class."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Every object under the url is listed, as with cloud ls --recursive, and the filters are applied to each page as it is listed, so only the matches are kept and they stream out while the search goes on. Names are the paths within the bucket, as cloud ls gives them. --storage-class lists S3 buckets with their storage classes, and adds a storage_class column."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Find the large parquet files of a bucket.",
+                example: "cloud find s3://mybucket/ --regex '\\.parquet$' --min-size 1GB",
+                result: None,
+            },
+            Example {
+                description: "Find the logs of last year still in the standard storage class.",
+                example: "cloud find s3://mybucket/logs/ --after 2024-01-01 --before 2025-01-01 --storage-class standard",
+                result: None,
+            },
+            Example {
+                description: "Remove the empty objects under a prefix.",
+                example: "cloud find s3://mybucket/uploads/ --max-size 0B | cloud rm",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+/// The filters checked against the listing, before any HEAD request
+struct Filters {
+    regex: Option<Regex>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    before: Option<DateTime<Utc>>,
+    after: Option<DateTime<Utc>>,
+}
+
+impl Filters {
+    fn from_call(call: &EvaluatedCall) -> Result<Self, Box<ShellError>> {
+        let regex = match call.get_flag::<String>("regex")? {
+            Some(pattern) => {
+                Some(
+                    Regex::new(&pattern).map_err(|e| ShellError::IncorrectValue {
+                        msg: format!("Invalid regular expression: {e}"),
+                        val_span: call.get_flag_span("regex").unwrap_or(call.head),
+                        call_span: call.head,
+                    })?,
+                )
+            }
+            None => None,
+        };
+        let size = |flag: &str| -> Result<Option<u64>, Box<ShellError>> {
+            match call.get_flag::<Value>(flag)? {
+                Some(value) => Ok(Some(value.as_filesize()?.get().max(0) as u64)),
+                None => Ok(None),
+            }
+        };
+        let time = |flag: &str| -> Result<Option<DateTime<Utc>>, Box<ShellError>> {
+            match call.get_flag::<Value>(flag)? {
+                Some(value) => Ok(Some(value.as_date()?.with_timezone(&Utc))),
+                None => Ok(None),
+            }
+        };
+        Ok(Filters {
+            regex,
+            min_size: size("min-size")?,
+            max_size: size("max-size")?,
+            before: time("before")?,
+            after: time("after")?,
+        })
+    }
+
+    fn matches(&self, meta: &ObjectMeta) -> bool {
+        self.regex
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(meta.location.as_ref()))
+            && self.min_size.is_none_or(|min| meta.size >= min)
+            && self.max_size.is_none_or(|max| meta.size <= max)
+            && self.before.is_none_or(|before| meta.last_modified < before)
+            && self.after.is_none_or(|after| meta.last_modified >= after)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let filters = Filters::from_call(call).map_err(|e| *e)?;
+    let storage_class = call
+        .get_flag::<String>("storage-class")?
+        .map(|class| class.to_ascii_uppercase());
+    let bytes = call.has_flag("bytes")?;
+
+    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
+    // Storage classes aren't in the listings of object_store, so buckets are listed with them
+    let mut matches = match storage_class {
+        Some(class) => {
+            let listing =
+                object_store
+                    .listing_client()
+                    .ok_or_else(|| ShellError::GenericError {
+                        error: format!(
+                            "Storage classes aren't known for {} urls",
+                            url.item.scheme()
+                        ),
+                        msg: "".into(),
+                        span: call.get_flag_span("storage-class"),
+                        help: Some("Storage classes are only filtered in S3 buckets".into()),
+                        inner: vec![],
+                    })?;
+            listing
+                .list(&path)
+                .try_filter(move |listed| {
+                    let found = filters.matches(&listed.meta) && listed.storage_class == class;
+                    std::future::ready(found)
+                })
+                .map_ok(|listed| (listed.meta, Some(listed.storage_class)))
+                .boxed()
+        }
+        None => object_store
+            .object_store()
+            .list(Some(&path))
+            .try_filter(move |meta| std::future::ready(filters.matches(meta)))
+            .map_ok(|meta| (meta, None))
+            .boxed(),
+    };
+
+    // Matches stream out as the pages are listed, like those of cloud ls
+    let runtime = Arc::clone(plugin.runtime(engine, call_span).map_err(|e| *e)?);
+    let run_limit = limit::current();
+    let verbose = verbose::enabled();
+    let next_page = move || {
+        let next = limit::scoped(run_limit.clone(), matches.next());
+        runtime.block_on(verbose::scoped(verbose, next))
+    };
+    let metadata = listing_metadata(&url.item);
+    let values = std::iter::from_fn(next_page).map(move |result| match result {
+        Ok((meta, storage_class)) => {
+            let mut record = record!(
+                "name" => Value::string(meta.location.to_string(), call_span),
+                "type" => Value::string("file", call_span),
+                "size" => match bytes {
+                    true => Value::int(meta.size as i64, call_span),
+                    false => Value::filesize(meta.size as i64, call_span),
+                },
+                "modified" => Value::date(meta.last_modified.fixed_offset(), call_span),
+            );
+            if let Some(storage_class) = storage_class {
+                record.push("storage_class", Value::string(storage_class, call_span));
+            }
+            Value::record(record, call_span)
+        }
+        Err(e) => Value::error(store_error(Action::List, &url.item, url.span, e), call_span),
+    });
+    Ok(PipelineData::ListStream(
+        ListStream::new(values, call_span, engine.signals().clone()),
+        Some(metadata),
+    ))
+}
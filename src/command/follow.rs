@@ -0,0 +1,142 @@
+use std::{
+    io::{self, Read},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, Bytes};
+use nu_protocol::{Signals, Span, shell_error::bridge::ShellErrorBridge};
+use object_store::{GetOptions, GetRange, path::Path};
+use tokio::runtime::Runtime;
+
+use super::{read_ahead::RANGE_SIZE, wait_until};
+use crate::{
+    error::{Action, store_error},
+    providers::{
+        NuObjectStore,
+        limit::{self, RateLimiter},
+    },
+};
+
+/// Reads an object as it grows, like `tail -f`: the object is read up to its size, then its
+/// size is checked every `interval` and the bytes appended since are read in ranges.
+///
+/// An object that shrinks was truncated or replaced, and is read again from the start. Reads
+/// end when interrupted, or after `idle` without growth.
+pub struct Follow {
+    runtime: Arc<Runtime>,
+    store: NuObjectStore,
+    path: Path,
+    /// Offset of the next range to request
+    offset: u64,
+    /// Size of the object when it was last checked
+    size: u64,
+    current: Bytes,
+    interval: Duration,
+    idle: Option<Duration>,
+    last_growth: Instant,
+    bwlimit: Option<Arc<RateLimiter>>,
+    signals: Signals,
+    span: Span,
+}
+
+impl Follow {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        runtime: Arc<Runtime>,
+        store: NuObjectStore,
+        path: Path,
+        size: u64,
+        interval: Duration,
+        idle: Option<Duration>,
+        bwlimit: Option<Arc<RateLimiter>>,
+        signals: Signals,
+        span: Span,
+    ) -> Self {
+        Follow {
+            runtime,
+            store,
+            path,
+            offset: 0,
+            size,
+            current: Bytes::new(),
+            interval,
+            idle,
+            last_growth: Instant::now(),
+            bwlimit,
+            signals,
+            span,
+        }
+    }
+
+    /// Read the next range up to the last known size
+    fn next_range(&mut self) -> object_store::Result<Bytes> {
+        let range = self.offset..(self.offset + RANGE_SIZE).min(self.size);
+        let options = GetOptions {
+            range: Some(GetRange::Bounded(range)),
+            ..GetOptions::default()
+        };
+        let bytes = self.runtime.block_on(async {
+            let result = self
+                .store
+                .object_store()
+                .get_opts(&self.path, options)
+                .await?;
+            match &self.bwlimit {
+                Some(bwlimit) => limit::read_throttled(result, bwlimit).await,
+                None => result.bytes().await,
+            }
+        })?;
+        self.offset += bytes.len() as u64;
+        Ok(bytes)
+    }
+
+    /// Wait for the next check of the size, returning false if the read should end instead
+    fn wait(&self, since: Instant) -> bool {
+        let mut due = since + self.interval;
+        if let Some(idle) = self.idle {
+            due = due.min(self.last_growth + idle);
+        }
+        if !self.runtime.block_on(wait_until(due, &self.signals)) {
+            return false;
+        }
+        self.idle
+            .is_none_or(|idle| self.last_growth.elapsed() < idle)
+    }
+
+    fn error(&self, e: object_store::Error) -> io::Error {
+        ShellErrorBridge(store_error(Action::Read, &self.path, self.span, e)).into()
+    }
+}
+
+impl Read for Follow {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut last_checked = Instant::now();
+        while self.current.is_empty() {
+            if self.offset < self.size {
+                self.current = self.next_range().map_err(|e| self.error(e))?;
+                self.last_growth = Instant::now();
+                if self.current.is_empty() {
+                    // Shorter than it was, found on the next check
+                    self.size = self.offset;
+                }
+                continue;
+            }
+            if !self.wait(last_checked) {
+                return Ok(0);
+            }
+            last_checked = Instant::now();
+            let meta = self
+                .runtime
+                .block_on(self.store.object_store().head(&self.path))
+                .map_err(|e| self.error(e))?;
+            if meta.size < self.offset {
+                self.offset = 0;
+            }
+            self.size = meta.size;
+        }
+        let len = buf.len().min(self.current.len());
+        self.current.copy_to_slice(&mut buf[..len]);
+        Ok(len)
+    }
+}
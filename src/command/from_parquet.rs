@@ -0,0 +1,60 @@
+use bytes::Buf;
+use nu_plugin::{EngineInterface, EvaluatedCall};
+use nu_protocol::{ByteStream, ByteStreamType, DeclId, PipelineData, ShellError, Span, Value};
+use object_store::path::Path;
+
+use crate::{
+    error::{Action, store_error},
+    providers::NuObjectStore,
+};
+
+/// The `from parquet` command that data files of tables are converted with, which the plugin
+/// doesn't provide itself
+pub fn find_decl(
+    engine: &EngineInterface,
+    format: &str,
+    span: Span,
+) -> Result<DeclId, Box<ShellError>> {
+    engine
+        .find_decl("from parquet")?
+        .ok_or_else(|| Box::new(ShellError::GenericError {
+            error: format!("Reading {format} tables needs a `from parquet` command"),
+            msg: "".into(),
+            span: Some(span),
+            help: Some(
+                "Define or import a `from parquet` command converting binary Parquet data to a table".into(),
+            ),
+            inner: vec![],
+        }))
+}
+
+/// Read the rows of a Parquet file with the `from parquet` command. Errors point at the url of
+/// the table the file is in, at `url_span`.
+pub async fn read(
+    engine: &EngineInterface,
+    decl: DeclId,
+    store: &NuObjectStore,
+    path: &Path,
+    span: Span,
+    url_span: Span,
+) -> Result<Vec<Value>, ShellError> {
+    let bytes = async { store.object_store().get(path).await?.bytes().await }
+        .await
+        .map_err(|e| store_error(Action::Read, path, url_span, e))?;
+    let stream = ByteStream::read(
+        bytes.reader(),
+        span,
+        engine.signals().clone(),
+        ByteStreamType::Unknown,
+    );
+    engine
+        .call_decl(
+            decl,
+            EvaluatedCall::new(span),
+            PipelineData::ByteStream(stream, None),
+            true,
+            false,
+        )?
+        .into_value(span)?
+        .into_list()
+}
@@ -0,0 +1,364 @@
+use std::{io::Read, vec};
+
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+use futures::TryStreamExt;
+use log::debug;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, Record, ShellError, Signature, Span,
+    SyntaxShape, Type, Value, record,
+};
+use object_store::path::Path;
+use serde::Deserialize;
+
+use super::{
+    avro::{self, Avro},
+    from_parquet, run_flags, with_rate_limit, with_request_log, with_timeout,
+};
+use crate::{CloudPlugin, providers::NuObjectStore, remote::RemotePath};
+
+/// Status of manifest entries for files removed in their snapshot
+const STATUS_DELETED: i64 = 2;
+/// Content of manifests and files holding data rather than deletes
+const CONTENT_DATA: i64 = 0;
+
+pub struct IcebergScan;
+
+impl PluginCommand for IcebergScan {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud iceberg scan"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            Signature::build("cloud iceberg scan")
+                .input_output_types(vec![(Type::Nothing, Type::table())])
+                .required(
+                    "uri",
+                    SyntaxShape::String,
+                    "The url of the Iceberg table, or of one of its metadata.json files.",
+                )
+                .named(
+                    "snapshot-id",
+                    SyntaxShape::Int,
+                    "Scan this snapshot instead of the current one",
+                    Some('s'),
+                )
+                .switch(
+                    "read",
+                    "Read the data files of the snapshot into a table instead of listing them",
+                    Some('r'),
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "List the data files of an Iceberg table snapshot from its manifests, or read them into a table."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Without a metadata.json file in the url, the table's metadata is found through metadata/version-hint.text or the highest metadata version. Reading converts data files with a `from parquet` command, which must be in scope, and isn't supported for snapshots with delete files."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "List the data files of the current snapshot of an Iceberg table.",
+                example: "cloud iceberg scan s3://mybucket/warehouse/db/events",
+                result: None,
+            },
+            Example {
+                description: "Read an earlier snapshot of an Iceberg table.",
+                example: "cloud iceberg scan s3://mybucket/warehouse/db/events --snapshot-id 3051729675574597004 --read",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let snapshot_id: Option<i64> = call.get_flag("snapshot-id")?;
+    let (store, path) = plugin.parse_url(engine, &url, call_span).await?;
+    let error = |msg: String| ShellError::GenericError {
+        error: format!("Could not read Iceberg table {}: {msg}", url.item),
+        msg: "".into(),
+        span: Some(url.span),
+        help: None,
+        inner: vec![],
+    };
+
+    let metadata_path = metadata_location(&store, &path).await.map_err(error)?;
+    debug!("Reading Iceberg metadata {metadata_path}");
+    let data = get(&store, &metadata_path).await.map_err(error)?;
+    let data = if metadata_path.as_ref().ends_with(".gz.metadata.json") {
+        let mut inflated = vec![];
+        GzDecoder::new(data.as_ref())
+            .read_to_end(&mut inflated)
+            .map_err(|e| error(format!("could not decompress {metadata_path}: {e}")))?;
+        Bytes::from(inflated)
+    } else {
+        data
+    };
+    let metadata: TableMetadata = serde_json::from_slice(&data)
+        .map_err(|e| error(format!("invalid metadata in {metadata_path}: {e}")))?;
+
+    // Tables without snapshots have no files
+    let Some(snapshot_id) = snapshot_id.or(metadata.current_snapshot_id.filter(|id| *id != -1))
+    else {
+        return Ok(PipelineData::Value(Value::list(vec![], call_span), None));
+    };
+    let Some(snapshot) = metadata
+        .snapshots
+        .iter()
+        .find(|snapshot| snapshot.snapshot_id == snapshot_id)
+    else {
+        return Err(error(format!("snapshot {snapshot_id} doesn't exist")));
+    };
+
+    // Format version 1 tables may list the manifests in the snapshot itself
+    let manifests = match &snapshot.manifest_list {
+        Some(manifest_list) => {
+            let (list_store, list_path) = file(plugin, engine, manifest_list, url.span).await?;
+            let data = get(&list_store, &list_path).await.map_err(error)?;
+            avro::read_container(&data)
+                .map_err(|e| error(format!("invalid manifest list {list_path}: {e}")))?
+                .iter()
+                .filter_map(|entry| match entry {
+                    Avro::Record(entry) => Some(entry.string("manifest_path")?.to_string()),
+                    _ => None,
+                })
+                .collect()
+        }
+        None => snapshot.manifests.clone(),
+    };
+
+    let mut files = vec![];
+    for manifest in manifests {
+        engine.signals().check(&call_span)?;
+        let (manifest_store, manifest_path) = file(plugin, engine, &manifest, url.span).await?;
+        debug!("Reading Iceberg manifest {manifest_path}");
+        let data = get(&manifest_store, &manifest_path).await.map_err(error)?;
+        let entries = avro::read_container(&data)
+            .map_err(|e| error(format!("invalid manifest {manifest_path}: {e}")))?;
+        for entry in entries {
+            let Avro::Record(entry) = entry else {
+                continue;
+            };
+            if entry.int("status") == Some(STATUS_DELETED) {
+                continue;
+            }
+            let Some(data_file) = entry.child("data_file") else {
+                return Err(error(format!(
+                    "an entry of {manifest_path} has no data file"
+                )));
+            };
+            files.push(DataFile::from_avro(data_file).ok_or_else(|| {
+                error(format!(
+                    "an entry of {manifest_path} has an invalid data file"
+                ))
+            })?);
+        }
+    }
+
+    if !call.has_flag("read")? {
+        let files = files
+            .into_iter()
+            .map(|file| file.into_value(call_span))
+            .collect();
+        return Ok(PipelineData::Value(Value::list(files, call_span), None));
+    }
+
+    if files.iter().any(|file| file.content != CONTENT_DATA) {
+        return Err(error(
+            "the snapshot has delete files, which aren't supported when reading".into(),
+        ));
+    }
+    if let Some(file) = files
+        .iter()
+        .find(|file| !file.format.eq_ignore_ascii_case("parquet"))
+    {
+        return Err(error(format!(
+            "{} is in the {} format, only Parquet data files can be read",
+            file.path, file.format
+        )));
+    }
+    let from_parquet = from_parquet::find_decl(engine, "Iceberg", call_span).map_err(|e| *e)?;
+    let mut rows = vec![];
+    for data_file in files {
+        engine.signals().check(&call_span)?;
+        let (file_store, file_path) = file(plugin, engine, &data_file.path, url.span).await?;
+        debug!("Reading Iceberg data file {file_path}");
+        rows.extend(
+            from_parquet::read(
+                engine,
+                from_parquet,
+                &file_store,
+                &file_path,
+                call_span,
+                url.span,
+            )
+            .await?,
+        );
+    }
+    Ok(PipelineData::Value(Value::list(rows, call_span), None))
+}
+
+/// Path of the metadata.json file of the table at `path`, or `path` itself if it is one
+async fn metadata_location(store: &NuObjectStore, path: &Path) -> Result<Path, String> {
+    if path.as_ref().ends_with(".metadata.json") {
+        return Ok(path.clone());
+    }
+    let dir = path.child("metadata");
+
+    // Tables without a catalog record the current version in a hint file
+    if let Ok(hint) = get(store, &dir.child("version-hint.text")).await {
+        let hint = String::from_utf8_lossy(&hint).trim().to_string();
+        return Ok(match hint.parse::<u64>() {
+            Ok(version) => dir.child(format!("v{version}.metadata.json")),
+            Err(_) => dir.child(hint),
+        });
+    }
+
+    // Metadata files are named `v<version>.metadata.json` or `<version>-<uuid>.metadata.json`
+    let versions: Vec<(u64, Path)> = store
+        .object_store()
+        .list(Some(&dir))
+        .try_filter_map(|meta| async move {
+            let name = meta.location.filename().unwrap_or_default();
+            let version = name
+                .strip_suffix(".metadata.json")
+                .map(|name| name.trim_start_matches('v'))
+                .and_then(|name| name.split(['-', '.']).next())
+                .and_then(|version| version.parse().ok());
+            Ok(version.map(|version| (version, meta.location)))
+        })
+        .try_collect()
+        .await
+        .map_err(|e| format!("could not list the metadata: {e}"))?;
+    versions
+        .into_iter()
+        .max_by_key(|(version, _)| *version)
+        .map(|(_, path)| path)
+        .ok_or_else(|| format!("no metadata.json files were found under {dir}"))
+}
+
+async fn get(store: &NuObjectStore, path: &Path) -> Result<Bytes, String> {
+    async { store.object_store().get(path).await?.bytes().await }
+        .await
+        .map_err(|e| format!("could not read {path}: {e}"))
+}
+
+/// Store and path of a file referenced by the metadata, which records absolute urls
+async fn file(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    location: &str,
+    span: Span,
+) -> Result<(NuObjectStore, Path), ShellError> {
+    // Hadoop's S3 connectors use their own schemes for the same urls
+    let location = match location.split_once("://") {
+        Some(("s3a" | "s3n", rest)) => format!("s3://{rest}"),
+        _ => location.to_string(),
+    };
+    let url = RemotePath::parse(&location, span, span)
+        .map_err(|e| *e)?
+        .url;
+    plugin.parse_url(engine, &url, span).await
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct TableMetadata {
+    current_snapshot_id: Option<i64>,
+    #[serde(default)]
+    snapshots: Vec<Snapshot>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Snapshot {
+    snapshot_id: i64,
+    manifest_list: Option<String>,
+    #[serde(default)]
+    manifests: Vec<String>,
+}
+
+struct DataFile {
+    content: i64,
+    path: String,
+    format: String,
+    partition: Vec<(String, Avro)>,
+    record_count: i64,
+    size: i64,
+}
+
+impl DataFile {
+    fn from_avro(data_file: &avro::Record) -> Option<Self> {
+        Some(DataFile {
+            // Added in format version 2, all files are data files before
+            content: data_file.int("content").unwrap_or(CONTENT_DATA),
+            path: data_file.string("file_path")?.to_string(),
+            format: data_file.string("file_format")?.to_string(),
+            partition: data_file
+                .child("partition")
+                .map(|partition| partition.0.clone())
+                .unwrap_or_default(),
+            record_count: data_file.int("record_count")?,
+            size: data_file.int("file_size_in_bytes")?,
+        })
+    }
+
+    fn into_value(self, span: Span) -> Value {
+        let content = match self.content {
+            CONTENT_DATA => "data",
+            1 => "position_deletes",
+            2 => "equality_deletes",
+            _ => "unknown",
+        };
+        let partition = self
+            .partition
+            .into_iter()
+            .map(|(name, value)| (name, avro::to_value(value, span)))
+            .collect::<Record>();
+        Value::record(
+            record!(
+                "path" => Value::string(self.path, span),
+                "content" => Value::string(content, span),
+                "format" => Value::string(self.format, span),
+                "size" => Value::filesize(self.size, span),
+                "record_count" => Value::int(self.record_count, span),
+                "partition" => Value::record(partition, span),
+            ),
+            span,
+        )
+    }
+}
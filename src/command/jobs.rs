@@ -0,0 +1,217 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Span, Spanned,
+    SyntaxShape, Type, Value, record,
+};
+use tokio::runtime::RuntimeFlavor;
+use url::Url;
+
+use crate::{
+    CloudPlugin,
+    jobs::{Job, JobStatus},
+};
+
+/// Run the transfer of a `--background` command as a job, returning its id
+pub(super) fn spawn(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+    url: &Spanned<Url>,
+    transfer: impl Future<Output = Result<u64, Box<ShellError>>> + Send + 'static,
+) -> Result<PipelineData, Box<ShellError>> {
+    let runtime = plugin.runtime(engine, call.head)?;
+    if runtime.handle().runtime_flavor() == RuntimeFlavor::CurrentThread {
+        return Err(Box::new(ShellError::GenericError {
+            error: "Background jobs need the multi-threaded runtime".into(),
+            msg: "".into(),
+            span: call.get_flag_span("background"),
+            help: Some(
+                "The current_thread runtime only runs while a command does, disable it in the runtime setting".into(),
+            ),
+            inner: vec![],
+        }));
+    }
+    let command = String::from_utf8_lossy(&engine.get_span_contents(call.head)?).into_owned();
+    let id = plugin
+        .cache
+        .jobs()
+        .spawn(runtime, engine, command, url.item.to_string(), transfer)?;
+    Ok(PipelineData::Value(Value::int(id as i64, call.head), None))
+}
+
+pub struct JobsList;
+
+impl PluginCommand for JobsList {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud jobs list"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud jobs list")
+            .input_output_types(vec![(Type::Nothing, Type::table())])
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "List the transfers started with --background, running or finished."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Show the uploads still running.",
+            example: "cloud jobs list | where status == running",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let jobs = plugin
+            .cache
+            .jobs()
+            .list()
+            .iter()
+            .map(|job| job_value(job, call.head))
+            .collect();
+        Ok(PipelineData::Value(Value::list(jobs, call.head), None))
+    }
+}
+
+pub struct JobsStatus;
+
+impl PluginCommand for JobsStatus {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud jobs status"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud jobs status")
+            .input_output_types(vec![(Type::Nothing, Type::record())])
+            .required("id", SyntaxShape::Int, "The id of the job.")
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Show the status of a transfer started with --background."
+    }
+
+    fn extra_description(&self) -> &str {
+        "The status is running, completed, failed, or cancelled. Completed jobs have the size of the object written, and failed ones the error."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Check on an upload.",
+            example: "let job = open --raw disk.img | cloud save --background s3://mybucket/disk.img; cloud jobs status $job",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let id: Spanned<i64> = call.req(0)?;
+        let job = u64::try_from(id.item)
+            .ok()
+            .and_then(|job| plugin.cache.jobs().get(job))
+            .ok_or_else(|| not_found(id))?;
+        Ok(PipelineData::Value(job_value(&job, call.head), None))
+    }
+}
+
+pub struct JobsCancel;
+
+impl PluginCommand for JobsCancel {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud jobs cancel"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud jobs cancel")
+            .input_output_types(vec![(Type::Nothing, Type::record())])
+            .required("id", SyntaxShape::Int, "The id of the job.")
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Stop a transfer started with --background."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Returns the job as it is after the cancel. Finished jobs are left as they are. Parts of a cancelled multipart upload are left behind like those of an interrupted save."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Cancel every running job.",
+            example: "cloud jobs list | where status == running | each { cloud jobs cancel $in.id }",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let id: Spanned<i64> = call.req(0)?;
+        let job = match u64::try_from(id.item) {
+            Ok(job) => plugin.cache.jobs().cancel(job, engine).map_err(|e| *e)?,
+            Err(_) => None,
+        };
+        let job = job.ok_or_else(|| not_found(id))?;
+        Ok(PipelineData::Value(job_value(&job, call.head), None))
+    }
+}
+
+fn job_value(job: &Job, span: Span) -> Value {
+    let date = |date: Option<_>| match date {
+        Some(date) => Value::date(date, span),
+        None => Value::nothing(span),
+    };
+    let (size, error) = match &job.status {
+        JobStatus::Completed(size) => (Value::filesize(*size as i64, span), None),
+        JobStatus::Failed(error) => (Value::nothing(span), Some(error.clone())),
+        _ => (Value::nothing(span), None),
+    };
+    Value::record(
+        record!(
+            "id" => Value::int(job.id as i64, span),
+            "status" => Value::string(job.status.name(), span),
+            "url" => Value::string(job.url.clone(), span),
+            "command" => Value::string(job.command.clone(), span),
+            "started" => date(Some(job.started.fixed_offset())),
+            "finished" => date(job.finished.map(|finished| finished.fixed_offset())),
+            "size" => size,
+            "error" => error.map_or(Value::nothing(span), |error| Value::string(error, span)),
+        ),
+        span,
+    )
+}
+
+fn not_found(id: Spanned<i64>) -> ShellError {
+    ShellError::GenericError {
+        error: format!("No job with id {}", id.item),
+        msg: "".into(),
+        span: Some(id.span),
+        help: Some("List the jobs with cloud jobs list".into()),
+        inner: vec![],
+    }
+}
@@ -0,0 +1,62 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{Category, Example, LabeledError, PipelineData, Signature, Type, Value, record};
+
+use crate::CloudPlugin;
+
+pub struct LastStats;
+
+impl PluginCommand for LastStats {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud last-stats"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud last-stats")
+            .input_output_types(vec![(Type::Nothing, Type::Any)])
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Show the duration, requests, and bytes transferred of the last cloud command."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Returns nothing before the first command. Bytes are counted from request bodies and the Content-Length of responses, and requests made by parallel upload parts or by `cloud ls` pages listed after the command returned aren't counted."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Check how many requests a recursive copy took.",
+            example: "cloud cp -r s3://mybucket/logs/ s3://backup/logs/; cloud last-stats | get requests",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let span = call.head;
+        let Some(stats) = plugin.last_stats.lock().expect("not poisoned").clone() else {
+            return Ok(PipelineData::empty());
+        };
+        let value = Value::record(
+            record!(
+                "command" => Value::string(stats.command, span),
+                "started" => Value::date(stats.started.fixed_offset(), span),
+                "duration" => Value::duration(stats.duration.as_nanos() as i64, span),
+                "success" => Value::bool(stats.success, span),
+                "requests" => Value::int(stats.requests as i64, span),
+                "bytes_sent" => Value::filesize(stats.bytes_sent as i64, span),
+                "bytes_received" => Value::filesize(stats.bytes_received as i64, span),
+            ),
+            span,
+        );
+        Ok(PipelineData::Value(value, None))
+    }
+}
@@ -0,0 +1,104 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, SyntaxShape, Type,
+};
+
+use crate::{
+    CloudPlugin,
+    credentials::{self, CloudCredentials},
+    prompt::prompt,
+};
+
+pub struct Login;
+
+impl PluginCommand for Login {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud login"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud login")
+            .input_output_types(vec![(Type::Any, Type::Nothing)])
+            .required(
+                "profile",
+                SyntaxShape::String,
+                "The profile to store credentials for.",
+            )
+            .switch(
+                "session-token",
+                "Also prompt for a session token",
+                Some('t'),
+            )
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Prompt for access keys and store them in the OS keychain"
+    }
+
+    fn extra_description(&self) -> &str {
+        "Stored credentials are used for buckets configured with the profile, or for all buckets when it matches AWS_PROFILE (default: 'default')."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Store keys for the prod profile",
+            example: "cloud login prod",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(command(plugin, engine, call))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let profile: String = call.req(0)?;
+
+    let access_key_id = prompt(engine, call_span, "Access key id: ", false).map_err(|e| *e)?;
+    let secret_access_key =
+        prompt(engine, call_span, "Secret access key: ", true).map_err(|e| *e)?;
+    let session_token = if call.has_flag("session-token")? {
+        Some(prompt(engine, call_span, "Session token: ", true).map_err(|e| *e)?)
+    } else {
+        None
+    };
+
+    let credentials = CloudCredentials {
+        access_key_id,
+        secret_access_key,
+        session_token,
+        expiry: None,
+    };
+    credentials::keychain_store(&profile, &credentials)
+        .await
+        .map_err(|e| ShellError::GenericError {
+            error: format!("Could not store credentials in the keychain: {e}"),
+            msg: "".into(),
+            span: Some(call_span),
+            help: None,
+            inner: vec![],
+        })?;
+
+    // Stores built with the previous credentials would otherwise keep being used
+    plugin.cache.clear(engine, call_span).await?;
+    Ok(PipelineData::empty())
+}
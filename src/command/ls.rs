@@ -1,14 +1,34 @@
-use std::{path::PathBuf, str::FromStr, vec};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::Arc,
+    vec,
+};
 
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt, stream};
+use http::HeaderMap;
 use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
 use nu_protocol::{
-    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
-    Type, Value, record,
+    Category, Example, LabeledError, ListStream, PipelineData, Record, ShellError, Signature,
+    Spanned, SyntaxShape, Type, Value, record,
 };
-use url::Url;
+use object_store::{Attribute, Attributes, GetOptions, ObjectMeta, path::Path};
 
-use crate::CloudPlugin;
+use super::{
+    concurrency, listing_metadata, run_flags, versions::version_client, with_rate_limit,
+    with_request_log, with_timeout,
+};
+use crate::{
+    CloudPlugin,
+    error::{Action, store_error},
+    providers::{
+        NuObjectStore, headers, limit,
+        listing::{ListedObject, ListingClient},
+        verbose,
+        versions::ObjectVersion,
+    },
+    remote::RemotePath,
+};
 
 pub struct Ls;
 
@@ -20,22 +40,91 @@ impl PluginCommand for Ls {
     }
 
     fn signature(&self) -> nu_protocol::Signature {
-        Signature::build("cloud ls")
-            .required("uri", SyntaxShape::String, "The url to use.")
-            .category(Category::FileSystem)
-            .input_output_types(vec![(Type::Nothing, Type::Any)])
+        run_flags(
+            Signature::build("cloud ls")
+                .required("uri", SyntaxShape::String, "The url to use.")
+                .switch(
+                    "recursive",
+                    "List every object under the url, instead of its objects and directories",
+                    Some('R'),
+                )
+                .switch(
+                    "long",
+                    "Add the etag, version, storage class, encryption, owner, content type, and user metadata of each object, read with a HEAD request",
+                    Some('l'),
+                )
+                .switch(
+                    "versions",
+                    "List the keys of a versioned S3 bucket with their number of versions and whether they are deleted, including deleted ones",
+                    None,
+                )
+                .switch(
+                    "bytes",
+                    "Show sizes as exact numbers of bytes instead of filesizes",
+                    Some('b'),
+                )
+                .named(
+                    "sort-by",
+                    SyntaxShape::String,
+                    "Sort the objects by name, size, or modified",
+                    Some('s'),
+                )
+                .switch("reverse", "Reverse the sort order", None)
+                .named(
+                    "limit",
+                    SyntaxShape::Int,
+                    "List at most this many objects, the first ones in the sort order with --sort-by",
+                    Some('n'),
+                )
+                .named(
+                    "concurrency",
+                    SyntaxShape::Int,
+                    "Number of HEAD requests made at once with --long (default: concurrency of the config, or 8)",
+                    Some('j'),
+                )
+                .category(Category::FileSystem)
+                .input_output_types(vec![(Type::Nothing, Type::Any)]),
+        )
     }
 
     fn description(&self) -> &str {
-        "List the filenames, sizes, modificationtime , etags, and versions of a cloud location."
+        "List the objects of a cloud location with the columns of the builtin ls: name, type, size, and modified."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Like the builtin ls, a single level is listed: the objects directly under the url, and the prefixes of the deeper ones as directories, listed first. Directories have no size or modification time. --recursive lists every object under the url instead, as the store lists them, without directories.
+
+--versions lists the keys of a versioned S3 bucket from its versions instead, so keys whose latest version is a delete marker are included, with a versions column of how many versions and delete markers each key has and a deleted column of whether its latest version is a delete marker. The size and modification time are those of the latest version."
     }
 
-    fn examples(&self) -> Vec<Example> {
-        vec![Example {
-            description: "List the files in a s3 bucket.",
-            example: "cloud ls s3://mybucket",
-            result: None,
-        }]
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "List the files in a s3 bucket.",
+                example: "cloud ls s3://mybucket",
+                result: None,
+            },
+            Example {
+                description: "List the objects of each subdirectory of a prefix.",
+                example: "cloud ls s3://mybucket/logs/ | where type == dir | each { cloud ls $'s3://mybucket/($in.name)/' } | flatten",
+                result: None,
+            },
+            Example {
+                description: "List the 20 largest objects anywhere in a s3 bucket.",
+                example: "cloud ls s3://mybucket --recursive --sort-by size --reverse --limit 20",
+                result: None,
+            },
+            Example {
+                description: "Find the keys of a versioned bucket with the most versions to clean up.",
+                example: "cloud ls --versions --recursive s3://mybucket/ | sort-by versions --reverse | first 10",
+                result: None,
+            },
+            Example {
+                description: "List the files in a s3 bucket with their etags, versions, storage classes, encryption, owners, content types, and metadata.",
+                example: "cloud ls --long s3://mybucket",
+                result: None,
+            },
+        ]
     }
 
     fn run(
@@ -46,8 +135,14 @@ impl PluginCommand for Ls {
         _input: PipelineData,
     ) -> Result<PipelineData, LabeledError> {
         plugin
-            .rt
-            .block_on(command(engine, plugin, call))
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
             .map_err(LabeledError::from)
     }
 }
@@ -58,49 +153,349 @@ async fn command(
     call: &EvaluatedCall,
 ) -> Result<PipelineData, ShellError> {
     let call_span = call.head;
-    let spanned_path: Spanned<PathBuf> = call.req(0)?;
-    let url_path = spanned_path.item;
-    let url = url_path
-        .to_str()
-        .expect("The path should already be unicode")
-        .to_string();
-    let url = Spanned {
-        item: Url::from_str(&url).map_err(|e| ShellError::IncorrectValue {
-            msg: format!("Invalid Url: {e}"),
-            val_span: spanned_path.span,
-            call_span,
-        })?,
-        span: spanned_path.span,
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let recursive = call.has_flag("recursive")?;
+    let long = call.has_flag("long")?;
+    let versions = call.has_flag("versions")?;
+    let bytes = call.has_flag("bytes")?;
+    let concurrency = concurrency(engine, call).map_err(|e| *e)?;
+    let sort_by = match call.get_flag::<Spanned<String>>("sort-by")? {
+        Some(sort_by) => Some(match sort_by.item.as_str() {
+            "name" => SortBy::Name,
+            "size" => SortBy::Size,
+            "modified" => SortBy::Modified,
+            other => {
+                return Err(ShellError::IncorrectValue {
+                    msg: format!("Can't sort by {other}, expected name, size, or modified"),
+                    val_span: sort_by.span,
+                    call_span,
+                });
+            }
+        }),
+        None => None,
+    };
+    let reverse = call.has_flag("reverse")?;
+    let limit = match call.get_flag::<i64>("limit")? {
+        Some(n) if n < 0 => {
+            return Err(ShellError::IncorrectValue {
+                msg: "The limit can't be negative".into(),
+                val_span: call.get_flag_span("limit").unwrap_or(call_span),
+                call_span,
+            });
+        }
+        Some(n) => Some(n as usize),
+        None => None,
     };
 
     let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
-    let list_stream = object_store.object_store().list(Some(&path));
+    let mut history = HashMap::new();
+    // Owners are only in the listings of S3, and only when asked for
+    let owners = match long {
+        true => object_store
+            .listing_client()
+            .map(ListingClient::with_owners),
+        false => None,
+    };
+    let (mut dirs, mut list_stream) = match recursive {
+        _ if versions => {
+            let client = version_client(&object_store, &url).map_err(|e| *e)?;
+            let listing = client
+                .list(&path)
+                .await
+                .map_err(|e| store_error(Action::List, &url.item, url.span, e))?;
+            let (dirs, objects) = latest_versions(listing, &path, recursive, &mut history);
+            (dirs, stream::iter(objects.into_iter().map(Ok)).boxed())
+        }
+        true => match &owners {
+            Some(client) => (vec![], client.list(&path).map_ok(owned).boxed()),
+            None => {
+                let objects = object_store.object_store().list(Some(&path));
+                (vec![], objects.map_ok(|meta| (meta, None)).boxed())
+            }
+        },
+        false => {
+            let list_error = |e| store_error(Action::List, &url.item, url.span, e);
+            let (dirs, objects): (_, Vec<_>) = match &owners {
+                Some(client) => {
+                    let (dirs, objects) = client
+                        .list_with_delimiter(&path)
+                        .await
+                        .map_err(list_error)?;
+                    (dirs, objects.into_iter().map(owned).collect())
+                }
+                None => {
+                    let listing = object_store
+                        .object_store()
+                        .list_with_delimiter(Some(&path))
+                        .await
+                        .map_err(list_error)?;
+                    let objects = listing.objects.into_iter().map(|meta| (meta, None));
+                    (listing.common_prefixes, objects.collect())
+                }
+            };
+            (dirs, stream::iter(objects.into_iter().map(Ok)).boxed())
+        }
+    };
+    // Directories come first in name order, and count towards the limit
+    if reverse {
+        dirs.reverse();
+    }
+    if sort_by.is_some() || reverse {
+        // Sorting needs the whole listing, but only the objects within the limit are kept
+        let compare = |(a, _): &Listed, (b, _): &Listed| {
+            let order = match sort_by {
+                Some(SortBy::Size) => a.size.cmp(&b.size),
+                Some(SortBy::Modified) => a.last_modified.cmp(&b.last_modified),
+                Some(SortBy::Name) | None => Ordering::Equal,
+            }
+            .then_with(|| a.location.cmp(&b.location));
+            match reverse {
+                true => order.reverse(),
+                false => order,
+            }
+        };
+        let mut objects = vec![];
+        while let Some(listed) = list_stream.next().await {
+            engine.signals().check(&call_span)?;
+            objects.push(listed.map_err(|e| store_error(Action::List, &url.item, url.span, e))?);
+            if let Some(limit) = limit
+                && objects.len() >= limit.max(1) * 2
+            {
+                objects.sort_unstable_by(compare);
+                objects.truncate(limit);
+            }
+        }
+        objects.sort_unstable_by(compare);
+        list_stream = stream::iter(objects.into_iter().map(Ok)).boxed();
+    }
+    if let Some(limit) = limit {
+        dirs.truncate(limit);
+        list_stream = list_stream.take(limit - dirs.len()).boxed();
+    }
+    // HEAD requests for the next objects are made while earlier ones are consumed, keeping the
+    // listing order
+    let deleted: HashSet<Path> = history
+        .iter()
+        .filter(|(_, history)| history.deleted)
+        .map(|(location, _)| location.clone())
+        .collect();
+    let mut list_stream = match long {
+        true => list_stream
+            .map(move |listed| {
+                let store = object_store.clone();
+                let deleted = matches!(&listed, Ok((meta, _)) if deleted.contains(&meta.location));
+                async move {
+                    let (meta, owner) = listed?;
+                    // Deleted keys have no current object to read
+                    if deleted {
+                        let details = Details {
+                            attributes: Attributes::new(),
+                            storage_class: None,
+                            encryption: None,
+                            owner,
+                        };
+                        return Ok((meta, Some(details)));
+                    }
+                    let options = GetOptions {
+                        head: true,
+                        ..GetOptions::default()
+                    };
+                    let (result, headers) =
+                        headers::captured(store.object_store().get_opts(&meta.location, options))
+                            .await;
+                    let details = Details {
+                        attributes: result?.attributes,
+                        storage_class: header(&headers, "x-amz-storage-class").or(
+                            // S3 leaves out the header for the standard class
+                            matches!(store, NuObjectStore::AmazonS3 { .. })
+                                .then(|| "STANDARD".to_string()),
+                        ),
+                        encryption: header(&headers, "x-amz-server-side-encryption"),
+                        owner,
+                    };
+                    Ok((meta, Some(details)))
+                }
+            })
+            .buffered(concurrency)
+            .boxed(),
+        false => list_stream.map_ok(|(meta, _)| (meta, None)).boxed(),
+    };
 
-    let values: Vec<Value> = list_stream
-        .map(|v| match v {
-            Ok(meta) => Value::record(
-                record!(
+    // Pages are fetched as the pipeline consumes the listing, so `cloud ls | first` doesn't
+    // wait for the whole listing
+    let runtime = Arc::clone(plugin.runtime(engine, call_span).map_err(|e| *e)?);
+    let run_limit = limit::current();
+    let verbose = verbose::enabled();
+    let next_page = move || {
+        let next = limit::scoped(run_limit.clone(), list_stream.next());
+        runtime.block_on(verbose::scoped(verbose, next))
+    };
+    let metadata = listing_metadata(&url.item);
+    let values = std::iter::from_fn(next_page).map(move |v| {
+        match v {
+            Ok((meta, details)) => {
+                // The columns of the builtin `ls`, so scripts written for it work on buckets
+                let mut record = record!(
                     "name" => Value::string(meta.location.to_string(), call_span),
-                    "size" => Value::filesize(meta.size as i64, call_span),
+                    "type" => Value::string("file", call_span),
+                    "size" => match bytes {
+                        true => Value::int(meta.size as i64, call_span),
+                        false => Value::filesize(meta.size as i64, call_span),
+                    },
                     "modified" => Value::date(meta.last_modified.fixed_offset(), call_span),
-                    "etag" => meta.e_tag.map(|s| Value::string(s, call_span)).unwrap_or(Value::nothing(call_span)),
-                    "version" => meta.version.map(|s| Value::string(s, call_span)).unwrap_or(Value::nothing(call_span)),
-                ),
-                call_span,
-            ),
-            Err(e) => {
-                let se = ShellError::GenericError {
-                    error: format!("Error fetching data from object store: {e}"),
-                    msg: "".into(),
-                    span: None,
-                    help: None,
-                    inner: vec![],
+                );
+                if let Some(history) = history.get(&meta.location) {
+                    record.push("versions", Value::int(history.versions as i64, call_span));
+                    record.push("deleted", Value::bool(history.deleted, call_span));
+                }
+                if let Some(details) = details {
+                    record.push(
+                        "etag",
+                        meta.e_tag
+                            .map(|s| Value::string(s, call_span))
+                            .unwrap_or(Value::nothing(call_span)),
+                    );
+                    record.push(
+                        "version",
+                        meta.version
+                            .map(|s| Value::string(s, call_span))
+                            .unwrap_or(Value::nothing(call_span)),
+                    );
+                    let string = |value: Option<String>| {
+                        value
+                            .map(|s| Value::string(s, call_span))
+                            .unwrap_or(Value::nothing(call_span))
+                    };
+                    record.push("storage_class", string(details.storage_class));
+                    record.push("encryption", string(details.encryption));
+                    record.push("owner", string(details.owner));
+                    let mut metadata = Record::new();
+                    let mut content_type = Value::nothing(call_span);
+                    for (attribute, value) in &details.attributes {
+                        let value = Value::string(value.as_ref(), call_span);
+                        match attribute {
+                            Attribute::ContentType => content_type = value,
+                            Attribute::Metadata(key) => metadata.push(key.as_ref(), value),
+                            _ => {}
+                        }
+                    }
+                    record.push("content_type", content_type);
+                    record.push("metadata", Value::record(metadata, call_span));
+                }
+                Value::record(record, call_span)
+            }
+            Err(e) => Value::error(store_error(Action::List, &url.item, url.span, e), call_span),
+        }
+    });
+
+    let dirs = dirs.into_iter().map(move |prefix| {
+        let mut record = record!(
+            "name" => Value::string(prefix.to_string(), call_span),
+            "type" => Value::string("dir", call_span),
+            "size" => Value::nothing(call_span),
+            "modified" => Value::nothing(call_span),
+        );
+        if versions {
+            record.push("versions", Value::nothing(call_span));
+            record.push("deleted", Value::nothing(call_span));
+        }
+        if long {
+            for column in [
+                "etag",
+                "version",
+                "storage_class",
+                "encryption",
+                "owner",
+                "content_type",
+            ] {
+                record.push(column, Value::nothing(call_span));
+            }
+            record.push("metadata", Value::record(Record::new(), call_span));
+        }
+        Value::record(record, call_span)
+    });
+    Ok(PipelineData::ListStream(
+        ListStream::new(dirs.chain(values), call_span, engine.signals().clone()),
+        Some(metadata),
+    ))
+}
+
+/// What `--versions` adds to a key
+struct History {
+    /// Versions and delete markers of the key
+    versions: usize,
+    /// Whether the latest version is a delete marker
+    deleted: bool,
+}
+
+/// An object of a listing, with its owner where the listing has it
+type Listed = (ObjectMeta, Option<String>);
+
+fn owned(listed: ListedObject) -> Listed {
+    (listed.meta, listed.owner)
+}
+
+/// The latest version of each key of a version listing, as the objects of a listing of `prefix`,
+/// with their histories. Without `recursive`, keys under deeper prefixes are left out and those
+/// prefixes returned as directories.
+fn latest_versions(
+    listing: Vec<ObjectVersion>,
+    prefix: &Path,
+    recursive: bool,
+    history: &mut HashMap<Path, History>,
+) -> (Vec<Path>, Vec<Listed>) {
+    let mut dirs = BTreeSet::new();
+    let mut objects = vec![];
+    // Versions are listed by key, newest first
+    for version in listing {
+        if !recursive {
+            let mut parts = version.location.prefix_match(prefix).into_iter().flatten();
+            if let (Some(part), Some(_)) = (parts.next(), parts.next()) {
+                dirs.insert(prefix.child(part));
+                continue;
+            }
+        }
+        match history.get_mut(&version.location) {
+            Some(history) => history.versions += 1,
+            None => {
+                history.insert(
+                    version.location.clone(),
+                    History {
+                        versions: 1,
+                        deleted: version.delete_marker,
+                    },
+                );
+                let meta = ObjectMeta {
+                    location: version.location,
+                    last_modified: version.last_modified,
+                    size: version.size,
+                    e_tag: None,
+                    version: Some(version.version_id),
                 };
-                Value::error(se, call_span)
+                objects.push((meta, version.owner));
             }
-        })
-        .collect()
-        .await;
+        }
+    }
+    (dirs.into_iter().collect(), objects)
+}
+
+/// What `--long` adds to an object, read with a HEAD request
+struct Details {
+    attributes: Attributes,
+    /// Provider specific, only known for S3
+    storage_class: Option<String>,
+    encryption: Option<String>,
+    /// From the listing, as HEAD requests don't report it
+    owner: Option<String>,
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    Some(headers.get(name)?.to_str().ok()?.to_string())
+}
 
-    Ok(PipelineData::Value(Value::list(values, call_span), None))
+#[derive(Clone, Copy)]
+enum SortBy {
+    Name,
+    Size,
+    Modified,
 }
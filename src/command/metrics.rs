@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, Record, Signature, Span, Type, Value, record,
+};
+
+use crate::{CloudPlugin, providers::metrics::Usage};
+
+pub struct Metrics;
+
+impl PluginCommand for Metrics {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud metrics"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud metrics")
+            .input_output_types(vec![(Type::Nothing, Type::record())])
+            .switch(
+                "reset",
+                "Start counting over after returning the totals",
+                Some('r'),
+            )
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Show the requests, retries, errors, and bytes transferred since the plugin started."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Every attempt of a request is counted, as each is billed, with errors by status class (4xx, 5xx) or by transport error (connect, timeout, network). Bytes are counted from request bodies and the Content-Length of responses, for the stores of S3 and HTTP urls. The requests and bytes of each provider, the scheme of the urls, are under providers."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Check how much data the session downloaded.",
+                example: "cloud metrics | get bytes_downloaded",
+                result: None,
+            },
+            Example {
+                description: "Count the requests of a script from zero.",
+                example: "cloud metrics --reset; nu sync.nu; cloud metrics | get requests",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let span = call.head;
+        let metrics = plugin.cache.metrics();
+        let totals = match call.has_flag("reset")? {
+            true => metrics.reset(),
+            false => metrics.totals(),
+        };
+        let value = Value::record(
+            record!(
+                "since" => Value::date(totals.since.fixed_offset(), span),
+                "requests" => counts(totals.requests, span),
+                "retries" => Value::int(totals.retries as i64, span),
+                "errors" => counts(totals.errors, span),
+                "bytes_uploaded" => Value::filesize(totals.bytes_uploaded as i64, span),
+                "bytes_downloaded" => Value::filesize(totals.bytes_downloaded as i64, span),
+                "providers" => Value::record(
+                    totals
+                        .providers
+                        .into_iter()
+                        .map(|(provider, usage)| (provider, usage_value(usage, span)))
+                        .collect(),
+                    span,
+                ),
+            ),
+            span,
+        );
+        Ok(PipelineData::Value(value, None))
+    }
+}
+
+/// Requests and bytes of a provider
+fn usage_value(usage: Usage, span: Span) -> Value {
+    Value::record(
+        record!(
+            "requests" => counts(usage.requests, span),
+            "bytes_uploaded" => Value::filesize(usage.bytes_uploaded as i64, span),
+            "bytes_downloaded" => Value::filesize(usage.bytes_downloaded as i64, span),
+        ),
+        span,
+    )
+}
+
+pub(super) fn counts(counts: BTreeMap<String, u64>, span: Span) -> Value {
+    let record = counts
+        .into_iter()
+        .map(|(key, count)| (key, Value::int(count as i64, span)))
+        .collect::<Record>();
+    Value::record(record, span)
+}
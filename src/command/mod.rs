@@ -1,29 +1,878 @@
-use crate::CloudPlugin;
+use std::{
+    fmt::Display,
+    io::IsTerminal,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use futures::TryStreamExt;
+use nu_plugin::{EngineInterface, EvaluatedCall};
+use nu_protocol::{
+    DataSource, FilesizeUnit, PipelineData, PipelineMetadata, ShellError, Signals, Signature, Span,
+    Spanned, SyntaxShape, Value, record,
+};
+use object_store::{ObjectMeta, path::Path};
+use url::Url;
+
+use crate::{
+    CloudPlugin,
+    audit::AuditLog,
+    config::Config,
+    error::{Action, store_error},
+    prompt::prompt,
+    providers::{
+        NuObjectStore, env_string,
+        limit::{self, RateLimiter},
+        stats, trace, verbose,
+    },
+    remote::Glob,
+};
+
+mod avro;
 mod clear;
+mod complete;
+mod concat;
+mod cost;
+mod cp;
+mod defaults;
+mod delta;
+mod du;
+mod events;
+mod find;
+mod follow;
+mod from_parquet;
+mod iceberg;
+mod jobs;
+mod last_stats;
+mod login;
 mod ls;
+mod metrics;
+mod multipart;
+mod mv;
 mod open;
+mod parquet_meta;
+mod presign;
+mod read_ahead;
 mod rm;
 mod save;
+mod stat;
 mod stub;
+mod sync;
+mod thrift;
+mod versions;
+mod watch;
+mod zip;
 
+/// The plugin's commands, with the defaults of their flags configured
 pub fn commands() -> Vec<Box<dyn nu_plugin::PluginCommand<Plugin = CloudPlugin>>> {
-    vec![
+    let commands: Vec<Box<dyn nu_plugin::PluginCommand<Plugin = CloudPlugin>>> = vec![
         Box::new(clear::Clear),
+        Box::new(complete::Complete),
+        Box::new(cp::Copy),
+        Box::new(cost::CostEstimate),
+        Box::new(du::Du),
+        Box::new(last_stats::LastStats),
+        Box::new(login::Login),
         Box::new(ls::Ls),
+        Box::new(metrics::Metrics),
+        Box::new(mv::Move),
         Box::new(open::Open),
+        Box::new(parquet_meta::ParquetMeta),
+        Box::new(presign::Presign),
+        Box::new(delta::DeltaOpen),
+        Box::new(iceberg::IcebergScan),
+        Box::new(jobs::JobsList),
+        Box::new(jobs::JobsStatus),
+        Box::new(jobs::JobsCancel),
         Box::new(rm::Remove),
         Box::new(save::Save),
+        Box::new(stat::Stat),
         Box::new(stub::Stub),
-    ]
+        Box::new(sync::Sync),
+        Box::new(versions::VersionsList),
+        Box::new(versions::VersionsRestore),
+        Box::new(versions::VersionsPurge),
+        Box::new(versions::Rollback),
+        Box::new(events::Events),
+        Box::new(watch::Watch),
+        Box::new(find::Find),
+        Box::new(concat::Concat),
+        Box::new(zip::Zip),
+    ];
+    commands
+        .into_iter()
+        .map(|command| {
+            Box::new(defaults::WithDefaults(command))
+                as Box<dyn nu_plugin::PluginCommand<Plugin = CloudPlugin>>
+        })
+        .collect()
+}
+
+/// Add the `--timeout`, `--rate-limit`, `--verbose`, and `--quiet` flags used by
+/// [`with_timeout`], [`with_rate_limit`], and [`with_request_log`]
+fn run_flags(signature: Signature) -> Signature {
+    signature
+        .named(
+            "timeout",
+            SyntaxShape::Duration,
+            "Fail if the command doesn't finish within this time",
+            None,
+        )
+        .named(
+            "rate-limit",
+            SyntaxShape::Number,
+            "Maximum number of requests per second made by the command",
+            None,
+        )
+        .switch(
+            "verbose",
+            "Print each request with its status and duration to stderr",
+            None,
+        )
+        .switch(
+            "quiet",
+            "Don't print progress or request logs, even with --progress, --verbose, or NU_CLOUD_LOG",
+            Some('q'),
+        )
+}
+
+/// Run the command, failing if it takes longer than the `--timeout` flag allows
+async fn with_timeout<T, E: From<ShellError>>(
+    call: &EvaluatedCall,
+    command: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    match duration_flag(call, "timeout", false).map_err(|e| E::from(*e))? {
+        Some(timeout) => tokio::time::timeout(timeout, command).await.map_err(|_| {
+            E::from(ShellError::GenericError {
+                error: format!("Command timed out after {timeout:?}"),
+                msg: "".into(),
+                span: call.get_flag_span("timeout"),
+                help: None,
+                inner: vec![],
+            })
+        })?,
+        None => command.await,
+    }
+}
+
+/// Add the `--bwlimit` flag read by [`bandwidth_limit`] to a transfer command
+fn bwlimit_flag(signature: Signature) -> Signature {
+    signature.named(
+        "bwlimit",
+        SyntaxShape::OneOf(vec![SyntaxShape::Filesize, SyntaxShape::String]),
+        "Maximum transfer rate per second, e.g. 10MB or '10MB/s' (default: bwlimit of the config)",
+        None,
+    )
+}
+
+/// Limiter for the `--bwlimit` flag, or the `bwlimit` of the config without it, counting bytes
+fn bandwidth_limit(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<Option<Arc<RateLimiter>>, Box<ShellError>> {
+    let value = match call.get_flag::<Value>("bwlimit")? {
+        Some(value) => value,
+        None => match Config::load(engine)?.bwlimit {
+            Some(value) => value,
+            None => return Ok(None),
+        },
+    };
+    let span = value.span();
+    let invalid = |msg: String| {
+        Box::new(ShellError::IncorrectValue {
+            msg,
+            val_span: span,
+            call_span: call.head,
+        })
+    };
+    let bytes = match &value {
+        Value::Filesize { val, .. } => val.get() as f64,
+        Value::String { val, .. } => parse_rate(val)
+            .ok_or_else(|| invalid(format!("Invalid rate {val}, expected e.g. '10MB/s'")))?,
+        other => {
+            return Err(invalid(format!(
+                "Invalid rate of type {}",
+                other.get_type()
+            )));
+        }
+    };
+    if bytes.is_nan() || bytes <= 0.0 {
+        return Err(invalid("The bandwidth limit must be positive".into()));
+    }
+    Ok(Some(Arc::new(RateLimiter::new(bytes))))
+}
+
+/// Parse rates like `10MB/s`, `512 KiB`, or `1.5GB/s` into bytes per second
+fn parse_rate(rate: &str) -> Option<f64> {
+    let rate = rate.trim();
+    let rate = rate.strip_suffix("/s").unwrap_or(rate);
+    let split = rate
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(rate.len());
+    let (number, unit) = rate.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let unit = match unit.trim() {
+        "" => 1,
+        unit => FILESIZE_UNITS
+            .iter()
+            .find(|u| u.as_str().eq_ignore_ascii_case(unit))?
+            .as_bytes(),
+    };
+    Some(number * unit as f64)
+}
+
+const FILESIZE_UNITS: [FilesizeUnit; 13] = [
+    FilesizeUnit::B,
+    FilesizeUnit::KB,
+    FilesizeUnit::MB,
+    FilesizeUnit::GB,
+    FilesizeUnit::TB,
+    FilesizeUnit::PB,
+    FilesizeUnit::EB,
+    FilesizeUnit::KiB,
+    FilesizeUnit::MiB,
+    FilesizeUnit::GiB,
+    FilesizeUnit::TiB,
+    FilesizeUnit::PiB,
+    FilesizeUnit::EiB,
+];
+
+/// Add the `--output-format` flag read by [`OutputFormat::from_call`]
+fn output_format_flag(signature: Signature) -> Signature {
+    signature.named(
+        "output-format",
+        SyntaxShape::String,
+        "Report the objects written or removed as a table with a row per object, a summary record, or nothing",
+        None,
+    )
+}
+
+/// How a command reports the objects it wrote or removed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Table,
+    Record,
+    Nothing,
+}
+
+impl OutputFormat {
+    /// The `--output-format` flag, if given
+    fn from_call(call: &EvaluatedCall) -> Result<Option<Self>, Box<ShellError>> {
+        let Some(format) = call.get_flag::<Spanned<String>>("output-format")? else {
+            return Ok(None);
+        };
+        match format.item.as_str() {
+            "table" => Ok(Some(OutputFormat::Table)),
+            "record" => Ok(Some(OutputFormat::Record)),
+            "nothing" => Ok(Some(OutputFormat::Nothing)),
+            other => Err(Box::new(ShellError::IncorrectValue {
+                msg: format!("Unknown output format {other}, expected table, record, or nothing"),
+                val_span: format.span,
+                call_span: call.head,
+            })),
+        }
+    }
+
+    /// Report the urls of `objects` with their sizes, if known. Tables have `url` and `size`
+    /// columns, and records the `url` given to the command, the number of `objects`, and their
+    /// total `size`.
+    fn output(self, url: &Url, objects: Vec<(String, Option<u64>)>, span: Span) -> PipelineData {
+        let size = |size: Option<u64>| match size {
+            Some(size) => Value::filesize(size as i64, span),
+            None => Value::nothing(span),
+        };
+        let value = match self {
+            OutputFormat::Table => Value::list(
+                objects
+                    .into_iter()
+                    .map(|(url, bytes)| {
+                        Value::record(
+                            record!(
+                                "url" => Value::string(url, span),
+                                "size" => size(bytes),
+                            ),
+                            span,
+                        )
+                    })
+                    .collect(),
+                span,
+            ),
+            OutputFormat::Record => {
+                let total = objects
+                    .iter()
+                    .filter_map(|(_, size)| *size)
+                    .reduce(|a, b| a + b);
+                Value::record(
+                    record!(
+                        "url" => Value::string(url.to_string(), span),
+                        "objects" => Value::int(objects.len() as i64, span),
+                        "size" => size(total),
+                    ),
+                    span,
+                )
+            }
+            OutputFormat::Nothing => return PipelineData::empty(),
+        };
+        PipelineData::Value(value, None)
+    }
+}
+
+/// Url of the object at `location`, under the `prefix` path the command was given as `url`
+fn object_url(url: &Url, prefix: &Path, location: &Path) -> String {
+    let mut url = url.clone();
+    if let Ok(mut segments) = url.path_segments_mut() {
+        segments.pop_if_empty();
+        for part in location.prefix_match(prefix).into_iter().flatten() {
+            segments.push(part.as_ref());
+        }
+    }
+    url.to_string()
+}
+
+/// List the objects matching `glob` under `prefix`, the path of its url. Like shell globs, no
+/// matches is an error.
+async fn glob_objects(
+    store: &NuObjectStore,
+    glob: &Glob,
+    prefix: &Path,
+    recursive: bool,
+) -> Result<Vec<ObjectMeta>, ShellError> {
+    let objects: Vec<ObjectMeta> = store
+        .object_store()
+        .list(Some(prefix))
+        .try_filter(|meta| std::future::ready(glob.matches(prefix, &meta.location, recursive)))
+        .try_collect()
+        .await
+        .map_err(|e| store_error(Action::List, &glob.url.item, glob.url.span, e))?;
+    if objects.is_empty() {
+        return Err(ShellError::GenericError {
+            error: format!("No objects match {glob}"),
+            msg: "".into(),
+            span: Some(glob.url.span),
+            help: None,
+            inner: vec![],
+        });
+    }
+    Ok(objects)
+}
+
+/// The `--concurrency` flag of commands working on many objects at once, or the `concurrency` of
+/// the config without it
+fn concurrency(engine: &EngineInterface, call: &EvaluatedCall) -> Result<usize, Box<ShellError>> {
+    match call.get_flag::<i64>("concurrency")? {
+        Some(n) if n < 1 => Err(Box::new(ShellError::IncorrectValue {
+            msg: "Concurrency must be at least 1".into(),
+            val_span: call.get_flag_span("concurrency").unwrap_or(call.head),
+            call_span: call.head,
+        })),
+        Some(n) => Ok(n as usize),
+        None => Ok(Config::load(engine)?.concurrency()),
+    }
+}
+
+/// The duration given to the flag `name`, which must be positive, or may be zero when `zero`
+/// allows it, e.g. for an idle time that ends a stream once nothing is waiting
+fn duration_flag(
+    call: &EvaluatedCall,
+    name: &str,
+    zero: bool,
+) -> Result<Option<Duration>, Box<ShellError>> {
+    // Durations are passed as nanoseconds
+    match call.get_flag::<i64>(name)? {
+        Some(nanos) if nanos < 0 || (nanos == 0 && !zero) => {
+            let msg = match zero {
+                true => format!("--{name} can't be negative"),
+                false => format!("--{name} must be positive"),
+            };
+            Err(Box::new(ShellError::IncorrectValue {
+                msg,
+                val_span: call.get_flag_span(name).unwrap_or(call.head),
+                call_span: call.head,
+            }))
+        }
+        Some(nanos) => Ok(Some(Duration::from_nanos(nanos as u64))),
+        None => Ok(None),
+    }
+}
+
+/// Interrupts are checked this often while commands wait
+const SIGNAL_CHECK: Duration = Duration::from_millis(100);
+
+/// Wait until `due`, returning false if interrupted before
+async fn wait_until(due: Instant, signals: &Signals) -> bool {
+    while Instant::now() < due {
+        if signals.interrupted() {
+            return false;
+        }
+        let remaining = due.saturating_duration_since(Instant::now());
+        tokio::time::sleep(remaining.min(SIGNAL_CHECK)).await;
+    }
+    true
+}
+
+/// An object named by the input of a batch command, with its size if the input gives one
+struct BatchObject {
+    location: Path,
+    size: Option<u64>,
+}
+
+/// The objects named by the input of a batch command: strings, or records with a `name` column
+/// like those of `cloud ls`, as paths within the store of the command's `url`. They must be under
+/// `prefix`, the path of the url. The directories `cloud ls` lists are skipped. Input that isn't a
+/// list is `None`, for commands given a url alone.
+fn batch_objects(
+    input: PipelineData,
+    url: &Spanned<Url>,
+    prefix: &Path,
+    span: Span,
+) -> Result<Option<Vec<BatchObject>>, Box<ShellError>> {
+    let values: Vec<Value> = match input {
+        PipelineData::ListStream(stream, _) => stream.into_iter().collect(),
+        PipelineData::Value(Value::List { vals, .. }, _) => vals,
+        _ => return Ok(None),
+    };
+    let objects = values
+        .into_iter()
+        .filter(|value| !is_dir(value))
+        .map(|value| {
+            let value_span = value.span();
+            let (name, size) = match value {
+                Value::String { val, .. } => (val, None),
+                Value::Record { val, .. } => {
+                    let name = val.get("name").ok_or_else(|| ShellError::CantFindColumn {
+                        col_name: "name".into(),
+                        span: None,
+                        src_span: value_span,
+                    })?;
+                    let size = match val.get("size") {
+                        Some(Value::Filesize { val, .. }) => Some(val.get().max(0) as u64),
+                        Some(Value::Int { val, .. }) => Some((*val).max(0) as u64),
+                        _ => None,
+                    };
+                    (name.as_str()?.to_string(), size)
+                }
+                Value::Error { error, .. } => return Err(error),
+                other => {
+                    return Err(Box::new(ShellError::UnsupportedInput {
+                    msg:
+                        "Batches are strings or records with a name column, like those of cloud ls"
+                            .into(),
+                    input: format!("{} input", other.get_type()),
+                    msg_span: span,
+                    input_span: value_span,
+                }));
+                }
+            };
+            let location = Path::parse(&name)
+                .ok()
+                .filter(|location| location.prefix_match(prefix).is_some())
+                .ok_or_else(|| ShellError::GenericError {
+                    error: format!("{name} isn't under {}", url.item),
+                    msg: "".into(),
+                    span: Some(value_span),
+                    help: Some(
+                        "Names are paths within the bucket, like those cloud ls lists".into(),
+                    ),
+                    inner: vec![],
+                })?;
+            Ok(BatchObject { location, size })
+        });
+    Ok(Some(objects.collect::<Result<_, Box<ShellError>>>()?))
+}
+
+/// Metadata of the rows `cloud ls` lists under `url`, which records it as their source so batch
+/// commands given them don't need it repeated
+fn listing_metadata(url: &Url) -> PipelineMetadata {
+    PipelineMetadata {
+        data_source: DataSource::FilePath(url.as_str().into()),
+        content_type: None,
+    }
+}
+
+/// The url input was listed under by `cloud ls`, which its names are relative to
+fn listed_url(input: &PipelineData) -> Option<String> {
+    match input.metadata()?.data_source {
+        DataSource::FilePath(source) => source
+            .into_os_string()
+            .into_string()
+            .ok()
+            .filter(|url| Url::parse(url).is_ok()),
+        _ => None,
+    }
+}
+
+/// Whether a row of input is a directory, as `cloud ls` and `ls` list them
+fn is_dir(value: &Value) -> bool {
+    match value {
+        Value::Record { val, .. } => val
+            .get("type")
+            .is_some_and(|kind| kind.as_str().is_ok_and(|kind| kind == "dir")),
+        _ => false,
+    }
+}
+
+/// Row of the result of a batch command for the object at `url`, with the url it was copied or
+/// moved to, its size if known, and the error if it failed
+fn batch_row(
+    url: String,
+    destination: Option<String>,
+    size: Option<u64>,
+    error: Option<ShellError>,
+    span: Span,
+) -> Value {
+    let mut row = record!("url" => Value::string(url, span));
+    if let Some(destination) = destination {
+        row.push("destination", Value::string(destination, span));
+    }
+    row.push(
+        "size",
+        size.map_or(Value::nothing(span), |size| {
+            Value::filesize(size as i64, span)
+        }),
+    );
+    row.push(
+        "status",
+        Value::string(if error.is_some() { "failed" } else { "ok" }, span),
+    );
+    row.push(
+        "error",
+        error.map_or(Value::nothing(span), |error| {
+            Value::string(error.to_string(), span)
+        }),
+    );
+    Value::record(row, span)
+}
+
+/// Add the `--yes` flag that skips the confirmations asked by [`confirm`]
+fn yes_flag(signature: Signature) -> Signature {
+    signature.switch(
+        "yes",
+        "Don't ask for confirmation before removing or overwriting objects",
+        Some('y'),
+    )
+}
+
+/// Whether destructive operations are confirmed: when running interactively without `--yes`
+fn confirming(engine: &EngineInterface, call: &EvaluatedCall) -> Result<bool, Box<ShellError>> {
+    // Plugins share the terminal of the shell through stderr
+    if call.has_flag("yes")? || !std::io::stderr().is_terminal() {
+        return Ok(false);
+    }
+    Ok(engine.find_decl("input")?.is_some())
+}
+
+/// Ask a yes or no question, failing unless it's answered with yes
+fn confirm(engine: &EngineInterface, question: &str, span: Span) -> Result<(), Box<ShellError>> {
+    let answer = prompt(engine, span, &format!("{question} [y/N] "), false)?;
+    match answer.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(Box::new(ShellError::GenericError {
+            error: "Cancelled".into(),
+            msg: "".into(),
+            span: Some(span),
+            help: Some("Pass --yes to skip the confirmation".into()),
+            inner: vec![],
+        })),
+    }
+}
+
+/// Ask to confirm overwriting the object at `path` if it exists, when confirmations are asked.
+/// Returns whether it exists, which is only checked when confirming or with an audit log.
+async fn confirm_overwrite(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+    store: &NuObjectStore,
+    path: &Path,
+    url: &Url,
+    audit: Option<&AuditLog>,
+) -> Result<bool, Box<ShellError>> {
+    let confirming = confirming(engine, call)?;
+    if !confirming && audit.is_none() {
+        return Ok(false);
+    }
+    let exists = store.object_store().head(path).await.is_ok();
+    if confirming && exists {
+        confirm(engine, &format!("Overwrite {url}?"), call.head)?;
+    }
+    Ok(exists)
+}
+
+/// Run the command, spacing its requests to stay under the `--rate-limit` flag
+async fn with_rate_limit<T, E: From<ShellError>>(
+    call: &EvaluatedCall,
+    command: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let limit = match call.get_flag::<f64>("rate-limit")? {
+        Some(per_second) if per_second.is_nan() || per_second <= 0.0 => {
+            return Err(E::from(ShellError::IncorrectValue {
+                msg: "The rate limit must be positive".into(),
+                val_span: call.get_flag_span("rate-limit").unwrap_or(call.head),
+                call_span: call.head,
+            }));
+        }
+        Some(per_second) => Some(Arc::new(RateLimiter::new(per_second))),
+        None => None,
+    };
+    limit::scoped(limit, command).await
+}
+
+/// Run the command, logging its requests to stderr with the `--verbose` flag or when
+/// `NU_CLOUD_LOG` is set, unless it's `--quiet`, and exporting spans of it and its requests
+/// when `tracing` is configured. Its totals are kept for `cloud last-stats`.
+async fn with_request_log<T, E: From<ShellError> + Display>(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+    command: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let verbose = !call.has_flag("quiet")?
+        && (call.has_flag("verbose")? || request_log_env(engine).map_err(|e| E::from(*e))?);
+    let config = Config::load(engine).map_err(|e| E::from(*e))?;
+    let traceparent = env_string(engine, TRACEPARENT_ENV).map_err(|e| E::from(*e))?;
+    let name = String::from_utf8_lossy(&engine.get_span_contents(call.head)?).into_owned();
+    // Command futures are large, and block_on keeps them on the stack of the plugin's thread
+    let command = trace::scoped(&config, traceparent, name.clone(), Box::pin(command));
+    let (result, stats) = stats::scoped(name, verbose::scoped(verbose, command)).await;
+    *plugin.last_stats.lock().expect("not poisoned") = Some(stats);
+    result
+}
+
+/// Environment variable that turns on [`with_request_log`] for every command
+const LOG_ENV: &str = "NU_CLOUD_LOG";
+
+/// Environment variable with the W3C trace context that exported traces continue
+const TRACEPARENT_ENV: &str = "TRACEPARENT";
+
+/// Whether [`LOG_ENV`] turns on request logging
+fn request_log_env(engine: &EngineInterface) -> Result<bool, Box<ShellError>> {
+    Ok(env_string(engine, LOG_ENV)?.is_some_and(|value| !matches!(value.as_str(), "0" | "false")))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::CloudPlugin;
-    use nu_command::{FromCsv, Select, ToCsv};
+    use nu_command::{FromCsv, RandomUuid, Select, ToCsv, Where};
     use nu_plugin_test_support::PluginTest;
-    use nu_protocol::{PipelineData, Span, Value, record};
+    use nu_protocol::{
+        ByteStream, ByteStreamType, LabeledError, ListStream, PipelineData, Record, ShellError,
+        Signals, Signature, Span, Value,
+        engine::{Call, Command, EngineState, Stack},
+        record,
+    };
+
+    /// Stands in for a Parquet reader, the data files of the test tables are CSV
+    #[derive(Clone)]
+    struct FromParquet;
+
+    impl Command for FromParquet {
+        fn name(&self) -> &str {
+            "from parquet"
+        }
+
+        fn signature(&self) -> Signature {
+            Signature::build("from parquet")
+        }
+
+        fn description(&self) -> &str {
+            "Test reader"
+        }
+
+        fn run(
+            &self,
+            engine_state: &EngineState,
+            stack: &mut Stack,
+            call: &Call,
+            input: PipelineData,
+        ) -> Result<PipelineData, ShellError> {
+            FromCsv.run(engine_state, stack, call, input)
+        }
+    }
+
+    /// Runs commands end to end, from their registration to their output, against the
+    /// in-memory stores of its own plugin
+    struct Harness {
+        plugin_test: PluginTest,
+    }
+
+    impl Harness {
+        fn new() -> Result<Self, Box<ShellError>> {
+            let mut plugin_test = PluginTest::new("cloud", CloudPlugin::default().into())?;
+            plugin_test.add_decl(Box::new(ToCsv))?;
+            plugin_test.add_decl(Box::new(FromCsv))?;
+            plugin_test.add_decl(Box::new(Select))?;
+            plugin_test.add_decl(Box::new(Where))?;
+            Ok(Harness { plugin_test })
+        }
+
+        /// Set the plugin config to `config`
+        fn with_config(mut self, config: Record) -> Self {
+            let mut engine_config = self
+                .plugin_test
+                .engine_state()
+                .get_config()
+                .as_ref()
+                .clone();
+            engine_config
+                .plugins
+                .insert("cloud".into(), Value::test_record(config));
+            self.plugin_test
+                .engine_state_mut()
+                .set_config(engine_config);
+            self
+        }
+
+        /// Evaluate `source`, collecting its output
+        fn eval(&mut self, source: &str) -> Result<Value, Box<ShellError>> {
+            self.plugin_test
+                .eval_with(source, PipelineData::Empty)?
+                .into_value(Span::test_data())
+                .map_err(Box::new)
+        }
+
+        fn write(&mut self, url: &str, data: &str) -> Result<(), Box<ShellError>> {
+            self.plugin_test.eval_with(
+                &format!("cloud save --raw {url}"),
+                PipelineData::Value(Value::test_string(data), None),
+            )?;
+            Ok(())
+        }
+
+        fn read(&mut self, url: &str) -> Result<String, Box<ShellError>> {
+            Ok(self
+                .eval(&format!("cloud open --raw {url}"))?
+                .coerce_into_string()?)
+        }
+
+        /// Names `cloud ls` lists under `url`
+        fn names(&mut self, url: &str) -> Result<Vec<String>, Box<ShellError>> {
+            self.eval(&format!("cloud ls --recursive {url}"))?
+                .into_list()?
+                .into_iter()
+                .map(|entry| {
+                    Ok(entry
+                        .get_data_by_key("name")
+                        .unwrap()
+                        .coerce_into_string()?)
+                })
+                .collect()
+        }
+    }
+
+    /// A request received by [`mock_s3`]
+    struct Request {
+        method: String,
+        /// Path and query of the request
+        target: String,
+        /// Headers with lowercase names
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl Request {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(header, _)| header == name)
+                .map(|(_, value)| value.as_str())
+        }
+
+        fn path(&self) -> &str {
+            self.target.split('?').next().unwrap_or_default()
+        }
+
+        /// The first parameter of the query, naming the operation, e.g. `partNumber=1`
+        fn operation(&self) -> &str {
+            self.target
+                .split_once('?')
+                .and_then(|(_, query)| query.split('&').find(|pair| !pair.is_empty()))
+                .unwrap_or_default()
+        }
+    }
+
+    /// Serves HTTP on a local port, answering every request with the raw response `handler`
+    /// gives for it, and returns the url of the server
+    fn mock_s3(
+        handler: impl Fn(&Request) -> String + Send + Sync + 'static,
+    ) -> std::io::Result<String> {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let handler = std::sync::Arc::new(handler);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { return };
+                let handler = std::sync::Arc::clone(&handler);
+                std::thread::spawn(move || -> std::io::Result<()> {
+                    let mut reader = BufReader::new(stream);
+                    loop {
+                        let mut request_line = String::new();
+                        if reader.read_line(&mut request_line)? == 0 {
+                            return Ok(());
+                        }
+                        let mut parts = request_line.split(' ');
+                        let (method, target) =
+                            (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+                        let mut headers = vec![];
+                        let mut header = String::new();
+                        while reader.read_line(&mut header)? > 2 {
+                            if let Some((name, value)) = header.split_once(':') {
+                                headers.push((name.to_ascii_lowercase(), value.trim().to_string()));
+                            }
+                            header.clear();
+                        }
+                        let mut request = Request {
+                            method: method.into(),
+                            target: target.into(),
+                            headers,
+                            body: vec![],
+                        };
+                        let length = request.header("content-length").unwrap_or("0");
+                        request.body = vec![0; length.parse().unwrap_or(0)];
+                        reader.read_exact(&mut request.body)?;
+                        reader.get_mut().write_all(handler(&request).as_bytes())?;
+                    }
+                });
+            }
+        });
+        Ok(url)
+    }
+
+    /// A response with `body` and its length
+    fn ok(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// A response to the requests of uploads and listings, as a bucket accepting every write
+    fn accept_writes(request: &Request) -> String {
+        match (request.method.as_str(), request.operation()) {
+            ("POST", "uploads=") => ok(
+                "<InitiateMultipartUploadResult><UploadId>u1</UploadId></InitiateMultipartUploadResult>",
+            ),
+            ("PUT", _) => "HTTP/1.1 200 OK\r\ncontent-length: 0\r\netag: \"1\"\r\n\r\n".into(),
+            ("GET", _) => ok("<ListBucketResult></ListBucketResult>"),
+            _ => ok(
+                "<CompleteMultipartUploadResult><ETag>\"2\"</ETag></CompleteMultipartUploadResult>",
+            ),
+        }
+    }
+
+    /// The config of an S3 bucket served by [`mock_s3`] at `endpoint`
+    fn mock_bucket(bucket: &str, endpoint: &str) -> Record {
+        record!(
+            "buckets" => Value::test_record(record!(
+                bucket => Value::test_record(record!(
+                    "endpoint" => Value::test_string(endpoint),
+                    "region" => Value::test_string("us-east-1"),
+                    "access_key_id" => Value::test_string("key"),
+                    "secret_access_key" => Value::test_string("secret"),
+                )),
+            )),
+        )
+    }
 
     #[test]
     fn test_save_open() -> Result<(), Box<dyn std::error::Error>> {
@@ -61,6 +910,21 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_save_to_flags() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save --to-flags {noheaders: true, separator: ';'} memory:/flags.csv
+            cloud open --raw memory:/flags.csv",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value, Value::test_string("1;2\n"));
+        Ok(())
+    }
+
     #[test]
     fn test_save_raw_open() -> Result<(), Box<dyn std::error::Error>> {
         let plugin = CloudPlugin::default();
@@ -102,4 +966,3640 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_list_long() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Select))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory://long/a.csv
+            [[a b]; [1 2]] | cloud save memory://long/b.csv
+            cloud ls --long -j 1 memory://long | select name content_type metadata",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        let entry = |name: &str| {
+            Value::test_record(record!(
+                "name" => Value::test_string(name),
+                "content_type" => Value::test_nothing(),
+                "metadata" => Value::test_record(record!()),
+            ))
+        };
+        assert_eq!(
+            value,
+            Value::test_list(vec![entry("a.csv"), entry("b.csv")])
+        );
+
+        // Only S3 reports storage classes, encryption, and owners
+        let value = plugin_test
+            .eval_with("cloud ls --long memory://long", PipelineData::Empty)?
+            .into_value(Span::test_data())?;
+        let entry = &value.as_list()?[0];
+        assert_eq!(
+            entry.get_data_by_key("storage_class"),
+            Some(Value::test_nothing())
+        );
+        assert_eq!(
+            entry.get_data_by_key("encryption"),
+            Some(Value::test_nothing())
+        );
+        assert_eq!(entry.get_data_by_key("owner"), Some(Value::test_nothing()));
+
+        // Without --long the columns match the builtin ls
+        let value = plugin_test
+            .eval_with("cloud ls memory://long", PipelineData::Empty)?
+            .into_value(Span::test_data())?;
+        let columns: Vec<&String> = value.as_list()?[0].as_record()?.columns().collect();
+        assert_eq!(columns, ["name", "type", "size", "modified"]);
+        assert!(matches!(
+            value.as_list()?[0].get_data_by_key("size"),
+            Some(Value::Filesize { .. })
+        ));
+
+        let value = plugin_test
+            .eval_with("cloud ls --bytes memory://long", PipelineData::Empty)?
+            .into_value(Span::test_data())?;
+        assert_eq!(
+            value.as_list()?[0].get_data_by_key("size"),
+            Some(Value::test_int(8))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_type_extensions() {
+        use super::open::content_type_extensions;
+
+        assert_eq!(content_type_extensions("application/json")[0], "json");
+        assert_eq!(content_type_extensions("text/csv; charset=utf-8")[0], "csv");
+        assert_eq!(content_type_extensions("application/yaml")[0], "yaml");
+        assert_eq!(content_type_extensions("application/ld+json")[0], "json");
+        assert_eq!(
+            content_type_extensions("text/tab-separated-values")[0],
+            "tsv"
+        );
+        assert!(content_type_extensions("application/x-unknown").is_empty());
+    }
+
+    #[test]
+    fn test_save_open_local() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("nu_plugin_cloud_{}", std::process::id()));
+        let url = format!("file://{}/foo.csv", dir.display());
+
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(FromCsv))?;
+        let result = plugin_test.eval_with(
+            &format!("[[a b]; [1 2]] | cloud save {url} | cloud open {url}"),
+            PipelineData::Empty,
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let value = result?.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![Value::test_record(record!(
+                "a" => Value::test_int(1),
+                "b" => Value::test_int(2),
+            ))])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_buckets() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Select))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory://one/foo.csv | cloud ls memory://two/ | select name",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value, Value::test_list(vec![]));
+
+        let result =
+            plugin_test.eval_with("cloud ls memory://one/ | select name", PipelineData::Empty)?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![Value::test_record(record!(
+                "name" => Value::test_string("foo.csv"),
+            ))])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(FromCsv))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/foo.csv
+            cloud cp memory:/foo.csv memory:/bar.csv
+            cloud cp memory:/bar.csv memory://other/baz.csv
+            cloud open memory://other/baz.csv",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![Value::test_record(record!(
+                "a" => Value::test_int(1),
+                "b" => Value::test_int(2),
+            ))])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalized_paths() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(FromCsv))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory://paths/dir//sub/../foo.csv
+            cloud open memory://paths/dir/foo.csv",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![Value::test_record(record!(
+                "a" => Value::test_int(1),
+                "b" => Value::test_int(2),
+            ))])
+        );
+
+        let result = plugin_test.eval_with("cloud open gopher://host/foo.csv", PipelineData::Empty);
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_recursive() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Select))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory://rm/dir/foo.csv
+            [[a b]; [1 2]] | cloud save memory://rm/dir/sub/bar.csv
+            [[a b]; [1 2]] | cloud save memory://rm/keep.csv
+            cloud rm -r memory://rm/dir
+            cloud ls memory://rm/ | select name",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![Value::test_record(record!(
+                "name" => Value::test_string("keep.csv"),
+            ))])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_recursive() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("nu_plugin_cloud_cp_{}", std::process::id()));
+        let url = format!("file://{}", dir.display());
+
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(FromCsv))?;
+        let result = plugin_test.eval_with(
+            &format!(
+                "[[a b]; [1 2]] | cloud save memory://cpr/src/foo.csv
+                [[a b]; [3 4]] | cloud save memory://cpr/src/sub/bar.csv
+                cloud cp -r -j 2 memory://cpr/src {url}
+                cloud cp -r --max-in-flight 1B {url} memory://cpr/uploaded
+                cloud open memory://cpr/uploaded/sub/bar.csv"
+            ),
+            PipelineData::Empty,
+        );
+        let copied_top_level = dir.join("foo.csv").exists();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let value = result?.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![Value::test_record(record!(
+                "a" => Value::test_int(3),
+                "b" => Value::test_int(4),
+            ))])
+        );
+        assert!(copied_top_level);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bwlimit() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        // Three 64KiB slices, the first is sent right away
+        let data = "a".repeat(3 * 64 * 1024);
+        let started = std::time::Instant::now();
+        let result = plugin_test.eval_with(
+            &format!(
+                "'{data}' | cloud save memory://bw/a.txt --bwlimit '512KiB/s'
+                cloud open memory://bw/a.txt --bwlimit 1MB"
+            ),
+            PipelineData::Empty,
+        )?;
+        assert!(started.elapsed() >= std::time::Duration::from_millis(200));
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value, Value::test_string(data));
+
+        let result = plugin_test.eval_with(
+            "cloud open memory://bw/a.txt --bwlimit 'fast'",
+            PipelineData::Empty,
+        );
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_part_size() -> Result<(), Box<dyn std::error::Error>> {
+        use super::multipart::PartSize;
+
+        const MIB: u64 = 1024 * 1024;
+        assert_eq!(PartSize::new(None, None).get() as u64, 5 * MIB);
+        // 10,000 parts of at least 100MB for a 1TB object
+        let part_size = PartSize::new(Some(1_000_000 * MIB), None);
+        assert!(part_size.get() as u64 * 10_000 >= 1_000_000 * MIB);
+        assert_eq!(
+            PartSize::new(Some(MIB), Some(8 * MIB)).get() as u64,
+            8 * MIB
+        );
+
+        // Spans several parts, each with the data of another one
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let data: String = ('a'..='l')
+            .map(|c| c.to_string().repeat(MIB as usize))
+            .collect();
+        let stream = ByteStream::read_string(data.clone(), Span::test_data(), Signals::empty());
+        plugin_test.eval_with(
+            "cloud save memory://parts/a.txt",
+            PipelineData::ByteStream(stream, None),
+        )?;
+        let result =
+            plugin_test.eval_with("cloud open memory://parts/a.txt", PipelineData::Empty)?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value, Value::test_string(data));
+        Ok(())
+    }
+
+    /// Fails after `len` bytes, like a connection dropping mid-transfer
+    struct Interrupted {
+        data: std::io::Cursor<Vec<u8>>,
+        len: u64,
+    }
+
+    impl std::io::Read for Interrupted {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.data.position() >= self.len {
+                // Let the uploads of the parts filled so far complete
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                return Err(std::io::ErrorKind::ConnectionReset.into());
+            }
+            self.data.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_resume() -> Result<(), Box<dyn std::error::Error>> {
+        const MIB: usize = 1024 * 1024;
+        let journal =
+            std::env::temp_dir().join(format!("nu_plugin_cloud_test_{}.json", std::process::id()));
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        // Set directly, as a statement before the save would collect the input stream
+        let mut config = plugin_test.engine_state().get_config().as_ref().clone();
+        config.plugins.insert(
+            "cloud".into(),
+            Value::test_record(record! {
+                "transfer_journal" => Value::test_string(journal.display().to_string()),
+            }),
+        );
+        plugin_test.engine_state_mut().set_config(config);
+        let data: Vec<u8> = (0..12 * MIB).map(|i| (i / MIB) as u8).collect();
+        let stream = |data: Vec<u8>, len: usize| {
+            let reader = Interrupted {
+                data: std::io::Cursor::new(data),
+                len: len as u64,
+            };
+            let stream = ByteStream::read(
+                reader,
+                Span::test_data(),
+                Signals::empty(),
+                ByteStreamType::Binary,
+            );
+            PipelineData::ByteStream(stream, None)
+        };
+
+        // Two 5MiB parts are uploaded before the input fails
+        let save = "cloud save memory://resume/a.bin --resume";
+        assert!(
+            plugin_test
+                .eval_with(save, stream(data.clone(), 11 * MIB))
+                .is_err()
+        );
+        let entries: serde_json::Value = serde_json::from_slice(&std::fs::read(&journal)?)?;
+        assert_eq!(
+            entries["uploads"]["memory://resume/a.bin"]["parts"]
+                .as_object()
+                .map(|parts| parts.len()),
+            Some(2)
+        );
+
+        // Different input discards the interrupted upload
+        assert!(
+            plugin_test
+                .eval_with(save, stream(vec![0xff; 6 * MIB], usize::MAX))
+                .is_err()
+        );
+        assert!(
+            plugin_test
+                .eval_with(save, stream(data.clone(), 11 * MIB))
+                .is_err()
+        );
+        plugin_test.eval_with(save, stream(data.clone(), usize::MAX))?;
+        let result =
+            plugin_test.eval_with("cloud open memory://resume/a.bin", PipelineData::Empty)?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value.coerce_binary()?, data.as_slice());
+
+        let entries: serde_json::Value = serde_json::from_slice(&std::fs::read(&journal)?)?;
+        assert!(entries["uploads"].as_object().is_some_and(|u| u.is_empty()));
+        std::fs::remove_file(journal)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_cache() -> Result<(), Box<dyn std::error::Error>> {
+        let dir =
+            std::env::temp_dir().join(format!("nu_plugin_cloud_cache_{}", std::process::id()));
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let mut eval = |source: &str, ttl: &str| -> Result<Value, Box<dyn std::error::Error>> {
+            let config = format!(
+                "$env.config.plugins.cloud = {{disk_cache: {{dir: '{}', max_size: 8B, ttl: {ttl}}}}}",
+                dir.display()
+            );
+            Ok(plugin_test
+                .eval_with(&format!("{config}\n{source}"), PipelineData::Empty)?
+                .into_value(Span::test_data())?)
+        };
+        let index = || -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+            Ok(serde_json::from_slice(&std::fs::read(
+                dir.join("index.json"),
+            )?)?)
+        };
+
+        eval("'hello' | cloud save memory://disk/a.txt", "1hr")?;
+        assert_eq!(
+            eval("cloud open memory://disk/a.txt", "1hr")?,
+            Value::test_string("hello")
+        );
+        let file = index()?["entries"]["memory://disk/a.txt"]["file"]
+            .as_str()
+            .map(|file| dir.join(file))
+            .ok_or("a.txt is cached")?;
+
+        // Served from disk until the ttl passes, then revalidated
+        std::fs::write(&file, "stale")?;
+        assert_eq!(
+            eval("cloud open memory://disk/a.txt", "1hr")?,
+            Value::test_string("stale")
+        );
+        eval("'world' | cloud save memory://disk/a.txt", "1hr")?;
+        assert_eq!(
+            eval("cloud open memory://disk/a.txt", "0sec")?,
+            Value::test_string("world")
+        );
+
+        // Both objects exceed the size limit, the least recently used one is evicted
+        eval("'other' | cloud save memory://disk/b.txt", "1hr")?;
+        eval("cloud open memory://disk/b.txt", "1hr")?;
+        let entries = index()?["entries"].clone();
+        assert!(entries.get("memory://disk/a.txt").is_none());
+        assert!(entries.get("memory://disk/b.txt").is_some());
+
+        eval("cloud cache-clear", "1hr")?;
+        assert!(!dir.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_sqlite() -> Result<(), Box<dyn std::error::Error>> {
+        let dir =
+            std::env::temp_dir().join(format!("nu_plugin_cloud_sqlite_{}", std::process::id()));
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let result = plugin_test.eval_with(
+            &format!(
+                "$env.config.plugins.cloud = {{disk_cache: {{dir: '{}'}}}}
+                'SQLite format 3' | cloud save memory://sqlite/data.db
+                cloud open --sqlite memory://sqlite/data.db",
+                dir.display()
+            ),
+            PipelineData::Empty,
+        )?;
+        let file = result.into_value(Span::test_data())?.into_string()?;
+        assert!(file.starts_with(&dir.display().to_string()));
+        assert_eq!(std::fs::read_to_string(&file)?, "SQLite format 3");
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_parquet_meta() -> Result<(), Box<dyn std::error::Error>> {
+        // Footer of a file with a required INT64 column `id` of 3 rows, in the Thrift compact
+        // protocol: field headers are (id delta << 4) | type, integers are zigzag varints
+        let footer = |created_by: &str| {
+            let varint = |mut n: usize, out: &mut Vec<u8>| {
+                while n >= 0x80 {
+                    out.push((n as u8 & 0x7f) | 0x80);
+                    n >>= 7;
+                }
+                out.push(n as u8);
+            };
+            let mut data = vec![
+                0x15, 0x02, // version: 1
+                0x19, 0x2c, // schema: list of 2 structs
+                0x48, 0x06, b's', b'c', b'h', b'e', b'm', b'a', // name
+                0x15, 0x02, 0x00, // num_children: 1
+                0x15, 0x04, // type: INT64
+                0x25, 0x00, // repetition: REQUIRED
+                0x18, 0x02, b'i', b'd', 0x00, // name
+                0x16, 0x06, // num_rows: 3
+                0x19, 0x1c, // row_groups: list of 1 struct
+                0x19, 0x1c, // columns: list of 1 struct
+                0x26, 0x08, // file_offset: 4
+                0x1c, // meta_data
+                0x15, 0x04, // type: INT64
+                0x19, 0x15, 0x00, // encodings: [PLAIN]
+                0x19, 0x18, 0x02, b'i', b'd', // path_in_schema: [id]
+                0x15, 0x02, // codec: SNAPPY
+                0x16, 0x06, // num_values: 3
+                0x16, 0x3c, // total_uncompressed_size: 30
+                0x16, 0x28, // total_compressed_size: 20
+                0x26, 0x08, // data_page_offset: 4
+                0x3c, // statistics
+                0x36, 0x00, // null_count: 0
+                0x28, 0x08, 3, 0, 0, 0, 0, 0, 0, 0, // max_value: 3
+                0x18, 0x08, 1, 0, 0, 0, 0, 0, 0, 0, // min_value: 1
+                0x00, 0x00, 0x00, // end of statistics, meta_data, and column
+                0x16, 0x3c, // total_byte_size: 30
+                0x16, 0x06, // num_rows: 3
+                0x00, // end of row group
+                0x28, // created_by
+            ];
+            varint(created_by.len(), &mut data);
+            data.extend_from_slice(created_by.as_bytes());
+            data.push(0x00);
+            let mut file = b"PAR1".to_vec();
+            file.extend_from_slice(&[0; 30]);
+            file.extend_from_slice(&data);
+            file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            file.extend_from_slice(b"PAR1");
+            file
+        };
+
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        // The second footer is larger than the first ranged read
+        let long = "x".repeat(100 * 1024);
+        for created_by in ["test", long.as_str()] {
+            let stream =
+                ByteStream::read_binary(footer(created_by), Span::test_data(), Signals::empty());
+            plugin_test.eval_with(
+                "cloud save memory://parquet/a.parquet",
+                PipelineData::ByteStream(stream, None),
+            )?;
+            let value = plugin_test
+                .eval_with(
+                    "cloud parquet-meta memory://parquet/a.parquet",
+                    PipelineData::Empty,
+                )?
+                .into_value(Span::test_data())?;
+            let get = |value: &Value, key: &str| value.as_record().ok()?.get(key).cloned();
+            assert_eq!(get(&value, "num_rows"), Some(Value::test_int(3)));
+            assert_eq!(
+                get(&value, "created_by"),
+                Some(Value::test_string(created_by))
+            );
+            assert_eq!(
+                get(&value, "schema"),
+                Some(Value::test_list(vec![Value::test_record(record!(
+                    "name" => Value::test_string("id"),
+                    "type" => Value::test_string("INT64"),
+                    "repetition" => Value::test_string("REQUIRED"),
+                    "logical_type" => Value::test_nothing(),
+                    "converted_type" => Value::test_nothing(),
+                    "num_children" => Value::test_nothing(),
+                ))]))
+            );
+            let row_group = get(&value, "row_groups").ok_or("row_groups")?.as_list()?[0].clone();
+            let column = get(&row_group, "columns").ok_or("columns")?.as_list()?[0].clone();
+            assert_eq!(get(&column, "path"), Some(Value::test_string("id")));
+            assert_eq!(
+                get(&column, "compression"),
+                Some(Value::test_string("SNAPPY"))
+            );
+            assert_eq!(get(&column, "min"), Some(Value::test_int(1)));
+            assert_eq!(get(&column, "max"), Some(Value::test_int(3)));
+            assert_eq!(
+                get(&column, "compressed_size"),
+                Some(Value::test_filesize(20))
+            );
+        }
+
+        let result = plugin_test.eval_with(
+            "'not parquet' | cloud save memory://parquet/b.parquet
+            cloud parquet-meta memory://parquet/b.parquet",
+            PipelineData::Empty,
+        );
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_ahead() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        // Three ranges, the last one partial
+        let data: Vec<u8> = (0..20 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let stream = ByteStream::read_binary(data.clone(), Span::test_data(), Signals::empty());
+        plugin_test.eval_with(
+            "cloud save memory://ahead/a.bin",
+            PipelineData::ByteStream(stream, None),
+        )?;
+
+        for read_ahead in [4, 0] {
+            let result = plugin_test.eval_with(
+                &format!(
+                    "$env.config.plugins.cloud = {{read_ahead: {read_ahead}}}
+                    cloud open --raw memory://ahead/a.bin"
+                ),
+                PipelineData::Empty,
+            )?;
+            let value = result.into_value(Span::test_data())?;
+            assert_eq!(value.as_binary()?, data.as_slice());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_current_thread_runtime() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(Select))?;
+        // Set directly, as a statement before the save would collect the input stream
+        let mut config = plugin_test.engine_state().get_config().as_ref().clone();
+        config.plugins.insert(
+            "cloud".into(),
+            Value::test_record(record! {
+                "runtime" => Value::test_record(record! {
+                    "current_thread" => Value::test_bool(true),
+                }),
+            }),
+        );
+        plugin_test.engine_state_mut().set_config(config);
+        // Large enough for a multipart upload and read-ahead
+        let data: Vec<u8> = (0..20 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let stream = ByteStream::read_binary(data.clone(), Span::test_data(), Signals::empty());
+        plugin_test.eval_with(
+            "cloud save memory://rt/a.bin",
+            PipelineData::ByteStream(stream, None),
+        )?;
+
+        let result =
+            plugin_test.eval_with("cloud open --raw memory://rt/a.bin", PipelineData::Empty)?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value.as_binary()?, data.as_slice());
+
+        let result =
+            plugin_test.eval_with("cloud ls memory://rt | select name", PipelineData::Empty)?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![Value::test_record(record!(
+                "name" => Value::test_string("a.bin"),
+            ))])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_delta_open() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(FromParquet))?;
+        let _ = plugin_test.add_decl(Box::new(Select))?;
+        let schema = r#"{\"type\":\"struct\",\"fields\":[{\"name\":\"a\",\"type\":\"long\",\"nullable\":true,\"metadata\":{}},{\"name\":\"year\",\"type\":\"integer\",\"nullable\":true,\"metadata\":{}}]}"#;
+        let commits = [
+            format!(
+                r#"{{"protocol":{{"minReaderVersion":1,"minWriterVersion":2}}}}
+{{"metaData":{{"id":"test","format":{{"provider":"parquet","options":{{}}}},"schemaString":"{schema}","partitionColumns":["year"],"configuration":{{}}}}}}
+{{"add":{{"path":"year=2024/part-0.parquet","partitionValues":{{"year":"2024"}},"size":6,"modificationTime":1700000000000,"dataChange":true,"stats":"{{\"numRecords\":2}}"}}}}"#
+            ),
+            r#"{"remove":{"path":"year=2024/part-0.parquet","dataChange":true}}
+{"add":{"path":"year=2025/part-1.parquet","partitionValues":{"year":"2025"},"size":4,"modificationTime":1700000001000,"dataChange":true}}"#
+                .to_string(),
+        ];
+        for (version, commit) in commits.iter().enumerate() {
+            plugin_test.eval_with(
+                &format!(
+                    "'{commit}' | cloud save --raw memory://delta/events/_delta_log/{version:020}.json"
+                ),
+                PipelineData::Empty,
+            )?;
+        }
+        plugin_test.eval_with(
+            "'a\n1\n2' | cloud save --raw memory://delta/events/year=2024/part-0.parquet;
+            'a\n3' | cloud save --raw memory://delta/events/year=2025/part-1.parquet",
+            PipelineData::Empty,
+        )?;
+
+        let files = plugin_test
+            .eval_with(
+                "cloud delta open memory://delta/events --version 0 --files | select path partition_values num_records",
+                PipelineData::Empty,
+            )?
+            .into_value(Span::test_data())?;
+        assert_eq!(
+            files,
+            Value::test_list(vec![Value::test_record(record!(
+                "path" => Value::test_string("year=2024/part-0.parquet"),
+                "partition_values" => Value::test_record(record!("year" => Value::test_int(2024))),
+                "num_records" => Value::test_int(2),
+            ))])
+        );
+
+        let mut rows = |command: &str| -> Result<Value, Box<dyn std::error::Error>> {
+            Ok(plugin_test
+                .eval_with(command, PipelineData::Empty)?
+                .into_value(Span::test_data())?)
+        };
+        let row = |a, year| {
+            Value::test_record(record!(
+                "a" => Value::test_int(a),
+                "year" => Value::test_int(year),
+            ))
+        };
+        assert_eq!(
+            rows("cloud delta open memory://delta/events --version 0")?,
+            Value::test_list(vec![row(1, 2024), row(2, 2024)])
+        );
+        assert_eq!(
+            rows("cloud delta open memory://delta/events")?,
+            Value::test_list(vec![row(3, 2025)])
+        );
+        assert!(rows("cloud delta open memory://delta/events --version 2").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_iceberg_scan() -> Result<(), Box<dyn std::error::Error>> {
+        fn long(n: i64, out: &mut Vec<u8>) {
+            let mut n = ((n << 1) ^ (n >> 63)) as u64;
+            while n >= 0x80 {
+                out.push(n as u8 | 0x80);
+                n >>= 7;
+            }
+            out.push(n as u8);
+        }
+        fn string(s: &str, out: &mut Vec<u8>) {
+            long(s.len() as i64, out);
+            out.extend_from_slice(s.as_bytes());
+        }
+        /// Object container file with the records in one uncompressed block
+        fn container(schema: &str, records: &[Vec<u8>]) -> Vec<u8> {
+            let sync = [7; 16];
+            let mut file = b"Obj\x01".to_vec();
+            long(1, &mut file);
+            string("avro.schema", &mut file);
+            string(schema, &mut file);
+            long(0, &mut file);
+            file.extend_from_slice(&sync);
+            let data = records.concat();
+            long(records.len() as i64, &mut file);
+            long(data.len() as i64, &mut file);
+            file.extend_from_slice(&data);
+            file.extend_from_slice(&sync);
+            file
+        }
+
+        let manifest_list_schema = r#"{"type":"record","name":"manifest_file","fields":[{"name":"manifest_path","type":"string"},{"name":"content","type":"int"}]}"#;
+        let manifest_schema = r#"{"type":"record","name":"manifest_entry","fields":[{"name":"status","type":"int"},{"name":"snapshot_id","type":["null","long"]},{"name":"data_file","type":{"type":"record","name":"r2","fields":[{"name":"content","type":"int"},{"name":"file_path","type":"string"},{"name":"file_format","type":"string"},{"name":"partition","type":{"type":"record","name":"r102","fields":[{"name":"day","type":["null","int"]}]}},{"name":"record_count","type":"long"},{"name":"file_size_in_bytes","type":"long"}]}}]}"#;
+        let manifest_list = |manifest: &str| {
+            let mut record = vec![];
+            string(manifest, &mut record);
+            long(0, &mut record);
+            container(manifest_list_schema, &[record])
+        };
+        let entry = |status, path: &str, day, records| {
+            let mut record = vec![];
+            long(status, &mut record);
+            long(0, &mut record);
+            long(0, &mut record);
+            string(path, &mut record);
+            string("PARQUET", &mut record);
+            long(1, &mut record);
+            long(day, &mut record);
+            long(records, &mut record);
+            long(10, &mut record);
+            record
+        };
+        let root = "memory://iceberg/events";
+        let files = [
+            (
+                "metadata/snap-1.avro",
+                manifest_list(&format!("{root}/metadata/m1.avro")),
+            ),
+            (
+                "metadata/snap-2.avro",
+                manifest_list(&format!("{root}/metadata/m2.avro")),
+            ),
+            (
+                "metadata/m1.avro",
+                container(
+                    manifest_schema,
+                    &[entry(1, &format!("{root}/data/a.parquet"), 1, 2)],
+                ),
+            ),
+            (
+                "metadata/m2.avro",
+                container(
+                    manifest_schema,
+                    &[
+                        entry(2, &format!("{root}/data/a.parquet"), 1, 2),
+                        entry(1, &format!("{root}/data/b.parquet"), 2, 1),
+                    ],
+                ),
+            ),
+            ("data/a.parquet", b"a\n1\n2".to_vec()),
+            ("data/b.parquet", b"a\n3".to_vec()),
+        ];
+
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(FromParquet))?;
+        let _ = plugin_test.add_decl(Box::new(Select))?;
+        for (path, data) in files {
+            let stream = ByteStream::read_binary(data, Span::test_data(), Signals::empty());
+            plugin_test.eval_with(
+                &format!("cloud save {root}/{path}"),
+                PipelineData::ByteStream(stream, None),
+            )?;
+        }
+        let snapshot = |id: i64| {
+            format!(r#"{{"snapshot-id":{id},"manifest-list":"{root}/metadata/snap-{id}.avro"}}"#)
+        };
+        // The highest metadata version is used without a version hint
+        for (version, current, snapshots) in [
+            (1, 1, snapshot(1)),
+            (2, 2, format!("{},{}", snapshot(1), snapshot(2))),
+        ] {
+            plugin_test.eval_with(
+                &format!(
+                    r#"'{{"format-version":2,"current-snapshot-id":{current},"snapshots":[{snapshots}]}}' | cloud save --raw {root}/metadata/v{version}.metadata.json"#
+                ),
+                PipelineData::Empty,
+            )?;
+        }
+
+        let mut eval = |command: &str| -> Result<Value, Box<dyn std::error::Error>> {
+            Ok(plugin_test
+                .eval_with(command, PipelineData::Empty)?
+                .into_value(Span::test_data())?)
+        };
+        let file = |name: &str, day, records| {
+            Value::test_record(record!(
+                "path" => Value::test_string(format!("{root}/data/{name}")),
+                "record_count" => Value::test_int(records),
+                "partition" => Value::test_record(record!("day" => Value::test_int(day))),
+            ))
+        };
+        assert_eq!(
+            eval(&format!(
+                "cloud iceberg scan {root} | select path record_count partition"
+            ))?,
+            Value::test_list(vec![file("b.parquet", 2, 1)])
+        );
+        assert_eq!(
+            eval(&format!(
+                "cloud iceberg scan {root} --snapshot-id 1 | select path record_count partition"
+            ))?,
+            Value::test_list(vec![file("a.parquet", 1, 2)])
+        );
+        assert_eq!(
+            eval(&format!("cloud iceberg scan {root} --snapshot-id 1 --read"))?,
+            Value::test_list(vec![
+                Value::test_record(record!("a" => Value::test_int(1))),
+                Value::test_record(record!("a" => Value::test_int(2))),
+            ])
+        );
+        assert!(eval(&format!("cloud iceberg scan {root} --snapshot-id 3")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_avro_save_open() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let value = plugin_test
+            .eval_with(
+                "[
+                    {id: 1, score: 1.5, name: a, tags: [x y], at: 2024-01-02T03:04:05Z, owner: {name: b}}
+                    {id: 2, score: 2, name: null, tags: [], at: 2024-01-02T03:04:05.5Z}
+                ] | cloud save memory://avro/rows.avro | cloud open memory://avro/rows.avro",
+                PipelineData::Empty,
+            )?
+            .into_value(Span::test_data())?;
+        let date = |s: &str| chrono::DateTime::parse_from_rfc3339(s).map(Value::test_date);
+        assert_eq!(
+            value,
+            Value::test_list(vec![
+                Value::test_record(record!(
+                    "id" => Value::test_int(1),
+                    "score" => Value::test_float(1.5),
+                    "name" => Value::test_string("a"),
+                    "tags" => Value::test_list(vec![Value::test_string("x"), Value::test_string("y")]),
+                    "at" => date("2024-01-02T03:04:05Z")?,
+                    "owner" => Value::test_record(record!("name" => Value::test_string("b"))),
+                )),
+                Value::test_record(record!(
+                    "id" => Value::test_int(2),
+                    "score" => Value::test_float(2.0),
+                    "name" => Value::test_nothing(),
+                    "tags" => Value::test_list(vec![]),
+                    "at" => date("2024-01-02T03:04:05.5Z")?,
+                    "owner" => Value::test_nothing(),
+                )),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_ndjson_stream() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let rows = (1..=3).map(|n| {
+            Value::test_record(record!(
+                "n" => Value::test_int(n),
+                "tags" => Value::test_list(vec![Value::test_string("x")]),
+                "missing" => Value::test_nothing(),
+            ))
+        });
+        let stream = ListStream::new(rows, Span::test_data(), Signals::empty());
+        plugin_test.eval_with(
+            "cloud save memory://ndjson/rows.jsonl",
+            PipelineData::ListStream(stream, None),
+        )?;
+        let value = plugin_test
+            .eval_with(
+                "cloud open --raw memory://ndjson/rows.jsonl",
+                PipelineData::Empty,
+            )?
+            .into_value(Span::test_data())?;
+        assert_eq!(
+            value.coerce_into_string()?,
+            "{\"n\":1,\"tags\":[\"x\"],\"missing\":null}\n\
+             {\"n\":2,\"tags\":[\"x\"],\"missing\":null}\n\
+             {\"n\":3,\"tags\":[\"x\"],\"missing\":null}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_by_hash() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(Select))?;
+        // SHA-256 of "hello"
+        let expected =
+            "memory://cas/blobs/2c/f24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        for _ in 0..2 {
+            let url = plugin_test
+                .eval_with(
+                    "'hello' | cloud save --by-hash memory://cas/blobs/",
+                    PipelineData::Empty,
+                )?
+                .into_value(Span::test_data())?;
+            assert_eq!(url, Value::test_string(expected));
+        }
+        let value = plugin_test
+            .eval_with(&format!("cloud open --raw {expected}"), PipelineData::Empty)?
+            .into_value(Span::test_data())?;
+        assert_eq!(value.coerce_into_string()?, "hello");
+        let listing = plugin_test
+            .eval_with("cloud ls memory://cas | select name", PipelineData::Empty)?
+            .into_value(Span::test_data())?;
+        assert_eq!(listing.as_list()?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip() -> Result<(), Box<dyn std::error::Error>> {
+        use std::io::Read;
+
+        let dir = std::env::temp_dir().join(format!("nu_plugin_cloud_zip_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let file = dir.join("local.txt");
+        std::fs::write(&file, "from disk".repeat(1000))?;
+
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let input = Value::test_list(vec![
+            Value::test_record(record!(
+                "name" => Value::test_string("a.txt"),
+                "data" => Value::test_string("hello"),
+            )),
+            Value::test_record(record!(
+                "name" => Value::test_string("dir/b.bin"),
+                "data" => Value::test_binary(vec![0, 1, 2]),
+            )),
+            Value::test_string(file.display().to_string()),
+        ]);
+        plugin_test.eval_with(
+            "cloud zip memory://zip/archive.zip",
+            PipelineData::Value(input, None),
+        )?;
+        let archive = plugin_test
+            .eval_with(
+                "cloud open --raw memory://zip/archive.zip",
+                PipelineData::Empty,
+            )?
+            .into_value(Span::test_data())?
+            .into_binary()?;
+        std::fs::remove_dir_all(&dir)?;
+
+        // Read the entries through the central directory, like unzip does
+        let u16_at = |at: usize| u16::from_le_bytes([archive[at], archive[at + 1]]) as usize;
+        let u32_at = |at: usize| u32::from_le_bytes(archive[at..at + 4].try_into().unwrap());
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(end), 0x06054b50);
+        assert_eq!(u16_at(end + 10), 3);
+        let mut at = u32_at(end + 16) as usize;
+        let mut entries = vec![];
+        for _ in 0..3 {
+            assert_eq!(u32_at(at), 0x02014b50);
+            let crc = u32_at(at + 16);
+            let compressed = u32_at(at + 20) as usize;
+            let name_len = u16_at(at + 28);
+            let extra_len = u16_at(at + 30);
+            let offset = u32_at(at + 42) as usize;
+            let name = String::from_utf8(archive[at + 46..at + 46 + name_len].to_vec())?;
+            let data_start = offset + 30 + u16_at(offset + 26) + u16_at(offset + 28);
+            let mut data = vec![];
+            flate2::read::DeflateDecoder::new(&archive[data_start..data_start + compressed])
+                .read_to_end(&mut data)?;
+            assert_eq!(crc32fast::hash(&data), crc);
+            entries.push((name, data));
+            at += 46 + name_len + extra_len;
+        }
+        let local_name = file
+            .display()
+            .to_string()
+            .trim_start_matches('/')
+            .to_string();
+        assert_eq!(
+            entries,
+            vec![
+                ("a.txt".to_string(), b"hello".to_vec()),
+                ("dir/b.bin".to_string(), vec![0, 1, 2]),
+                (local_name, "from disk".repeat(1000).into_bytes()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_complete() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        plugin_test.eval_with(
+            "'x' | cloud save memory://complete/a/x.csv
+            'x' | cloud save memory://complete/ab.csv
+            'x' | cloud save memory://complete/b.csv",
+            PipelineData::Empty,
+        )?;
+        let mut complete = |context: &str| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+            let value = plugin_test
+                .eval_with(&format!("cloud complete '{context}'"), PipelineData::Empty)?
+                .into_value(Span::test_data())?;
+            let mut completions = vec![];
+            for completion in value.into_list()? {
+                completions.push(completion.into_string()?);
+            }
+            completions.sort();
+            Ok(completions)
+        };
+        assert_eq!(
+            complete("cloud open memory://complete/a")?,
+            ["memory://complete/a/", "memory://complete/ab.csv"]
+        );
+        assert_eq!(
+            complete("cloud open memory://complete/a/")?,
+            ["memory://complete/a/x.csv"]
+        );
+        assert!(complete("cloud open memory://compl")?.is_empty());
+        assert!(complete("cloud open foo")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_url() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let result = plugin_test.eval_with(
+            "$env.config.plugins.cloud = {default_url: 'memory://config/base'}
+            'from config' | cloud save reports/jan.txt
+            cloud open memory://config/base/reports/jan.txt",
+            PipelineData::Empty,
+        )?;
+        assert_eq!(
+            result.into_value(Span::test_data())?.into_string()?,
+            "from config"
+        );
+        let result = plugin_test.eval_with(
+            "$env.CLOUD_DEFAULT_URL = 'memory://env/'
+            'from env' | cloud save memory://env/reports/jan.txt
+            cloud open reports/jan.txt",
+            PipelineData::Empty,
+        )?;
+        assert_eq!(
+            result.into_value(Span::test_data())?.into_string()?,
+            "from env"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_url_expansion() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let result = plugin_test.eval_with(
+            "$env.config.plugins.cloud = {aliases: {data: 'memory://expand/data/'}}
+            $env.BUCKET = 'expand'
+            'expanded' | cloud save ~data/$env.BUCKET.txt
+            cloud open memory://$env.BUCKET/data/expand.txt",
+            PipelineData::Empty,
+        )?;
+        assert_eq!(
+            result.into_value(Span::test_data())?.into_string()?,
+            "expanded"
+        );
+        assert!(
+            plugin_test
+                .eval_with("cloud open ~missing/a.txt", PipelineData::Empty)
+                .is_err()
+        );
+        assert!(
+            plugin_test
+                .eval_with(
+                    "cloud open memory://$env.UNSET_BUCKET/a.txt",
+                    PipelineData::Empty
+                )
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_sort_limit() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        plugin_test.eval_with(
+            "'aaaa' | cloud save memory://sort/a.txt
+            'bb' | cloud save memory://sort/b.txt
+            'ccc' | cloud save memory://sort/c.txt
+            'd' | cloud save memory://sort/d.txt",
+            PipelineData::Empty,
+        )?;
+        let mut names = |command: &str| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+            let value = plugin_test
+                .eval_with(command, PipelineData::Empty)?
+                .into_value(Span::test_data())?;
+            let mut names = vec![];
+            for entry in value.into_list()? {
+                names.push(entry.get_data_by_key("name").unwrap().into_string()?);
+            }
+            Ok(names)
+        };
+        assert_eq!(
+            names("cloud ls memory://sort --sort-by size --reverse --limit 2")?,
+            ["a.txt", "c.txt"]
+        );
+        assert_eq!(
+            names("cloud ls memory://sort --sort-by size")?,
+            ["d.txt", "b.txt", "c.txt", "a.txt"]
+        );
+        assert_eq!(names("cloud ls memory://sort --reverse -n 1")?, ["d.txt"]);
+        assert_eq!(names("cloud ls memory://sort --limit 0")?.len(), 0);
+        assert!(names("cloud ls memory://sort --sort-by color").is_err());
+        // -r is --recursive in the other commands, so it's no shorthand for --reverse
+        assert!(names("cloud ls memory://sort -r").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_error() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let Err(error) = plugin_test.eval_with(
+            "cloud open memory://errors/missing.txt",
+            PipelineData::Empty,
+        ) else {
+            panic!("opening a missing object should fail");
+        };
+        let error = LabeledError::from(error);
+        assert_eq!(error.msg, "Could not read memory://errors/missing.txt");
+        assert_eq!(error.code.as_deref(), Some("cloud::not_found"));
+        assert!(error.help.is_some());
+        assert_eq!(error.labels.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_format() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let mut eval = |command: &str| -> Result<Value, Box<dyn std::error::Error>> {
+            Ok(plugin_test
+                .eval_with(command, PipelineData::Empty)?
+                .into_value(Span::test_data())?)
+        };
+        assert_eq!(
+            eval("'hello' | cloud save memory://output/dir/a.txt --output-format record")?,
+            Value::test_record(record!(
+                "url" => Value::test_string("memory://output/dir/a.txt"),
+                "objects" => Value::test_int(1),
+                "size" => Value::test_filesize(5),
+            ))
+        );
+        eval("'hi' | cloud save memory://output/dir/sub/b.txt")?;
+        let mut copies = vec![];
+        for row in
+            eval("cloud cp -r memory://output/dir memory://output/copy --output-format table")?
+                .into_list()?
+        {
+            copies.push(row.get_data_by_key("url").unwrap().into_string()?);
+        }
+        copies.sort();
+        assert_eq!(
+            copies,
+            [
+                "memory://output/copy/a.txt",
+                "memory://output/copy/sub/b.txt"
+            ]
+        );
+        assert_eq!(
+            eval("cloud rm -r memory://output/copy --output-format record")?
+                .get_data_by_key("objects"),
+            Some(Value::test_int(2))
+        );
+        assert_eq!(
+            eval("cloud rm memory://output/dir/a.txt --output-format table")?,
+            Value::test_list(vec![Value::test_record(record!(
+                "url" => Value::test_string("memory://output/dir/a.txt"),
+                "size" => Value::test_nothing(),
+            ))])
+        );
+        assert_eq!(
+            eval("cloud rm memory://output/dir/sub/b.txt")?,
+            Value::test_nothing()
+        );
+        assert!(eval("cloud rm memory://output/dir/a.txt --output-format yaml").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_glob() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let mut eval = |command: &str| -> Result<Value, Box<dyn std::error::Error>> {
+            Ok(plugin_test
+                .eval_with(command, PipelineData::Empty)?
+                .into_value(Span::test_data())?)
+        };
+        for name in ["a.gz", "b.gz", "c.txt", "2024/d.gz"] {
+            eval(&format!("'{name}' | cloud save memory://glob/logs/{name}"))?;
+        }
+        let mut urls = |command: &str| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+            let mut urls = vec![];
+            for row in eval(command)?.into_list()? {
+                urls.push(row.get_data_by_key("url").unwrap().into_string()?);
+            }
+            urls.sort();
+            Ok(urls)
+        };
+        assert_eq!(
+            urls(
+                "cloud cp 'memory://glob/logs/*.gz' memory://glob/archive/ --output-format table"
+            )?,
+            ["memory://glob/archive/a.gz", "memory://glob/archive/b.gz"]
+        );
+        assert_eq!(
+            urls("cloud cp 'memory://glob/logs/?.txt' memory://glob/text/ --output-format table")?,
+            ["memory://glob/text/c.txt"]
+        );
+        assert_eq!(
+            urls("cloud rm -r 'memory://glob/[at]*' --output-format table")?,
+            [
+                "memory://glob/archive/a.gz",
+                "memory://glob/archive/b.gz",
+                "memory://glob/text/c.txt"
+            ]
+        );
+        assert_eq!(
+            urls("cloud rm 'memory://glob/**/*.gz' --output-format table")?,
+            [
+                "memory://glob/logs/2024/d.gz",
+                "memory://glob/logs/a.gz",
+                "memory://glob/logs/b.gz"
+            ]
+        );
+        assert!(urls("cloud rm 'memory://glob/**/*.gz'").is_err());
+        assert!(urls("cloud rm 'memory://glob/logs/[a'").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_overview() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let mut config = plugin_test.engine_state().get_config().as_ref().clone();
+        config.plugins.insert(
+            "cloud".into(),
+            Value::test_record(record! {
+                "default_url" => Value::test_string("s3://mybucket/reports"),
+                "profile" => Value::test_string("analytics"),
+                "concurrency" => Value::test_int(32),
+                "buckets" => Value::test_record(record! {
+                    "s3://prod-data" => Value::test_record(record! {
+                        "profile" => Value::test_string("prod"),
+                        "region" => Value::test_string("eu-west-1"),
+                    }),
+                }),
+            }),
+        );
+        plugin_test.engine_state_mut().set_config(config);
+        let overview = plugin_test
+            .eval_with("cloud", PipelineData::Empty)?
+            .into_value(Span::test_data())?;
+
+        let commands = overview.get_data_by_key("commands").unwrap().into_list()?;
+        assert!(
+            commands
+                .iter()
+                .any(|row| row.get_data_by_key("name") == Some(Value::test_string("cloud ls")))
+        );
+        assert!(
+            !commands
+                .iter()
+                .any(|row| row.get_data_by_key("name") == Some(Value::test_string("cloud")))
+        );
+        let profiles = overview.get_data_by_key("profiles").unwrap().into_list()?;
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(
+            profiles[0].get_data_by_key("profile"),
+            Some(Value::test_string("prod"))
+        );
+        assert_eq!(
+            profiles[0].get_data_by_key("credentials"),
+            Some(Value::test_string("default"))
+        );
+        let defaults = overview.get_data_by_key("defaults").unwrap();
+        assert_eq!(
+            defaults.get_data_by_key("default_url"),
+            Some(Value::test_string("s3://mybucket/reports"))
+        );
+        assert_eq!(
+            defaults.get_data_by_key("request_log"),
+            Some(Value::test_bool(false))
+        );
+        assert_eq!(
+            defaults.get_data_by_key("aws_profile"),
+            Some(Value::test_string("analytics"))
+        );
+        assert_eq!(
+            defaults.get_data_by_key("concurrency"),
+            Some(Value::test_int(32))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_spans() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let Err(error) = plugin_test.eval_with(
+            "cloud cp memory://spans/missing.txt memory://spans/copy.txt",
+            PipelineData::Empty,
+        ) else {
+            panic!("copying a missing object should fail");
+        };
+        let error = LabeledError::from(error);
+        let contents = plugin_test
+            .engine_state()
+            .get_span_contents(error.labels[0].span);
+        assert_eq!(contents, b"memory://spans/missing.txt");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracing() -> Result<(), Box<dyn std::error::Error>> {
+        // A collector passing on the exports it is sent
+        let (sender, exports) = std::sync::mpsc::channel();
+        let endpoint = mock_s3(move |request| {
+            let body = String::from_utf8_lossy(&request.body).into_owned();
+            let _ = sender.send((request.method.clone(), request.target.clone(), body));
+            ok("")
+        })?;
+
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let mut config = plugin_test.engine_state().get_config().as_ref().clone();
+        config.plugins.insert(
+            "cloud".into(),
+            Value::test_record(record! {
+                "tracing" => Value::test_record(record! {
+                    "endpoint" => Value::test_string(endpoint),
+                }),
+            }),
+        );
+        plugin_test.engine_state_mut().set_config(config);
+        plugin_test.eval_with(
+            "$env.TRACEPARENT = '00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01'; cloud ls memory://traced/",
+            PipelineData::Empty,
+        )?;
+
+        let (method, target, body) = exports.recv_timeout(std::time::Duration::from_secs(10))?;
+        assert_eq!(method, "POST");
+        assert!(target.starts_with("/v1/traces"));
+        let body: serde_json::Value = serde_json::from_str(&body)?;
+        let span = &body["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["name"], "cloud ls");
+        assert_eq!(span["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(span["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(span["status"]["code"], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_last_stats() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let mut eval = |command: &str| -> Result<Value, Box<dyn std::error::Error>> {
+            Ok(plugin_test
+                .eval_with(command, PipelineData::Empty)?
+                .into_value(Span::test_data())?)
+        };
+        assert_eq!(eval("cloud last-stats")?, Value::test_nothing());
+        eval("'hello' | cloud save memory://stats/a.txt")?;
+        let stats = eval("cloud last-stats")?;
+        assert_eq!(
+            stats.get_data_by_key("command"),
+            Some(Value::test_string("cloud save"))
+        );
+        assert_eq!(
+            stats.get_data_by_key("success"),
+            Some(Value::test_bool(true))
+        );
+        assert!(eval("cloud open memory://stats/missing.txt").is_err());
+        assert_eq!(
+            eval("cloud last-stats")?.get_data_by_key("success"),
+            Some(Value::test_bool(false))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_multipart_stats() -> Result<(), Box<dyn std::error::Error>> {
+        // A bucket that is also the collector of the traces, passing on their exports
+        let (sender, exports) = std::sync::mpsc::channel();
+        let endpoint = mock_s3(move |request| {
+            if request.path() == "/v1/traces" {
+                let _ = sender.send(request.body.clone());
+            }
+            accept_writes(request)
+        })?;
+        let mut config = mock_bucket("s3://counted", &endpoint);
+        config.push("upload_part_size", Value::test_filesize(5 * 1024 * 1024));
+        config.push(
+            "tracing",
+            Value::test_record(record! {
+                "endpoint" => Value::test_string(&endpoint),
+            }),
+        );
+        let mut harness = Harness::new()?.with_config(config);
+
+        // The parts uploaded concurrently count towards the run, like the requests around them
+        let data = vec![0; 6 * 1024 * 1024];
+        let stream = ByteStream::read_binary(data, Span::test_data(), Signals::empty());
+        harness.plugin_test.eval_with(
+            "cloud save s3://counted/big.bin",
+            PipelineData::ByteStream(stream, None),
+        )?;
+        let stats = harness.eval("cloud last-stats")?;
+        assert_eq!(stats.get_data_by_key("requests"), Some(Value::test_int(4)));
+        let sent = stats.get_data_by_key("bytes_sent").ok_or("bytes_sent")?;
+        assert!(sent.as_filesize()?.get() >= 6 * 1024 * 1024);
+
+        let export = exports.recv_timeout(std::time::Duration::from_secs(10))?;
+        let export: serde_json::Value = serde_json::from_slice(&export)?;
+        let spans = export["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .ok_or("spans")?;
+        let command = spans.iter().find(|span| span["name"] == "cloud save");
+        let command = command.ok_or("command span")?;
+        let requests = spans
+            .iter()
+            .filter(|span| span["parentSpanId"] == command["spanId"])
+            .count();
+        assert_eq!(requests, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug_log() -> Result<(), Box<dyn std::error::Error>> {
+        // A server answering every request with the same object
+        let url = format!(
+            "{}/data.txt",
+            mock_s3(|request| {
+                let body = match request.method.as_str() {
+                    "HEAD" => "",
+                    _ => "hello",
+                };
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: 5\r\netag: \"1\"\r\n\
+                     last-modified: Tue, 15 Nov 1994 08:12:31 GMT\r\n\
+                     x-amz-request-id: req-1\r\n\r\n{body}"
+                )
+            })?
+        );
+
+        let path =
+            std::env::temp_dir().join(format!("nu_plugin_cloud_test_{}.log", std::process::id()));
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("cloud", plugin.into())?;
+        let mut config = plugin_test.engine_state().get_config().as_ref().clone();
+        config.plugins.insert(
+            "cloud".into(),
+            Value::test_record(record! {
+                "debug_log" => Value::test_record(record! {
+                    "path" => Value::test_string(path.display().to_string()),
+                }),
+            }),
+        );
+        plugin_test.engine_state_mut().set_config(config);
+        let data = plugin_test
+            .eval_with(
+                &format!("cloud open --raw '{url}?X-Amz-Signature=secret'"),
+                PipelineData::Empty,
+            )?
+            .into_value(Span::test_data())?;
+        assert_eq!(data.coerce_into_binary()?, b"hello");
+
+        let log = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+        assert!(!log.contains("secret"));
+        let line: serde_json::Value = serde_json::from_str(log.lines().last().unwrap())?;
+        assert_eq!(line["method"], "GET");
+        assert_eq!(line["url"], url);
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["x-amz-request-id"], "req-1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_metrics() -> Result<(), Box<dyn std::error::Error>> {
+        // A server with a single object, answering other paths with 404
+        let base = mock_s3(|request| match request.target.as_str() {
+            "/data.txt" => "HTTP/1.1 200 OK\r\ncontent-length: 5\r\netag: \"1\"\r\n\
+                            last-modified: Tue, 15 Nov 1994 08:12:31 GMT\r\n\r\nhello"
+                .into(),
+            _ => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n".into(),
+        })?;
+
+        let mut plugin_test = PluginTest::new("cloud", CloudPlugin::default().into())?;
+        plugin_test.eval_with(
+            &format!("cloud open --raw {base}/data.txt"),
+            PipelineData::Empty,
+        )?;
+        let missing = format!("cloud open --raw {base}/missing.txt");
+        assert!(
+            plugin_test
+                .eval_with(&missing, PipelineData::Empty)
+                .is_err()
+        );
+
+        let metrics = plugin_test
+            .eval_with("cloud metrics --reset", PipelineData::Empty)?
+            .into_value(Span::test_data())?;
+        // A HEAD for each url, then a GET for the one that exists
+        let requests = metrics.get_data_by_key("requests").unwrap();
+        assert_eq!(requests.get_data_by_key("HEAD").unwrap().as_int()?, 2);
+        assert_eq!(requests.get_data_by_key("GET").unwrap().as_int()?, 1);
+        let errors = metrics.get_data_by_key("errors").unwrap();
+        assert_eq!(errors.get_data_by_key("4xx").unwrap().as_int()?, 1);
+        let http = metrics.get_data_by_key("providers").unwrap();
+        let http = http.get_data_by_key("http").unwrap();
+        let requests = http.get_data_by_key("requests").unwrap();
+        assert_eq!(requests.get_data_by_key("HEAD").unwrap().as_int()?, 2);
+        assert_eq!(
+            metrics
+                .get_data_by_key("bytes_downloaded")
+                .unwrap()
+                .as_filesize()?
+                .get(),
+            5
+        );
+
+        // Counting starts over after a reset
+        let metrics = plugin_test
+            .eval_with("cloud metrics", PipelineData::Empty)?
+            .into_value(Span::test_data())?;
+        let requests = metrics.get_data_by_key("requests").unwrap();
+        assert!(requests.as_record()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_audit_log() -> Result<(), Box<dyn std::error::Error>> {
+        let path =
+            std::env::temp_dir().join(format!("nu_plugin_cloud_audit_{}.log", std::process::id()));
+        let mut plugin_test = PluginTest::new("cloud", CloudPlugin::default().into())?;
+        let mut config = plugin_test.engine_state().get_config().as_ref().clone();
+        config.plugins.insert(
+            "cloud".into(),
+            Value::test_record(record! {
+                "audit_log" => Value::test_string(path.display().to_string()),
+            }),
+        );
+        plugin_test.engine_state_mut().set_config(config);
+        for command in [
+            "'a' | cloud save memory://audit/a.txt",
+            "'b' | cloud save memory://audit/a.txt",
+            "cloud cp memory://audit/a.txt memory://audit/b.txt",
+            "cloud cp -r memory://audit/ memory://audit/",
+            "cloud mv memory://audit/b.txt memory://audit/c.txt",
+            "['c.txt'] | cloud mv memory://audit/ memory://audit/moved/",
+            "cloud rm -r memory://audit/",
+        ] {
+            plugin_test.eval_with(command, PipelineData::Empty)?;
+        }
+
+        let log = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+        let lines = log
+            .lines()
+            .map(|line| {
+                let line: serde_json::Value = serde_json::from_str(line)?;
+                assert_eq!(line["result"], "ok");
+                Ok(format!(
+                    "{} {} {}",
+                    line["command"], line["action"], line["url"]
+                ))
+            })
+            .collect::<Result<Vec<_>, serde_json::Error>>()?;
+        // Only writes replacing objects and removals are recorded, including the sources of moves
+        assert_eq!(
+            lines,
+            [
+                r#""cloud save" "overwrite" "memory://audit/a.txt""#,
+                r#""cloud cp" "overwrite" "memory://audit/a.txt""#,
+                r#""cloud cp" "overwrite" "memory://audit/b.txt""#,
+                r#""cloud mv" "delete" "memory://audit/b.txt""#,
+                r#""cloud mv" "delete" "memory://audit/c.txt""#,
+                r#""cloud rm" "delete" "memory://audit/a.txt""#,
+                r#""cloud rm" "delete" "memory://audit/moved/c.txt""#,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_harness_commands() -> Result<(), Box<dyn std::error::Error>> {
+        let harness = Harness::new()?;
+        for command in super::commands() {
+            let decl = harness
+                .plugin_test
+                .engine_state()
+                .find_decl(command.name().as_bytes(), &[]);
+            assert!(decl.is_some(), "{} isn't registered", command.name());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_harness_save_open_ls_rm() -> Result<(), Box<dyn std::error::Error>> {
+        let mut harness = Harness::new()?;
+        harness.write("memory://h/a.txt", "one")?;
+        assert_eq!(harness.read("memory://h/a.txt")?, "one");
+
+        // Saving again replaces the object, also for reads it was cached for
+        harness.write("memory://h/a.txt", "two")?;
+        assert_eq!(harness.read("memory://h/a.txt")?, "two");
+        assert_eq!(
+            harness
+                .eval("cloud open memory://h/a.txt")?
+                .coerce_into_string()?,
+            "two"
+        );
+
+        harness.write("memory://h/dir/b.txt", "b")?;
+        harness.write("memory://h/dir2/c.txt", "c")?;
+        // Objects under the prefix are listed recursively, named by their paths in the bucket
+        assert_eq!(
+            harness.names("memory://h/")?,
+            ["a.txt", "dir/b.txt", "dir2/c.txt"]
+        );
+        assert_eq!(harness.names("memory://h/dir/")?, ["dir/b.txt"]);
+
+        harness.eval("cloud rm memory://h/a.txt")?;
+        assert!(harness.read("memory://h/a.txt").is_err());
+        // Prefixes are removed by their path segments, not as strings
+        harness.eval("cloud rm -r memory://h/dir")?;
+        assert_eq!(harness.names("memory://h/")?, ["dir2/c.txt"]);
+        assert_eq!(harness.read("memory://h/dir2/c.txt")?, "c");
+
+        // Each harness has its own stores
+        let mut other = Harness::new()?;
+        assert!(other.names("memory://h/")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_harness_config_cp() -> Result<(), Box<dyn std::error::Error>> {
+        let mut harness = Harness::new()?.with_config(record! {
+            "default_url" => Value::test_string("memory://cfg/team/"),
+        });
+        harness.write("a.txt", "a")?;
+        harness.eval("cloud cp a.txt memory://cfg/backup/a.txt")?;
+        assert_eq!(harness.read("memory://cfg/backup/a.txt")?, "a");
+        assert_eq!(
+            harness.names("memory://cfg/")?,
+            ["backup/a.txt", "team/a.txt"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync() -> Result<(), Box<dyn std::error::Error>> {
+        let mut harness = Harness::new()?;
+        for (name, data) in [("a.txt", "a"), ("b.tmp", "b"), ("sub/c.txt", "c")] {
+            harness.write(&format!("memory://sync/src/{name}"), data)?;
+        }
+        for (name, data) in [("stale.txt", "x"), ("d.tmp", "d")] {
+            harness.write(&format!("memory://sync/dst/{name}"), data)?;
+        }
+        let sync =
+            |flags: &str| format!("cloud sync {flags} memory://sync/src/ memory://sync/dst/");
+        let count =
+            |summary: &Value, key: &str| summary.get_data_by_key(key).unwrap().as_int().unwrap();
+
+        // Nothing changes when more would be deleted than allowed
+        assert!(
+            harness
+                .eval(&sync("-x [*.tmp] --delete --max-delete 0"))
+                .is_err()
+        );
+        assert_eq!(
+            harness.names("memory://sync/dst/")?,
+            ["dst/d.tmp", "dst/stale.txt"]
+        );
+
+        // Excluded objects are neither copied nor deleted
+        let summary = harness.eval(&sync("-x [*.tmp] --delete"))?;
+        assert_eq!(
+            [
+                count(&summary, "created"),
+                count(&summary, "updated"),
+                count(&summary, "deleted")
+            ],
+            [2, 0, 1]
+        );
+        assert_eq!(
+            harness.names("memory://sync/dst/")?,
+            ["dst/a.txt", "dst/d.tmp", "dst/sub/c.txt"]
+        );
+        let actions = summary.get_data_by_key("actions").unwrap().into_list()?;
+        assert!(actions.iter().any(|action| {
+            action.get_data_by_key("action") == Some(Value::test_string("delete"))
+                && action.get_data_by_key("url")
+                    == Some(Value::test_string("memory://sync/dst/stale.txt"))
+        }));
+        let summary = harness.eval(&sync("-x [*.tmp]"))?;
+        assert_eq!(
+            [count(&summary, "created"), count(&summary, "unchanged")],
+            [0, 2]
+        );
+
+        // Newer sources are copied, while changes of the same size at the destination are only
+        // found by comparing contents
+        harness.write("memory://sync/src/a.txt", "A")?;
+        let summary = harness.eval(&sync("-x [*.tmp]"))?;
+        assert_eq!(count(&summary, "updated"), 1);
+        assert_eq!(harness.read("memory://sync/dst/a.txt")?, "A");
+        harness.write("memory://sync/dst/a.txt", "Z")?;
+        let summary = harness.eval(&sync("-x [*.tmp]"))?;
+        assert_eq!(count(&summary, "updated"), 0);
+        let summary = harness.eval(&sync("-x [*.tmp] --checksum"))?;
+        assert_eq!(
+            [count(&summary, "updated"), count(&summary, "unchanged")],
+            [1, 1]
+        );
+        assert_eq!(harness.read("memory://sync/dst/a.txt")?, "A");
+
+        harness.eval("cloud sync -i [*.tmp] memory://sync/src/ memory://sync/tmp/")?;
+        assert_eq!(harness.names("memory://sync/tmp/")?, ["tmp/b.tmp"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_background_save() -> Result<(), Box<dyn std::error::Error>> {
+        let mut harness = Harness::new()?;
+        let id = harness
+            .eval("'hello' | cloud save --background memory://jobs/a.txt")?
+            .as_int()?;
+        let status = |harness: &mut Harness| -> Result<Value, Box<ShellError>> {
+            harness.eval(&format!("cloud jobs status {id}"))
+        };
+        let mut job = status(&mut harness)?;
+        for _ in 0..100 {
+            if job.get_data_by_key("status") != Some(Value::test_string("running")) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            job = status(&mut harness)?;
+        }
+        assert_eq!(
+            job.get_data_by_key("status"),
+            Some(Value::test_string("completed"))
+        );
+        assert_eq!(job.get_data_by_key("size"), Some(Value::test_filesize(5)));
+        assert_eq!(harness.read("memory://jobs/a.txt")?, "hello");
+
+        // Finished jobs stay as they are
+        let job = harness.eval(&format!("cloud jobs cancel {id}"))?;
+        assert_eq!(
+            job.get_data_by_key("status"),
+            Some(Value::test_string("completed"))
+        );
+        assert_eq!(harness.eval("cloud jobs list")?.into_list()?.len(), 1);
+        assert!(harness.eval("cloud jobs status 42").is_err());
+        assert!(
+            harness
+                .eval("'hello' | cloud save --background --by-hash memory://jobs/cas/")
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_streamed_copy() -> Result<(), Box<dyn std::error::Error>> {
+        // Large enough to be copied in ranges and parts, with ranges of distinct content so
+        // misordered ones show
+        let data: String = (0..20 * 1024 * 1024 + 123)
+            .map(|i: usize| char::from(b'a' + (i / 4096 % 26) as u8))
+            .collect();
+        let dir =
+            std::env::temp_dir().join(format!("nu_plugin_cloud_stream_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let mut harness = Harness::new()?;
+        harness.write("memory://streamed/big.txt", &data)?;
+        harness.write("memory://streamed/sub/small.txt", "small")?;
+
+        let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+            harness.eval(&format!(
+                "cloud cp memory://streamed/big.txt file://{}/big.txt",
+                dir.display()
+            ))?;
+            assert!(std::fs::read_to_string(dir.join("big.txt"))? == data);
+            harness.eval(&format!(
+                "cloud cp --recursive memory://streamed/ file://{}/all/",
+                dir.display()
+            ))?;
+            assert!(std::fs::read_to_string(dir.join("all/big.txt"))? == data);
+            assert_eq!(
+                std::fs::read_to_string(dir.join("all/sub/small.txt"))?,
+                "small"
+            );
+            Ok(())
+        })();
+        std::fs::remove_dir_all(&dir)?;
+        result
+    }
+
+    #[test]
+    fn test_ls_levels() -> Result<(), Box<dyn std::error::Error>> {
+        let mut harness = Harness::new()?;
+        for name in ["a.txt", "sub/b.txt", "sub/deeper/c.txt", "z.txt"] {
+            harness.write(&format!("memory://levels/{name}"), name)?;
+        }
+        let column = |rows: &Value, key: &str| -> Vec<Value> {
+            let rows = rows.as_list().unwrap();
+            rows.iter()
+                .map(|row| row.get_data_by_key(key).unwrap())
+                .collect()
+        };
+
+        // Directories first, then the objects of the level
+        let rows = harness.eval("cloud ls memory://levels/")?;
+        assert_eq!(
+            column(&rows, "name"),
+            ["sub", "a.txt", "z.txt"].map(Value::test_string)
+        );
+        assert_eq!(
+            column(&rows, "type"),
+            ["dir", "file", "file"].map(Value::test_string)
+        );
+        assert_eq!(column(&rows, "size")[0], Value::test_nothing());
+        let rows = harness.eval("cloud ls memory://levels/sub/")?;
+        assert_eq!(
+            column(&rows, "name"),
+            ["sub/deeper", "sub/b.txt"].map(Value::test_string)
+        );
+        let rows = harness.eval("cloud ls --limit 2 memory://levels/")?;
+        assert_eq!(
+            column(&rows, "name"),
+            ["sub", "a.txt"].map(Value::test_string)
+        );
+
+        let rows = harness.eval("cloud ls --recursive memory://levels/")?;
+        assert_eq!(rows.as_list()?.len(), 4);
+
+        // Directories are named so they can be listed in turn
+        let rows = harness.eval("cloud ls memory://levels/ | where type == dir")?;
+        assert_eq!(column(&rows, "name"), [Value::test_string("sub")]);
+        let rows = harness.eval("cloud ls memory://levels/sub/ | where type == dir")?;
+        assert_eq!(column(&rows, "name"), [Value::test_string("sub/deeper")]);
+
+        // Batches skip the directories
+        let rows = harness.eval("cloud ls memory://levels/ | cloud rm memory://levels/")?;
+        assert_eq!(rows.as_list()?.len(), 2);
+        assert_eq!(
+            harness.names("memory://levels/")?,
+            ["sub/b.txt", "sub/deeper/c.txt"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_input() -> Result<(), Box<dyn std::error::Error>> {
+        let mut harness = Harness::new()?;
+        for name in ["a.txt", "b.txt", "sub/c.txt"] {
+            harness.write(&format!("memory://batch/in/{name}"), name)?;
+        }
+        let statuses = |rows: Value| -> Result<Vec<String>, Box<ShellError>> {
+            rows.into_list()?
+                .into_iter()
+                .map(|row| {
+                    Ok(row
+                        .get_data_by_key("status")
+                        .unwrap()
+                        .coerce_into_string()?)
+                })
+                .collect()
+        };
+
+        // Records of cloud ls, with names relative to the bucket
+        let rows = harness.eval(
+            "cloud ls --recursive memory://batch/in/ | cloud cp memory://batch/in/ memory://batch/out/",
+        )?;
+        assert_eq!(statuses(rows.clone())?, ["ok", "ok", "ok"]);
+        let row = &rows.into_list()?[2];
+        assert_eq!(
+            row.get_data_by_key("destination"),
+            Some(Value::test_string("memory://batch/out/sub/c.txt"))
+        );
+        assert_eq!(harness.read("memory://batch/out/sub/c.txt")?, "sub/c.txt");
+
+        let rows = harness.eval(
+            "['in/a.txt' 'in/missing.txt'] | cloud mv memory://batch/in/ memory://batch/moved/",
+        )?;
+        assert_eq!(statuses(rows)?, ["ok", "failed"]);
+        assert_eq!(
+            harness.names("memory://batch/")?,
+            [
+                "in/b.txt",
+                "in/sub/c.txt",
+                "moved/a.txt",
+                "out/a.txt",
+                "out/b.txt",
+                "out/sub/c.txt"
+            ]
+        );
+
+        // Objects outside the url are refused before anything is removed
+        assert!(
+            harness
+                .eval("['in/b.txt' 'out/a.txt'] | cloud rm memory://batch/in/")
+                .is_err()
+        );
+        let rows = harness.eval("['out/a.txt' 'out/b.txt'] | cloud rm -j 1 memory://batch/out/")?;
+        assert_eq!(statuses(rows)?, ["ok", "ok"]);
+        assert_eq!(harness.names("memory://batch/out/")?, ["out/sub/c.txt"]);
+
+        harness.eval("cloud mv memory://batch/in/b.txt memory://batch/in/d.txt")?;
+        assert_eq!(harness.read("memory://batch/in/d.txt")?, "b.txt");
+        assert_eq!(
+            harness.names("memory://batch/in/")?,
+            ["in/d.txt", "in/sub/c.txt"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mv_verify() -> Result<(), Box<dyn std::error::Error>> {
+        // A bucket taking uploads, whose copy of `short.txt` is missing bytes
+        let endpoint = mock_s3(|request| match request.method.as_str() {
+            "HEAD" => {
+                let size = if request.target.ends_with("/short.txt") {
+                    1
+                } else {
+                    5
+                };
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {size}\r\nlast-modified: Wed, 01 Jan 2025 00:00:00 GMT\r\netag: \"copy\"\r\n\r\n"
+                )
+            }
+            _ => "HTTP/1.1 200 OK\r\netag: \"copy\"\r\ncontent-length: 0\r\n\r\n".into(),
+        })?;
+        let mut harness = Harness::new()?.with_config(mock_bucket("s3://moved", &endpoint));
+        for name in ["ok.txt", "short.txt", "batch.txt"] {
+            harness.write(&format!("memory://moving/{name}"), "hello")?;
+        }
+
+        // Sources are removed once their copy in the other bucket matches them
+        harness.eval("cloud mv memory://moving/ok.txt s3://moved/ok.txt")?;
+        assert!(
+            harness
+                .eval("cloud mv memory://moving/short.txt s3://moved/short.txt")
+                .is_err()
+        );
+        let rows = harness
+            .eval("['short.txt' 'batch.txt'] | cloud mv -j 1 memory://moving/ s3://moved/")?;
+        let statuses: Vec<Option<Value>> = rows
+            .into_list()?
+            .iter()
+            .map(|row| row.get_data_by_key("status"))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                Some(Value::test_string("failed")),
+                Some(Value::test_string("ok"))
+            ]
+        );
+        assert_eq!(harness.names("memory://moving/")?, ["short.txt"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rm_older_than() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("nu_plugin_cloud_aged_{}", std::process::id()));
+        let age = |days: u64| {
+            std::time::SystemTime::now() - std::time::Duration::from_secs(days * 24 * 3600)
+        };
+        std::fs::create_dir_all(dir.join("sub"))?;
+        for (name, days) in [
+            ("old.txt", 40),
+            ("new.txt", 1),
+            ("sub/old.log", 40),
+            ("sub/new.log", 0),
+        ] {
+            std::fs::File::create(dir.join(name))?.set_modified(age(days))?;
+        }
+        let mut harness = Harness::new()?;
+
+        let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+            harness.eval(&format!(
+                "cloud rm --older-than 30day 'file://{}/**/*.log'",
+                dir.display()
+            ))?;
+            assert!(!dir.join("sub/old.log").exists());
+            assert!(dir.join("sub/new.log").exists());
+            harness.eval(&format!(
+                "cloud rm -r --older-than 30day file://{}/",
+                dir.display()
+            ))?;
+            assert!(!dir.join("old.txt").exists());
+            assert!(dir.join("new.txt").exists());
+
+            // Single objects and batches aren't listed, so can't be filtered
+            let single = format!(
+                "cloud rm --older-than 30day file://{}/new.txt",
+                dir.display()
+            );
+            assert!(harness.eval(&single).is_err());
+            assert!(dir.join("new.txt").exists());
+            let error = harness
+                .eval(&format!(
+                    "cloud rm -r --older-than 0day file://{}/",
+                    dir.display()
+                ))
+                .unwrap_err();
+            assert!(format!("{error:?}").contains("--older-than must be positive"));
+            assert!(dir.join("new.txt").exists());
+            Ok(())
+        })();
+        std::fs::remove_dir_all(&dir)?;
+        result
+    }
+
+    #[test]
+    fn test_rm_listed() -> Result<(), Box<dyn std::error::Error>> {
+        let mut harness = Harness::new()?;
+        for name in ["a.tmp", "b.txt", "sub/c.tmp"] {
+            harness.write(&format!("memory://listed/{name}"), name)?;
+        }
+
+        // Rows of cloud ls are removed from the url they were listed under
+        let rows = harness
+            .eval(r"cloud ls --recursive memory://listed/ | where name =~ '\.tmp$' | cloud rm")?;
+        assert_eq!(rows.into_list()?.len(), 2);
+        assert_eq!(harness.names("memory://listed/")?, ["b.txt"]);
+        assert!(harness.eval("['b.txt'] | cloud rm").is_err());
+        assert!(harness.eval("cloud rm").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stat() -> Result<(), Box<dyn std::error::Error>> {
+        // A bucket giving the checksum of an object to HEAD requests in checksum mode
+        let endpoint = mock_s3(|request| {
+            let checksum = match request.header("x-amz-checksum-mode") {
+                Some(mode) if mode.eq_ignore_ascii_case("enabled") => {
+                    "x-amz-checksum-crc32c: yZRlqg==\r\n"
+                }
+                _ => "",
+            };
+            format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: 5\r\nlast-modified: Wed, 01 Jan 2025 00:00:00 GMT\r\netag: \"e\"\r\ncontent-type: text/plain\r\n{checksum}\r\n"
+            )
+        })?;
+        let mut harness = Harness::new()?.with_config(mock_bucket("s3://summed", &endpoint));
+        harness.write("memory://stat/hello.txt", "hello")?;
+
+        let stat = harness.eval("cloud stat s3://summed/hello.txt")?;
+        assert_eq!(stat.get_data_by_key("size"), Some(Value::test_filesize(5)));
+        assert_eq!(
+            stat.get_data_by_key("content_type"),
+            Some(Value::test_string("text/plain"))
+        );
+        let checksums = stat.get_data_by_key("checksums").ok_or("checksums")?;
+        assert_eq!(
+            checksums.get_data_by_key("crc32c"),
+            Some(Value::test_string("yZRlqg=="))
+        );
+
+        // Checksums are only computed on request, when the store has none
+        let stat = harness.eval("cloud stat --bytes memory://stat/hello.txt")?;
+        assert_eq!(stat.get_data_by_key("size"), Some(Value::test_int(5)));
+        let checksums = stat.get_data_by_key("checksums").ok_or("checksums")?;
+        assert!(checksums.as_record()?.is_empty());
+        let stat = harness.eval("cloud stat --compute memory://stat/hello.txt")?;
+        let checksums = stat.get_data_by_key("checksums").ok_or("checksums")?;
+        assert_eq!(
+            checksums.get_data_by_key("crc32"),
+            Some(Value::test_string("NhCmhg=="))
+        );
+        assert_eq!(
+            checksums.get_data_by_key("sha256"),
+            Some(Value::test_string(
+                "LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ="
+            ))
+        );
+        assert!(
+            harness
+                .eval("cloud stat memory://stat/missing.txt")
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_presign() -> Result<(), Box<dyn std::error::Error>> {
+        // A bucket starting the uploads it is sent
+        let endpoint = mock_s3(|_| {
+            ok(
+                "<InitiateMultipartUploadResult><UploadId>u1</UploadId></InitiateMultipartUploadResult>",
+            )
+        })?;
+        let mut harness = Harness::new()?.with_config(mock_bucket("s3://signed", &endpoint));
+
+        let url = harness.eval(
+            "cloud presign s3://signed/in/a.csv --method put --content-type text/csv --expires 2hr",
+        )?;
+        let url = url.as_str()?;
+        assert!(url.starts_with(&format!(
+            "{endpoint}/signed/in/a.csv?X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential=key%2F"
+        )));
+        assert!(url.contains(
+            "&X-Amz-Expires=7200&X-Amz-SignedHeaders=content-type%3Bhost&X-Amz-Signature="
+        ));
+
+        // Parts are signed for the upload started for them
+        let upload = harness.eval("cloud presign s3://signed/in/big.tar --parts 3")?;
+        assert_eq!(
+            upload.get_data_by_key("upload_id"),
+            Some(Value::test_string("u1"))
+        );
+        let parts = upload
+            .get_data_by_key("parts")
+            .ok_or("parts")?
+            .into_list()?;
+        assert_eq!(parts.len(), 3);
+        assert!(
+            parts[1]
+                .as_str()?
+                .contains("?X-Amz-Algorithm=AWS4-HMAC-SHA256&")
+        );
+        assert!(parts[1].as_str()?.contains("&partNumber=2&uploadId=u1"));
+
+        assert!(
+            harness
+                .eval("cloud presign s3://signed/a.csv --method delete")
+                .is_err()
+        );
+        assert!(
+            harness
+                .eval("cloud presign s3://signed/a.csv --expires 8day")
+                .is_err()
+        );
+        assert!(
+            harness
+                .eval("cloud presign s3://signed/a.csv --content-type text/csv")
+                .is_err()
+        );
+        assert!(harness.eval("cloud presign memory://signed/a.csv").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_storage_class() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::{Arc, Mutex};
+
+        // A bucket recording the storage class of each request and whether it was signed
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&requests);
+        let endpoint = mock_s3(move |request| {
+            let storage_class = request.header("x-amz-storage-class").map(|storage_class| {
+                let signed = request
+                    .header("authorization")
+                    .is_some_and(|auth| auth.contains("x-amz-storage-class"));
+                format!(" {storage_class} signed={signed}")
+            });
+            received.lock().unwrap().push(format!(
+                "{} {}?{}{}",
+                request.method,
+                request.path(),
+                request.operation(),
+                storage_class.unwrap_or_default()
+            ));
+            accept_writes(request)
+        })?;
+        let config = record!(
+            "buckets" => Value::test_record(record!(
+                "s3://archive" => Value::test_record(record!(
+                    "endpoint" => Value::test_string(endpoint),
+                    "region" => Value::test_string("us-east-1"),
+                    "access_key_id" => Value::test_string("key"),
+                    "secret_access_key" => Value::test_string("secret"),
+                    "storage_class" => Value::test_string("GLACIER"),
+                )),
+            )),
+            "upload_part_size" => Value::test_filesize(5 * 1024 * 1024),
+        );
+        let mut harness = Harness::new()?.with_config(config);
+
+        // Only writes of objects set the storage class, covered by their signature
+        harness.eval("'a' | cloud save s3://archive/a.txt")?;
+        let data = vec![0; 6 * 1024 * 1024];
+        let stream = ByteStream::read_binary(data, Span::test_data(), Signals::empty());
+        harness.plugin_test.eval_with(
+            "cloud save s3://archive/big.bin",
+            PipelineData::ByteStream(stream, None),
+        )?;
+        harness.eval("cloud ls s3://archive/")?;
+        harness.eval("cloud rm s3://archive/a.txt")?;
+        let mut requests = requests.lock().unwrap().clone();
+        requests.sort();
+        assert_eq!(
+            requests,
+            [
+                "DELETE /archive/a.txt?",
+                "GET /archive?delimiter=%2F",
+                "POST /archive/big.bin?uploadId=u1",
+                "POST /archive/big.bin?uploads= GLACIER signed=true",
+                "PUT /archive/a.txt? GLACIER signed=true",
+                "PUT /archive/big.bin?partNumber=1",
+                "PUT /archive/big.bin?partNumber=2",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rate_limit_parts() -> Result<(), Box<dyn std::error::Error>> {
+        use std::{
+            sync::{Arc, Mutex},
+            time::Instant,
+        };
+
+        // A bucket recording when the parts of uploads arrive
+        let parts = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&parts);
+        let endpoint = mock_s3(move |request| {
+            if request.operation().starts_with("partNumber=") {
+                received.lock().unwrap().push(Instant::now());
+            }
+            accept_writes(request)
+        })?;
+        let mut config = mock_bucket("s3://limited", &endpoint);
+        config.push("upload_part_size", Value::test_filesize(5 * 1024 * 1024));
+        let mut harness = Harness::new()?.with_config(config);
+
+        // Parts are uploaded concurrently, yet spaced like the other requests of the run
+        let data = vec![0; 6 * 1024 * 1024];
+        let stream = ByteStream::read_binary(data, Span::test_data(), Signals::empty());
+        harness.plugin_test.eval_with(
+            "cloud save s3://limited/big.bin --rate-limit 4",
+            PipelineData::ByteStream(stream, None),
+        )?;
+        let parts = parts.lock().unwrap();
+        assert_eq!(parts.len(), 2);
+        let apart = parts[1].duration_since(parts[0]);
+        assert!(apart >= std::time::Duration::from_millis(200), "{apart:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_stored_checksums() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::{Arc, Mutex};
+
+        // A bucket keeping the SHA-256 of its objects, which are never downloaded
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&requests);
+        let endpoint = mock_s3(move |request| {
+            received
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", request.method, request.target));
+            match request.method.as_str() {
+                // The SHA-256 of `hello`
+                "HEAD" => "HTTP/1.1 200 OK\r\ncontent-length: 5\r\nlast-modified: Wed, 01 Jan 2030 00:00:00 GMT\r\netag: \"e\"\r\nx-amz-checksum-sha256: LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=\r\n\r\n".into(),
+                "GET" => {
+                    let contents: String = ["same.txt", "changed.txt"]
+                        .map(|key| format!("<Contents><Key>dst/{key}</Key><LastModified>2030-01-01T00:00:00.000Z</LastModified><ETag>\"e\"</ETag><Size>5</Size></Contents>"))
+                        .concat();
+                    ok(&format!(
+                        "<ListBucketResult><IsTruncated>false</IsTruncated>{contents}</ListBucketResult>"
+                    ))
+                }
+                _ => "HTTP/1.1 200 OK\r\netag: \"e\"\r\ncontent-length: 0\r\n\r\n".into(),
+            }
+        })?;
+        let mut harness = Harness::new()?.with_config(mock_bucket("s3://summed", &endpoint));
+        harness.write("memory://summing/same.txt", "hello")?;
+        harness.write("memory://summing/changed.txt", "hullo")?;
+
+        // Both are newer at the destination, but one differs
+        let summary = harness.eval("cloud sync --checksum memory://summing/ s3://summed/dst/")?;
+        assert_eq!(summary.get_data_by_key("updated"), Some(Value::test_int(1)));
+        assert_eq!(
+            summary.get_data_by_key("unchanged"),
+            Some(Value::test_int(1))
+        );
+        let requests = requests.lock().unwrap();
+        assert!(
+            requests
+                .iter()
+                .all(|request| !request.starts_with("GET /summed/dst/"))
+        );
+        assert!(requests.contains(&"PUT /summed/dst/changed.txt".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_update() -> Result<(), Box<dyn std::error::Error>> {
+        let dir =
+            std::env::temp_dir().join(format!("nu_plugin_cloud_update_{}", std::process::id()));
+        let age = |days: u64| {
+            std::time::SystemTime::now() - std::time::Duration::from_secs(days * 24 * 3600)
+        };
+        std::fs::create_dir_all(dir.join("src"))?;
+        std::fs::create_dir_all(dir.join("dst"))?;
+        for name in ["a.txt", "b.txt"] {
+            std::fs::write(dir.join("src").join(name), name)?;
+            std::fs::File::options()
+                .write(true)
+                .open(dir.join("src").join(name))?
+                .set_modified(age(10))?;
+        }
+        let mut harness = Harness::new()?;
+
+        let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+            let (src, dst) = (dir.join("src"), dir.join("dst"));
+            let recursive = format!(
+                "cloud cp -r --update file://{}/ file://{}/",
+                src.display(),
+                dst.display()
+            );
+            harness.eval(&recursive)?;
+            assert_eq!(std::fs::read_to_string(dst.join("a.txt"))?, "a.txt");
+
+            // Copies changed since the source are kept, and older ones replaced. Local copies
+            // are hard links, so they are replaced rather than written through.
+            for (name, contents) in [("a.txt", "changed at the destination"), ("b.txt", "stale")] {
+                std::fs::remove_file(dst.join(name))?;
+                std::fs::write(dst.join(name), contents)?;
+            }
+            std::fs::File::options()
+                .write(true)
+                .open(dst.join("b.txt"))?
+                .set_modified(age(20))?;
+            harness.eval(&recursive)?;
+            assert_eq!(
+                std::fs::read_to_string(dst.join("a.txt"))?,
+                "changed at the destination"
+            );
+            assert_eq!(std::fs::read_to_string(dst.join("b.txt"))?, "b.txt");
+
+            let single = |update: &str| {
+                format!(
+                    "cloud cp {update} file://{}/a.txt file://{}/a.txt",
+                    src.display(),
+                    dst.display()
+                )
+            };
+            harness.eval(&single("--update"))?;
+            assert_eq!(
+                std::fs::read_to_string(dst.join("a.txt"))?,
+                "changed at the destination"
+            );
+
+            // Sync keeps newer objects at the destination even of another size
+            let sync = |update: &str| {
+                format!(
+                    "cloud sync {update} file://{}/ file://{}/",
+                    src.display(),
+                    dst.display()
+                )
+            };
+            assert_eq!(
+                harness.eval(&sync("--update"))?.get_data_by_key("updated"),
+                Some(Value::test_int(0))
+            );
+            assert_eq!(
+                std::fs::read_to_string(dst.join("a.txt"))?,
+                "changed at the destination"
+            );
+            assert_eq!(
+                harness.eval(&sync(""))?.get_data_by_key("updated"),
+                Some(Value::test_int(1))
+            );
+            assert_eq!(std::fs::read_to_string(dst.join("a.txt"))?, "a.txt");
+
+            std::fs::remove_file(dst.join("a.txt"))?;
+            std::fs::write(dst.join("a.txt"), "changed again")?;
+            harness.eval(&single("--update"))?;
+            assert_eq!(std::fs::read_to_string(dst.join("a.txt"))?, "changed again");
+            std::fs::remove_file(dst.join("a.txt"))?;
+            harness.eval(&single("--update"))?;
+            assert_eq!(std::fs::read_to_string(dst.join("a.txt"))?, "a.txt");
+            Ok(())
+        })();
+        std::fs::remove_dir_all(&dir)?;
+        result
+    }
+
+    #[test]
+    fn test_sync_digest_cache() -> Result<(), Box<dyn std::error::Error>> {
+        let dir =
+            std::env::temp_dir().join(format!("nu_plugin_cloud_digests_{}", std::process::id()));
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        let write = |file: &std::path::Path, contents: &str, days: u64| {
+            std::fs::write(file, contents)?;
+            let modified =
+                std::time::SystemTime::now() - std::time::Duration::from_secs(days * 24 * 3600);
+            std::fs::File::options()
+                .write(true)
+                .open(file)?
+                .set_modified(modified)
+        };
+        std::fs::create_dir_all(&src)?;
+        std::fs::create_dir_all(&dst)?;
+        write(&src.join("a.txt"), "same", 2)?;
+        write(&dst.join("a.txt"), "same", 1)?;
+        let mut harness = Harness::new()?;
+
+        let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+            let sync = format!(
+                "cloud sync --checksum file://{}/ file://{}/",
+                src.display(),
+                dst.display()
+            );
+            let updated = |summary: Value| summary.get_data_by_key("updated");
+            assert_eq!(updated(harness.eval(&sync)?), Some(Value::test_int(0)));
+
+            // Digests are reused while the size and modification time of a file are the same
+            let modified = std::fs::metadata(dst.join("a.txt"))?.modified()?;
+            let touch = |modified| {
+                std::fs::File::options()
+                    .write(true)
+                    .open(dst.join("a.txt"))?
+                    .set_modified(modified)
+            };
+            std::fs::write(dst.join("a.txt"), "diff")?;
+            touch(modified)?;
+            assert_eq!(updated(harness.eval(&sync)?), Some(Value::test_int(0)));
+            touch(modified - std::time::Duration::from_secs(60))?;
+            assert_eq!(updated(harness.eval(&sync)?), Some(Value::test_int(1)));
+            assert_eq!(std::fs::read_to_string(dst.join("a.txt"))?, "same");
+            Ok(())
+        })();
+        std::fs::remove_dir_all(&dir)?;
+        result
+    }
+
+    #[test]
+    fn test_du() -> Result<(), Box<dyn std::error::Error>> {
+        let mut harness = Harness::new()?;
+        for (name, contents) in [
+            ("logs/2024/01/a.gz", "aaaa"),
+            ("logs/2024/02/b.gz", "bb"),
+            ("logs/2025/01/c.gz", "c"),
+            ("logs/index.txt", "index"),
+            ("readme.txt", "hi"),
+        ] {
+            harness.write(&format!("memory://usage/{name}"), contents)?;
+        }
+        let column = |rows: &Value, key: &str| -> Vec<Value> {
+            let rows = rows.as_list().unwrap();
+            rows.iter()
+                .map(|row| row.get_data_by_key(key).unwrap())
+                .collect()
+        };
+
+        let rows = harness.eval("cloud du --bytes memory://usage/")?;
+        assert_eq!(
+            column(&rows, "name"),
+            ["logs", "readme.txt"].map(Value::test_string)
+        );
+        assert_eq!(
+            column(&rows, "type"),
+            ["dir", "file"].map(Value::test_string)
+        );
+        assert_eq!(column(&rows, "objects"), [4, 1].map(Value::test_int));
+        assert_eq!(column(&rows, "size"), [12, 2].map(Value::test_int));
+
+        let rows = harness.eval("cloud du --bytes --depth 2 memory://usage/logs/")?;
+        assert_eq!(
+            column(&rows, "name"),
+            [
+                "logs/2024/01",
+                "logs/2024/02",
+                "logs/2025/01",
+                "logs/index.txt"
+            ]
+            .map(Value::test_string)
+        );
+        assert_eq!(column(&rows, "size"), [4, 2, 1, 5].map(Value::test_int));
+
+        let rows = harness.eval("cloud du --bytes --depth 0 memory://usage/")?;
+        assert_eq!(column(&rows, "objects"), [Value::test_int(5)]);
+        assert_eq!(column(&rows, "size"), [Value::test_int(14)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find() -> Result<(), Box<dyn std::error::Error>> {
+        let mut harness = Harness::new()?;
+        for (name, contents) in [
+            ("data/a.parquet", "aaaaaaaaaa"),
+            ("data/b.parquet", "b"),
+            ("data/c.csv", "cccccccccc"),
+            ("data/empty.parquet", ""),
+        ] {
+            harness.write(&format!("memory://search/{name}"), contents)?;
+        }
+        let found =
+            |harness: &mut Harness, filters: &str| -> Result<Vec<String>, Box<ShellError>> {
+                harness
+                    .eval(&format!("cloud find memory://search/ {filters}"))?
+                    .into_list()?
+                    .into_iter()
+                    .map(|row| Ok(row.get_data_by_key("name").unwrap().coerce_into_string()?))
+                    .collect()
+            };
+
+        assert_eq!(
+            found(&mut harness, r"--regex '\.parquet$' --min-size 1B")?,
+            ["data/a.parquet", "data/b.parquet"]
+        );
+        assert_eq!(
+            found(&mut harness, "--min-size 2B --max-size 10B")?,
+            ["data/a.parquet", "data/c.csv"]
+        );
+        assert_eq!(found(&mut harness, "--after 2020-01-01")?.len(), 4);
+        assert!(found(&mut harness, "--before 2020-01-01")?.is_empty());
+        assert!(found(&mut harness, "--regex '['").is_err());
+        assert!(found(&mut harness, "--storage-class standard").is_err());
+
+        // Matches are removed from the url they were found under
+        harness.eval("cloud find memory://search/ --max-size 0B | cloud rm")?;
+        assert_eq!(found(&mut harness, "")?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_storage_class() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::{Arc, Mutex};
+
+        // A bucket listing its objects with their storage classes in two pages, recording the
+        // requests it is sent
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&requests);
+        let endpoint = mock_s3(move |request| {
+            received
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", request.method, request.target));
+            let object = |key: &str, class: &str| {
+                format!(
+                    "<Contents><Key>{key}</Key><LastModified>2025-01-01T00:00:00.000Z</LastModified><ETag>\"e\"</ETag><Size>5</Size>{class}</Contents>"
+                )
+            };
+            let page = match request.target.contains("continuation-token=next") {
+                false => format!(
+                    "<IsTruncated>true</IsTruncated><NextContinuationToken>next</NextContinuationToken>{}{}",
+                    object("logs/a.gz", "<StorageClass>GLACIER</StorageClass>"),
+                    object("logs/b.gz", ""),
+                ),
+                true => format!(
+                    "<IsTruncated>false</IsTruncated>{}",
+                    object("logs/c.gz", "<StorageClass>GLACIER</StorageClass>"),
+                ),
+            };
+            ok(&format!("<ListBucketResult>{page}</ListBucketResult>"))
+        })?;
+        let mut harness = Harness::new()?.with_config(mock_bucket("s3://archive", &endpoint));
+
+        // Classes come from the listing, without a request for each object
+        let found = harness.eval("cloud find s3://archive/logs/ --storage-class glacier")?;
+        let found = found.as_list()?;
+        let names: Vec<Value> = found
+            .iter()
+            .map(|row| row.get_data_by_key("name").unwrap())
+            .collect();
+        assert_eq!(names, ["logs/a.gz", "logs/c.gz"].map(Value::test_string));
+        assert_eq!(
+            found[0].get_data_by_key("storage_class"),
+            Some(Value::test_string("GLACIER"))
+        );
+        let standard = harness.eval("cloud find s3://archive/logs/ --storage-class standard")?;
+        assert_eq!(standard.as_list()?.len(), 1);
+        let requests = requests.lock().unwrap();
+        assert!(requests.iter().all(|request| request.starts_with("GET ")));
+        assert_eq!(requests.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_owners() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::{Arc, Mutex};
+
+        // A bucket listing one object with its owner and a deeper prefix
+        let listings = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&listings);
+        let endpoint = mock_s3(move |request| match request.method.as_str() {
+            "HEAD" => "HTTP/1.1 200 OK\r\ncontent-length: 5\r\netag: \"e\"\r\n\
+                 last-modified: Wed, 01 Jan 2025 00:00:00 GMT\r\n\r\n"
+                .into(),
+            _ => {
+                received.lock().unwrap().push(request.target.clone());
+                ok("<ListBucketResult><IsTruncated>false</IsTruncated>\
+                    <Contents><Key>logs/a.gz</Key><LastModified>2025-01-01T00:00:00.000Z</LastModified>\
+                    <ETag>\"e\"</ETag><Size>5</Size><Owner><ID>123abc</ID></Owner></Contents>\
+                    <CommonPrefixes><Prefix>logs/2025/</Prefix></CommonPrefixes></ListBucketResult>")
+            }
+        })?;
+        let mut harness = Harness::new()?.with_config(mock_bucket("s3://owned", &endpoint));
+
+        // Owners come from the listing, asked for with fetch-owner
+        let listing = harness
+            .eval("cloud ls --long s3://owned/logs/")?
+            .into_list()?;
+        assert_eq!(listing.len(), 2);
+        assert_eq!(
+            listing[0].get_data_by_key("name"),
+            Some(Value::test_string("logs/2025"))
+        );
+        assert_eq!(
+            listing[0].get_data_by_key("owner"),
+            Some(Value::test_nothing())
+        );
+        assert_eq!(
+            listing[1].get_data_by_key("owner"),
+            Some(Value::test_string("123abc"))
+        );
+        let listings = listings.lock().unwrap();
+        assert_eq!(listings.len(), 1);
+        assert!(listings[0].contains("&delimiter=%2F&fetch-owner=true"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch() -> Result<(), Box<dyn std::error::Error>> {
+        let dir =
+            std::env::temp_dir().join(format!("nu_plugin_cloud_watch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("existing.txt"), "existing")?;
+        let changes = {
+            let dir = dir.clone();
+            std::thread::spawn(move || -> std::io::Result<()> {
+                let pause = || std::thread::sleep(std::time::Duration::from_millis(300));
+                pause();
+                std::fs::write(dir.join("a.txt"), "a")?;
+                pause();
+                std::fs::write(dir.join("a.txt"), "changed")?;
+                pause();
+                std::fs::remove_file(dir.join("a.txt"))
+            })
+        };
+        let mut harness = Harness::new()?;
+
+        let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+            // Objects there when the watch starts aren't changes
+            let events = harness.eval(&format!(
+                "cloud watch file://{}/ --interval 50ms --idle 1sec",
+                dir.display()
+            ))?;
+            let events: Vec<(Option<Value>, Option<Value>)> = events
+                .into_list()?
+                .into_iter()
+                .map(|event| (event.get_data_by_key("event"), event.get_data_by_key("url")))
+                .collect();
+            let url = format!("file://{}/a.txt", dir.display());
+            assert_eq!(
+                events,
+                ["created", "modified", "deleted"].map(|event| (
+                    Some(Value::test_string(event)),
+                    Some(Value::test_string(&url))
+                ))
+            );
+            Ok(())
+        })();
+        changes.join().unwrap()?;
+        std::fs::remove_dir_all(&dir)?;
+        result
+    }
+
+    #[test]
+    fn test_open_lines() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::{Arc, Mutex};
+
+        // A server with a large CSV file of 16 byte lines, recording the ranges read of it
+        let data: String = std::iter::once("a,b\n".to_string())
+            .chain((0..100_000).map(|i| format!("{i:07},{i:07}\n")))
+            .collect();
+        let ranges = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&ranges);
+        let url = format!(
+            "{}/data.csv",
+            mock_s3(move |request| {
+                let range = request
+                    .header("range")
+                    .and_then(|range| range.strip_prefix("bytes=")?.split_once('-'))
+                    .map(|(start, end)| {
+                        (
+                            start.parse::<usize>().unwrap(),
+                            end.parse::<usize>().unwrap(),
+                        )
+                    });
+                let headers = "etag: \"1\"\r\nlast-modified: Tue, 15 Nov 1994 08:12:31 GMT";
+                match (request.method.as_str(), range) {
+                    ("HEAD", _) => format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n{headers}\r\n\r\n",
+                        data.len()
+                    ),
+                    (_, Some((start, end))) => {
+                        received.lock().unwrap().push((start, end));
+                        let body = &data[start..=end.min(data.len() - 1)];
+                        format!(
+                            "HTTP/1.1 206 Partial Content\r\ncontent-length: {}\r\ncontent-range: bytes {start}-{}/{}\r\n{headers}\r\n\r\n{body}",
+                            body.len(),
+                            start + body.len() - 1,
+                            data.len()
+                        )
+                    }
+                    (_, None) => format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n{headers}\r\n\r\n{data}",
+                        data.len()
+                    ),
+                }
+            })?
+        );
+        let mut harness = Harness::new()?;
+
+        // The header and two rows, from the first range
+        let rows = harness.eval(&format!("cloud open {url} --lines 3"))?;
+        assert_eq!(rows.as_list()?.len(), 2);
+        assert_eq!(
+            rows.as_list()?[1].get_data_by_key("b"),
+            Some(Value::test_int(1))
+        );
+        assert_eq!(*ranges.lock().unwrap(), [(0, 65535)]);
+
+        // Ranges double until they have the lines
+        ranges.lock().unwrap().clear();
+        let lines = harness.eval(&format!("cloud open --raw {url} --lines 20000"))?;
+        assert_eq!(lines.coerce_into_binary()?.len(), 4 + 19_999 * 16);
+        assert_eq!(
+            *ranges.lock().unwrap(),
+            [(0, 65535), (65536, 196607), (196608, 458751)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_follow() -> Result<(), Box<dyn std::error::Error>> {
+        use std::io::Write;
+
+        let dir =
+            std::env::temp_dir().join(format!("nu_plugin_cloud_follow_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let log = dir.join("app.log");
+        std::fs::write(&log, "one\n")?;
+        let appends = {
+            let log = log.clone();
+            std::thread::spawn(move || -> std::io::Result<()> {
+                for line in ["two\n", "three\n"] {
+                    std::thread::sleep(std::time::Duration::from_millis(300));
+                    std::fs::OpenOptions::new()
+                        .append(true)
+                        .open(&log)?
+                        .write_all(line.as_bytes())?;
+                }
+                Ok(())
+            })
+        };
+        let mut harness = Harness::new()?;
+
+        let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+            // The object is read whole, then what is appended to it until it stops growing
+            let url = format!("file://{}", log.display());
+            let bytes = harness.eval(&format!(
+                "cloud open --raw --follow {url} --interval 50ms --idle 1sec"
+            ))?;
+            assert_eq!(bytes.coerce_into_binary()?, b"one\ntwo\nthree\n");
+
+            let error = harness
+                .eval(&format!("cloud open --follow {url}"))
+                .unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .contains("Only raw objects can be followed")
+            );
+            Ok(())
+        })();
+        appends.join().unwrap()?;
+        std::fs::remove_dir_all(&dir)?;
+        result
+    }
+
+    #[test]
+    fn test_config_overridden_by_flags() -> Result<(), Box<dyn std::error::Error>> {
+        let mut harness = Harness::new()?;
+        harness.write("memory://settings/a.txt", "a")?;
+        let mut harness = harness.with_config(record! {
+            "bwlimit" => Value::test_string("fast"),
+        });
+
+        // The config applies without the flag, which overrides it
+        let error = harness
+            .eval("cloud open --raw memory://settings/a.txt")
+            .unwrap_err();
+        // Plugin errors come back labeled, with the message of the invalid value as a label
+        assert!(format!("{error:?}").contains("Invalid rate fast"));
+        let bytes = harness.eval("cloud open --raw memory://settings/a.txt --bwlimit 1MB")?;
+        assert_eq!(bytes.coerce_into_binary()?, b"a");
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_defaults() -> Result<(), Box<dyn std::error::Error>> {
+        let mut harness = Harness::new()?;
+        harness.write("memory://defaults/a.txt", "a")?;
+        harness.write("memory://defaults/b.txt", "b")?;
+        let mut harness = harness.with_config(record! {
+            "ls" => Value::test_record(record! {
+                "limit" => Value::test_int(1),
+                "bytes" => Value::test_bool(true),
+            }),
+            "rm" => Value::test_record(record! {
+                "no_such_flag" => Value::test_bool(true),
+            }),
+            "save" => Value::test_record(record! {
+                "part_size" => Value::test_filesize(16 * 1024 * 1024),
+            }),
+        });
+
+        // Flags default to the settings of the command, and given flags override them
+        let listing = harness.eval("cloud ls memory://defaults/")?.into_list()?;
+        assert_eq!(listing.len(), 1);
+        assert_eq!(listing[0].get_data_by_key("size"), Some(Value::test_int(1)));
+        let listing = harness.eval("cloud ls memory://defaults/ --limit 5")?;
+        assert_eq!(listing.into_list()?.len(), 2);
+
+        harness.eval("'c' | cloud save memory://defaults/c.txt")?;
+        assert_eq!(harness.read("memory://defaults/c.txt")?, "c");
+        let error = harness
+            .eval("'d' | cloud save memory://defaults/d.txt --part-size 0b")
+            .unwrap_err();
+        assert!(format!("{error:?}").contains("--part-size must be positive"));
+
+        let error = harness
+            .eval("cloud rm memory://defaults/a.txt")
+            .unwrap_err();
+        assert!(format!("{error:?}").contains("cloud rm has no --no-such-flag flag"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_credentials_refresh() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::{Arc, Mutex};
+
+        // A bucket with one object, recording the access keys requests are signed with
+        let keys = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&keys);
+        let endpoint = mock_s3(move |request| {
+            let key = request
+                .header("authorization")
+                .and_then(|auth| auth.split_once("Credential=")?.1.split_once('/'))
+                .map(|(key, _)| key.to_string());
+            received.lock().unwrap().extend(key);
+            let body = match request.method.as_str() {
+                "HEAD" => "",
+                _ => "hello",
+            };
+            format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: 5\r\netag: \"1\"\r\n\
+                 last-modified: Tue, 15 Nov 1994 08:12:31 GMT\r\n\r\n{body}"
+            )
+        })?;
+        let mut harness = Harness::new()?;
+        harness.plugin_test.add_decl(Box::new(RandomUuid))?;
+
+        // Expired credentials are resolved again for every request of a command
+        let data = harness.eval(&format!(
+            r#"$env.config.plugins.cloud = {{
+                buckets: {{
+                    "s3://rotating": {{
+                        endpoint: "{endpoint}"
+                        region: us-east-1
+                        credentials: {{|url| {{
+                            access_key_id: (random uuid)
+                            secret_access_key: secret
+                            expiry: 2000-01-01
+                        }} }}
+                    }}
+                }}
+            }}
+            cloud open --raw s3://rotating/a.txt"#
+        ))?;
+        assert_eq!(data.coerce_into_binary()?, b"hello");
+        let keys = keys.lock().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_ne!(keys[0], keys[1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cost_estimate() -> Result<(), Box<dyn std::error::Error>> {
+        // Costs equal to the number of requests and bytes
+        let mut harness = Harness::new()?.with_config(record!(
+            "pricing" => Value::test_record(record!(
+                "memory" => Value::test_record(record!(
+                    "egress_per_gib" => Value::test_float(1073741824.0),
+                    "per_1000_requests" => Value::test_record(record!(
+                        "GET" => Value::test_float(1000.0),
+                    )),
+                )),
+            )),
+        ));
+        harness.write("memory://cost/data/a.txt", "aa")?;
+        harness.write("memory://cost/data/sub/b.txt", "bbb")?;
+        let cost = |estimate: &Value, key: &str| {
+            let row = estimate.as_list().unwrap()[0].clone();
+            row.get_data_by_key(key).unwrap()
+        };
+
+        // A GET for each object and one for the listing
+        let estimate = harness.eval("cloud cost estimate memory://cost/data/")?;
+        assert_eq!(cost(&estimate, "provider"), Value::test_string("memory"));
+        assert_eq!(cost(&estimate, "request_cost"), Value::test_float(3.0));
+        assert_eq!(cost(&estimate, "transfer_cost"), Value::test_float(5.0));
+        assert_eq!(cost(&estimate, "cost"), Value::test_float(8.0));
+
+        // Uploads of the input, with unpriced PUT requests and ingress
+        let estimate = harness.eval(
+            "[[name size]; [a 10b] [b 20b]] | cloud cost estimate --upload memory://cost/data/",
+        )?;
+        assert_eq!(cost(&estimate, "bytes_uploaded"), Value::test_filesize(30));
+        assert_eq!(cost(&estimate, "cost"), Value::test_float(0.0));
+        assert!(
+            harness
+                .eval("cloud cost estimate --upload memory://cost/data/")
+                .is_err()
+        );
+
+        // Providers without prices have no costs
+        let estimate =
+            harness.eval("[[size]; [1kb]] | cloud cost estimate https://example.com/")?;
+        assert_eq!(cost(&estimate, "cost"), Value::test_nothing());
+        Ok(())
+    }
+
+    #[test]
+    fn test_versions() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::{Arc, Mutex};
+
+        // A versioned bucket answering listings with the same versions, and recording the
+        // copies and deletes it is sent
+        let listing = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListVersionsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+<IsTruncated>false</IsTruncated>
+<Version><Key>docs/a.txt</Key><VersionId>v3</VersionId><IsLatest>true</IsLatest><LastModified>2025-03-01T00:00:00.000Z</LastModified><Size>3</Size></Version>
+<Version><Key>docs/a.txt</Key><VersionId>v2</VersionId><IsLatest>false</IsLatest><LastModified>2025-02-01T00:00:00.000Z</LastModified><Size>2</Size></Version>
+<Version><Key>docs/a.txt</Key><VersionId>v1</VersionId><IsLatest>false</IsLatest><LastModified>2025-01-01T00:00:00.000Z</LastModified><Size>1</Size></Version>
+<Version><Key>docs/a.txt.bak</Key><VersionId>v4</VersionId><IsLatest>true</IsLatest><LastModified>2025-01-01T00:00:00.000Z</LastModified><Size>1</Size></Version>
+<DeleteMarker><Key>docs/b.txt</Key><VersionId>m1</VersionId><IsLatest>true</IsLatest><LastModified>2025-02-01T00:00:00.000Z</LastModified></DeleteMarker>
+<Version><Key>docs/b.txt</Key><VersionId>v5</VersionId><IsLatest>false</IsLatest><LastModified>2025-01-01T00:00:00.000Z</LastModified><Size>5</Size></Version>
+</ListVersionsResult>"#;
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&requests);
+        let endpoint = mock_s3(move |request| {
+            let mut line = format!("{} {}", request.method, request.target);
+            if let Some(source) = request.header("x-amz-copy-source") {
+                line = format!("{line} from {source}");
+            }
+            received.lock().unwrap().push(line);
+            match request.method.as_str() {
+                "GET" => ok(listing),
+                "DELETE" => "HTTP/1.1 204 No Content\r\n\r\n".into(),
+                _ => ok(""),
+            }
+        })?;
+        let mut harness = Harness::new()?.with_config(mock_bucket("s3://versioned", &endpoint));
+        let column = |rows: &Value, key: &str| -> Vec<Value> {
+            let rows = rows.as_list().unwrap();
+            rows.iter()
+                .map(|row| row.get_data_by_key(key).unwrap())
+                .collect()
+        };
+
+        // Versions of the object itself, not of others starting with its name
+        let versions = harness.eval("cloud versions list s3://versioned/docs/a.txt")?;
+        assert_eq!(
+            column(&versions, "version_id"),
+            ["v3", "v2", "v1"].map(Value::test_string)
+        );
+        assert_eq!(
+            column(&versions, "latest"),
+            [true, false, false].map(Value::test_bool)
+        );
+
+        // Listings from the versions include deleted keys, with their histories
+        let keys = harness.eval("cloud ls --versions s3://versioned/docs/")?;
+        assert_eq!(
+            column(&keys, "name"),
+            ["docs/a.txt", "docs/a.txt.bak", "docs/b.txt"].map(Value::test_string)
+        );
+        assert_eq!(column(&keys, "versions"), [3, 1, 2].map(Value::test_int));
+        assert_eq!(
+            column(&keys, "deleted"),
+            [false, false, true].map(Value::test_bool)
+        );
+        let dirs = harness.eval("cloud ls --versions s3://versioned/")?;
+        assert_eq!(column(&dirs, "name"), [Value::test_string("docs")]);
+        assert_eq!(column(&dirs, "type"), [Value::test_string("dir")]);
+
+        harness.eval("cloud versions restore s3://versioned/docs/a.txt --version-id v1")?;
+        assert!(requests.lock().unwrap().contains(
+            &"PUT /versioned/docs/a.txt from /versioned/docs/a.txt?versionId=v1".to_string()
+        ));
+        assert!(
+            harness
+                .eval("cloud versions restore s3://versioned/docs/a.txt")
+                .is_err()
+        );
+
+        // Rollbacks restore the version before the latest by default, or the object removed
+        requests.lock().unwrap().clear();
+        harness.eval("cloud rollback s3://versioned/docs/a.txt")?;
+        harness.eval("cloud rollback s3://versioned/docs/b.txt")?;
+        harness.eval("cloud rollback s3://versioned/docs/a.txt --to-version v1")?;
+        let copies: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.starts_with("PUT"))
+            .cloned()
+            .collect();
+        assert_eq!(
+            copies,
+            [
+                "PUT /versioned/docs/a.txt from /versioned/docs/a.txt?versionId=v2",
+                "PUT /versioned/docs/b.txt from /versioned/docs/b.txt?versionId=v5",
+                "PUT /versioned/docs/a.txt from /versioned/docs/a.txt?versionId=v1",
+            ]
+        );
+        assert!(
+            harness
+                .eval("cloud rollback s3://versioned/docs/a.txt.bak")
+                .is_err()
+        );
+
+        // The newest version of each object is kept, counting delete markers
+        let purged = harness.eval("cloud versions purge s3://versioned/docs/ --keep-last 1")?;
+        assert_eq!(
+            column(&purged, "version_id"),
+            ["v2", "v1", "v5"].map(Value::test_string)
+        );
+        assert_eq!(
+            column(&purged, "url")[2],
+            Value::test_string("s3://versioned/docs/b.txt")
+        );
+        let deletes = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.starts_with("DELETE"))
+            .count();
+        assert_eq!(deletes, 3);
+        assert!(
+            harness
+                .eval("cloud versions purge s3://versioned/docs/ --keep-last 0")
+                .is_err()
+        );
+        assert!(
+            harness
+                .eval("cloud versions list memory://versioned/docs/")
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_events() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        };
+
+        // A queue with the notifications of one upload, a removal, an object outside the
+        // prefix, and an SNS wrapped upload, then nothing more
+        let record = |event: &str, key: &str| {
+            format!(
+                r#"{{"eventName":"{event}","eventTime":"2025-01-01T00:00:00.000Z","s3":{{"bucket":{{"name":"notified"}},"object":{{"key":"{key}","size":3,"eTag":"abc"}}}}}}"#
+            )
+        };
+        let notification = |records: &[String]| format!(r#"{{"Records":[{}]}}"#, records.join(","));
+        let wrapped = serde_json::json!({
+            "Type": "Notification",
+            "Message": notification(&[record("ObjectCreated:Copy", "in/c.txt")]),
+        });
+        let bodies = [
+            notification(&[
+                record("ObjectCreated:Put", "in/my+file%21.txt"),
+                record("ObjectRemoved:Delete", "in/b.txt"),
+            ]),
+            notification(&[record("ObjectCreated:Put", "out/a.txt")]),
+            wrapped.to_string(),
+            r#"{"Service":"Amazon S3","Event":"s3:TestEvent"}"#.into(),
+        ];
+        let messages: Vec<_> = bodies
+            .iter()
+            .enumerate()
+            .map(|(i, body)| serde_json::json!({"ReceiptHandle": format!("r{i}"), "Body": body}))
+            .collect();
+        let messages = serde_json::json!({ "Messages": messages }).to_string();
+        let receives = Arc::new(AtomicUsize::new(0));
+        let deletes = Arc::new(AtomicUsize::new(0));
+        let (received, deleted) = (Arc::clone(&receives), Arc::clone(&deletes));
+        let endpoint = mock_s3(move |request| match request.header("x-amz-target") {
+            Some("AmazonSQS.ReceiveMessage") if received.fetch_add(1, Ordering::SeqCst) == 0 => {
+                ok(&messages)
+            }
+            Some("AmazonSQS.DeleteMessage") => {
+                deleted.fetch_add(1, Ordering::SeqCst);
+                ok("{}")
+            }
+            _ => ok("{}"),
+        })?;
+        let queue = format!("{endpoint}/123456789012/events");
+        // The endpoint is only for the plain http of the queue
+        let mut harness = Harness::new()?.with_config(mock_bucket("s3://notified", &endpoint));
+
+        let events = harness.eval(&format!(
+            "cloud events s3://notified/in/ --queue {queue} --idle 0sec"
+        ))?;
+        let events = events.as_list()?;
+        let column = |key: &str| -> Vec<Value> {
+            events
+                .iter()
+                .map(|event| event.get_data_by_key(key).unwrap())
+                .collect()
+        };
+        assert_eq!(
+            column("event"),
+            ["created", "deleted", "created"].map(Value::test_string)
+        );
+        assert_eq!(
+            column("url"),
+            [
+                "s3://notified/in/my%20file!.txt",
+                "s3://notified/in/b.txt",
+                "s3://notified/in/c.txt"
+            ]
+            .map(Value::test_string)
+        );
+        assert_eq!(column("name")[0], Value::test_string("in/my file!.txt"));
+        // Every message is deleted, including those without events under the prefix
+        assert_eq!(deletes.load(Ordering::SeqCst), 4);
+        assert_eq!(receives.load(Ordering::SeqCst), 2);
+
+        harness.eval(&format!(
+            "cloud events s3://notified/in/ --queue {queue} --idle 0sec --keep"
+        ))?;
+        assert_eq!(deletes.load(Ordering::SeqCst), 4);
+        assert!(
+            harness
+                .eval(&format!("cloud events memory://notified/ --queue {queue}"))
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_concat() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::{Arc, Mutex};
+
+        // A bucket answering heads and listings of its objects, and recording the requests of
+        // the uploads it is sent
+        const MIB: usize = 1024 * 1024;
+        let objects = [
+            ("parts/a", 6 * MIB),
+            ("parts/b", 6 * MIB),
+            ("parts/c", 10),
+            ("small", 10),
+            ("huge", 6 * 1024 * MIB),
+        ];
+        let contents: String = objects
+            .iter()
+            .filter(|(key, _)| key.starts_with("parts/"))
+            .map(|(key, size)| format!("<Contents><Key>{key}</Key><LastModified>2025-01-01T00:00:00.000Z</LastModified><ETag>\"{key}\"</ETag><Size>{size}</Size></Contents>"))
+            .collect();
+        let listing = format!(
+            "<ListBucketResult><IsTruncated>false</IsTruncated>{contents}</ListBucketResult>"
+        );
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&requests);
+        let endpoint = mock_s3(move |request| {
+            let mut line = format!("{} {}", request.method, request.target);
+            if let Some(source) = request.header("x-amz-copy-source") {
+                line = format!("{line} from {source}");
+            }
+            for header in ["x-amz-copy-source-range", "x-amz-meta-owner"] {
+                if let Some(value) = request.header(header) {
+                    line = format!("{line} {value}");
+                }
+            }
+            let key = request
+                .target
+                .split('?')
+                .next()
+                .unwrap_or("")
+                .trim_start_matches("/joined/");
+            let response = match request.method.as_str() {
+                "HEAD" => match objects.iter().find(|(object, _)| *object == key) {
+                    Some((_, size)) => format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {size}\r\nlast-modified: Wed, 01 Jan 2025 00:00:00 GMT\r\netag: \"{key}\"\r\nx-amz-meta-owner: me\r\n\r\n"
+                    ),
+                    None => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n".into(),
+                },
+                "GET" => ok(&listing),
+                "POST" if request.target.ends_with("?uploads=") => ok(
+                    "<InitiateMultipartUploadResult><UploadId>u1</UploadId></InitiateMultipartUploadResult>",
+                ),
+                // Parts are tagged with the name of their source
+                "PUT" => {
+                    let source = request.header("x-amz-copy-source").unwrap_or("");
+                    let etag = source.rsplit('/').next().unwrap_or("");
+                    ok(&format!(
+                        "<CopyPartResult><ETag>{etag}</ETag></CopyPartResult>"
+                    ))
+                }
+                _ => {
+                    line = format!("{line} {}", String::from_utf8_lossy(&request.body));
+                    ok("<CompleteMultipartUploadResult></CompleteMultipartUploadResult>")
+                }
+            };
+            received.lock().unwrap().push(line);
+            response
+        })?;
+        let mut harness = Harness::new()?.with_config(mock_bucket("s3://joined", &endpoint));
+        let uploads = |requests: &Mutex<Vec<String>>| -> Vec<String> {
+            let requests = requests.lock().unwrap();
+            requests
+                .iter()
+                .filter(|request| !request.starts_with("HEAD") && !request.starts_with("GET"))
+                .cloned()
+                .collect()
+        };
+
+        // Globs are joined in name order, each object copied as a part. Parts are copied
+        // concurrently, in any order, but completed in the order of their objects.
+        harness.eval("cloud concat s3://joined/merged s3://joined/parts/*")?;
+        let mut joined = uploads(&requests);
+        joined[1..4].sort();
+        assert_eq!(
+            joined,
+            [
+                "POST /joined/merged?uploads=",
+                "PUT /joined/merged?partNumber=1&uploadId=u1 from /joined/parts/a",
+                "PUT /joined/merged?partNumber=2&uploadId=u1 from /joined/parts/b",
+                "PUT /joined/merged?partNumber=3&uploadId=u1 from /joined/parts/c",
+                "POST /joined/merged?uploadId=u1 <CompleteMultipartUpload>\
+                 <Part><PartNumber>1</PartNumber><ETag>a</ETag></Part>\
+                 <Part><PartNumber>2</PartNumber><ETag>b</ETag></Part>\
+                 <Part><PartNumber>3</PartNumber><ETag>c</ETag></Part>\
+                 </CompleteMultipartUpload>",
+            ]
+        );
+
+        // Only the last object may be smaller than a part
+        requests.lock().unwrap().clear();
+        assert!(
+            harness
+                .eval("cloud concat s3://joined/merged s3://joined/small s3://joined/parts/a")
+                .is_err()
+        );
+        harness.eval("cloud concat s3://joined/merged s3://joined/parts/a s3://joined/small")?;
+        assert_eq!(uploads(&requests).len(), 4);
+
+        // Copies of objects too large to copy in one request are made in parts, with the
+        // metadata of the source
+        requests.lock().unwrap().clear();
+        harness.eval("cloud cp s3://joined/huge s3://joined/copy")?;
+        let mut copied = uploads(&requests);
+        // Parts are copied concurrently
+        copied[1..3].sort();
+        assert_eq!(
+            copied,
+            [
+                "POST /joined/copy?uploads= me",
+                "PUT /joined/copy?partNumber=1&uploadId=u1 from /joined/huge bytes=0-5368709119",
+                "PUT /joined/copy?partNumber=2&uploadId=u1 from /joined/huge bytes=5368709120-6442450943",
+                "POST /joined/copy?uploadId=u1 <CompleteMultipartUpload>\
+                 <Part><PartNumber>1</PartNumber><ETag>huge</ETag></Part>\
+                 <Part><PartNumber>2</PartNumber><ETag>huge</ETag></Part>\
+                 </CompleteMultipartUpload>",
+            ]
+        );
+
+        assert!(harness.eval("cloud concat s3://joined/merged").is_err());
+        assert!(
+            harness
+                .eval("cloud concat memory://joined/merged memory://joined/a")
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "fixtures")]
+    #[test]
+    fn test_fixtures() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        };
+
+        // A server throttling its first request
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let url = format!(
+            "{}/data.txt",
+            mock_s3(move |request| {
+                let body = match request.method.as_str() {
+                    "HEAD" => "",
+                    _ => "hello",
+                };
+                match counter.fetch_add(1, Ordering::SeqCst) {
+                    0 => "HTTP/1.1 503 Slow Down\r\ncontent-length: 0\r\n\r\n".into(),
+                    _ => format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: 5\r\netag: \"1\"\r\n\
+                         last-modified: Tue, 15 Nov 1994 08:12:31 GMT\r\n\r\n{body}"
+                    ),
+                }
+            })?
+        );
+
+        let path = std::env::temp_dir().join(format!(
+            "nu_plugin_cloud_fixtures_{}.jsonl",
+            std::process::id()
+        ));
+        let harness = |mode: &str| {
+            Ok::<_, Box<ShellError>>(Harness::new()?.with_config(record! {
+                "fixtures" => Value::test_record(record! {
+                    "path" => Value::test_string(path.display().to_string()),
+                    "mode" => Value::test_string(mode),
+                }),
+                "retry" => Value::test_record(record! {
+                    "init_backoff" => Value::test_duration(1_000_000),
+                }),
+            }))
+        };
+        assert_eq!(harness("record")?.read(&url)?, "hello");
+        let recorded = requests.load(Ordering::SeqCst);
+
+        // The throttled response is replayed, and retried, without reaching the server
+        let mut replay = harness("replay")?;
+        let result = replay.read(&url);
+        let retries = replay.eval("cloud metrics")?.get_data_by_key("retries");
+        std::fs::remove_file(&path)?;
+        assert_eq!(result?, "hello");
+        assert_eq!(retries, Some(Value::test_int(1)));
+        assert_eq!(requests.load(Ordering::SeqCst), recorded);
+
+        // Every response is replayed once
+        assert!(replay.read(&url).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replica_failover() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::{Arc, Mutex};
+
+        // A primary without one object and failing to serve another, and a replica with both
+        let primary = mock_s3(|request| {
+            match request.path() {
+                "/prod/flaky.txt" => {
+                    "HTTP/1.1 500 Internal Server Error\r\ncontent-length: 0\r\n\r\n"
+                }
+                _ => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n",
+            }
+            .into()
+        })?;
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&requests);
+        let replica = mock_s3(move |request| {
+            received.lock().unwrap().push(request.path().to_string());
+            "HTTP/1.1 200 OK\r\ncontent-length: 5\r\netag: \"1\"\r\n\
+             last-modified: Tue, 15 Nov 1994 08:12:31 GMT\r\n\r\nhello"
+                .into()
+        })?;
+        let config = record!(
+            "buckets" => Value::test_record(record!(
+                "s3://prod" => Value::test_record(record!(
+                    "endpoint" => Value::test_string(&primary),
+                    "region" => Value::test_string("us-east-1"),
+                    "access_key_id" => Value::test_string("key"),
+                    "secret_access_key" => Value::test_string("secret"),
+                    "replicas" => Value::test_list(vec![Value::test_string("s3://replica")]),
+                )),
+                "s3://replica" => Value::test_record(record!(
+                    "endpoint" => Value::test_string(replica),
+                    "region" => Value::test_string("us-east-1"),
+                    "access_key_id" => Value::test_string("key"),
+                    "secret_access_key" => Value::test_string("secret"),
+                )),
+            )),
+            "retry" => Value::test_record(record!(
+                "max_retries" => Value::test_int(0),
+            )),
+        );
+        let mut harness = Harness::new()?.with_config(config);
+
+        // An object missing from the primary isn't looked for in the replica
+        let error = harness.read("s3://prod/missing.txt").unwrap_err();
+        assert!(format!("{error:?}").contains("not_found"), "{error:?}");
+        assert!(requests.lock().unwrap().is_empty());
+
+        // A server error fails over to the replica
+        assert_eq!(harness.read("s3://prod/flaky.txt")?, "hello");
+        assert_eq!(*requests.lock().unwrap(), ["/replica/flaky.txt"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fault_injection() -> Result<(), Box<dyn std::error::Error>> {
+        // A server answering every request with the same object
+        let url = format!(
+            "{}/data.txt",
+            mock_s3(|request| {
+                let body = match request.method.as_str() {
+                    "HEAD" => "",
+                    _ => "hello",
+                };
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: 5\r\netag: \"1\"\r\n\
+                     last-modified: Tue, 15 Nov 1994 08:12:31 GMT\r\n\r\n{body}"
+                )
+            })?
+        );
+        let harness = |faults: Record| {
+            Harness::new().map(|harness| {
+                harness.with_config(record! {
+                    "fault_injection" => Value::test_record(faults),
+                    "retry" => Value::test_record(record! {
+                        "max_retries" => Value::test_int(3),
+                        "init_backoff" => Value::test_duration(1_000_000),
+                    }),
+                })
+            })
+        };
+        let count = |metrics: &Value, key: &str, name: &str| {
+            metrics
+                .get_data_by_key(key)
+                .and_then(|counts| counts.get_data_by_key(name)?.as_int().ok())
+                .unwrap_or(0)
+        };
+
+        // Injected errors are retried like real ones
+        let mut flaky = harness(record! {
+            "error_rate" => Value::test_float(0.5),
+            "seed" => Value::test_int(2),
+        })?;
+        assert_eq!(flaky.read(&url)?, "hello");
+        let metrics = flaky.eval("cloud metrics")?;
+        assert!(count(&metrics, "errors", "5xx") > 0);
+        assert_eq!(
+            metrics.get_data_by_key("retries"),
+            Some(Value::test_int(count(&metrics, "errors", "5xx")))
+        );
+
+        let mut failing = harness(record! { "error_rate" => Value::test_float(1.0) })?;
+        assert!(failing.read(&url).is_err());
+        let metrics = failing.eval("cloud metrics")?;
+        assert_eq!(count(&metrics, "errors", "5xx"), 4);
+
+        let mut truncating = harness(record! { "truncate_rate" => Value::test_float(1.0) })?;
+        assert!(truncating.read(&url).is_err());
+
+        let mut slow = harness(record! {
+            "delay_rate" => Value::test_float(1.0),
+            "delay" => Value::test_duration(10_000_000_000),
+        })?;
+        let started = std::time::Instant::now();
+        assert!(
+            slow.eval(&format!("cloud open --raw {url} --timeout 100ms"))
+                .is_err()
+        );
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        Ok(())
+    }
+
+    /// Runs the commands against a real S3 API, the endpoint in `NU_CLOUD_TEST_S3_ENDPOINT`,
+    /// e.g. MinIO or LocalStack started by `just s3-tests`
+    #[cfg(feature = "s3-tests")]
+    mod s3 {
+        use super::Harness;
+        use nu_protocol::{ByteStream, PipelineData, Record, Signals, Span, Value, record};
+
+        const MIB: usize = 1024 * 1024;
+
+        fn env(name: &str, default: &str) -> String {
+            std::env::var(name).unwrap_or_else(|_| default.into())
+        }
+
+        /// A harness for the test bucket with `config`, and a prefix only this test writes to
+        fn s3(
+            test: &str,
+            mut config: Record,
+        ) -> Result<(Harness, String), Box<dyn std::error::Error>> {
+            let endpoint = std::env::var("NU_CLOUD_TEST_S3_ENDPOINT")
+                .map_err(|_| "NU_CLOUD_TEST_S3_ENDPOINT must be set, see `just s3-tests`")?;
+            let bucket = format!(
+                "s3://{}",
+                env("NU_CLOUD_TEST_S3_BUCKET", "nu-plugin-cloud-test")
+            );
+            config.push(
+                "buckets",
+                Value::test_record(record! {
+                    bucket.clone() => Value::test_record(record! {
+                        "endpoint" => Value::test_string(endpoint),
+                        "region" => Value::test_string(env("NU_CLOUD_TEST_S3_REGION", "us-east-1")),
+                        "access_key_id" => Value::test_string(
+                            env("NU_CLOUD_TEST_S3_ACCESS_KEY_ID", "minioadmin"),
+                        ),
+                        "secret_access_key" => Value::test_string(
+                            env("NU_CLOUD_TEST_S3_SECRET_ACCESS_KEY", "minioadmin"),
+                        ),
+                    }),
+                }),
+            );
+            let prefix = format!("{bucket}/nu_plugin_cloud-{}-{test}", std::process::id());
+            Ok((Harness::new()?.with_config(config), prefix))
+        }
+
+        fn binary(data: &[u8]) -> PipelineData {
+            let stream =
+                ByteStream::read_binary(data.to_vec(), Span::test_data(), Signals::empty());
+            PipelineData::ByteStream(stream, None)
+        }
+
+        #[test]
+        fn test_s3_save_open_ls_rm() -> Result<(), Box<dyn std::error::Error>> {
+            let (mut harness, prefix) = s3("basic", record! {})?;
+            harness.write(&format!("{prefix}/a.txt"), "a")?;
+            harness.write(&format!("{prefix}/dir/b.txt"), "b")?;
+            harness.eval(&format!("cloud cp {prefix}/a.txt {prefix}/c.txt"))?;
+            assert_eq!(harness.read(&format!("{prefix}/c.txt"))?, "a");
+            assert_eq!(harness.names(&format!("{prefix}/"))?.len(), 3);
+
+            harness.eval(&format!("cloud rm {prefix}/a.txt"))?;
+            assert!(harness.read(&format!("{prefix}/a.txt")).is_err());
+            harness.eval(&format!("cloud rm -r {prefix}/"))?;
+            assert!(harness.names(&format!("{prefix}/"))?.is_empty());
+            Ok(())
+        }
+
+        #[test]
+        fn test_s3_multipart_ranged_reads() -> Result<(), Box<dyn std::error::Error>> {
+            // Four 5MiB parts, read back in three 8MiB ranges
+            let (mut harness, prefix) = s3(
+                "multipart",
+                record! {
+                    "upload_part_size" => Value::test_filesize(5 * MIB as i64),
+                    "read_ahead" => Value::test_int(2),
+                },
+            )?;
+            let url = format!("{prefix}/a.bin");
+            let data: Vec<u8> = (0..20 * MIB).map(|i| (i % 251) as u8).collect();
+            harness
+                .plugin_test
+                .eval_with(&format!("cloud save {url}"), binary(&data))?;
+
+            let read = harness.eval(&format!("cloud open --raw {url}"))?;
+            assert_eq!(read.as_binary()?, data.as_slice());
+            let listed = harness.eval(&format!("cloud ls {url}"))?;
+            let size = listed.as_list()?[0].get_data_by_key("size");
+            assert_eq!(size, Some(Value::test_filesize(data.len() as i64)));
+            harness.eval(&format!("cloud rm -r {prefix}/"))?;
+            Ok(())
+        }
+
+        #[test]
+        fn test_s3_conditional_reads() -> Result<(), Box<dyn std::error::Error>> {
+            // Every read revalidates the cached object with If-None-Match
+            let dir = std::env::temp_dir()
+                .join(format!("nu_plugin_cloud_s3_cache_{}", std::process::id()));
+            let (mut harness, prefix) = s3(
+                "conditional",
+                record! {
+                    "disk_cache" => Value::test_record(record! {
+                        "dir" => Value::test_string(dir.display().to_string()),
+                        "ttl" => Value::test_duration(0),
+                    }),
+                },
+            )?;
+            let url = format!("{prefix}/a.txt");
+            harness.write(&url, "one")?;
+            let first = harness.eval(&format!("cloud open {url}"));
+            let unchanged = harness.eval(&format!("cloud open {url}"));
+            harness.write(&url, "two")?;
+            let changed = harness.eval(&format!("cloud open {url}"));
+            let _ = std::fs::remove_dir_all(&dir);
+            assert_eq!(first?.coerce_into_string()?, "one");
+            assert_eq!(unchanged?.coerce_into_string()?, "one");
+            assert_eq!(changed?.coerce_into_string()?, "two");
+
+            // Content-addressed saves skip objects that exist
+            let by_hash = format!("'two' | cloud save --by-hash {prefix}/cas");
+            let saved = harness.eval(&by_hash)?;
+            assert_eq!(harness.eval(&by_hash)?, saved);
+            harness.eval(&format!("cloud rm -r {prefix}/"))?;
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,218 @@
+use std::time::{Duration, Instant};
+
+use log::debug;
+use object_store::{MultipartUpload, PutPayloadMut, PutResult, Result};
+use tokio::task::JoinSet;
+
+use crate::providers::{limit, stats, trace, verbose};
+
+/// Smallest part accepted by S3, except for the last one
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+const MAX_PART_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+const MAX_PARTS: u64 = 10_000;
+/// Without a known size, parts double in size every this many parts, allowing objects of
+/// about 5TB within [`MAX_PARTS`]
+const PARTS_PER_DOUBLING: u64 = 1_000;
+/// Fast uploads use parts taking about this long, so fewer requests are needed
+const TARGET_PART_DURATION: Duration = Duration::from_secs(5);
+/// Bound on the part size chosen for throughput, as every part in flight is held in memory
+const MAX_THROUGHPUT_PART_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Chooses the size of each part of an upload.
+///
+/// Parts are large enough to fit the whole object within the 10,000 parts S3 allows, and grow
+/// further when parts upload quickly. Sizes never shrink during an upload.
+#[derive(Debug)]
+pub struct PartSize {
+    /// Size of the whole upload, if known in advance
+    total: Option<u64>,
+    /// Keep the initial size, for stores requiring parts of equal size
+    fixed: bool,
+    size: u64,
+    parts: u64,
+    written: u64,
+    /// Bytes and time of the parts uploaded so far
+    uploaded: u64,
+    upload_time: Duration,
+}
+
+impl PartSize {
+    /// Sizes for an upload of `total` bytes, or of unknown size. A configured `fixed` size is
+    /// only raised if the object wouldn't fit otherwise.
+    pub fn new(total: Option<u64>, fixed: Option<u64>) -> Self {
+        let mut part_size = PartSize {
+            total,
+            fixed: fixed.is_some(),
+            size: fixed
+                .unwrap_or(MIN_PART_SIZE)
+                .clamp(MIN_PART_SIZE, MAX_PART_SIZE),
+            parts: 0,
+            written: 0,
+            uploaded: 0,
+            upload_time: Duration::ZERO,
+        };
+        part_size.size = part_size.size.max(part_size.required());
+        part_size
+    }
+
+    /// Continue sizing after the parts of an interrupted upload
+    pub fn resumed(mut self, lens: impl IntoIterator<Item = u64>) -> Self {
+        for len in lens {
+            self.started(len);
+        }
+        self
+    }
+
+    /// Size of the next part
+    pub fn get(&self) -> usize {
+        self.size as usize
+    }
+
+    fn started(&mut self, len: u64) {
+        self.parts += 1;
+        self.written += len;
+        if self.fixed {
+            return;
+        }
+        let size = self
+            .required()
+            .max(self.for_throughput())
+            .clamp(self.size, MAX_PART_SIZE)
+            .next_multiple_of(1024 * 1024);
+        if size != self.size {
+            debug!("Increasing upload part size to {size} bytes");
+            self.size = size;
+        }
+    }
+
+    fn finished(&mut self, len: u64, elapsed: Duration) {
+        self.uploaded += len;
+        self.upload_time += elapsed;
+    }
+
+    /// Smallest part size that fits the rest of the upload in the remaining parts
+    fn required(&self) -> u64 {
+        let remaining_parts = MAX_PARTS.saturating_sub(self.parts).max(1);
+        match self.total {
+            Some(total) if total >= self.written => {
+                (total - self.written).div_ceil(remaining_parts)
+            }
+            // The size is unknown or was wrong, grow the parts so a large upload still fits
+            _ => MIN_PART_SIZE << (self.parts / PARTS_PER_DOUBLING).min(10),
+        }
+    }
+
+    fn for_throughput(&self) -> u64 {
+        if self.upload_time.is_zero() {
+            return 0;
+        }
+        let per_second = self.uploaded as f64 / self.upload_time.as_secs_f64();
+        ((per_second * TARGET_PART_DURATION.as_secs_f64()) as u64).min(MAX_THROUGHPUT_PART_SIZE)
+    }
+}
+
+/// Multipart upload like [`object_store::WriteMultipart`], with parts sized by [`PartSize`]
+pub struct MultipartWriter {
+    upload: Box<dyn MultipartUpload>,
+    buffer: PutPayloadMut,
+    part_size: PartSize,
+    /// Parts in flight, returning their size and upload time
+    tasks: JoinSet<Result<(u64, Duration)>>,
+}
+
+impl MultipartWriter {
+    pub fn new(upload: Box<dyn MultipartUpload>, part_size: PartSize) -> Self {
+        MultipartWriter {
+            upload,
+            buffer: PutPayloadMut::new(),
+            part_size,
+            tasks: JoinSet::new(),
+        }
+    }
+
+    /// Wait until less than `max_concurrency` parts are in flight
+    pub async fn wait_for_capacity(&mut self, max_concurrency: usize) -> Result<()> {
+        while let Some(result) = self.tasks.try_join_next() {
+            self.part_finished(result)?;
+        }
+        while !self.tasks.is_empty() && self.tasks.len() >= max_concurrency {
+            let result = self.tasks.join_next().await.expect("The set is not empty");
+            self.part_finished(result)?;
+        }
+        Ok(())
+    }
+
+    /// Copy `buf` into the upload, starting parts as they fill up
+    pub fn write(&mut self, mut buf: &[u8]) {
+        while !buf.is_empty() {
+            let remaining = self.part_size.get() - self.buffer.content_length();
+            let len = buf.len().min(remaining);
+            self.buffer.extend_from_slice(&buf[..len]);
+            if len == remaining {
+                self.put_part();
+            }
+            buf = &buf[len..];
+        }
+    }
+
+    /// Add `bytes` to the upload without copying them
+    pub fn put(&mut self, mut bytes: bytes::Bytes) {
+        while !bytes.is_empty() {
+            let remaining = self.part_size.get() - self.buffer.content_length();
+            if bytes.len() < remaining {
+                self.buffer.push(bytes);
+                return;
+            }
+            self.buffer.push(bytes.split_to(remaining));
+            self.put_part();
+        }
+    }
+
+    /// Upload the last part and complete the upload once all parts are done
+    pub async fn finish(mut self) -> Result<PutResult> {
+        if !self.buffer.is_empty() {
+            self.put_part();
+        }
+        self.wait_for_capacity(0).await?;
+
+        match self.upload.complete().await {
+            Err(e) => {
+                self.tasks.shutdown().await;
+                self.upload.abort().await?;
+                Err(e)
+            }
+            Ok(result) => Ok(result),
+        }
+    }
+
+    /// Stop uploading and discard the parts uploaded so far
+    pub async fn abort(&mut self) -> Result<()> {
+        self.tasks.shutdown().await;
+        self.upload.abort().await
+    }
+
+    fn put_part(&mut self) {
+        let part = std::mem::take(&mut self.buffer);
+        let len = part.content_length() as u64;
+        self.part_size.started(len);
+        // Tasks don't inherit the scope of the command run, so it's carried over
+        let upload = limit::scoped(limit::current(), self.upload.put_part(part.into()));
+        let upload = verbose::scoped(verbose::enabled(), upload);
+        let upload = stats::in_run(stats::current(), upload);
+        let upload = trace::in_run(trace::current(), upload);
+        self.tasks.spawn(async move {
+            let start = Instant::now();
+            upload.await?;
+            Ok((len, start.elapsed()))
+        });
+    }
+
+    fn part_finished(
+        &mut self,
+        result: std::result::Result<Result<(u64, Duration)>, tokio::task::JoinError>,
+    ) -> Result<()> {
+        let (len, elapsed) = result.expect("Part uploads don't panic")?;
+        self.part_size.finished(len, elapsed);
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, SyntaxShape, Type, Value,
+};
+
+use super::{
+    OutputFormat, bandwidth_limit, batch_objects, bwlimit_flag, confirm, confirm_overwrite,
+    confirming,
+    cp::{Limits, Overwrites, Transfer, copy_batch, existing_objects, transfer_flags},
+    output_format_flag, run_flags, with_rate_limit, with_request_log, with_timeout, yes_flag,
+};
+use crate::{
+    CloudPlugin,
+    audit::AuditLog,
+    cache::ObjectStoreCacheKey,
+    config::Config,
+    error::{Action, store_error},
+    remote::RemotePath,
+};
+
+pub struct Move;
+
+impl PluginCommand for Move {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud mv"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            transfer_flags(
+                output_format_flag(yes_flag(bwlimit_flag(Signature::build("cloud mv")))),
+                "in a batch ",
+            )
+            .input_output_types(vec![(Type::Any, Type::Any)])
+            .required(
+                "source",
+                SyntaxShape::String,
+                "The url of the file to move, or the url the objects of a batch are under.",
+            )
+            .required(
+                "destination",
+                SyntaxShape::String,
+                "The url to move the file to.",
+            )
+            .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Move a file within or between cloud storage locations"
+    }
+
+    fn extra_description(&self) -> &str {
+        "Objects are copied, then removed from the source once the copy succeeds. Copies to another bucket, account, or store are checked against the source first, by size and by ETag between S3 buckets, and the source is kept if they differ. Given a list of names as input, like the output of cloud ls, the objects with those paths within the bucket of the source url are moved instead, keeping their paths relative to it. They must be under the source url. Returns a row per object with its url, its new url, and the error if it couldn't be moved."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Rename a file in a s3 bucket.",
+                example: "cloud mv s3://mybucket/draft.csv s3://mybucket/final.csv",
+                result: None,
+            },
+            Example {
+                description: "Move the processed files to another prefix.",
+                example: "cloud ls s3://mybucket/inbox/ | where name =~ '.done$' | cloud mv s3://mybucket/inbox/ s3://mybucket/processed/",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(
+                    call,
+                    with_timeout(call, command(engine, plugin, call, input)),
+                ),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+    input: PipelineData,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let source = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let destination = RemotePath::from_call(engine, call, 1).map_err(|e| *e)?.url;
+
+    let (source_store, source_path) = plugin.parse_url(engine, &source, call_span).await?;
+    let (dest_store, dest_path) = plugin.parse_url(engine, &destination, call_span).await?;
+    let bwlimit = bandwidth_limit(engine, call).map_err(|e| *e)?;
+    let format = OutputFormat::from_call(call).map_err(|e| *e)?;
+    let audit = AuditLog::open(engine, call).map_err(|e| *e)?;
+    let config = Config::load(engine).map_err(|e| *e)?;
+    let mut copy = Transfer {
+        source: &source_store,
+        destination: &dest_store,
+        same_store: ObjectStoreCacheKey::from(&source_store)
+            == ObjectStoreCacheKey::from(&dest_store),
+        bwlimit: bwlimit.as_deref(),
+        config: &config,
+        overwrites: None,
+        removals: audit.as_ref(),
+    };
+
+    let batch = batch_objects(input, &source, &source_path, call_span).map_err(|e| *e)?;
+    plugin.cache.invalidate(&source_store, &source_path).await;
+    plugin.cache.invalidate(&dest_store, &dest_path).await;
+    if let Some(objects) = batch {
+        if confirming(engine, call).map_err(|e| *e)? {
+            let question = format!(
+                "Move the {} objects of the input into {}, overwriting objects with the same names?",
+                objects.len(),
+                destination.item
+            );
+            confirm(engine, &question, call_span).map_err(|e| *e)?;
+        }
+        if let Some(audit) = &audit {
+            copy.overwrites = Some(Overwrites {
+                audit,
+                existing: existing_objects(&dest_store, &dest_path, &destination).await?,
+                url: &destination.item,
+                path: &dest_path,
+            });
+        }
+        let limits = Limits::from_call(engine, call).map_err(|e| *e)?;
+        let rows = copy_batch(
+            engine,
+            &copy,
+            &limits,
+            objects,
+            (&source, &source_path),
+            (&destination.item, &dest_path),
+            true,
+        )
+        .await?;
+        return Ok(PipelineData::Value(Value::list(rows, call_span), None));
+    }
+
+    if copy.same_store && source_path == dest_path {
+        return Ok(PipelineData::empty());
+    }
+    let exists = confirm_overwrite(
+        engine,
+        call,
+        &dest_store,
+        &dest_path,
+        &destination.item,
+        audit.as_ref(),
+    )
+    .await
+    .map_err(|e| *e)?;
+    if let Some(audit) = &audit {
+        copy.overwrites = Some(Overwrites {
+            audit,
+            existing: HashSet::from_iter(exists.then(|| dest_path.clone())),
+            url: &destination.item,
+            path: &dest_path,
+        });
+    }
+    let move_error = |e| {
+        let target = format!("{} to {}", source.item, destination.item);
+        store_error(Action::Copy, target, source.span, e)
+    };
+    let size = copy
+        .object(&source_path, &dest_path, None)
+        .await
+        .map_err(move_error)?;
+    if !copy.same_store {
+        copy.verify(&source_path, &dest_path)
+            .await
+            .map_err(move_error)?;
+    }
+    copy.remove(&source_path, &source.item)
+        .await
+        .map_err(|e| store_error(Action::Delete, &source.item, source.span, e))?;
+
+    Ok(match format {
+        Some(format) => format.output(
+            &destination.item,
+            vec![(destination.item.to_string(), size)],
+            call_span,
+        ),
+        None => PipelineData::empty(),
+    })
+}
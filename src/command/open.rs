@@ -1,16 +1,37 @@
-use std::{path::PathBuf, str::FromStr, vec};
+use std::{path::PathBuf, sync::Arc, time::Duration, vec};
 
-use bytes::Buf;
+use bytes::{Buf, Bytes, BytesMut};
+use futures::future::select_ok;
 use log::debug;
 use nu_plugin::{EngineInterface, PluginCommand};
 use nu_protocol::{
     ByteStream, ByteStreamType, Category, DataSource, Example, IntoInterruptiblePipelineData,
-    LabeledError, PipelineData, PipelineMetadata, ShellError, Signature, Spanned, SyntaxShape,
-    Type,
+    LabeledError, PipelineData, PipelineMetadata, ShellError, Signature, Span, Spanned,
+    SyntaxShape, Type, Value,
 };
+use object_store::{Attribute, GetOptions, GetRange};
 use url::Url;
 
-use crate::CloudPlugin;
+use super::{
+    avro, bandwidth_limit, bwlimit_flag, duration_flag,
+    follow::Follow,
+    read_ahead::{RANGE_SIZE, ReadAhead},
+    run_flags, with_rate_limit, with_request_log, with_timeout,
+};
+use crate::{
+    CloudPlugin,
+    config::Config,
+    disk_cache::DiskCache,
+    error::{Action, store_error},
+    providers::{
+        limit::{self, RateLimiter},
+        unavailable,
+    },
+    remote::RemotePath,
+};
+
+/// Ranges fetched ahead of the reader of a raw open
+const DEFAULT_READ_AHEAD: usize = 4;
 
 pub struct Open;
 
@@ -22,23 +43,70 @@ impl PluginCommand for Open {
     }
 
     fn signature(&self) -> nu_protocol::Signature {
-        Signature::build("cloud open")
-            .input_output_types(vec![(Type::Nothing, Type::Any), (Type::String, Type::Any)])
-            .rest("url", SyntaxShape::String, "The cloud url to file to open.")
-            .switch("raw", "open file as raw binary", Some('r'))
-            .category(Category::FileSystem)
+        run_flags(
+            bwlimit_flag(Signature::build("cloud open"))
+                .input_output_types(vec![(Type::Nothing, Type::Any), (Type::String, Type::Any)])
+                .rest("url", SyntaxShape::String, "The cloud url to file to open.")
+                .switch("raw", "open file as raw binary", Some('r'))
+                .named(
+                    "lines",
+                    SyntaxShape::Int,
+                    "Only read the first lines of the file, in growing ranges until it has them",
+                    Some('n'),
+                )
+                .switch(
+                    "follow",
+                    "Keep streaming the bytes appended to the object, like tail -f, with --raw",
+                    Some('f'),
+                )
+                .named(
+                    "interval",
+                    SyntaxShape::Duration,
+                    "Time between checks of the size of the object with --follow (default: 1sec)",
+                    None,
+                )
+                .named(
+                    "idle",
+                    SyntaxShape::Duration,
+                    "Stop following once the object doesn't grow for this long",
+                    None,
+                )
+                .switch(
+                    "sqlite",
+                    "Keep a local copy of a SQLite database in the disk cache and return its path",
+                    None,
+                )
+                .category(Category::FileSystem),
+        )
     }
 
     fn description(&self) -> &str {
         "Load a file into a cell, converting to table if possible (avoid by appending '--raw')."
     }
 
-    fn examples(&self) -> Vec<Example> {
-        vec![Example {
-            description: "Load a file from s3.",
-            example: "cloud open s3://mybucket/file.txt",
-            result: None,
-        }]
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Load a file from s3.",
+                example: "cloud open s3://mybucket/file.txt",
+                result: None,
+            },
+            Example {
+                description: "Peek at the header and first rows of a large CSV file.",
+                example: "cloud open s3://mybucket/big.csv --lines 100",
+                result: None,
+            },
+            Example {
+                description: "Follow a log written by appending to the object.",
+                example: "cloud open --raw --follow file:///var/log/app.log | lines | each { |line| print $line }",
+                result: None,
+            },
+            Example {
+                description: "Query a SQLite database from s3.",
+                example: "open (cloud open --sqlite s3://mybucket/data.db) | query db 'select * from users'",
+                result: None,
+            },
+        ]
     }
 
     fn run(
@@ -49,8 +117,17 @@ impl PluginCommand for Open {
         input: PipelineData,
     ) -> Result<PipelineData, LabeledError> {
         plugin
-            .rt
-            .block_on(command(plugin, engine, call, input))
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(
+                    call,
+                    with_timeout(call, command(plugin, engine, call, input)),
+                ),
+            ))
             .map_err(LabeledError::from)
     }
 }
@@ -63,37 +140,76 @@ async fn command(
 ) -> Result<PipelineData, ShellError> {
     let call_span = call.head;
     let raw = call.has_flag("raw")?;
-    let spanned_path: Spanned<PathBuf> = call.req(0)?;
-    let path = spanned_path.item;
-    let url = path
-        .to_str()
-        .expect("The path should already be unicode")
-        .to_string();
-    let url = Spanned {
-        item: Url::from_str(&url).map_err(|e| ShellError::IncorrectValue {
-            msg: format!("Invalid Url: {e}"),
-            val_span: spanned_path.span,
-            call_span,
-        })?,
-        span: spanned_path.span,
-    };
+    let remote = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?;
+    let url = &remote.url;
+    let path = PathBuf::from(url.item.to_string());
 
-    let bytes = plugin.cache.get(engine, &url, call_span).await?;
+    if call.has_flag("sqlite")? {
+        return sqlite_copy(plugin, engine, url, call_span).await;
+    }
+
+    let bwlimit = bandwidth_limit(engine, call).map_err(|e| *e)?;
+    let lines = match call.get_flag::<i64>("lines")? {
+        Some(n) if n < 0 => {
+            return Err(ShellError::IncorrectValue {
+                msg: "The number of lines can't be negative".into(),
+                val_span: call.get_flag_span("lines").unwrap_or(call_span),
+                call_span,
+            });
+        }
+        lines => lines.map(|n| n as usize),
+    };
 
     let content_type = if raw {
-        path.extension()
-            .map(|ext| ext.to_string_lossy().to_string())
-            .and_then(|ref s| detect_content_type(s))
+        remote.extension().and_then(|s| detect_content_type(&s))
     } else {
         None
     };
 
+    if call.has_flag("follow")? {
+        if !raw || lines.is_some() {
+            return Err(ShellError::GenericError {
+                error: "Only raw objects can be followed".into(),
+                msg: "".into(),
+                span: call.get_flag_span("follow"),
+                help: Some("Follow the object with --raw, without --lines".into()),
+                inner: vec![],
+            });
+        }
+        return Ok(PipelineData::ByteStream(
+            follow(plugin, engine, call, url, bwlimit).await?,
+            Some(PipelineMetadata {
+                data_source: DataSource::FilePath(path),
+                content_type,
+            }),
+        ));
+    }
+
+    if raw
+        && bwlimit.is_none()
+        && lines.is_none()
+        && let Some(stream) = read_ahead(plugin, engine, url, call_span).await?
+    {
+        return Ok(PipelineData::ByteStream(
+            stream,
+            Some(PipelineMetadata {
+                data_source: DataSource::FilePath(path),
+                content_type,
+            }),
+        ));
+    }
+
+    let bytes = match lines {
+        Some(lines) => {
+            read_lines(plugin, engine, url, call_span, lines, bwlimit.as_deref()).await?
+        }
+        None => read(plugin, engine, url, call_span, bwlimit.as_deref()).await?,
+    };
+
     let extension: Option<String> = if raw {
         None
     } else {
-        path.extension()
-            .map(|ext| ext.to_string_lossy().to_string().to_owned())
-            .map(|s| s.to_lowercase())
+        remote.extension().map(|s| s.to_lowercase())
     };
 
     let converter = if !raw {
@@ -101,12 +217,42 @@ async fn command(
             debug!("Attempting to use converter: {ext}");
             engine.find_decl(format!("from {}", ext))?
         } else {
-            None
+            // Without an extension, convert by the Content-Type like `http get` does
+            let mut converter = None;
+            if let Some(content_type) = stored_content_type(plugin, engine, url, call_span).await {
+                for ext in content_type_extensions(&content_type) {
+                    debug!("Attempting to use converter for {content_type}: {ext}");
+                    converter = engine.find_decl(format!("from {ext}"))?;
+                    if converter.is_some() {
+                        break;
+                    }
+                }
+            }
+            converter
         }
     } else {
         None
     };
 
+    // Nushell has no Avro converter, decode it here unless one was defined
+    if converter.is_none() && extension.as_deref() == Some("avro") {
+        let rows = avro::read_container(&bytes).map_err(|e| ShellError::GenericError {
+            error: format!("Could not read Avro from {}: {e}", url.item),
+            msg: "".into(),
+            span: Some(url.span),
+            help: None,
+            inner: vec![],
+        })?;
+        let rows = rows
+            .into_iter()
+            .map(|row| avro::to_value(row, call_span))
+            .collect();
+        return Ok(PipelineData::Value(
+            Value::list(rows, call_span),
+            Some(PipelineMetadata::default().with_data_source(DataSource::FilePath(path))),
+        ));
+    }
+
     let stream = PipelineData::ByteStream(
         ByteStream::read(
             bytes.reader(),
@@ -136,6 +282,330 @@ async fn command(
     }
 }
 
+/// Stream a large object for a raw open, fetching ranges ahead of the reader.
+///
+/// Returns `None` if the object should be read whole instead: when it fits in one range, has
+/// replicas or the disk cache to read from, or read-ahead is turned off.
+async fn read_ahead(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    url: &Spanned<Url>,
+    call_span: Span,
+) -> Result<Option<ByteStream>, ShellError> {
+    let config = Config::load(engine).map_err(|e| *e)?;
+    let depth = config.read_ahead.unwrap_or(DEFAULT_READ_AHEAD);
+    let has_replicas = config
+        .bucket(&url.item)
+        .and_then(|(_, bucket)| bucket.replicas.as_ref())
+        .is_some_and(|replicas| !replicas.is_empty());
+    if depth == 0 || has_replicas || config.disk_cache.is_some() {
+        return Ok(None);
+    }
+
+    let (store, path) = plugin.parse_url(engine, url, call_span).await?;
+    let meta = store
+        .object_store()
+        .head(&path)
+        .await
+        .map_err(|e| store_error(Action::Read, &url.item, url.span, e))?;
+    if meta.size <= RANGE_SIZE {
+        return Ok(None);
+    }
+    let size = meta.size;
+    let reader = ReadAhead::new(
+        Arc::clone(plugin.runtime(engine, call_span).map_err(|e| *e)?),
+        store,
+        path,
+        meta,
+        depth,
+        url.span,
+    );
+    Ok(Some(
+        ByteStream::read(
+            reader,
+            call_span,
+            engine.signals().clone(),
+            ByteStreamType::Unknown,
+        )
+        .with_known_size(Some(size)),
+    ))
+}
+
+/// Stream the object as it grows, from its start
+async fn follow(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    call: &nu_plugin::EvaluatedCall,
+    url: &Spanned<Url>,
+    bwlimit: Option<Arc<RateLimiter>>,
+) -> Result<ByteStream, ShellError> {
+    let call_span = call.head;
+    let interval = duration_flag(call, "interval", false)
+        .map_err(|e| *e)?
+        .unwrap_or(DEFAULT_FOLLOW_INTERVAL);
+    let idle = duration_flag(call, "idle", true).map_err(|e| *e)?;
+
+    let (store, path) = plugin.parse_url(engine, url, call_span).await?;
+    let meta = store
+        .object_store()
+        .head(&path)
+        .await
+        .map_err(|e| store_error(Action::Read, &url.item, url.span, e))?;
+    let reader = Follow::new(
+        Arc::clone(plugin.runtime(engine, call_span).map_err(|e| *e)?),
+        store,
+        path,
+        meta.size,
+        interval,
+        idle,
+        bwlimit,
+        engine.signals().clone(),
+        url.span,
+    );
+    Ok(ByteStream::read(
+        reader,
+        call_span,
+        engine.signals().clone(),
+        ByteStreamType::Unknown,
+    ))
+}
+
+const DEFAULT_FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Path of a local copy of a SQLite database, which builtin `open` reads lazily like a local
+/// database. The database itself can't be returned, as plugins can't pass on the engine's
+/// SQLite values.
+async fn sqlite_copy(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    url: &Spanned<Url>,
+    call_span: Span,
+) -> Result<PipelineData, ShellError> {
+    let config = Config::load(engine).map_err(|e| *e)?;
+    let disk_cache = DiskCache::new(&config.disk_cache.unwrap_or_default()).map_err(|e| {
+        ShellError::GenericError {
+            error: format!("Could not open disk cache: {e}"),
+            msg: "".into(),
+            span: Some(call_span),
+            help: None,
+            inner: vec![],
+        }
+    })?;
+    let (store, path) = plugin.parse_url(engine, url, call_span).await?;
+    let file = disk_cache
+        .get_file(&store, &path, &url.item)
+        .await
+        .map_err(|e| store_error(Action::Read, &url.item, url.span, e))?;
+    Ok(PipelineData::Value(
+        Value::string(file.display().to_string(), call_span),
+        None,
+    ))
+}
+
+/// Read the object, failing over to the replicas configured for the url
+async fn read(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    url: &Spanned<Url>,
+    call_span: Span,
+    bwlimit: Option<&RateLimiter>,
+) -> Result<Bytes, ShellError> {
+    let config = Config::load(engine).map_err(|e| *e)?;
+    let urls = config
+        .replica_urls(&url.item)
+        .map_err(|e| ShellError::GenericError {
+            error: format!("Invalid replica url for {}: {e}", url.item),
+            msg: "".into(),
+            span: Some(url.span),
+            help: None,
+            inner: vec![],
+        })?
+        .into_iter()
+        .map(|item| Spanned {
+            item,
+            span: url.span,
+        })
+        .collect::<Vec<_>>();
+
+    if urls.len() == 1 {
+        return read_one(plugin, engine, url, call_span, bwlimit).await;
+    }
+
+    let race = config
+        .bucket(&url.item)
+        .and_then(|(_, bucket)| bucket.race_replicas)
+        .unwrap_or(false);
+    if race {
+        // Racing reads bypass the cache, which would serialize them
+        let reads = urls
+            .iter()
+            .map(|url| Box::pin(read_direct(plugin, engine, url, call_span, bwlimit)));
+        return select_ok(reads).await.map(|(bytes, _)| bytes);
+    }
+
+    // Only failures of the server are worth another replica, others would fail there too
+    let mut first_error = None;
+    for url in &urls {
+        match unavailable::captured(read_one(plugin, engine, url, call_span, bwlimit)).await {
+            (Ok(bytes), _) => return Ok(bytes),
+            (Err(e), true) => {
+                debug!("Read from {} failed, trying next replica: {e}", url.item);
+                first_error.get_or_insert(e);
+            }
+            (Err(e), false) => return Err(first_error.unwrap_or(e)),
+        }
+    }
+    Err(first_error.expect("There is at least one url"))
+}
+
+/// Read the object through the cache, unless its transfer rate is limited
+async fn read_one(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    url: &Spanned<Url>,
+    call_span: Span,
+    bwlimit: Option<&RateLimiter>,
+) -> Result<Bytes, ShellError> {
+    match bwlimit {
+        Some(_) => read_direct(plugin, engine, url, call_span, bwlimit).await,
+        None => plugin.cache.get(engine, url, call_span).await,
+    }
+}
+
+async fn read_direct(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    url: &Spanned<Url>,
+    call_span: Span,
+    bwlimit: Option<&RateLimiter>,
+) -> Result<Bytes, ShellError> {
+    let (store, path) = plugin.parse_url(engine, url, call_span).await?;
+    let get = async {
+        let result = store.object_store().get(&path).await?;
+        match bwlimit {
+            Some(bwlimit) => limit::read_throttled(result, bwlimit).await,
+            None => result.bytes().await,
+        }
+    };
+    get.await
+        .map_err(|e| store_error(Action::Read, &url.item, url.span, e))
+}
+
+/// Read the first `lines` lines of the object, with the newline ending the last, in ranges
+/// growing from [`LINES_RANGE_SIZE`] until they have that many. Ranges are read for the ETag of
+/// the first, so lines of different versions aren't mixed.
+async fn read_lines(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    url: &Spanned<Url>,
+    call_span: Span,
+    lines: usize,
+    bwlimit: Option<&RateLimiter>,
+) -> Result<Bytes, ShellError> {
+    if lines == 0 {
+        return Ok(Bytes::new());
+    }
+    let (store, path) = plugin.parse_url(engine, url, call_span).await?;
+    let read_error = |e| store_error(Action::Read, &url.item, url.span, e);
+    let meta = store.object_store().head(&path).await.map_err(read_error)?;
+    let mut data = BytesMut::new();
+    let mut found = 0;
+    let mut length = LINES_RANGE_SIZE;
+    while (data.len() as u64) < meta.size {
+        let start = data.len() as u64;
+        let options = GetOptions {
+            range: Some(GetRange::Bounded(start..(start + length).min(meta.size))),
+            if_match: meta.e_tag.clone(),
+            ..GetOptions::default()
+        };
+        let result = store
+            .object_store()
+            .get_opts(&path, options)
+            .await
+            .map_err(read_error)?;
+        let range = match bwlimit {
+            Some(bwlimit) => limit::read_throttled(result, bwlimit).await,
+            None => result.bytes().await,
+        }
+        .map_err(read_error)?;
+        if range.is_empty() {
+            break;
+        }
+        for (i, _) in range.iter().enumerate().filter(|(_, byte)| **byte == b'\n') {
+            found += 1;
+            if found == lines {
+                data.extend_from_slice(&range[..=i]);
+                return Ok(data.freeze());
+            }
+        }
+        data.extend_from_slice(&range);
+        length = (length * 2).min(RANGE_SIZE);
+    }
+    Ok(data.freeze())
+}
+
+/// First range read by `--lines`, enough for a hundred lines of most files
+const LINES_RANGE_SIZE: u64 = 64 * 1024;
+
+/// Content-Type of the object, if the store records one
+async fn stored_content_type(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    url: &Spanned<Url>,
+    call_span: Span,
+) -> Option<String> {
+    let head = async {
+        let (store, path) = plugin.parse_url(engine, url, call_span).await?;
+        let options = GetOptions {
+            head: true,
+            ..GetOptions::default()
+        };
+        let result = store
+            .object_store()
+            .get_opts(&path, options)
+            .await
+            .map_err(|e| store_error(Action::Read, &url.item, url.span, e))?;
+        Ok::<_, ShellError>(result.attributes.get(&Attribute::ContentType).cloned())
+    };
+    // The object was read already, so a failure only means it is left unconverted
+    match head.await {
+        Ok(content_type) => content_type.map(|value| value.to_string()),
+        Err(e) => {
+            debug!("Could not read the content type of {}: {e}", url.item);
+            None
+        }
+    }
+}
+
+/// Extensions whose `from` commands may parse data of the content type, most specific first
+pub fn content_type_extensions(content_type: &str) -> Vec<String> {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    let (_, subtype) = essence.split_once('/').unwrap_or_default();
+    let mut extensions: Vec<String> = match subtype {
+        "yaml" | "x-yaml" => vec!["yaml".into()],
+        "tab-separated-values" => vec!["tsv".into()],
+        "toml" => vec!["toml".into()],
+        "msgpack" | "x-msgpack" | "vnd.msgpack" => vec!["msgpack".into()],
+        _ => vec![],
+    };
+    // Structured syntax suffixes, e.g. application/ld+json
+    if let Some((_, suffix)) = subtype.rsplit_once('+') {
+        extensions.push(suffix.into());
+    }
+    extensions.extend(
+        mime_guess::get_mime_extensions_str(&essence)
+            .into_iter()
+            .flatten()
+            .map(|ext| ext.to_string()),
+    );
+    extensions
+}
+
 fn detect_content_type(extension: &str) -> Option<String> {
     // This will allow the overriding of metadata to be consistent with
     // the content type
@@ -0,0 +1,426 @@
+use std::{error::Error, vec};
+
+use bytes::Bytes;
+use nu_plugin::{EngineInterface, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, Record, ShellError, Signature, Span,
+    SyntaxShape, Type, Value, record,
+};
+use object_store::{GetOptions, GetRange, path::Path};
+
+use super::{
+    run_flags,
+    thrift::{self, Struct, Thrift},
+    with_rate_limit, with_request_log, with_timeout,
+};
+use crate::{CloudPlugin, providers::NuObjectStore, remote::RemotePath};
+
+/// Bytes read from the end of the file at first, enough for the footer of most files
+const FOOTER_READ: u64 = 64 * 1024;
+const MAGIC: &[u8] = b"PAR1";
+/// Magic of files with an encrypted footer
+const ENCRYPTED_MAGIC: &[u8] = b"PARE";
+
+pub struct ParquetMeta;
+
+impl PluginCommand for ParquetMeta {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud parquet-meta"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            Signature::build("cloud parquet-meta")
+                .input_output_types(vec![(Type::Nothing, Type::record())])
+                .required("uri", SyntaxShape::String, "The url of the Parquet file.")
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Read the schema, row groups, column statistics, and compression of a Parquet file from its footer, without downloading the data."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Show the schema of a Parquet file in s3.",
+                example: "cloud parquet-meta s3://mybucket/data.parquet | get schema",
+                result: None,
+            },
+            Example {
+                description: "Show the statistics of the columns of the first row group.",
+                example: "cloud parquet-meta s3://mybucket/data.parquet | get row_groups.0.columns",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &nu_plugin::EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &nu_plugin::EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
+
+    let metadata =
+        read_metadata(&object_store, &path)
+            .await
+            .map_err(|e| ShellError::GenericError {
+                error: format!("Could not read Parquet metadata of {}: {e}", url.item),
+                msg: "".into(),
+                span: Some(url.span),
+                help: None,
+                inner: vec![],
+            })?;
+    Ok(PipelineData::Value(
+        file_metadata(&metadata, call_span),
+        None,
+    ))
+}
+
+async fn read_metadata(
+    store: &NuObjectStore,
+    path: &Path,
+) -> Result<Struct, Box<dyn Error + Send + Sync>> {
+    let footer = read_footer(store, path).await?;
+    thrift::decode_struct(&footer).map_err(|e| format!("the footer is invalid: {e}").into())
+}
+
+/// Read the encoded file metadata at the end of the file, with a second ranged read if it
+/// is larger than [`FOOTER_READ`]
+async fn read_footer(
+    store: &NuObjectStore,
+    path: &Path,
+) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+    let options = GetOptions {
+        range: Some(GetRange::Suffix(FOOTER_READ)),
+        ..GetOptions::default()
+    };
+    let result = store.object_store().get_opts(path, options).await?;
+    let size = result.meta.size;
+    let tail_start = result.range.start;
+    let tail = result.bytes().await?;
+
+    // The file ends with the metadata length and the magic
+    if size < 12 || tail.len() < 8 {
+        return Err("not a Parquet file, it is too small".into());
+    }
+    let (len, magic) = tail[tail.len() - 8..].split_at(4);
+    match magic {
+        MAGIC => {}
+        ENCRYPTED_MAGIC => return Err("files with encrypted footers are not supported".into()),
+        _ => return Err("not a Parquet file, the magic bytes are missing".into()),
+    }
+    let len = u32::from_le_bytes(len.try_into().expect("4 bytes")) as u64;
+    if len + 12 > size {
+        return Err("the footer length exceeds the file size".into());
+    }
+
+    let metadata_start = size - 8 - len;
+    let tail = tail.slice(..tail.len() - 8);
+    if metadata_start >= tail_start {
+        return Ok(tail.slice((metadata_start - tail_start) as usize..));
+    }
+    let head = store
+        .object_store()
+        .get_range(path, metadata_start..tail_start)
+        .await?;
+    Ok([head, tail].concat().into())
+}
+
+/// The `FileMetaData` of the footer as a record
+fn file_metadata(metadata: &Struct, span: Span) -> Value {
+    let schema = metadata.list(2);
+    let key_value_metadata = metadata
+        .list(5)
+        .iter()
+        .filter_map(Thrift::as_struct)
+        .map(|kv| {
+            let value = kv
+                .string(2)
+                .map_or(Value::nothing(span), |v| Value::string(v, span));
+            (kv.string(1).unwrap_or_default(), value)
+        })
+        .collect::<Record>();
+
+    Value::record(
+        record!(
+            "version" => optional_int(metadata.int(1), span),
+            "num_rows" => optional_int(metadata.int(3), span),
+            "created_by" => optional_string(metadata.string(6), span),
+            // The first element is the root of the schema
+            "schema" => Value::list(
+                schema
+                    .iter()
+                    .skip(1)
+                    .filter_map(Thrift::as_struct)
+                    .map(|element| schema_element(element, span))
+                    .collect(),
+                span,
+            ),
+            "row_groups" => Value::list(
+                metadata
+                    .list(4)
+                    .iter()
+                    .filter_map(Thrift::as_struct)
+                    .map(|group| row_group(group, span))
+                    .collect(),
+                span,
+            ),
+            "key_value_metadata" => Value::record(key_value_metadata, span),
+        ),
+        span,
+    )
+}
+
+fn schema_element(element: &Struct, span: Span) -> Value {
+    let physical_type = match element.int(5) {
+        Some(_) => Some("GROUP"),
+        None => element.int(1).map(physical_type_name),
+    };
+    Value::record(
+        record!(
+            "name" => optional_string(element.string(4), span),
+            "type" => optional_string(physical_type, span),
+            "repetition" => optional_string(element.int(3).map(repetition_name), span),
+            "logical_type" => optional_string(
+                element.child(10).and_then(Struct::union_field).map(logical_type_name),
+                span,
+            ),
+            "converted_type" => optional_string(element.int(6).map(converted_type_name), span),
+            "num_children" => optional_int(element.int(5), span),
+        ),
+        span,
+    )
+}
+
+fn row_group(row_group: &Struct, span: Span) -> Value {
+    Value::record(
+        record!(
+            "num_rows" => optional_int(row_group.int(3), span),
+            "total_byte_size" => optional_filesize(row_group.int(2), span),
+            "compressed_size" => optional_filesize(row_group.int(6), span),
+            "columns" => Value::list(
+                row_group
+                    .list(1)
+                    .iter()
+                    .filter_map(Thrift::as_struct)
+                    .filter_map(|chunk| chunk.child(3))
+                    .map(|column| column_chunk(column, span))
+                    .collect(),
+                span,
+            ),
+        ),
+        span,
+    )
+}
+
+/// A `ColumnMetaData` with its statistics
+fn column_chunk(column: &Struct, span: Span) -> Value {
+    let physical_type = column.int(1);
+    let path = column
+        .list(3)
+        .iter()
+        .filter_map(|part| match part {
+            Thrift::Binary(part) => Some(String::from_utf8_lossy(part).into_owned()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(".");
+    let encodings = column
+        .list(2)
+        .iter()
+        .filter_map(Thrift::as_int)
+        .map(|encoding| Value::string(encoding_name(encoding), span))
+        .collect();
+    let statistics = column.child(12).cloned().unwrap_or_default();
+    // `min_value` and `max_value` replace `min` and `max`, which are kept for old files
+    let stat = |new: i16, old: i16| {
+        statistics
+            .binary(new)
+            .or_else(|| statistics.binary(old))
+            .map_or(Value::nothing(span), |data| {
+                statistic(physical_type, data, span)
+            })
+    };
+
+    Value::record(
+        record!(
+            "path" => Value::string(path, span),
+            "type" => optional_string(physical_type.map(physical_type_name), span),
+            "compression" => optional_string(column.int(4).map(codec_name), span),
+            "encodings" => Value::list(encodings, span),
+            "num_values" => optional_int(column.int(5), span),
+            "compressed_size" => optional_filesize(column.int(7), span),
+            "uncompressed_size" => optional_filesize(column.int(6), span),
+            "null_count" => optional_int(statistics.int(3), span),
+            "distinct_count" => optional_int(statistics.int(4), span),
+            "min" => stat(6, 2),
+            "max" => stat(5, 1),
+        ),
+        span,
+    )
+}
+
+/// A min or max statistic, which is plain encoded in the column's physical type
+fn statistic(physical_type: Option<i64>, data: &[u8], span: Span) -> Value {
+    match (physical_type, data.len()) {
+        (Some(0), 1) => Value::bool(data[0] != 0, span),
+        (Some(1), 4) => Value::int(
+            i32::from_le_bytes(data.try_into().expect("4 bytes")) as i64,
+            span,
+        ),
+        (Some(2), 8) => Value::int(i64::from_le_bytes(data.try_into().expect("8 bytes")), span),
+        (Some(4), 4) => Value::float(
+            f32::from_le_bytes(data.try_into().expect("4 bytes")) as f64,
+            span,
+        ),
+        (Some(5), 8) => Value::float(f64::from_le_bytes(data.try_into().expect("8 bytes")), span),
+        (Some(6), _) => match std::str::from_utf8(data) {
+            Ok(s) => Value::string(s, span),
+            Err(_) => Value::binary(data, span),
+        },
+        _ => Value::binary(data, span),
+    }
+}
+
+fn optional_int(n: Option<i64>, span: Span) -> Value {
+    n.map_or(Value::nothing(span), |n| Value::int(n, span))
+}
+
+fn optional_filesize(n: Option<i64>, span: Span) -> Value {
+    n.map_or(Value::nothing(span), |n| Value::filesize(n, span))
+}
+
+fn optional_string(s: Option<impl Into<String>>, span: Span) -> Value {
+    s.map_or(Value::nothing(span), |s| Value::string(s, span))
+}
+
+fn physical_type_name(n: i64) -> &'static str {
+    match n {
+        0 => "BOOLEAN",
+        1 => "INT32",
+        2 => "INT64",
+        3 => "INT96",
+        4 => "FLOAT",
+        5 => "DOUBLE",
+        6 => "BYTE_ARRAY",
+        7 => "FIXED_LEN_BYTE_ARRAY",
+        _ => "UNKNOWN",
+    }
+}
+
+fn repetition_name(n: i64) -> &'static str {
+    match n {
+        0 => "REQUIRED",
+        1 => "OPTIONAL",
+        2 => "REPEATED",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Name of a `LogicalType` by the id of its union field
+fn logical_type_name(id: i16) -> &'static str {
+    match id {
+        1 => "STRING",
+        2 => "MAP",
+        3 => "LIST",
+        4 => "ENUM",
+        5 => "DECIMAL",
+        6 => "DATE",
+        7 => "TIME",
+        8 => "TIMESTAMP",
+        10 => "INTEGER",
+        11 => "NULL",
+        12 => "JSON",
+        13 => "BSON",
+        14 => "UUID",
+        15 => "FLOAT16",
+        16 => "VARIANT",
+        17 => "GEOMETRY",
+        18 => "GEOGRAPHY",
+        _ => "UNKNOWN",
+    }
+}
+
+fn converted_type_name(n: i64) -> &'static str {
+    match n {
+        0 => "UTF8",
+        1 => "MAP",
+        2 => "MAP_KEY_VALUE",
+        3 => "LIST",
+        4 => "ENUM",
+        5 => "DECIMAL",
+        6 => "DATE",
+        7 => "TIME_MILLIS",
+        8 => "TIME_MICROS",
+        9 => "TIMESTAMP_MILLIS",
+        10 => "TIMESTAMP_MICROS",
+        11 => "UINT_8",
+        12 => "UINT_16",
+        13 => "UINT_32",
+        14 => "UINT_64",
+        15 => "INT_8",
+        16 => "INT_16",
+        17 => "INT_32",
+        18 => "INT_64",
+        19 => "JSON",
+        20 => "BSON",
+        21 => "INTERVAL",
+        _ => "UNKNOWN",
+    }
+}
+
+fn codec_name(n: i64) -> &'static str {
+    match n {
+        0 => "UNCOMPRESSED",
+        1 => "SNAPPY",
+        2 => "GZIP",
+        3 => "LZO",
+        4 => "BROTLI",
+        5 => "LZ4",
+        6 => "ZSTD",
+        7 => "LZ4_RAW",
+        _ => "UNKNOWN",
+    }
+}
+
+fn encoding_name(n: i64) -> &'static str {
+    match n {
+        0 => "PLAIN",
+        2 => "PLAIN_DICTIONARY",
+        3 => "RLE",
+        4 => "BIT_PACKED",
+        5 => "DELTA_BINARY_PACKED",
+        6 => "DELTA_LENGTH_BYTE_ARRAY",
+        7 => "DELTA_BYTE_ARRAY",
+        8 => "RLE_DICTIONARY",
+        9 => "BYTE_STREAM_SPLIT",
+        _ => "UNKNOWN",
+    }
+}
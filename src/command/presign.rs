@@ -0,0 +1,203 @@
+use std::time::Duration;
+
+use http::Method;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
+    Type, Value, record,
+};
+
+use super::{duration_flag, run_flags, with_rate_limit, with_request_log, with_timeout};
+use crate::{
+    CloudPlugin,
+    error::{Action, store_error},
+    providers::{concat::MAX_PARTS, presign::MAX_EXPIRY},
+    remote::RemotePath,
+};
+
+pub struct Presign;
+
+impl PluginCommand for Presign {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud presign"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            Signature::build("cloud presign")
+                .input_output_types(vec![
+                    (Type::Nothing, Type::String),
+                    (Type::Nothing, Type::record()),
+                ])
+                .required("uri", SyntaxShape::String, "The url of the object to presign.")
+                .named(
+                    "method",
+                    SyntaxShape::String,
+                    "Request the url is signed for, get to download the object or put to upload it (default: get)",
+                    Some('m'),
+                )
+                .named(
+                    "expires",
+                    SyntaxShape::Duration,
+                    "How long the url can be used for, up to 7 days (default: 1hr)",
+                    Some('e'),
+                )
+                .named(
+                    "content-type",
+                    SyntaxShape::String,
+                    "Content type uploads must be sent with, which the object gets",
+                    None,
+                )
+                .named(
+                    "parts",
+                    SyntaxShape::Int,
+                    "Start a multipart upload and presign this many parts of it, with its completion",
+                    None,
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Create a temporary url of an object in an S3 bucket, to download or upload it without credentials."
+    }
+
+    fn extra_description(&self) -> &str {
+        "The url is signed with the credentials of the bucket and works until it expires, for anyone who has it. With --content-type, uploads must be sent with that Content-Type header. With --parts, a multipart upload is started and a record is returned with its upload_id, the urls to put each part to, the url to post the CompleteMultipartUpload document with the ETags of the parts to, and the url to delete to abort it."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Share a download link for a day.",
+                example: "cloud presign s3://mybucket/report.pdf --expires 1day",
+                result: None,
+            },
+            Example {
+                description: "Give a partner a slot to upload a CSV file to.",
+                example: "cloud presign s3://mybucket/inbox/partner.csv --method put --content-type text/csv",
+                result: None,
+            },
+            Example {
+                description: "Let a partner upload a large file in 20 parts.",
+                example: "cloud presign s3://mybucket/inbox/dump.tar --parts 20 --expires 2day",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let method = match call.get_flag::<Spanned<String>>("method")? {
+        Some(method) => match method.item.to_ascii_lowercase().as_str() {
+            "get" => Method::GET,
+            "put" => Method::PUT,
+            other => {
+                return Err(ShellError::IncorrectValue {
+                    msg: format!("Can't presign {other} requests, expected get or put"),
+                    val_span: method.span,
+                    call_span,
+                });
+            }
+        },
+        None => Method::GET,
+    };
+    let expires_in = match duration_flag(call, "expires", false).map_err(|e| *e)? {
+        Some(expires_in) => {
+            if expires_in < Duration::from_secs(1) || expires_in > MAX_EXPIRY {
+                return Err(ShellError::IncorrectValue {
+                    msg: "Urls expire after a second to 7 days".into(),
+                    val_span: call.get_flag_span("expires").unwrap_or(call_span),
+                    call_span,
+                });
+            }
+            expires_in
+        }
+        None => DEFAULT_EXPIRY,
+    };
+    let content_type: Option<String> = call.get_flag("content-type")?;
+    let parts = match call.get_flag::<i64>("parts")? {
+        Some(parts) if parts < 1 || parts > MAX_PARTS as i64 => {
+            return Err(ShellError::IncorrectValue {
+                msg: format!("Uploads have 1 to {MAX_PARTS} parts"),
+                val_span: call.get_flag_span("parts").unwrap_or(call_span),
+                call_span,
+            });
+        }
+        Some(parts) => Some(parts as usize),
+        None => None,
+    };
+    if content_type.is_some() && method != Method::PUT && parts.is_none() {
+        return Err(ShellError::IncorrectValue {
+            msg: "Only uploads have a content type, presign them with --method put or --parts"
+                .into(),
+            val_span: call.get_flag_span("content-type").unwrap_or(call_span),
+            call_span,
+        });
+    }
+
+    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
+    let client = object_store
+        .presign_client()
+        .ok_or_else(|| ShellError::GenericError {
+            error: format!("Urls can't be presigned for {} urls", url.item.scheme()),
+            msg: "".into(),
+            span: Some(url.span),
+            help: Some("Urls are only presigned for objects in S3 buckets".into()),
+            inner: vec![],
+        })?;
+    let expires = chrono::Utc::now() + expires_in;
+    let Some(parts) = parts else {
+        let presigned = client
+            .url(&method, &path, content_type.as_deref(), expires_in)
+            .await
+            .map_err(|e| store_error(Action::Read, &url.item, url.span, e))?;
+        return Ok(PipelineData::Value(
+            Value::string(presigned, call_span),
+            None,
+        ));
+    };
+    let upload = client
+        .upload(&path, parts, content_type.as_deref(), expires_in)
+        .await
+        .map_err(|e| store_error(Action::Write, &url.item, url.span, e))?;
+    let string = |value: String| Value::string(value, call_span);
+    let record = record!(
+        "url" => string(url.item.to_string()),
+        "upload_id" => string(upload.upload_id),
+        "parts" => Value::list(upload.parts.into_iter().map(string).collect(), call_span),
+        "complete" => string(upload.complete),
+        "abort" => string(upload.abort),
+        "expires" => Value::date(expires.fixed_offset(), call_span),
+    );
+    Ok(PipelineData::Value(Value::record(record, call_span), None))
+}
+
+const DEFAULT_EXPIRY: Duration = Duration::from_secs(3600);
@@ -0,0 +1,129 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+    sync::Arc,
+};
+
+use bytes::{Buf, Bytes};
+use nu_protocol::{Span, shell_error::bridge::ShellErrorBridge};
+use object_store::{GetOptions, GetRange, ObjectMeta, path::Path};
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+use crate::{
+    error::{Action, store_error},
+    providers::{
+        NuObjectStore,
+        limit::{self, RateLimiter},
+        verbose,
+    },
+};
+
+/// Size of the ranges requested by [`ReadAhead`]
+pub const RANGE_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Reads an object sequentially in ranges, fetching the next ones in the background while the
+/// current one is consumed, so downstream processing and the transfer overlap.
+///
+/// Ranges are requested for the ETag of `meta`, so an object replaced while it is read fails
+/// the read instead of mixing versions.
+pub struct ReadAhead {
+    runtime: Arc<Runtime>,
+    store: NuObjectStore,
+    path: Path,
+    meta: ObjectMeta,
+    /// Ranges in flight, in order
+    pending: VecDeque<JoinHandle<object_store::Result<Bytes>>>,
+    /// Offset of the next range to request
+    next: u64,
+    current: Bytes,
+    /// Ranges fetched ahead of the one being read
+    depth: usize,
+    /// Limit and logging of the command run, as ranges are fetched after it returned
+    run_limit: Option<Arc<RateLimiter>>,
+    verbose: bool,
+    span: Span,
+}
+
+impl ReadAhead {
+    pub fn new(
+        runtime: Arc<Runtime>,
+        store: NuObjectStore,
+        path: Path,
+        meta: ObjectMeta,
+        depth: usize,
+        span: Span,
+    ) -> Self {
+        ReadAhead {
+            runtime,
+            store,
+            path,
+            meta,
+            pending: VecDeque::new(),
+            next: 0,
+            current: Bytes::new(),
+            depth,
+            run_limit: limit::current(),
+            verbose: verbose::enabled(),
+            span,
+        }
+    }
+
+    /// Request ranges until `count` are in flight
+    fn fill(&mut self, count: usize) {
+        while self.pending.len() < count && self.next < self.meta.size {
+            let range = self.next..(self.next + RANGE_SIZE).min(self.meta.size);
+            self.next = range.end;
+            let store = self.store.clone();
+            let path = self.path.clone();
+            let options = GetOptions {
+                range: Some(GetRange::Bounded(range)),
+                if_match: self.meta.e_tag.clone(),
+                ..GetOptions::default()
+            };
+            let fetch = async move {
+                store
+                    .object_store()
+                    .get_opts(&path, options)
+                    .await?
+                    .bytes()
+                    .await
+            };
+            let fetch = limit::scoped(self.run_limit.clone(), fetch);
+            let fetch = verbose::scoped(self.verbose, fetch);
+            self.pending.push_back(self.runtime.spawn(fetch));
+        }
+    }
+
+    fn error(&self, e: object_store::Error) -> io::Error {
+        ShellErrorBridge(store_error(Action::Read, &self.path, self.span, e)).into()
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.current.is_empty() {
+            self.fill(1);
+            let Some(range) = self.pending.pop_front() else {
+                return Ok(0);
+            };
+            self.current = self
+                .runtime
+                .block_on(range)
+                .map_err(|source| self.error(object_store::Error::JoinError { source }))?
+                .map_err(|e| self.error(e))?;
+            // Fetch the next ranges while this one is consumed
+            self.fill(self.depth);
+        }
+        let len = buf.len().min(self.current.len());
+        self.current.copy_to_slice(&mut buf[..len]);
+        Ok(len)
+    }
+}
+
+impl Drop for ReadAhead {
+    fn drop(&mut self) {
+        for range in &self.pending {
+            range.abort();
+        }
+    }
+}
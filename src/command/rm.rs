@@ -1,13 +1,22 @@
-use std::{path::PathBuf, str::FromStr, vec};
+use std::vec;
 
+use futures::{StreamExt, TryStreamExt, stream};
 use nu_plugin::{EngineInterface, PluginCommand};
 use nu_protocol::{
-    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
-    Type,
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, SyntaxShape, Type, Value,
 };
-use url::Url;
 
-use crate::CloudPlugin;
+use super::{
+    OutputFormat, batch_objects, batch_row, concurrency, confirm, confirming, duration_flag,
+    glob_objects, listed_url, object_url, output_format_flag, run_flags, with_rate_limit,
+    with_request_log, with_timeout, yes_flag,
+};
+use crate::{
+    CloudPlugin,
+    audit::{AuditAction, AuditLog},
+    error::{Action, store_error},
+    remote::RemotePath,
+};
 
 pub struct Remove;
 
@@ -19,22 +28,76 @@ impl PluginCommand for Remove {
     }
 
     fn signature(&self) -> nu_protocol::Signature {
-        Signature::build("cloud rm")
-            .input_output_types(vec![(Type::Any, Type::Nothing)])
-            .required("uri", SyntaxShape::String, "The file url to use.")
-            .category(Category::FileSystem)
+        run_flags(
+            output_format_flag(yes_flag(Signature::build("cloud rm")))
+                .input_output_types(vec![(Type::Any, Type::Any)])
+                .optional(
+                    "uri",
+                    SyntaxShape::String,
+                    "The file url to use, which may have a glob pattern in its path, or the url the objects of a batch are under, by default the url cloud ls listed them under.",
+                )
+                .switch(
+                    "recursive",
+                    "Remove every object under the url, or under prefixes matching its pattern",
+                    Some('r'),
+                )
+                .named(
+                    "older-than",
+                    SyntaxShape::Duration,
+                    "Only remove the objects listed with --recursive or a glob that were last modified longer ago than this",
+                    None,
+                )
+                .named(
+                    "concurrency",
+                    SyntaxShape::Int,
+                    "Number of objects of a batch removed at once (default: concurrency of the config, or 8)",
+                    Some('j'),
+                )
+                .category(Category::FileSystem),
+        )
     }
 
     fn description(&self) -> &str {
         "Remove a file from cloud sotrage"
     }
 
-    fn examples(&self) -> Vec<Example> {
-        vec![Example {
-            description: "Remove a file from s3.",
-            example: "cloud rm s3://mybucket/file.txt",
-            result: None,
-        }]
+    fn extra_description(&self) -> &str {
+        "Given a list of names as input, like the output of cloud ls, the objects with those paths within the bucket of the url are removed instead, --concurrency at a time. They must be under the url, which may be left out for rows of cloud ls. Returns a row per object with its url, and the error if it couldn't be removed."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Remove a file from s3.",
+                example: "cloud rm s3://mybucket/file.txt",
+                result: None,
+            },
+            Example {
+                description: "Remove everything under a prefix.",
+                example: "cloud rm -r s3://mybucket/logs/",
+                result: None,
+            },
+            Example {
+                description: "Remove the compressed logs of every day.",
+                example: "cloud rm 's3://mybucket/logs/**/*.gz'",
+                result: None,
+            },
+            Example {
+                description: "Remove the temporary files more than 30 days old.",
+                example: "cloud rm --recursive --older-than 30day s3://mybucket/tmp/",
+                result: None,
+            },
+            Example {
+                description: "Remove the temporary files listed under a prefix.",
+                example: "cloud ls s3://mybucket/uploads/ | where name =~ '\\.tmp$' | cloud rm",
+                result: None,
+            },
+            Example {
+                description: "Remove the large logs, with a row per object.",
+                example: "cloud ls --recursive s3://mybucket/logs/ | where size > 1GB | cloud rm s3://mybucket/logs/",
+                result: None,
+            },
+        ]
     }
 
     fn run(
@@ -42,11 +105,20 @@ impl PluginCommand for Remove {
         plugin: &Self::Plugin,
         engine: &EngineInterface,
         call: &nu_plugin::EvaluatedCall,
-        _input: PipelineData,
+        input: PipelineData,
     ) -> Result<PipelineData, LabeledError> {
         plugin
-            .rt
-            .block_on(command(engine, plugin, call))
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(
+                    call,
+                    with_timeout(call, command(engine, plugin, call, input)),
+                ),
+            ))
             .map_err(LabeledError::from)
     }
 }
@@ -55,35 +127,150 @@ async fn command(
     engine: &EngineInterface,
     plugin: &CloudPlugin,
     call: &nu_plugin::EvaluatedCall,
+    input: PipelineData,
 ) -> Result<PipelineData, ShellError> {
     let call_span = call.head;
-    let url_path: Spanned<PathBuf> = call.req(0)?;
-    let url = url_path
-        .item
-        .to_str()
-        .expect("The path should already be unicode")
-        .to_string();
-    let url = Spanned {
-        item: Url::from_str(&url).map_err(|e| ShellError::IncorrectValue {
-            msg: format!("Invalid Url: {e}"),
-            val_span: url_path.span,
-            call_span,
-        })?,
-        span: url_path.span,
+    let (remote, glob) = match listed_url(&input) {
+        // The names of rows of cloud ls are under the url it listed
+        Some(listed) if call.positional.is_empty() => (
+            RemotePath::parse(&listed, call_span, call_span).map_err(|e| *e)?,
+            None,
+        ),
+        _ if call.positional.is_empty() => {
+            return Err(ShellError::MissingParameter {
+                param_name: "uri".into(),
+                span: call_span,
+            });
+        }
+        _ => {
+            let remote = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?;
+            let glob = remote.glob().map_err(|e| *e)?;
+            (remote, glob)
+        }
     };
-    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
-
-    object_store
-        .object_store()
-        .delete(&path)
-        .await
-        .map_err(|e| ShellError::GenericError {
-            error: format!("Could not delete delete from cloud storage: {}", e),
+    let url = remote.url;
+    // Globs are expanded by listing the prefix before the pattern
+    let base = glob.as_ref().map_or(&url, |glob| &glob.url);
+    let (object_store, path) = plugin.parse_url(engine, base, call_span).await?;
+    let delete_error = |e| store_error(Action::Delete, &url.item, url.span, e);
+    let format = OutputFormat::from_call(call).map_err(|e| *e)?;
+    let recursive = call.has_flag("recursive")?;
+    let older_than = duration_flag(call, "older-than", false)
+        .map_err(|e| *e)?
+        .map(|age| chrono::Utc::now() - chrono::Duration::nanoseconds(age.as_nanos() as i64));
+    let audit = AuditLog::open(engine, call).map_err(|e| *e)?;
+    let mut removed = vec![];
+    let batch = match glob {
+        Some(_) => None,
+        None => batch_objects(input, &url, &path, call_span).map_err(|e| *e)?,
+    };
+    if older_than.is_some() && (batch.is_some() || !(glob.is_some() || recursive)) {
+        return Err(ShellError::GenericError {
+            error: "--older-than only filters the objects listed with --recursive or a glob".into(),
             msg: "".into(),
-            span: Some(call_span),
-            help: None,
+            span: call.get_flag_span("older-than"),
+            help: Some("Filter a batch on its modified column instead, e.g. with where".into()),
             inner: vec![],
-        })?;
+        });
+    }
+    let old = |modified: &chrono::DateTime<chrono::Utc>| {
+        older_than.is_none_or(|cutoff| *modified < cutoff)
+    };
+    plugin.cache.invalidate(&object_store, &path).await;
+
+    if let Some(objects) = batch {
+        let concurrency = concurrency(engine, call).map_err(|e| *e)?;
+        if confirming(engine, call).map_err(|e| *e)? {
+            let question = format!("Remove the {} objects of the input?", objects.len());
+            confirm(engine, &question, call_span).map_err(|e| *e)?;
+        }
+        let store = object_store.object_store();
+        let mut deletes = stream::iter(objects)
+            .map(|object| async move {
+                let result = store.delete(&object.location).await;
+                (object.location, result)
+            })
+            .buffered(concurrency);
+        let mut rows = vec![];
+        while let Some((location, result)) = deletes.next().await {
+            engine.signals().check(&call_span)?;
+            let object = object_url(&url.item, &path, &location);
+            if let Some(audit) = &audit {
+                audit.record(AuditAction::Delete, &object, result.as_ref().map(|_| ()));
+            }
+            let error = result.err().map(&delete_error);
+            rows.push(batch_row(object, None, None, error, call_span));
+        }
+        return Ok(PipelineData::Value(Value::list(rows, call_span), None));
+    }
+
+    if glob.is_some() || recursive {
+        let store = object_store.object_store();
+        let locations = match &glob {
+            Some(glob) => {
+                let mut objects = glob_objects(&object_store, glob, &path, recursive).await?;
+                objects.retain(|meta| old(&meta.last_modified));
+                if confirming(engine, call).map_err(|e| *e)? {
+                    let question = format!("Remove the {} objects matching {glob}?", objects.len());
+                    confirm(engine, &question, call_span).map_err(|e| *e)?;
+                }
+                stream::iter(objects.into_iter().map(|meta| Ok(meta.location))).boxed()
+            }
+            None => {
+                if confirming(engine, call).map_err(|e| *e)? {
+                    let question = match older_than {
+                        Some(cutoff) => format!(
+                            "Remove every object under {} last modified before {}?",
+                            url.item,
+                            cutoff.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+                        ),
+                        None => format!("Remove every object under {}?", url.item),
+                    };
+                    confirm(engine, &question, call_span).map_err(|e| *e)?;
+                }
+                store
+                    .list(Some(&path))
+                    .try_filter(move |meta| std::future::ready(old(&meta.last_modified)))
+                    .map_ok(|meta| meta.location)
+                    .boxed()
+            }
+        };
+
+        // Stores batch these into bulk deletes where supported, e.g. 1000 keys per S3
+        // request, and otherwise issue a bounded number of deletes concurrently
+        let mut deleted = store.delete_stream(locations);
+        while let Some(result) = deleted.next().await {
+            engine.signals().check(&call_span)?;
+            let location = match result {
+                Ok(location) => location,
+                Err(e) => {
+                    // Failed deletes of a batch aren't reported by location
+                    if let Some(audit) = &audit {
+                        audit.record(AuditAction::Delete, &url.item, Err(&e));
+                    }
+                    return Err(delete_error(e));
+                }
+            };
+            if let Some(audit) = &audit {
+                let object = object_url(&base.item, &path, &location);
+                audit.record(AuditAction::Delete, &object, Ok::<_, &str>(()));
+            }
+            // Only kept when reported, as prefixes may hold millions of objects
+            if format.is_some() {
+                removed.push((object_url(&base.item, &path, &location), None));
+            }
+        }
+    } else {
+        let result = object_store.object_store().delete(&path).await;
+        if let Some(audit) = &audit {
+            audit.record(AuditAction::Delete, &url.item, result.as_ref().map(|_| ()));
+        }
+        result.map_err(delete_error)?;
+        removed.push((url.item.to_string(), None));
+    }
 
-    Ok(PipelineData::empty())
+    Ok(match format {
+        Some(format) => format.output(&url.item, removed, call_span),
+        None => PipelineData::empty(),
+    })
 }
@@ -1,7 +1,6 @@
 use std::{
     io::{ErrorKind, Read},
-    path::PathBuf,
-    str::FromStr,
+    sync::Arc,
     vec,
 };
 
@@ -10,13 +9,29 @@ use log::debug;
 use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
 use nu_protocol::{
     ByteStreamSource, Category, Example, LabeledError, ListStream, PipelineData, ShellError,
-    Signals, Signature, Span, Spanned, SyntaxShape, Type, Value, process::ChildPipe,
-    shell_error::io::IoError,
+    Signals, Signature, Span, Spanned, SyntaxShape, Type, Value,
+    ast::PathMember,
+    process::ChildPipe,
+    shell_error::{bridge::ShellErrorBridge, io::IoError},
 };
-use object_store::{PutPayload, WriteMultipart};
+use object_store::{PutPayload, PutPayloadMut, path::Path};
+use sha2::{Digest, Sha256};
 use url::Url;
 
-use crate::CloudPlugin;
+use super::{
+    OutputFormat, avro, bandwidth_limit, bwlimit_flag, confirm_overwrite, jobs,
+    multipart::{MultipartWriter, PartSize},
+    output_format_flag, run_flags, with_rate_limit, with_request_log, with_timeout, yes_flag,
+};
+use crate::{
+    CloudPlugin,
+    audit::{AuditAction, AuditLog},
+    config::Config,
+    error::{Action, store_error},
+    providers::{NuObjectStore, limit::RateLimiter},
+    remote::RemotePath,
+    transfer::{Journal, JournaledUpload, Replay},
+};
 
 pub struct Save;
 
@@ -28,19 +43,60 @@ impl PluginCommand for Save {
     }
 
     fn signature(&self) -> nu_protocol::Signature {
-        Signature::build("cloud save")
-            .input_output_types(vec![(Type::Any, Type::Nothing)])
-            .required("uri", SyntaxShape::String, "The file url to use.")
-            .switch("raw", "save file as raw binary", Some('r'))
-            .category(Category::FileSystem)
+        run_flags(
+            output_format_flag(yes_flag(bwlimit_flag(Signature::build("cloud save"))))
+                .input_output_types(vec![(Type::Any, Type::Any)])
+                .required("uri", SyntaxShape::String, "The file url to use.")
+                .switch("raw", "save file as raw binary", Some('r'))
+                .named(
+                    "part-size",
+                    SyntaxShape::Filesize,
+                    "Fixed size of upload parts, in place of upload_part_size of the config",
+                    None,
+                )
+                .named(
+                    "to-flags",
+                    SyntaxShape::Record(vec![]),
+                    "Flags of the to command converting the input to the format of the url, e.g. {noheaders: true}",
+                    None,
+                )
+                .switch(
+                    "resume",
+                    "Continue an interrupted upload of the same input",
+                    None,
+                )
+                .switch(
+                    "background",
+                    "Upload in the plugin after the command returns the id of its job, see cloud jobs",
+                    Some('b'),
+                )
+                .switch(
+                    "by-hash",
+                    "Save under the url as a prefix, named after the SHA-256 of the data, and return the object's url",
+                    None,
+                )
+                .category(Category::FileSystem),
+        )
     }
 
-    fn examples(&self) -> Vec<Example> {
-        vec![Example {
-            description: "Save a csv file to s3.",
-            example: "[[a b]; [1 1] [1 2] [2 1] [2 2] [3 1] [3 2]] | to csv | cloud save s3://mybucket/file.csv",
-            result: None,
-        }]
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Save a csv file to s3.",
+                example: "[[a b]; [1 1] [1 2] [2 1] [2 2] [3 1] [3 2]] | to csv | cloud save s3://mybucket/file.csv",
+                result: None,
+            },
+            Example {
+                description: "Save a table as csv without its header row.",
+                example: "[[a b]; [1 1] [1 2]] | cloud save --to-flags {noheaders: true} s3://mybucket/rows.csv",
+                result: None,
+            },
+            Example {
+                description: "Store a build artifact once, however often it is saved.",
+                example: "open --raw target/release/app | cloud save --by-hash s3://mybucket/cas/",
+                result: None,
+            },
+        ]
     }
 
     fn description(&self) -> &str {
@@ -55,8 +111,17 @@ impl PluginCommand for Save {
         input: PipelineData,
     ) -> Result<PipelineData, LabeledError> {
         plugin
-            .rt
-            .block_on(command(plugin, engine, call, input))
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(
+                    call,
+                    with_timeout(call, command(plugin, engine, call, input)),
+                ),
+            ))
             .map_err(|e| LabeledError::from(*e))
     }
 }
@@ -69,143 +134,618 @@ async fn command(
 ) -> Result<PipelineData, Box<ShellError>> {
     let raw = call.has_flag("raw")?;
     let call_span = call.head;
-    let url_path: Spanned<PathBuf> = call.req(0)?;
-    let url = url_path
-        .item
-        .to_str()
-        .expect("The path should already be unicode")
-        .to_string();
-    let url = Spanned {
-        item: Url::from_str(&url).map_err(|e| ShellError::IncorrectValue {
-            msg: format!("Invalid Url: {e}"),
-            val_span: url_path.span,
-            call_span,
-        })?,
-        span: url_path.span,
+    let bwlimit = bandwidth_limit(engine, call)?;
+    let config = upload_config(engine, call)?;
+    let resume = call.has_flag("resume")?;
+    let remote = RemotePath::from_call(engine, call, 0)?;
+    let url = &remote.url;
+
+    let format = OutputFormat::from_call(call)?;
+    let background = call.has_flag("background")?;
+
+    if call.has_flag("by-hash")? {
+        if background {
+            return Err(Box::new(ShellError::IncompatibleParameters {
+                left_message: "can't save by hash".into(),
+                left_span: call.get_flag_span("by-hash").unwrap_or(call_span),
+                right_message: "in the background".into(),
+                right_span: call.get_flag_span("background").unwrap_or(call_span),
+            }));
+        }
+        let bytes = input_to_bytes(input, &remote, true, engine, call, call_span)?;
+        let size = bytes.len() as u64;
+        let object = save_by_hash(
+            plugin, engine, bytes, url, config, call_span, bwlimit, resume,
+        )
+        .await?;
+        return Ok(match format {
+            Some(format) => {
+                format.output(&url.item, vec![(object.to_string(), Some(size))], call_span)
+            }
+            None => PipelineData::Value(Value::string(object.to_string(), call_span), None),
+        });
+    }
+
+    let (object_store, path) = plugin.parse_url(engine, url, call_span).await?;
+    let audit = AuditLog::open(engine, call)?;
+    let overwrite = confirm_overwrite(
+        engine,
+        call,
+        &object_store,
+        &path,
+        &url.item,
+        audit.as_ref(),
+    )
+    .await?;
+    plugin.cache.invalidate(&object_store, &path).await;
+
+    let Some(input) = Input::new(input, &remote, raw, engine, call, call_span)? else {
+        // Without output from an external command nothing is written
+        return Ok(PipelineData::Empty);
+    };
+    let target = Target {
+        object_store,
+        path,
+        url: url.clone(),
+        config,
+        // Background jobs are stopped by `cloud jobs cancel` rather than ctrl-c
+        signals: match background {
+            true => Signals::empty(),
+            false => engine.signals().clone(),
+        },
+        bwlimit,
+        resume,
+    };
+    let save = async move {
+        let size = upload(&target, input, call_span).await;
+        if let Some(audit) = audit.as_ref().filter(|_| overwrite) {
+            audit.record(
+                AuditAction::Overwrite,
+                &target.url.item,
+                size.as_ref().map(|_| ()),
+            );
+        }
+        size
     };
 
-    match input {
-        PipelineData::ByteStream(stream, _metadata) => {
-            debug!("Handling byte stream");
+    if background {
+        return jobs::spawn(plugin, engine, call, url, save);
+    }
+    let size = save.await?;
+    Ok(match format {
+        Some(format) => format.output(
+            &url.item,
+            vec![(url.item.to_string(), Some(size))],
+            call_span,
+        ),
+        None => PipelineData::empty(),
+    })
+}
 
-            match stream.into_source() {
-                ByteStreamSource::Read(read) => {
-                    bytestream_to_cloud(plugin, engine, read, &url, call_span).await?;
-                }
-                ByteStreamSource::File(source) => {
-                    bytestream_to_cloud(plugin, engine, source, &url, call_span).await?;
-                }
-                ByteStreamSource::Child(mut child) => {
-                    if let Some(stdout) = child.stdout.take() {
-                        let res = match stdout {
-                            ChildPipe::Pipe(pipe) => {
-                                bytestream_to_cloud(plugin, engine, pipe, &url, call_span).await
-                            }
-                            ChildPipe::Tee(tee) => {
-                                bytestream_to_cloud(plugin, engine, tee, &url, call_span).await
-                            }
-                        };
-                        res?;
-                    };
-                }
+/// Input of a save, sorted by how it is uploaded
+enum Input {
+    Read(Box<dyn Read + Send>, Option<u64>),
+    List(ListStream),
+    Ndjson(PipelineData),
+    Bytes(Bytes),
+}
+
+impl Input {
+    /// Values are converted to the format of the url here, as converters are commands of the
+    /// engine, which background saves can't call once the command returns. Output of an
+    /// external command that has none is `None`.
+    fn new(
+        input: PipelineData,
+        remote: &RemotePath,
+        raw: bool,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        span: Span,
+    ) -> Result<Option<Self>, Box<ShellError>> {
+        Ok(Some(match input {
+            PipelineData::ByteStream(stream, _metadata) => {
+                debug!("Handling byte stream");
+
+                let size = stream.known_size();
+                let read: Box<dyn Read + Send> = match stream.into_source() {
+                    ByteStreamSource::Read(read) => read,
+                    ByteStreamSource::File(source) => Box::new(source),
+                    ByteStreamSource::Child(mut child) => match child.stdout.take() {
+                        Some(ChildPipe::Pipe(pipe)) => Box::new(pipe),
+                        Some(ChildPipe::Tee(tee)) => tee,
+                        None => return Ok(None),
+                    },
+                };
+                Input::Read(read, size)
+            }
+            PipelineData::ListStream(ls, _pipeline_metadata) if raw => {
+                debug!("Handling list stream");
+                Input::List(ls)
             }
+            input @ (PipelineData::ListStream(..) | PipelineData::Value(Value::List { .. }, _))
+                if is_ndjson(remote) =>
+            {
+                debug!("Handling list as NDJSON");
+                Input::Ndjson(input)
+            }
+            input => {
+                debug!("Handling input");
+                Input::Bytes(input_to_bytes(input, remote, raw, engine, call, span)?)
+            }
+        }))
+    }
+}
 
-            Ok(PipelineData::Empty)
-        }
-        PipelineData::ListStream(ls, _pipeline_metadata) if raw => {
-            debug!("Handling list stream");
-            liststream_to_cloud(plugin, engine, ls, &url, call_span).await?;
-            Ok(PipelineData::empty())
-        }
-        input => {
-            debug!("Handling input");
-            let bytes = input_to_bytes(input, &url_path.item, raw, engine, call, call_span)?;
-            stream_bytes(plugin, engine, bytes, &url, call_span).await?;
-            Ok(PipelineData::empty())
-        }
+/// Upload the input to the target, returning its size
+async fn upload(target: &Target, input: Input, span: Span) -> Result<u64, Box<ShellError>> {
+    match input {
+        Input::Read(read, size) => bytestream_to_cloud(target, read, size, span).await,
+        Input::List(ls) => liststream_to_cloud(target, ls, span).await,
+        Input::Ndjson(input) => ndjson_to_cloud(target, input, span).await,
+        Input::Bytes(bytes) => Ok(stream_bytes(target, bytes).await?),
     }
 }
 
 async fn liststream_to_cloud(
-    plugin: &CloudPlugin,
-    engine: &EngineInterface,
+    target: &Target,
     ls: ListStream,
-    url: &Spanned<Url>,
     span: Span,
-) -> Result<(), Box<ShellError>> {
-    let signals = engine.signals();
-    let (object_store, path) = plugin.parse_url(engine, url, span).await?;
-    let upload = object_store
-        .object_store()
-        .put_multipart(&path)
-        .await
-        .unwrap();
-    let mut write = WriteMultipart::new(upload);
+) -> Result<u64, Box<ShellError>> {
+    let mut write = Upload::to_target(target, None).await?;
 
     for v in ls {
-        signals.check(&span)?;
+        target.signals.check(&span)?;
         let bytes = value_to_bytes(v)?;
-        write.write(&bytes)
+        if let Some(bwlimit) = &target.bwlimit {
+            bwlimit.acquire_many(bytes.len() as u64).await;
+        }
+        write.put(bytes).await?;
     }
 
-    let _ = write.finish().await.map_err(|e| ShellError::GenericError {
-        error: format!("Could not write to S3: {e}"),
-        msg: "".into(),
-        span: None,
-        help: None,
-        inner: vec![],
-    })?;
-
-    Ok(())
+    Ok(write.finish().await?)
 }
 
-async fn bytestream_to_cloud(
+/// Save content-addressed under the `prefix` url, as `<prefix>/ab/cdef...` for a SHA-256 of
+/// `abcdef...`. The upload is skipped if the object already exists.
+#[allow(clippy::too_many_arguments)]
+async fn save_by_hash(
     plugin: &CloudPlugin,
     engine: &EngineInterface,
-    source: impl Read,
-    url: &Spanned<Url>,
+    bytes: Bytes,
+    prefix: &Spanned<Url>,
+    config: Config,
     span: Span,
-) -> Result<(), Box<ShellError>> {
-    stream_to_cloud_async(plugin, engine, source, url, span).await
+    bwlimit: Option<Arc<RateLimiter>>,
+    resume: bool,
+) -> Result<Url, Box<ShellError>> {
+    let hash = Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    let mut url = prefix.item.clone();
+    let path = format!(
+        "{}/{}/{}",
+        url.path().trim_end_matches('/'),
+        &hash[..2],
+        &hash[2..]
+    );
+    url.set_path(&path);
+    let url = Spanned {
+        item: url,
+        span: prefix.span,
+    };
+
+    let (object_store, path) = plugin.parse_url(engine, &url, span).await?;
+    match object_store.object_store().head(&path).await {
+        Ok(_) => debug!("{} already exists, skipping the upload", url.item),
+        Err(object_store::Error::NotFound { .. }) => {
+            let target = Target {
+                object_store,
+                path,
+                url: url.clone(),
+                config,
+                signals: engine.signals().clone(),
+                bwlimit,
+                resume,
+            };
+            stream_bytes(&target, bytes).await?;
+        }
+        Err(e) => return Err(Box::new(store_error(Action::Read, &url.item, url.span, e))),
+    }
+    Ok(url.item)
 }
 
-async fn stream_to_cloud_async(
-    plugin: &CloudPlugin,
+/// The config of the upload, with the part size of `--part-size` in place of `upload_part_size`
+fn upload_config(
     engine: &EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<Config, Box<ShellError>> {
+    let mut config = Config::load(engine)?;
+    if let Some(value) = call.get_flag::<Value>("part-size")? {
+        let size = value.as_filesize()?;
+        if size.get() <= 0 {
+            return Err(Box::new(ShellError::IncorrectValue {
+                msg: "--part-size must be positive".into(),
+                val_span: value.span(),
+                call_span: call.head,
+            }));
+        }
+        config.upload_part_size = Some(size);
+    }
+    Ok(config)
+}
+
+/// Whether the url is for newline-delimited JSON, which is written a line per value
+fn is_ndjson(remote: &RemotePath) -> bool {
+    remote
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jsonl") || ext.eq_ignore_ascii_case("ndjson"))
+}
+
+/// Write each value as a line of JSON as it arrives, so long pipelines aren't buffered
+async fn ndjson_to_cloud(
+    target: &Target,
+    input: PipelineData,
+    span: Span,
+) -> Result<u64, Box<ShellError>> {
+    let mut write = Upload::to_target(target, None).await?;
+
+    let mut line = vec![];
+    for v in input {
+        target.signals.check(&span)?;
+        line.clear();
+        serde_json::to_writer(&mut line, &value_to_json(&v, span)?).map_err(|e| {
+            ShellError::GenericError {
+                error: format!("Could not serialize a value to JSON: {e}"),
+                msg: "".into(),
+                span: Some(v.span()),
+                help: None,
+                inner: vec![],
+            }
+        })?;
+        line.push(b'\n');
+        if let Some(bwlimit) = &target.bwlimit {
+            bwlimit.acquire_many(line.len() as u64).await;
+        }
+        write.write(&line).await?;
+    }
+
+    Ok(write.finish().await?)
+}
+
+/// Convert a value to JSON the way `to json` does
+fn value_to_json(value: &Value, span: Span) -> Result<serde_json::Value, Box<ShellError>> {
+    use serde_json::Value as Json;
+
+    Ok(match value {
+        Value::Bool { val, .. } => Json::Bool(*val),
+        Value::Int { val, .. } => Json::from(*val),
+        Value::Filesize { val, .. } => Json::from(val.get()),
+        Value::Duration { val, .. } => Json::from(*val),
+        Value::Float { val, .. } => Json::from(*val),
+        Value::Date { val, .. } => Json::String(val.to_string()),
+        Value::String { val, .. } => Json::String(val.clone()),
+        Value::Glob { val, .. } => Json::String(val.clone()),
+        Value::Nothing { .. } | Value::Range { .. } => Json::Null,
+        Value::Binary { val, .. } => Json::Array(val.iter().map(|b| Json::from(*b)).collect()),
+        Value::List { vals, .. } => Json::Array(
+            vals.iter()
+                .map(|v| value_to_json(v, span))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Record { val, .. } => Json::Object(
+            val.iter()
+                .map(|(k, v)| Ok((k.clone(), value_to_json(v, span)?)))
+                .collect::<Result<_, Box<ShellError>>>()?,
+        ),
+        Value::CellPath { val, .. } => Json::Array(
+            val.members
+                .iter()
+                .map(|member| match member {
+                    PathMember::String { val, .. } => Json::String(val.clone()),
+                    PathMember::Int { val, .. } => Json::from(*val),
+                })
+                .collect(),
+        ),
+        Value::Custom { val, .. } => value_to_json(&val.to_base_value(value.span())?, span)?,
+        Value::Error { error, .. } => return Err(error.clone()),
+        Value::Closure { .. } => {
+            return Err(Box::new(ShellError::UnsupportedInput {
+                msg: "closures can't be written as JSON".into(),
+                input: "value originates from here".into(),
+                msg_span: span,
+                input_span: value.span(),
+            }));
+        }
+    })
+}
+
+async fn bytestream_to_cloud(
+    target: &Target,
     source: impl Read,
-    url: &Spanned<Url>,
+    size: Option<u64>,
     span: Span,
-) -> Result<(), Box<ShellError>> {
-    let signals = engine.signals();
-    let (object_store, path) = plugin.parse_url(engine, url, span).await?;
-    let upload = object_store
-        .object_store()
-        .put_multipart(&path)
+) -> Result<u64, Box<ShellError>> {
+    let mut write = Upload::to_target(target, size).await?;
+
+    let _ = generic_copy(
+        source,
+        &mut write,
+        span,
+        &target.signals,
+        target.bwlimit.as_deref(),
+    )
+    .await?;
+
+    Ok(write.finish().await?)
+}
+
+/// Where a save writes, resolved before the upload starts, as background saves can't call the
+/// engine once the command returns
+struct Target {
+    object_store: NuObjectStore,
+    path: Path,
+    url: Spanned<Url>,
+    config: Config,
+    signals: Signals,
+    /// Bytes per second read from the input
+    bwlimit: Option<Arc<RateLimiter>>,
+    /// Continue an upload recorded in the transfer journal
+    resume: bool,
+}
+
+/// Destination of a streamed save, reporting failures against its url
+pub(super) struct Upload {
+    url: Spanned<Url>,
+    sink: Sink,
+    /// Bytes of input given to the upload, including any skipped when resuming
+    written: u64,
+}
+
+/// How an [`Upload`] is written. Stores without multipart support (HTTP/WebDAV servers) are
+/// written with a single PUT once the whole stream has been buffered.
+enum Sink {
+    Multipart {
+        write: MultipartWriter,
+        /// Parts allowed in flight before writing waits for one to complete
+        max_concurrency: usize,
+        /// Input already uploaded before a resumed upload was interrupted
+        replay: Replay,
+    },
+    Single {
+        object_store: NuObjectStore,
+        path: Path,
+        buffer: PutPayloadMut,
+    },
+}
+
+impl Upload {
+    /// Start an upload of `size` bytes, if known, to size its parts. With `resume`, an upload
+    /// to the url found in the transfer journal is continued.
+    pub(super) async fn start(
+        config: &Config,
+        object_store: NuObjectStore,
+        path: Path,
+        url: &Spanned<Url>,
+        size: Option<u64>,
+        resume: bool,
+    ) -> Result<Self, ShellError> {
+        let sink = Sink::start(config, object_store, path, url, size, resume).await?;
+        Ok(Upload {
+            url: url.clone(),
+            sink,
+            written: 0,
+        })
+    }
+
+    async fn to_target(target: &Target, size: Option<u64>) -> Result<Self, ShellError> {
+        Upload::start(
+            &target.config,
+            target.object_store.clone(),
+            target.path.clone(),
+            &target.url,
+            size,
+            target.resume,
+        )
         .await
-        .unwrap();
-    let mut write = WriteMultipart::new(upload);
+    }
 
-    let _ = generic_copy(source, &mut write, span, signals)?;
+    /// Copy a borrowed buffer into the upload
+    pub(super) async fn write(&mut self, buf: &[u8]) -> Result<(), ShellError> {
+        self.written += buf.len() as u64;
+        self.sink.write(buf, &self.url).await
+    }
 
-    let _ = write.finish().await.map_err(|e| ShellError::GenericError {
-        error: format!("Could not write to S3: {e}"),
-        msg: "".into(),
-        span: None,
-        help: None,
-        inner: vec![],
-    })?;
+    /// Add owned bytes to the upload without copying them
+    async fn put(&mut self, bytes: Bytes) -> Result<(), ShellError> {
+        self.written += bytes.len() as u64;
+        self.sink.put(bytes, &self.url).await
+    }
 
-    Ok(())
+    /// Complete the upload, returning the size of the object
+    pub(super) async fn finish(self) -> Result<u64, ShellError> {
+        self.sink.finish(&self.url).await?;
+        Ok(self.written)
+    }
+}
+
+impl Sink {
+    async fn start(
+        config: &Config,
+        object_store: NuObjectStore,
+        path: Path,
+        url: &Spanned<Url>,
+        size: Option<u64>,
+        resume: bool,
+    ) -> Result<Self, ShellError> {
+        let fixed_part_size = config.upload_part_size.map(|size| size.get().max(0) as u64);
+        let max_concurrency = config.upload_concurrency();
+
+        if resume {
+            let Some(store) = object_store.multipart_store() else {
+                return Err(ShellError::GenericError {
+                    error: format!("Uploads to {} urls can't be resumed", url.item.scheme()),
+                    msg: "".into(),
+                    span: Some(url.span),
+                    help: Some("Save without --resume".into()),
+                    inner: vec![],
+                });
+            };
+            let journal = Journal::open(config).map_err(|e| ShellError::GenericError {
+                error: format!("Could not open transfer journal: {e}"),
+                msg: "".into(),
+                span: Some(url.span),
+                help: None,
+                inner: vec![],
+            })?;
+            let (upload, uploaded) =
+                JournaledUpload::resume(store, path, url.item.to_string(), Arc::new(journal))
+                    .await
+                    .map_err(|e| ShellError::GenericError {
+                        error: format!("Could not resume upload: {e}"),
+                        msg: "".into(),
+                        span: Some(url.span),
+                        help: None,
+                        inner: vec![],
+                    })?;
+            if !uploaded.is_empty() {
+                debug!("Resuming upload after {} uploaded parts", uploaded.len());
+            }
+            let part_size =
+                PartSize::new(size, fixed_part_size).resumed(uploaded.iter().map(|part| part.len));
+            return Ok(Sink::Multipart {
+                write: MultipartWriter::new(Box::new(upload), part_size),
+                max_concurrency,
+                replay: Replay::new(uploaded),
+            });
+        }
+
+        match object_store.object_store().put_multipart(&path).await {
+            Ok(upload) => Ok(Sink::Multipart {
+                write: MultipartWriter::new(upload, PartSize::new(size, fixed_part_size)),
+                max_concurrency,
+                replay: Replay::default(),
+            }),
+            Err(object_store::Error::NotImplemented) => {
+                debug!("Multipart uploads not supported, buffering for a single put");
+                Ok(Sink::Single {
+                    object_store,
+                    path,
+                    buffer: PutPayloadMut::new(),
+                })
+            }
+            Err(e) => Err(store_error(Action::Write, &url.item, url.span, e)),
+        }
+    }
+
+    async fn write(&mut self, buf: &[u8], url: &Spanned<Url>) -> Result<(), ShellError> {
+        match self {
+            Sink::Multipart {
+                write,
+                max_concurrency,
+                replay,
+            } => {
+                let buf = &buf[skip_replayed(write, replay, buf, url).await?..];
+                if buf.is_empty() {
+                    return Ok(());
+                }
+                // Parts are buffered in memory until uploaded, so don't read ahead of the network
+                write
+                    .wait_for_capacity(*max_concurrency)
+                    .await
+                    .map_err(|e| store_error(Action::Write, &url.item, url.span, e))?;
+                write.write(buf);
+            }
+            Sink::Single { buffer, .. } => buffer.extend_from_slice(buf),
+        }
+        Ok(())
+    }
+
+    async fn put(&mut self, mut bytes: Bytes, url: &Spanned<Url>) -> Result<(), ShellError> {
+        match self {
+            Sink::Multipart {
+                write,
+                max_concurrency,
+                replay,
+            } => {
+                let skipped = skip_replayed(write, replay, &bytes, url).await?;
+                let bytes = bytes.split_off(skipped);
+                if bytes.is_empty() {
+                    return Ok(());
+                }
+                write
+                    .wait_for_capacity(*max_concurrency)
+                    .await
+                    .map_err(|e| store_error(Action::Write, &url.item, url.span, e))?;
+                write.put(bytes);
+            }
+            Sink::Single { buffer, .. } => buffer.push(bytes),
+        }
+        Ok(())
+    }
+
+    async fn finish(self, url: &Spanned<Url>) -> Result<(), ShellError> {
+        let result = match self {
+            Sink::Multipart { replay, .. } if !replay.is_done() => {
+                return Err(ShellError::GenericError {
+                    error: "Could not resume upload: the input is shorter than the data uploaded before".into(),
+                    msg: "".into(),
+                    span: Some(url.span),
+                    help: None,
+                    inner: vec![],
+                });
+            }
+            Sink::Multipart { write, .. } => write.finish().await.map(|_| ()),
+            Sink::Single {
+                object_store,
+                path,
+                buffer,
+            } => object_store
+                .object_store()
+                .put(&path, buffer.freeze())
+                .await
+                .map(|_| ()),
+        };
+        result.map_err(|e| store_error(Action::Write, &url.item, url.span, e))
+    }
+}
+
+/// Skip the start of `buf` uploaded before the upload was resumed, returning its length.
+///
+/// The interrupted upload is discarded if the input differs from it, so the save can be
+/// started over.
+async fn skip_replayed(
+    write: &mut MultipartWriter,
+    replay: &mut Replay,
+    buf: &[u8],
+    url: &Spanned<Url>,
+) -> Result<usize, ShellError> {
+    match replay.skip(buf) {
+        Ok(skipped) => Ok(skipped),
+        Err(e) => {
+            let _ = write.abort().await;
+            Err(ShellError::GenericError {
+                error: format!("Could not resume upload of {}: {e}", url.item),
+                msg: "".into(),
+                span: Some(url.span),
+                help: Some("The interrupted upload was discarded, save again to start over".into()),
+                inner: vec![],
+            })
+        }
+    }
 }
 
 const DEFAULT_BUF_SIZE: usize = 8192;
+/// Size of the slices values are uploaded in when the bandwidth is limited
+const THROTTLED_CHUNK_SIZE: usize = 64 * 1024;
 
 // Copied from [`std::io::copy`]
-fn generic_copy(
+async fn generic_copy(
     mut reader: impl Read,
-    writer: &mut WriteMultipart,
+    writer: &mut Upload,
     span: Span,
     signals: &Signals,
+    bwlimit: Option<&RateLimiter>,
 ) -> Result<u64, Box<ShellError>> {
     let buf = &mut [0; DEFAULT_BUF_SIZE];
     let mut len = 0;
@@ -215,21 +755,31 @@ fn generic_copy(
             Ok(0) => break,
             Ok(n) => n,
             Err(e) if e.kind() == ErrorKind::Interrupted => continue,
-            Err(e) => return Err(Box::new(ShellError::Io(IoError::new(e, span, None)))),
+            // Errors of the input stream are passed through the reader
+            Err(e) => {
+                return Err(Box::new(match ShellErrorBridge::try_from(e) {
+                    Ok(ShellErrorBridge(e)) => e,
+                    Err(e) => ShellError::Io(IoError::new(e, span, None)),
+                }));
+            }
         };
         len += n;
-        writer.write(&buf[..n]);
+        if let Some(bwlimit) = bwlimit {
+            bwlimit.acquire_many(n as u64).await;
+        }
+        writer.write(&buf[..n]).await?;
     }
     Ok(len as u64)
 }
 
-/// Convert [`Value::String`] [`Value::Binary`] or [`Value::List`] into [`Vec`] of bytes
+/// Convert [`Value::String`] [`Value::Binary`] or [`Value::List`] into [`Bytes`]
 ///
+/// Strings and binaries are moved without copying their data.
 /// Propagates [`Value::Error`] and creates error otherwise
-fn value_to_bytes(value: Value) -> Result<Vec<u8>, Box<ShellError>> {
+fn value_to_bytes(value: Value) -> Result<Bytes, Box<ShellError>> {
     match value {
-        Value::String { val, .. } => Ok(val.into_bytes()),
-        Value::Binary { val, .. } => Ok(val),
+        Value::String { val, .. } => Ok(Bytes::from(val)),
+        Value::Binary { val, .. } => Ok(Bytes::from(val)),
         Value::List { vals, .. } => {
             let val = vals
                 .into_iter()
@@ -238,11 +788,11 @@ fn value_to_bytes(value: Value) -> Result<Vec<u8>, Box<ShellError>> {
                 .join("\n")
                 + "\n";
 
-            Ok(val.into_bytes())
+            Ok(Bytes::from(val))
         }
         // Propagate errors by explicitly matching them before the final case.
         Value::Error { error, .. } => Err(Box::new(*error)),
-        other => Ok(other.coerce_into_string()?.into_bytes()),
+        other => Ok(Bytes::from(other.coerce_into_string()?)),
     }
 }
 
@@ -250,12 +800,12 @@ fn value_to_bytes(value: Value) -> Result<Vec<u8>, Box<ShellError>> {
 /// to format of output file
 fn input_to_bytes(
     input: PipelineData,
-    path: &std::path::Path,
+    remote: &RemotePath,
     raw: bool,
     engine: &EngineInterface,
     call: &EvaluatedCall,
     span: Span,
-) -> Result<Vec<u8>, Box<ShellError>> {
+) -> Result<Bytes, Box<ShellError>> {
     let ext = if raw {
         None
     } else if let PipelineData::ByteStream(..) = input {
@@ -263,8 +813,7 @@ fn input_to_bytes(
     } else if let PipelineData::Value(Value::String { .. }, ..) = input {
         None
     } else {
-        path.extension()
-            .map(|name| name.to_string_lossy().to_string())
+        remote.extension()
     };
 
     let input = if let Some(ext) = ext {
@@ -287,34 +836,74 @@ fn convert_to_extension(
 ) -> Result<PipelineData, Box<ShellError>> {
     if let Some(decl_id) = engine.find_decl(format!("to {extension}"))? {
         debug!("Found to {extension} decl: converting input");
-        let command_output = engine.call_decl(decl_id, call.clone(), input, true, false)?;
+        let converter = converter_call(call)?;
+        let command_output = engine.call_decl(decl_id, converter, input, true, false)?;
         Ok(command_output)
+    } else if extension.eq_ignore_ascii_case("avro") {
+        // Nushell has no Avro converter, encode it here unless one was defined
+        let span = call.head;
+        let rows = match input.into_value(span)? {
+            Value::List { vals, .. } => vals,
+            value => vec![value],
+        };
+        let data = avro::write_container(&rows).map_err(|e| ShellError::GenericError {
+            error: format!("Could not write Avro: {e}"),
+            msg: "".into(),
+            span: Some(span),
+            help: None,
+            inner: vec![],
+        })?;
+        Ok(PipelineData::Value(Value::binary(data, span), None))
     } else {
         Ok(input)
     }
 }
 
-async fn stream_bytes(
-    plugin: &CloudPlugin,
-    engine: &EngineInterface,
-    bytes: Vec<u8>,
-    url: &Spanned<Url>,
-    span: Span,
-) -> Result<(), ShellError> {
-    let (object_store, path) = plugin.parse_url(engine, url, span).await?;
+/// Call of a `to` converter, with the flags of `--to-flags`. Switches are given as `true`, and
+/// left out when `false`.
+fn converter_call(call: &EvaluatedCall) -> Result<EvaluatedCall, Box<ShellError>> {
+    let mut converter = EvaluatedCall::new(call.head);
+    let Some(flags) = call.get_flag::<Value>("to-flags")? else {
+        return Ok(converter);
+    };
+    let span = flags.span();
+    for (name, value) in flags.into_record()? {
+        let name = Spanned { item: name, span };
+        match value {
+            Value::Bool { val: true, .. } => converter.add_flag(name),
+            Value::Bool { val: false, .. } => continue,
+            value => converter.add_named(name, value),
+        };
+    }
+    Ok(converter)
+}
+
+async fn stream_bytes(target: &Target, bytes: Bytes) -> Result<u64, ShellError> {
+    let size = bytes.len() as u64;
 
-    let payload = PutPayload::from_bytes(Bytes::from(bytes));
-    object_store
+    if let Some(bwlimit) = &target.bwlimit {
+        // Feed the value in slices, so the upload is paced like a stream
+        let mut write = Upload::to_target(target, Some(size)).await?;
+        for start in (0..bytes.len()).step_by(THROTTLED_CHUNK_SIZE) {
+            let chunk = bytes.slice(start..(start + THROTTLED_CHUNK_SIZE).min(bytes.len()));
+            bwlimit.acquire_many(chunk.len() as u64).await;
+            write.put(chunk).await?;
+        }
+        return write.finish().await;
+    }
+    if target.resume {
+        let mut write = Upload::to_target(target, Some(size)).await?;
+        write.put(bytes).await?;
+        return write.finish().await;
+    }
+
+    let payload = PutPayload::from_bytes(bytes);
+    target
+        .object_store
         .object_store()
-        .put(&path, payload)
+        .put(&target.path, payload)
         .await
-        .map_err(|e| ShellError::GenericError {
-            error: format!("Could not write to S3: {e}"),
-            msg: "".into(),
-            span: None,
-            help: None,
-            inner: vec![],
-        })?;
+        .map_err(|e| store_error(Action::Write, &target.url.item, target.url.span, e))?;
 
-    Ok(())
+    Ok(size)
 }
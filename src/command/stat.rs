@@ -0,0 +1,175 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use futures::TryStreamExt;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, Record, ShellError, Signature, SyntaxShape,
+    Type, Value, record,
+};
+use object_store::{Attribute, GetOptions, path::Path};
+use sha2::{Digest, Sha256};
+
+use super::{run_flags, with_rate_limit, with_request_log, with_timeout};
+use crate::{
+    CloudPlugin,
+    error::{Action, store_error},
+    providers::{NuObjectStore, checksums::ObjectChecksums},
+    remote::RemotePath,
+};
+
+pub struct Stat;
+
+impl PluginCommand for Stat {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud stat"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            Signature::build("cloud stat")
+                .input_output_types(vec![(Type::Nothing, Type::record())])
+                .required("uri", SyntaxShape::String, "The url of the object to describe.")
+                .switch(
+                    "compute",
+                    "Compute the SHA-256 and CRC32 of the object by downloading it, when the store has no checksums of it",
+                    Some('c'),
+                )
+                .switch(
+                    "bytes",
+                    "Give the size as an integer number of bytes instead of a filesize",
+                    Some('b'),
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Describe an object, with the checksums its store keeps of it."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Returns the name, size, modification time, etag, version, and content type of the object, and a checksums record of algorithms and base64 values, as S3 gives them. S3 only has checksums of objects uploaded with one, which may be of their parts for objects uploaded in parts. Other stores have none, unless --compute is given."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Show the checksums S3 keeps of an object.",
+                example: "cloud stat s3://mybucket/backup.tar | get checksums",
+                result: None,
+            },
+            Example {
+                description: "Compare a local file with its upload.",
+                example: "(cloud stat --compute file:///backups/backup.tar).checksums.sha256 == (cloud stat --compute s3://mybucket/backup.tar).checksums.sha256",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let compute = call.has_flag("compute")?;
+    let bytes = call.has_flag("bytes")?;
+    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
+    let read_error = |e| store_error(Action::Read, &url.item, url.span, e);
+
+    let mut object = match object_store.checksum_client() {
+        Some(client) => client.head(&path).await.map_err(read_error)?,
+        None => {
+            let options = GetOptions {
+                head: true,
+                ..GetOptions::default()
+            };
+            let result = object_store
+                .object_store()
+                .get_opts(&path, options)
+                .await
+                .map_err(read_error)?;
+            ObjectChecksums {
+                size: result.meta.size,
+                last_modified: Some(result.meta.last_modified),
+                e_tag: result.meta.e_tag,
+                version: result.meta.version,
+                content_type: result
+                    .attributes
+                    .get(&Attribute::ContentType)
+                    .map(|value| value.to_string()),
+                checksums: vec![],
+            }
+        }
+    };
+    if compute && object.checksums.is_empty() {
+        object.checksums = compute_checksums(&object_store, &path)
+            .await
+            .map_err(read_error)?;
+    }
+
+    let string = |value: Option<String>| {
+        value.map_or(Value::nothing(call_span), |s| Value::string(s, call_span))
+    };
+    let mut checksums = Record::new();
+    for (algorithm, value) in object.checksums {
+        checksums.push(algorithm, Value::string(value, call_span));
+    }
+    let record = record!(
+        "name" => Value::string(path.to_string(), call_span),
+        "type" => Value::string("file", call_span),
+        "size" => match bytes {
+            true => Value::int(object.size as i64, call_span),
+            false => Value::filesize(object.size as i64, call_span),
+        },
+        "modified" => object.last_modified.map_or(Value::nothing(call_span), |time| {
+            Value::date(time.fixed_offset(), call_span)
+        }),
+        "etag" => string(object.e_tag),
+        "version" => string(object.version),
+        "content_type" => string(object.content_type),
+        "checksums" => Value::record(checksums, call_span),
+    );
+    Ok(PipelineData::Value(Value::record(record, call_span), None))
+}
+
+/// SHA-256 and CRC32 of the contents of an object, streamed through the plugin, encoded like
+/// the checksums of S3
+async fn compute_checksums(
+    store: &NuObjectStore,
+    location: &Path,
+) -> object_store::Result<Vec<(&'static str, String)>> {
+    let mut sha256 = Sha256::new();
+    let mut crc32 = crc32fast::Hasher::new();
+    let mut chunks = store.object_store().get(location).await?.into_stream();
+    while let Some(chunk) = chunks.try_next().await? {
+        sha256.update(&chunk);
+        crc32.update(&chunk);
+    }
+    Ok(vec![
+        ("crc32", STANDARD.encode(crc32.finalize().to_be_bytes())),
+        ("sha256", STANDARD.encode(sha256.finalize())),
+    ])
+}
@@ -1,7 +1,15 @@
-use nu_plugin::PluginCommand;
-use nu_protocol::{Category, PipelineData, Signature, Type, Value};
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, Record, ShellError, Signature, Span, Type,
+    Value, record,
+};
 
-use crate::CloudPlugin;
+use crate::{
+    CloudPlugin,
+    config::{BucketConfig, Config, ConfigDuration},
+    providers::env_string,
+    remote,
+};
 
 pub struct Stub;
 
@@ -15,23 +23,150 @@ impl PluginCommand for Stub {
     fn signature(&self) -> nu_protocol::Signature {
         Signature::build("cloud")
             .category(Category::FileSystem)
-            .input_output_types(vec![(Type::Nothing, Type::String)])
+            .input_output_types(vec![(Type::Nothing, Type::record())])
     }
 
     fn description(&self) -> &str {
         "Provides the ability to read and write files from cloud storage"
     }
 
+    fn extra_description(&self) -> &str {
+        "Returns the subcommands, the settings of configured buckets, and the defaults in effect, to find commands and check the configuration."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Check which AWS profile and region urls without bucket settings use.",
+            example: "cloud | get defaults",
+            result: None,
+        }]
+    }
+
     fn run(
         &self,
         _plugin: &Self::Plugin,
-        engine: &nu_plugin::EngineInterface,
-        call: &nu_plugin::EvaluatedCall,
-        _input: nu_protocol::PipelineData,
-    ) -> Result<nu_protocol::PipelineData, nu_protocol::LabeledError> {
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let span = call.head;
+        let config = Config::load(engine).map_err(|e| *e)?;
+        let commands = super::commands()
+            .iter()
+            .filter(|command| command.name() != self.name())
+            .map(|command| {
+                Value::record(
+                    record!(
+                        "name" => Value::string(command.name(), span),
+                        "description" => Value::string(command.description(), span),
+                    ),
+                    span,
+                )
+            })
+            .collect();
+
+        let mut buckets: Vec<_> = config
+            .buckets
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        buckets.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let profiles = buckets
+            .into_iter()
+            .map(|(prefix, bucket)| profile(prefix, bucket, span))
+            .collect();
+
         Ok(PipelineData::Value(
-            Value::string(engine.get_help()?, call.head),
+            Value::record(
+                record!(
+                    "commands" => Value::list(commands, span),
+                    "profiles" => Value::list(profiles, span),
+                    "defaults" => defaults(engine, &config, span).map_err(|e| *e)?,
+                ),
+                span,
+            ),
             None,
         ))
     }
 }
+
+/// Settings of urls under `prefix` from the `buckets` config
+fn profile(prefix: String, bucket: BucketConfig, span: Span) -> Value {
+    let optional = |value: Option<String>| {
+        value.map_or(Value::nothing(span), |value| Value::string(value, span))
+    };
+    // OIDC tokens replace the store's own authentication
+    let credentials = if bucket.oidc.is_some() {
+        "oidc"
+    } else if bucket.access_key_id.is_some() && bucket.secret_access_key.is_some() {
+        "static"
+    } else if bucket.credentials.is_some() {
+        "closure"
+    } else {
+        "default"
+    };
+    Value::record(
+        record!(
+            "prefix" => Value::string(prefix, span),
+            "profile" => optional(bucket.profile),
+            "region" => optional(bucket.region),
+            "endpoint" => optional(bucket.endpoint),
+            "credentials" => Value::string(credentials, span),
+            "replicas" => Value::int(bucket.replicas.map_or(0, |replicas| replicas.len()) as i64, span),
+            "storage_class" => optional(bucket.storage_class),
+        ),
+        span,
+    )
+}
+
+/// Settings applying to urls without bucket settings, and to every command
+fn defaults(
+    engine: &EngineInterface,
+    config: &Config,
+    span: Span,
+) -> Result<Value, Box<ShellError>> {
+    let optional = |value: Option<String>| {
+        value.map_or(Value::nothing(span), |value| Value::string(value, span))
+    };
+    let duration = |value: Option<ConfigDuration>| {
+        value.map_or(Value::nothing(span), |ConfigDuration(duration)| {
+            Value::duration(duration.as_nanos() as i64, span)
+        })
+    };
+    let mut aliases: Vec<_> = config
+        .aliases
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    aliases.sort();
+    let aliases = aliases
+        .into_iter()
+        .map(|(name, url)| (name, Value::string(url, span)))
+        .collect::<Record>();
+
+    Ok(Value::record(
+        record!(
+            "default_url" => optional(remote::default_url(engine)?),
+            "aliases" => Value::record(aliases, span),
+            "aws_profile" => Value::string(
+                match &config.profile {
+                    Some(profile) => profile.clone(),
+                    None => env_string(engine, "AWS_PROFILE")?.unwrap_or_else(|| "default".into()),
+                },
+                span,
+            ),
+            "region" => optional(env_string(engine, "NU_CLOUD_S3_REGION")?),
+            "timeout" => duration(config.timeout),
+            "requests_per_second" => config
+                .requests_per_second
+                .map_or(Value::nothing(span), |rate| Value::float(rate, span)),
+            "concurrency" => Value::int(config.concurrency() as i64, span),
+            "bwlimit" => config.bwlimit.clone().unwrap_or(Value::nothing(span)),
+            "disk_cache" => Value::bool(config.disk_cache.is_some(), span),
+            "request_log" => Value::bool(super::request_log_env(engine)?, span),
+        ),
+        span,
+    ))
+}
@@ -0,0 +1,556 @@
+use std::collections::{BTreeMap, HashSet};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use futures::{StreamExt, TryStreamExt, stream};
+use nu_glob::{MatchOptions, Pattern};
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
+    Type, Value, record,
+};
+use object_store::{ObjectMeta, local::LocalFileSystem, path::Path};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use super::{
+    bandwidth_limit, bwlimit_flag, confirm, confirming,
+    cp::{Limits, Overwrites, Transfer, copy_recursive, transfer_flags, up_to_date},
+    object_url, run_flags, with_rate_limit, with_request_log, with_timeout, yes_flag,
+};
+use crate::{
+    CloudPlugin,
+    audit::{AuditAction, AuditLog},
+    cache::{Cache, ObjectStoreCacheKey},
+    config::Config,
+    error::{Action, store_error},
+    providers::NuObjectStore,
+    remote::RemotePath,
+};
+
+pub struct Sync;
+
+impl PluginCommand for Sync {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud sync"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            transfer_flags(
+                yes_flag(bwlimit_flag(Signature::build("cloud sync"))),
+                "",
+            )
+            .input_output_types(vec![(Type::Nothing, Type::record())])
+            .required(
+                "source",
+                SyntaxShape::String,
+                "The url of the prefix to copy from.",
+            )
+            .required(
+                "destination",
+                SyntaxShape::String,
+                "The url of the prefix to bring up to date.",
+            )
+            .named(
+                "include",
+                SyntaxShape::List(Box::new(SyntaxShape::String)),
+                "Only sync objects matching one of these glob patterns",
+                Some('i'),
+            )
+            .named(
+                "exclude",
+                SyntaxShape::List(Box::new(SyntaxShape::String)),
+                "Skip objects matching any of these glob patterns, which are never deleted either",
+                Some('x'),
+            )
+            .switch(
+                "checksum",
+                "Compare the contents of objects of the same size, rather than their modification times",
+                Some('c'),
+            )
+            .switch(
+                "update",
+                "Leave objects at the destination that are at least as new as at the source, or have the same etag, even of another size",
+                Some('u'),
+            )
+            .switch(
+                "delete",
+                "Delete objects at the destination that aren't at the source",
+                Some('d'),
+            )
+            .named(
+                "max-delete",
+                SyntaxShape::Int,
+                "Fail without changing anything if more than this many objects would be deleted",
+                None,
+            )
+            .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Copy the objects under a prefix that are missing or changed at another."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Objects are matched by their paths relative to the source and destination urls. Objects missing at the destination are created, and ones that differ in size or were modified at the source after the destination are updated. With --checksum, objects of the same size are compared by their contents instead, by the checksums S3 keeps of them when both sides have one of the same algorithm, and otherwise by SHA-256, downloading the objects without a stored one. With --update, objects modified at the destination since the source, or with the same etag, are never replaced, whatever their size or contents.
+
+Patterns of --include and --exclude without a / match any segment of the relative path, so `*.tmp` matches files and `.git` everything under it, while patterns with a / match the relative path from its start. Excludes take precedence over includes.
+
+Returns a record of the number of objects created, updated, deleted, and unchanged, the bytes copied, and the actions taken with the url of each object."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Upload the changes to a local directory.",
+                example: "cloud sync file:///srv/site/ s3://mybucket/site/",
+                result: None,
+            },
+            Example {
+                description: "Mirror a bucket, deleting what was removed from it, but never more than 100 objects.",
+                example: "cloud sync --delete --max-delete 100 s3://mybucket/ s3://backup/mybucket/",
+                result: None,
+            },
+            Example {
+                description: "Back up the data files, comparing their contents.",
+                example: "cloud sync -c --include [*.csv *.parquet] --exclude [tmp/**] s3://mybucket/data/ file:///backup/data/",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let source = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?;
+    if source.glob().map_err(|e| *e)?.is_some() {
+        return Err(ShellError::GenericError {
+            error: "Sync copies from a prefix, not a glob pattern".into(),
+            msg: "".into(),
+            span: Some(source.url.span),
+            help: Some("Give the pattern to --include instead".into()),
+            inner: vec![],
+        });
+    }
+    let source = source.url;
+    let destination = RemotePath::from_call(engine, call, 1).map_err(|e| *e)?.url;
+    let filters = Filters::from_call(call).map_err(|e| *e)?;
+    let max_delete = match call.get_flag::<Spanned<i64>>("max-delete")? {
+        Some(max) if max.item < 0 => {
+            return Err(ShellError::IncorrectValue {
+                msg: "The number of deletions allowed can't be negative".into(),
+                val_span: max.span,
+                call_span,
+            });
+        }
+        max => max.map(|max| max.item as usize),
+    };
+    let limits = Limits::from_call(engine, call).map_err(|e| *e)?;
+
+    let (source_store, source_path) = plugin.parse_url(engine, &source, call_span).await?;
+    let (dest_store, dest_path) = plugin.parse_url(engine, &destination, call_span).await?;
+    let sources = list(&source_store, &source_path, &source, &filters).await?;
+    let mut destinations = list(&dest_store, &dest_path, &destination, &filters).await?;
+
+    // Compare the objects on both sides, leaving the extraneous ones at the destination
+    let mut created = vec![];
+    let mut updated = vec![];
+    let mut unchanged = 0;
+    let mut same_size = vec![];
+    let checksum = call.has_flag("checksum")?;
+    let update = call.has_flag("update")?;
+    for (relative, meta) in sources {
+        match destinations.remove(&relative) {
+            None => created.push(meta),
+            Some(existing) if update && up_to_date(&meta, &existing) => unchanged += 1,
+            Some(existing) if existing.size != meta.size => updated.push((meta, existing)),
+            Some(existing) if checksum => same_size.push((meta, existing)),
+            Some(existing) if meta.last_modified > existing.last_modified => {
+                updated.push((meta, existing))
+            }
+            Some(_) => unchanged += 1,
+        }
+    }
+    let mut compared = stream::iter(same_size)
+        .map(|(meta, existing)| async {
+            let same = same_contents(
+                &plugin.cache,
+                (&source_store, &meta),
+                (&dest_store, &existing),
+            )
+            .await
+            .map_err(|e| {
+                let target = format!("{} with {}", source.item, destination.item);
+                store_error(Action::Read, target, source.span, e)
+            })?;
+            Ok::<_, ShellError>((meta, existing, same))
+        })
+        .buffer_unordered(limits.concurrency);
+    while let Some(result) = compared.next().await {
+        engine.signals().check(&call_span)?;
+        match result? {
+            (_, _, true) => unchanged += 1,
+            (meta, existing, false) => updated.push((meta, existing)),
+        }
+    }
+    drop(compared);
+
+    let extraneous: Vec<_> = match call.has_flag("delete")? {
+        true => destinations.into_values().collect(),
+        false => vec![],
+    };
+    if let Some(max) = max_delete
+        && extraneous.len() > max
+    {
+        return Err(ShellError::GenericError {
+            error: format!(
+                "Sync would delete {} objects, more than the {max} allowed",
+                extraneous.len()
+            ),
+            msg: "".into(),
+            span: call.get_flag_span("max-delete"),
+            help: Some(
+                "Nothing was copied or deleted. Check the source url, or raise --max-delete".into(),
+            ),
+            inner: vec![],
+        });
+    }
+    if !extraneous.is_empty() && confirming(engine, call).map_err(|e| *e)? {
+        let question = format!(
+            "Delete the {} objects under {} that aren't under {}?",
+            extraneous.len(),
+            destination.item,
+            source.item
+        );
+        confirm(engine, &question, call_span).map_err(|e| *e)?;
+    }
+
+    let audit = AuditLog::open(engine, call).map_err(|e| *e)?;
+    let bwlimit = bandwidth_limit(engine, call).map_err(|e| *e)?;
+    let config = Config::load(engine).map_err(|e| *e)?;
+    let copy = Transfer {
+        source: &source_store,
+        destination: &dest_store,
+        same_store: ObjectStoreCacheKey::from(&source_store)
+            == ObjectStoreCacheKey::from(&dest_store),
+        bwlimit: bwlimit.as_deref(),
+        config: &config,
+        overwrites: audit.as_ref().map(|audit| Overwrites {
+            audit,
+            existing: updated
+                .iter()
+                .map(|(_, existing)| existing.location.clone())
+                .collect(),
+            url: &destination.item,
+            path: &dest_path,
+        }),
+        removals: None,
+    };
+    plugin.cache.invalidate(&dest_store, &dest_path).await;
+
+    let updates: HashSet<Path> = updated
+        .iter()
+        .map(|(_, existing)| existing.location.clone())
+        .collect();
+    let objects = created
+        .into_iter()
+        .chain(updated.into_iter().map(|(meta, _)| meta))
+        .collect();
+    let copied = copy_recursive(
+        engine,
+        &copy,
+        &limits,
+        objects,
+        &source_path,
+        &dest_path,
+        call.has_flag("progress")? && !call.has_flag("quiet")?,
+        source.span,
+    )
+    .await?;
+
+    let mut actions = vec![];
+    let (mut created, mut updated, mut bytes) = (0, 0, 0);
+    for (to, size) in copied {
+        let action = match updates.contains(&to) {
+            true => {
+                updated += 1;
+                "update"
+            }
+            false => {
+                created += 1;
+                "create"
+            }
+        };
+        bytes += size;
+        actions.push((action, object_url(&destination.item, &dest_path, &to)));
+    }
+
+    // Deleted last, so an interrupted sync leaves the destination with more objects, not fewer
+    let mut deleted = 0;
+    let store = dest_store.object_store();
+    let mut deletes = store
+        .delete_stream(stream::iter(extraneous.into_iter().map(|meta| Ok(meta.location))).boxed());
+    while let Some(result) = deletes.next().await {
+        engine.signals().check(&call_span)?;
+        let location = match result {
+            Ok(location) => location,
+            Err(e) => {
+                // Failed deletes of a batch aren't reported by location
+                if let Some(audit) = &audit {
+                    audit.record(AuditAction::Delete, &destination.item, Err(&e));
+                }
+                return Err(store_error(
+                    Action::Delete,
+                    &destination.item,
+                    destination.span,
+                    e,
+                ));
+            }
+        };
+        let url = object_url(&destination.item, &dest_path, &location);
+        if let Some(audit) = &audit {
+            audit.record(AuditAction::Delete, &url, Ok::<_, &str>(()));
+        }
+        deleted += 1;
+        actions.push(("delete", url));
+    }
+
+    let span = call_span;
+    let value = Value::record(
+        record!(
+            "created" => Value::int(created, span),
+            "updated" => Value::int(updated, span),
+            "deleted" => Value::int(deleted, span),
+            "unchanged" => Value::int(unchanged, span),
+            "bytes" => Value::filesize(bytes as i64, span),
+            "actions" => Value::list(
+                actions
+                    .into_iter()
+                    .map(|(action, url)| {
+                        Value::record(
+                            record!(
+                                "action" => Value::string(action, span),
+                                "url" => Value::string(url, span),
+                            ),
+                            span,
+                        )
+                    })
+                    .collect(),
+                span,
+            ),
+        ),
+        span,
+    );
+    Ok(PipelineData::Value(value, None))
+}
+
+/// The objects under `prefix`, the path of `url`, that pass the filters, by relative path
+async fn list(
+    store: &NuObjectStore,
+    prefix: &Path,
+    url: &Spanned<Url>,
+    filters: &Filters,
+) -> Result<BTreeMap<String, ObjectMeta>, ShellError> {
+    store
+        .object_store()
+        .list(Some(prefix))
+        .try_filter_map(|meta| {
+            let relative = relative_path(prefix, &meta.location);
+            std::future::ready(Ok(filters.matches(&relative).then_some((relative, meta))))
+        })
+        .try_collect()
+        .await
+        .map_err(|e| store_error(Action::List, &url.item, url.span, e))
+}
+
+/// Path of `location` relative to `prefix`, with `/` separated segments
+fn relative_path(prefix: &Path, location: &Path) -> String {
+    location
+        .prefix_match(prefix)
+        .into_iter()
+        .flatten()
+        .map(|part| part.as_ref().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Whether two objects have the same contents, by the checksums their stores keep of them when
+/// they have one of the same algorithm, and otherwise by their SHA-256, downloading the objects
+/// without a stored one
+async fn same_contents(
+    cache: &Cache,
+    (source, from): (&NuObjectStore, &ObjectMeta),
+    (destination, to): (&NuObjectStore, &ObjectMeta),
+) -> object_store::Result<bool> {
+    let (from_stored, to_stored) = futures::try_join!(
+        stored_checksums(source, &from.location),
+        stored_checksums(destination, &to.location)
+    )?;
+    if let Some((algorithm, value)) = from_stored
+        .iter()
+        .find(|(algorithm, _)| to_stored.iter().any(|(other, _)| other == algorithm))
+    {
+        return Ok(to_stored.contains(&(algorithm, value.clone())));
+    }
+    let sha256 = |stored: Vec<(&str, String)>| {
+        stored
+            .into_iter()
+            .find_map(|(algorithm, value)| (algorithm == "sha256").then_some(value))
+    };
+    let from_digest = match sha256(from_stored) {
+        Some(value) => value,
+        None => digest(cache, source, from).await?,
+    };
+    let to_digest = match sha256(to_stored) {
+        Some(value) => value,
+        None => digest(cache, destination, to).await?,
+    };
+    Ok(from_digest == to_digest)
+}
+
+/// Checksums of the whole contents of an object kept by its store, leaving out those of objects
+/// uploaded in parts that are of the checksums of their parts
+async fn stored_checksums(
+    store: &NuObjectStore,
+    location: &Path,
+) -> object_store::Result<Vec<(&'static str, String)>> {
+    let Some(client) = store.checksum_client() else {
+        return Ok(vec![]);
+    };
+    let mut checksums = client.head(location).await?.checksums;
+    checksums.retain(|(_, value)| !value.contains('-'));
+    Ok(checksums)
+}
+
+/// SHA-256 of the contents of an object, base64 encoded like the checksums S3 keeps. Local
+/// files are read on the blocking thread pool, so the files compared at once are hashed in
+/// parallel, and their digests are cached until their size or modification time changes.
+async fn digest(
+    cache: &Cache,
+    store: &NuObjectStore,
+    meta: &ObjectMeta,
+) -> object_store::Result<String> {
+    if let NuObjectStore::Local(_) = store {
+        let file = LocalFileSystem::new().path_to_filesystem(&meta.location)?;
+        let key = (file.clone(), meta.size, meta.last_modified);
+        if let Some(digest) = cache.get_digest(&key).await {
+            return Ok(digest);
+        }
+        let digest = tokio::task::spawn_blocking(move || file_digest(&file)).await??;
+        cache.put_digest(key, digest.clone()).await;
+        return Ok(digest);
+    }
+    let mut hasher = Sha256::new();
+    let mut chunks = store
+        .object_store()
+        .get(&meta.location)
+        .await?
+        .into_stream();
+    while let Some(chunk) = chunks.try_next().await? {
+        hasher.update(&chunk);
+    }
+    Ok(STANDARD.encode(hasher.finalize()))
+}
+
+fn file_digest(file: &std::path::Path) -> object_store::Result<String> {
+    let io_error = |e: std::io::Error| match e.kind() {
+        std::io::ErrorKind::NotFound => object_store::Error::NotFound {
+            path: file.display().to_string(),
+            source: e.into(),
+        },
+        _ => object_store::Error::Generic {
+            store: "LocalFileSystem",
+            source: e.into(),
+        },
+    };
+    let mut hasher = Sha256::new();
+    let mut reader = std::fs::File::open(file).map_err(io_error)?;
+    std::io::copy(&mut reader, &mut hasher).map_err(io_error)?;
+    Ok(STANDARD.encode(hasher.finalize()))
+}
+
+/// The `--include` and `--exclude` patterns, matched against relative paths
+struct Filters {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Filters {
+    fn from_call(call: &EvaluatedCall) -> Result<Self, Box<ShellError>> {
+        let patterns = |flag: &str| -> Result<Vec<Pattern>, Box<ShellError>> {
+            let span = call.get_flag_span(flag).unwrap_or(call.head);
+            call.get_flag::<Vec<String>>(flag)?
+                .unwrap_or_default()
+                .iter()
+                .map(|pattern| {
+                    Pattern::new(pattern).map_err(|e| {
+                        Box::new(ShellError::GenericError {
+                            error: format!("Invalid glob pattern {pattern}: {}", e.msg),
+                            msg: "".into(),
+                            span: Some(span),
+                            help: Some(
+                                "Wrap glob characters in brackets to match them literally, e.g. [*]"
+                                    .into(),
+                            ),
+                            inner: vec![],
+                        })
+                    })
+                })
+                .collect()
+        };
+        Ok(Filters {
+            include: patterns("include")?,
+            exclude: patterns("exclude")?,
+        })
+    }
+
+    /// Whether the object at `relative` is synced
+    fn matches(&self, relative: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| pattern_matches(p, relative)))
+            && !self.exclude.iter().any(|p| pattern_matches(p, relative))
+    }
+}
+
+/// Patterns with a `/` match the path or a prefix of it, and others any of its segments
+fn pattern_matches(pattern: &Pattern, relative: &str) -> bool {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::default()
+    };
+    if !pattern.as_str().contains('/') {
+        return relative
+            .split('/')
+            .any(|segment| pattern.matches_with(segment, options));
+    }
+    relative
+        .match_indices('/')
+        .map(|(end, _)| &relative[..end])
+        .chain([relative])
+        .any(|prefix| pattern.matches_with(prefix, options))
+}
@@ -0,0 +1,199 @@
+use std::{collections::BTreeMap, error::Error};
+
+/// Nesting beyond this is rejected, so malformed data can't exhaust the stack
+const MAX_DEPTH: usize = 64;
+
+/// A value decoded from the Thrift compact protocol, which Parquet uses for its footer.
+///
+/// Values are decoded without a schema, callers pick the fields they need by id.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Thrift {
+    Bool(bool),
+    Int(i64),
+    Double(f64),
+    Binary(Vec<u8>),
+    List(Vec<Thrift>),
+    Map(Vec<(Thrift, Thrift)>),
+    Struct(Struct),
+}
+
+/// Fields of a struct by id
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Struct(BTreeMap<i16, Thrift>);
+
+impl Struct {
+    pub fn get(&self, id: i16) -> Option<&Thrift> {
+        self.0.get(&id)
+    }
+
+    pub fn int(&self, id: i16) -> Option<i64> {
+        match self.get(id)? {
+            Thrift::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn binary(&self, id: i16) -> Option<&[u8]> {
+        match self.get(id)? {
+            Thrift::Binary(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self, id: i16) -> Option<String> {
+        self.binary(id)
+            .map(|data| String::from_utf8_lossy(data).into_owned())
+    }
+
+    pub fn list(&self, id: i16) -> &[Thrift] {
+        match self.get(id) {
+            Some(Thrift::List(items)) => items,
+            _ => &[],
+        }
+    }
+
+    pub fn child(&self, id: i16) -> Option<&Struct> {
+        match self.get(id)? {
+            Thrift::Struct(child) => Some(child),
+            _ => None,
+        }
+    }
+
+    /// Id of the field set in a union
+    pub fn union_field(&self) -> Option<i16> {
+        self.0.keys().next().copied()
+    }
+}
+
+impl Thrift {
+    pub fn as_struct(&self) -> Option<&Struct> {
+        match self {
+            Thrift::Struct(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Thrift::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+/// Decode a struct from the start of `data`
+pub fn decode_struct(data: &[u8]) -> Result<Struct, Box<dyn Error + Send + Sync>> {
+    Decoder { data, pos: 0 }.read_struct(0)
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Decoder<'_> {
+    fn read_struct(&mut self, depth: usize) -> Result<Struct, Box<dyn Error + Send + Sync>> {
+        let mut fields = BTreeMap::new();
+        let mut last_id = 0i16;
+        loop {
+            let header = self.byte()?;
+            if header == 0 {
+                return Ok(Struct(fields));
+            }
+            let kind = header & 0x0f;
+            // Ids are sent as a delta from the previous field when it fits in the header
+            let id = match header >> 4 {
+                0 => self.zigzag()? as i16,
+                delta => last_id.wrapping_add(delta as i16),
+            };
+            last_id = id;
+            // Boolean fields carry their value in the header
+            let value = match kind {
+                1 => Thrift::Bool(true),
+                2 => Thrift::Bool(false),
+                _ => self.value(kind, depth)?,
+            };
+            fields.insert(id, value);
+        }
+    }
+
+    fn value(&mut self, kind: u8, depth: usize) -> Result<Thrift, Box<dyn Error + Send + Sync>> {
+        if depth > MAX_DEPTH {
+            return Err("nesting is too deep".into());
+        }
+        Ok(match kind {
+            1 | 2 => Thrift::Bool(self.byte()? == 1),
+            3 => Thrift::Int(self.byte()? as i8 as i64),
+            4..=6 => Thrift::Int(self.zigzag()?),
+            7 => {
+                let bytes = self.bytes(8)?.try_into().expect("8 bytes were read");
+                Thrift::Double(f64::from_le_bytes(bytes))
+            }
+            8 => {
+                let len = self.varint()? as usize;
+                Thrift::Binary(self.bytes(len)?.to_vec())
+            }
+            9 | 10 => {
+                let header = self.byte()?;
+                let len = match header >> 4 {
+                    15 => self.varint()? as usize,
+                    len => len as usize,
+                };
+                let kind = header & 0x0f;
+                // Every element takes at least a byte, bounding the allocation
+                let mut items = Vec::with_capacity(len.min(self.remaining()));
+                for _ in 0..len {
+                    items.push(self.value(kind, depth + 1)?);
+                }
+                Thrift::List(items)
+            }
+            11 => {
+                let len = self.varint()? as usize;
+                let kinds = if len > 0 { self.byte()? } else { 0 };
+                let mut entries = Vec::with_capacity(len.min(self.remaining()));
+                for _ in 0..len {
+                    let key = self.value(kinds >> 4, depth + 1)?;
+                    let value = self.value(kinds & 0x0f, depth + 1)?;
+                    entries.push((key, value));
+                }
+                Thrift::Map(entries)
+            }
+            12 => Thrift::Struct(self.read_struct(depth + 1)?),
+            kind => return Err(format!("unknown field type {kind}").into()),
+        })
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn byte(&mut self) -> Result<u8, Box<dyn Error + Send + Sync>> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&[u8], Box<dyn Error + Send + Sync>> {
+        if len > self.remaining() {
+            return Err("unexpected end of data".into());
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("varint is too long".into())
+    }
+
+    fn zigzag(&mut self) -> Result<i64, Box<dyn Error + Send + Sync>> {
+        let n = self.varint()?;
+        Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+}
@@ -0,0 +1,490 @@
+use futures::{StreamExt, stream};
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
+    Type, Value, record,
+};
+use url::Url;
+
+use super::{
+    concurrency, confirm, confirming, object_url, run_flags, with_rate_limit, with_request_log,
+    with_timeout, yes_flag,
+};
+use crate::{
+    CloudPlugin,
+    audit::{AuditAction, AuditLog},
+    error::{Action, store_error},
+    providers::{
+        NuObjectStore,
+        versions::{ObjectVersion, VersionClient},
+    },
+    remote::RemotePath,
+};
+
+pub struct VersionsList;
+
+impl PluginCommand for VersionsList {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud versions list"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            Signature::build("cloud versions list")
+                .input_output_types(vec![(Type::Nothing, Type::table())])
+                .required(
+                    "uri",
+                    SyntaxShape::String,
+                    "The url of the object or prefix to list the versions of.",
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "List the versions and delete markers of the objects under a url in a versioned S3 bucket."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Versions are listed by name, newest first, with whether they are the latest version and whether they are delete markers."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Find the version of a file from before a change.",
+            example: "cloud versions list s3://mybucket/config.json | where modified < 2025-01-01",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, list(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+pub struct VersionsRestore;
+
+impl PluginCommand for VersionsRestore {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud versions restore"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            yes_flag(Signature::build("cloud versions restore"))
+                .input_output_types(vec![(Type::Nothing, Type::Nothing)])
+                .required(
+                    "uri",
+                    SyntaxShape::String,
+                    "The url of the object to restore.",
+                )
+                .named(
+                    "version-id",
+                    SyntaxShape::String,
+                    "The version to restore, as listed by cloud versions list",
+                    None,
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Restore an earlier version of an object in a versioned S3 bucket."
+    }
+
+    fn extra_description(&self) -> &str {
+        "The version is copied over the current one by the store, which keeps the current one as an earlier version. Objects that were removed are restored the same way, over their delete marker."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Restore the version of a file before the latest one.",
+            example: "let previous = cloud versions list s3://mybucket/config.json | get 1.version_id; cloud versions restore s3://mybucket/config.json --version-id $previous",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(
+                    call,
+                    with_timeout(call, restore(engine, plugin, call, "version-id")),
+                ),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+pub struct Rollback;
+
+impl PluginCommand for Rollback {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud rollback"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            yes_flag(Signature::build("cloud rollback"))
+                .input_output_types(vec![(Type::Nothing, Type::Nothing)])
+                .required(
+                    "uri",
+                    SyntaxShape::String,
+                    "The url of the object to roll back.",
+                )
+                .named(
+                    "to-version",
+                    SyntaxShape::String,
+                    "The version to make current, as listed by cloud versions list (default: the one before the latest)",
+                    None,
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Make an earlier version of an object in a versioned S3 bucket the current one."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Like cloud versions restore, the version is copied over the current one, which is kept as an earlier version, so a rollback can be rolled back too. Without --to-version, the version before the latest is restored, skipping delete markers."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Undo the last deploy of a file.",
+                example: "cloud rollback s3://mybucket/site/index.html",
+                result: None,
+            },
+            Example {
+                description: "Go back to a known good version.",
+                example: "cloud rollback s3://mybucket/config.json --to-version 3HL4kqtJlcpXroDTDmJ+rmSpXd3dIbrHY",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(
+                    call,
+                    with_timeout(call, restore(engine, plugin, call, "to-version")),
+                ),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+pub struct VersionsPurge;
+
+impl PluginCommand for VersionsPurge {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud versions purge"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            yes_flag(Signature::build("cloud versions purge"))
+                .input_output_types(vec![(Type::Nothing, Type::table())])
+                .required(
+                    "uri",
+                    SyntaxShape::String,
+                    "The url of the object or prefix to purge the versions of.",
+                )
+                .named(
+                    "keep-last",
+                    SyntaxShape::Int,
+                    "Number of the newest versions of each object to keep, at least 1",
+                    Some('k'),
+                )
+                .named(
+                    "concurrency",
+                    SyntaxShape::Int,
+                    "Number of versions removed at once (default: concurrency of the config, or 8)",
+                    Some('j'),
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Remove all but the newest versions of the objects under a url in a versioned S3 bucket."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Delete markers count as versions. The latest version of each object is always kept, so the objects themselves stay as they are. Returns a row per version removed with its url, version id, and the error if it couldn't be removed."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Keep the three newest versions of every report.",
+            example: "cloud versions purge s3://mybucket/reports/ --keep-last 3",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, purge(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn list(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
+    let versions = version_client(&object_store, &url)
+        .map_err(|e| *e)?
+        .list(&path)
+        .await
+        .map_err(|e| store_error(Action::List, &url.item, url.span, e))?;
+    let rows = versions
+        .into_iter()
+        .map(|version| {
+            Value::record(
+                record!(
+                    "name" => Value::string(version.location.to_string(), call_span),
+                    "version_id" => Value::string(version.version_id, call_span),
+                    "latest" => Value::bool(version.is_latest, call_span),
+                    "delete_marker" => Value::bool(version.delete_marker, call_span),
+                    "size" => Value::filesize(version.size as i64, call_span),
+                    "modified" => Value::date(version.last_modified.fixed_offset(), call_span),
+                ),
+                call_span,
+            )
+        })
+        .collect();
+    Ok(PipelineData::Value(Value::list(rows, call_span), None))
+}
+
+/// Restore the version given with `flag`. Rollbacks restore the version before the latest
+/// without one.
+async fn restore(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+    flag: &str,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let version_id: Option<String> = call.get_flag(flag)?;
+    if version_id.is_none() && flag == "version-id" {
+        return Err(ShellError::MissingParameter {
+            param_name: "--version-id".into(),
+            span: call_span,
+        });
+    }
+    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
+    let versions = version_client(&object_store, &url).map_err(|e| *e)?;
+    let version_id = match version_id {
+        Some(version_id) => version_id,
+        None => versions
+            .list(&path)
+            .await
+            .map_err(|e| store_error(Action::List, &url.item, url.span, e))?
+            .into_iter()
+            // Versions of objects under the path are listed too
+            .find(|version| {
+                version.location == path && !version.is_latest && !version.delete_marker
+            })
+            .map(|version| version.version_id)
+            .ok_or_else(|| ShellError::GenericError {
+                error: format!("{} has no earlier version", url.item),
+                msg: "".into(),
+                span: Some(url.span),
+                help: Some("cloud versions list shows the versions of an object".into()),
+                inner: vec![],
+            })?,
+    };
+    let audit = AuditLog::open(engine, call).map_err(|e| *e)?;
+    if confirming(engine, call).map_err(|e| *e)? {
+        let question = format!("Replace {} with version {version_id}?", url.item);
+        confirm(engine, &question, call_span).map_err(|e| *e)?;
+    }
+
+    plugin.cache.invalidate(&object_store, &path).await;
+    let result = versions.restore(&path, &version_id).await;
+    if let Some(audit) = &audit {
+        let outcome = result.as_ref().map(|_| ());
+        audit.record(AuditAction::Overwrite, &url.item, outcome);
+    }
+    result.map_err(|e| {
+        let target = format!("version {version_id} of {}", url.item);
+        store_error(Action::Copy, target, url.span, e)
+    })?;
+    Ok(PipelineData::empty())
+}
+
+async fn purge(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let keep: Spanned<i64> =
+        call.get_flag("keep-last")?
+            .ok_or_else(|| ShellError::MissingParameter {
+                param_name: "--keep-last".into(),
+                span: call_span,
+            })?;
+    if keep.item < 1 {
+        return Err(ShellError::IncorrectValue {
+            msg: "At least the latest version of each object is kept".into(),
+            val_span: keep.span,
+            call_span,
+        });
+    }
+    let concurrency = concurrency(engine, call).map_err(|e| *e)?;
+    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
+    let versions = &version_client(&object_store, &url).map_err(|e| *e)?;
+    let audit = AuditLog::open(engine, call).map_err(|e| *e)?;
+    let listed = versions
+        .list(&path)
+        .await
+        .map_err(|e| store_error(Action::List, &url.item, url.span, e))?;
+    let purged = older_versions(listed, keep.item as usize);
+    if purged.is_empty() {
+        return Ok(PipelineData::Value(Value::list(vec![], call_span), None));
+    }
+    if confirming(engine, call).map_err(|e| *e)? {
+        let question = format!(
+            "Remove {} older versions of the objects under {}?",
+            purged.len(),
+            url.item
+        );
+        confirm(engine, &question, call_span).map_err(|e| *e)?;
+    }
+
+    let mut deletes = stream::iter(purged)
+        .map(|version| async move {
+            let result = versions
+                .delete(&version.location, &version.version_id)
+                .await;
+            (version, result)
+        })
+        .buffered(concurrency);
+    let mut rows = vec![];
+    while let Some((version, result)) = deletes.next().await {
+        engine.signals().check(&call_span)?;
+        let object = object_url(&url.item, &path, &version.location);
+        let target = format!("{object}?versionId={}", version.version_id);
+        if let Some(audit) = &audit {
+            audit.record(AuditAction::Delete, &target, result.as_ref().map(|_| ()));
+        }
+        let error = result
+            .err()
+            .map(|e| store_error(Action::Delete, &target, url.span, e));
+        rows.push(Value::record(
+            record!(
+                "url" => Value::string(object, call_span),
+                "version_id" => Value::string(version.version_id, call_span),
+                "modified" => Value::date(version.last_modified.fixed_offset(), call_span),
+                "size" => Value::filesize(version.size as i64, call_span),
+                "status" => Value::string(if error.is_some() { "failed" } else { "ok" }, call_span),
+                "error" => error.map_or(Value::nothing(call_span), |error| {
+                    Value::string(error.to_string(), call_span)
+                }),
+            ),
+            call_span,
+        ));
+    }
+    Ok(PipelineData::Value(Value::list(rows, call_span), None))
+}
+
+/// The versions of each object after the `keep` newest, from a listing by object and newest first
+fn older_versions(versions: Vec<ObjectVersion>, keep: usize) -> Vec<ObjectVersion> {
+    let mut newer = 0;
+    let mut object = None;
+    versions
+        .into_iter()
+        .filter(|version| {
+            if object.as_ref() != Some(&version.location) {
+                object = Some(version.location.clone());
+                newer = 0;
+            }
+            newer += 1;
+            newer > keep
+        })
+        .collect()
+}
+
+pub(super) fn version_client(
+    object_store: &NuObjectStore,
+    url: &Spanned<Url>,
+) -> Result<VersionClient, Box<ShellError>> {
+    object_store.version_client().ok_or_else(|| {
+        Box::new(ShellError::GenericError {
+            error: format!("Versions aren't supported for {} urls", url.item.scheme()),
+            msg: "".into(),
+            span: Some(url.span),
+            help: Some("Object versions are only supported in S3 buckets".into()),
+            inner: vec![],
+        })
+    })
+}
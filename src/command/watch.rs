@@ -0,0 +1,274 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::TryStreamExt;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, ListStream, PipelineData, ShellError, Signals, Signature,
+    Span, SyntaxShape, Type, Value, record,
+};
+use object_store::{ObjectMeta, path::Path};
+use url::Url;
+
+use super::{
+    duration_flag, object_url, run_flags, wait_until, with_rate_limit, with_request_log,
+    with_timeout,
+};
+use crate::{
+    CloudPlugin,
+    error::{Action, store_error},
+    providers::{NuObjectStore, limit, verbose},
+    remote::RemotePath,
+};
+
+pub struct Watch;
+
+impl PluginCommand for Watch {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud watch"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            Signature::build("cloud watch")
+                .input_output_types(vec![(Type::Nothing, Type::list(Type::record()))])
+                .required(
+                    "uri",
+                    SyntaxShape::String,
+                    "The url of the prefix to watch for changes.",
+                )
+                .named(
+                    "interval",
+                    SyntaxShape::Duration,
+                    "Time between listings of the prefix (default: 5sec)",
+                    None,
+                )
+                .named(
+                    "idle",
+                    SyntaxShape::Duration,
+                    "Stop once nothing changes for this long, instead of watching for more",
+                    None,
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Stream the objects created, modified, and deleted under a url, by listing it again and again."
+    }
+
+    fn extra_description(&self) -> &str {
+        "The objects under the url are listed when the command starts, and again every --interval, and each difference from the listing before is emitted as a record with whether the object was created, modified, or deleted, its name, url, size, etag, and modification time. Objects are modified when their etag, size, or modification time changes. The stream doesn't end until it is interrupted, or after --idle without changes, so its records can be handled as they come with each. Every listing lists the whole prefix, so use cloud events for large S3 prefixes."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Load each CSV file uploaded to a prefix into a local database.",
+                example: "cloud watch s3://mybucket/inbox/ | where event == created and name ends-with .csv | each { |e| cloud open $e.url | into sqlite inbox.db }",
+                result: None,
+            },
+            Example {
+                description: "Print the changes to a local directory for a minute after the last one.",
+                example: "cloud watch file:///srv/site/ --interval 1sec --idle 1min",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(call, with_timeout(call, command(engine, plugin, call))),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let interval = duration_flag(call, "interval", false)
+        .map_err(|e| *e)?
+        .unwrap_or(DEFAULT_INTERVAL);
+    let idle = duration_flag(call, "idle", true).map_err(|e| *e)?;
+
+    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
+    let list_error = {
+        let url = url.clone();
+        move |e| store_error(Action::List, &url.item, url.span, e)
+    };
+    let known = list(&object_store, &path).await.map_err(&list_error)?;
+    let mut changes = ChangeStream {
+        store: object_store,
+        prefix: path,
+        url: url.item.clone(),
+        interval,
+        idle,
+        known,
+        last_listed: Instant::now(),
+        last_change: Instant::now(),
+        pending: VecDeque::new(),
+        failed: false,
+        signals: engine.signals().clone(),
+        span: call_span,
+    };
+
+    let runtime = Arc::clone(plugin.runtime(engine, call_span).map_err(|e| *e)?);
+    let run_limit = limit::current();
+    let verbose = verbose::enabled();
+    let values = std::iter::from_fn(move || {
+        let next = limit::scoped(run_limit.clone(), changes.next());
+        runtime
+            .block_on(verbose::scoped(verbose, next))
+            .map(|result| result.unwrap_or_else(|e| Value::error(list_error(e), call_span)))
+    });
+    Ok(PipelineData::ListStream(
+        ListStream::new(values, call_span, engine.signals().clone()),
+        None,
+    ))
+}
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Changes to the objects under a prefix, found by listing it as they are consumed
+struct ChangeStream {
+    store: NuObjectStore,
+    prefix: Path,
+    url: Url,
+    interval: Duration,
+    idle: Option<Duration>,
+    /// Objects of the last listing, by path
+    known: BTreeMap<Path, ObjectMeta>,
+    last_listed: Instant,
+    last_change: Instant,
+    /// Changes found and not yet emitted
+    pending: VecDeque<Value>,
+    /// Whether a listing failed, which ends the stream after the error
+    failed: bool,
+    signals: Signals,
+    span: Span,
+}
+
+impl ChangeStream {
+    async fn next(&mut self) -> Option<object_store::Result<Value>> {
+        loop {
+            if self.failed {
+                return None;
+            }
+            if let Some(change) = self.pending.pop_front() {
+                return Some(Ok(change));
+            }
+            if let Some(idle) = self.idle
+                && self.last_change.elapsed() >= idle
+            {
+                return None;
+            }
+
+            let mut wait = self.interval.saturating_sub(self.last_listed.elapsed());
+            if let Some(idle) = self.idle {
+                wait = wait.min(idle.saturating_sub(self.last_change.elapsed()));
+            }
+            if !wait_until(Instant::now() + wait, &self.signals).await {
+                return None;
+            }
+            if self.last_listed.elapsed() < self.interval {
+                // Woken for the idle time to end
+                continue;
+            }
+
+            self.last_listed = Instant::now();
+            let listing = match list(&self.store, &self.prefix).await {
+                Ok(listing) => listing,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            };
+            self.compare(listing);
+            if !self.pending.is_empty() {
+                self.last_change = Instant::now();
+            }
+        }
+    }
+
+    /// Queue the differences of a listing from the last one, which it replaces
+    fn compare(&mut self, listing: BTreeMap<Path, ObjectMeta>) {
+        let mut known = std::mem::take(&mut self.known);
+        for meta in listing.values() {
+            let event = match known.remove(&meta.location) {
+                None => "created",
+                Some(previous)
+                    if previous.e_tag != meta.e_tag
+                        || previous.size != meta.size
+                        || previous.last_modified != meta.last_modified =>
+                {
+                    "modified"
+                }
+                Some(_) => continue,
+            };
+            self.pending.push_back(self.change(event, meta, true));
+        }
+        for meta in known.values() {
+            self.pending.push_back(self.change("deleted", meta, false));
+        }
+        self.known = listing;
+    }
+
+    fn change(&self, event: &str, meta: &ObjectMeta, exists: bool) -> Value {
+        let span = self.span;
+        let url = object_url(&self.url, &self.prefix, &meta.location);
+        Value::record(
+            record!(
+                "event" => Value::string(event, span),
+                "name" => Value::string(meta.location.to_string(), span),
+                "url" => Value::string(url, span),
+                "size" => match exists {
+                    true => Value::filesize(meta.size as i64, span),
+                    false => Value::nothing(span),
+                },
+                "etag" => match (&meta.e_tag, exists) {
+                    (Some(etag), true) => Value::string(etag, span),
+                    _ => Value::nothing(span),
+                },
+                "modified" => match exists {
+                    true => Value::date(meta.last_modified.fixed_offset(), span),
+                    false => Value::nothing(span),
+                },
+            ),
+            span,
+        )
+    }
+}
+
+async fn list(
+    store: &NuObjectStore,
+    prefix: &Path,
+) -> object_store::Result<BTreeMap<Path, ObjectMeta>> {
+    store
+        .object_store()
+        .list(Some(prefix))
+        .map_ok(|meta| (meta.location.clone(), meta))
+        .try_collect()
+        .await
+}
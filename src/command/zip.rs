@@ -0,0 +1,466 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::PathBuf,
+    vec,
+};
+
+use chrono::{Datelike, Timelike, Utc};
+use flate2::{Compression, write::DeflateEncoder};
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Span, SyntaxShape, Type,
+    Value,
+};
+
+use super::{
+    OutputFormat, confirm_overwrite, output_format_flag, run_flags, save::Upload, with_rate_limit,
+    with_request_log, with_timeout, yes_flag,
+};
+use crate::{
+    CloudPlugin,
+    audit::{AuditAction, AuditLog},
+    config::Config,
+    remote::RemotePath,
+};
+
+/// Size of the reads from local files
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Sizes, offsets, and counts at or above these need the Zip64 extensions
+const MAX_U32: u64 = u32::MAX as u64;
+const MAX_U16: usize = u16::MAX as usize;
+/// Version 4.5, which introduced Zip64, and 2.0 otherwise
+const VERSION_ZIP64: u16 = 45;
+const VERSION_DEFAULT: u16 = 20;
+/// Sizes and CRC follow the data in a descriptor, and names are UTF-8
+const FLAGS: u16 = 0x0808;
+const METHOD_STORE: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+
+pub struct Zip;
+
+impl PluginCommand for Zip {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud zip"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        run_flags(
+            output_format_flag(yes_flag(Signature::build("cloud zip")))
+                .input_output_types(vec![
+                    (Type::table(), Type::Any),
+                    (Type::List(Box::new(Type::String)), Type::Any),
+                ])
+                .required("uri", SyntaxShape::String, "The url of the zip archive.")
+                .switch(
+                    "store",
+                    "Store the entries without compressing them",
+                    Some('s'),
+                )
+                .category(Category::FileSystem),
+        )
+    }
+
+    fn description(&self) -> &str {
+        "Stream a zip archive of the input into a remote object."
+    }
+
+    fn extra_description(&self) -> &str {
+        "The input is a list of records with the `name` and `data` of each entry, or of local file paths, which are added under the path as given. The archive is uploaded as it is built, without writing it to local disk."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Archive generated reports.",
+                example: "[{name: a.csv, data: ([[a]; [1]] | to csv)} {name: b.json, data: ({b: 2} | to json)}] | cloud zip s3://mybucket/reports.zip",
+                result: None,
+            },
+            Example {
+                description: "Archive local log files.",
+                example: "glob logs/*.log | cloud zip s3://mybucket/logs.zip",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .runtime(engine, call.head)
+            .map_err(|e| *e)?
+            .block_on(with_request_log(
+                plugin,
+                engine,
+                call,
+                with_rate_limit(
+                    call,
+                    with_timeout(call, command(engine, plugin, call, input)),
+                ),
+            ))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+    input: PipelineData,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    let url = RemotePath::from_call(engine, call, 0).map_err(|e| *e)?.url;
+    let format = OutputFormat::from_call(call).map_err(|e| *e)?;
+    let method = match call.has_flag("store")? {
+        true => METHOD_STORE,
+        false => METHOD_DEFLATE,
+    };
+
+    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
+    let audit = AuditLog::open(engine, call).map_err(|e| *e)?;
+    let overwrite = confirm_overwrite(
+        engine,
+        call,
+        &object_store,
+        &path,
+        &url.item,
+        audit.as_ref(),
+    )
+    .await
+    .map_err(|e| *e)?;
+    plugin.cache.invalidate(&object_store, &path).await;
+    let config = Config::load(engine).map_err(|e| *e)?;
+    let mut upload = Upload::start(&config, object_store, path, &url, None, false).await?;
+    let mut zip = ZipStream::default();
+    for value in input {
+        engine.signals().check(&call_span)?;
+        match entry(value).map_err(|e| *e)? {
+            Entry::Data(name, data) => {
+                zip.start_entry(&name, method);
+                zip.write(&data);
+                zip.finish_entry();
+                upload.write(&zip.take()).await?;
+            }
+            Entry::File(name, file, span) => {
+                let file = match file.is_relative() {
+                    true => PathBuf::from(engine.get_current_dir()?).join(file),
+                    false => file,
+                };
+                let error = |e: std::io::Error| ShellError::GenericError {
+                    error: format!("Could not read {}: {e}", file.display()),
+                    msg: "".into(),
+                    span: Some(span),
+                    help: None,
+                    inner: vec![],
+                };
+                let mut reader = File::open(&file).map_err(error)?;
+                let mut buf = vec![0; CHUNK_SIZE];
+                zip.start_entry(&name, method);
+                loop {
+                    let len = reader.read(&mut buf).map_err(error)?;
+                    if len == 0 {
+                        break;
+                    }
+                    zip.write(&buf[..len]);
+                    upload.write(&zip.take()).await?;
+                    engine.signals().check(&call_span)?;
+                }
+                zip.finish_entry();
+                upload.write(&zip.take()).await?;
+            }
+        }
+    }
+    zip.finish();
+    upload.write(&zip.take()).await?;
+    // The object is only replaced once the upload completes
+    let size = upload.finish().await;
+    if let Some(audit) = audit.as_ref().filter(|_| overwrite) {
+        audit.record(AuditAction::Overwrite, &url.item, size.as_ref().map(|_| ()));
+    }
+    let size = size?;
+    Ok(match format {
+        Some(format) => format.output(
+            &url.item,
+            vec![(url.item.to_string(), Some(size))],
+            call_span,
+        ),
+        None => PipelineData::empty(),
+    })
+}
+
+enum Entry {
+    Data(String, Vec<u8>),
+    File(String, PathBuf, Span),
+}
+
+fn entry(value: Value) -> Result<Entry, Box<ShellError>> {
+    let span = value.span();
+    match value {
+        Value::String { val, .. } => {
+            // Entries are named by the path as given, without a leading root
+            let name = val.replace('\\', "/").trim_start_matches('/').to_string();
+            Ok(Entry::File(name, PathBuf::from(val), span))
+        }
+        Value::Record { val, .. } => {
+            let name = val
+                .get("name")
+                .ok_or_else(|| ShellError::CantFindColumn {
+                    col_name: "name".into(),
+                    span: None,
+                    src_span: span,
+                })?
+                .coerce_string()?;
+            let data = match val.get("data") {
+                Some(Value::Binary { val, .. }) => val.clone(),
+                Some(Value::String { val, .. }) => val.clone().into_bytes(),
+                Some(other) => {
+                    return Err(Box::new(ShellError::OnlySupportsThisInputType {
+                        exp_input_type: "string or binary".into(),
+                        wrong_type: other.get_type().to_string(),
+                        dst_span: span,
+                        src_span: other.span(),
+                    }));
+                }
+                None => {
+                    return Err(Box::new(ShellError::CantFindColumn {
+                        col_name: "data".into(),
+                        span: None,
+                        src_span: span,
+                    }));
+                }
+            };
+            Ok(Entry::Data(name, data))
+        }
+        Value::Error { error, .. } => Err(error),
+        other => Err(Box::new(ShellError::OnlySupportsThisInputType {
+            exp_input_type: "record or string".into(),
+            wrong_type: other.get_type().to_string(),
+            dst_span: span,
+            src_span: span,
+        })),
+    }
+}
+
+/// Writes a zip archive sequentially, for output that can't seek back to fill in the sizes of
+/// entries. Output accumulates until [`ZipStream::take`].
+#[derive(Default)]
+struct ZipStream {
+    out: Vec<u8>,
+    /// Bytes of the archive produced so far
+    offset: u64,
+    entries: Vec<ZipEntry>,
+    current: Option<Current>,
+}
+
+struct ZipEntry {
+    name: String,
+    method: u16,
+    time: u16,
+    date: u16,
+    crc: u32,
+    compressed: u64,
+    uncompressed: u64,
+    /// Offset of the local header
+    offset: u64,
+}
+
+impl ZipEntry {
+    fn zip64(&self) -> bool {
+        self.compressed >= MAX_U32 || self.uncompressed >= MAX_U32 || self.offset >= MAX_U32
+    }
+}
+
+struct Current {
+    entry: ZipEntry,
+    crc: crc32fast::Hasher,
+    deflate: Option<DeflateEncoder<Vec<u8>>>,
+}
+
+impl ZipStream {
+    fn start_entry(&mut self, name: &str, method: u16) {
+        let now = Utc::now();
+        let entry = ZipEntry {
+            name: name.to_string(),
+            method,
+            time: ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16,
+            date: (((now.year() - 1980).max(0) as u32) << 9 | (now.month() << 5) | now.day())
+                as u16,
+            crc: 0,
+            compressed: 0,
+            uncompressed: 0,
+            offset: self.offset,
+        };
+        // The CRC and sizes are zero here, they follow in the data descriptor
+        let mut header = vec![];
+        put_u32(&mut header, 0x04034b50);
+        put_u16(&mut header, VERSION_ZIP64);
+        put_u16(&mut header, FLAGS);
+        put_u16(&mut header, method);
+        put_u16(&mut header, entry.time);
+        put_u16(&mut header, entry.date);
+        put_u32(&mut header, 0);
+        put_u32(&mut header, 0);
+        put_u32(&mut header, 0);
+        put_u16(&mut header, name.len() as u16);
+        put_u16(&mut header, 0);
+        header.extend_from_slice(name.as_bytes());
+        self.emit(&header);
+        self.current = Some(Current {
+            entry,
+            crc: crc32fast::Hasher::new(),
+            deflate: (method == METHOD_DEFLATE)
+                .then(|| DeflateEncoder::new(vec![], Compression::default())),
+        });
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        let current = self.current.as_mut().expect("an entry is started");
+        current.crc.update(data);
+        current.entry.uncompressed += data.len() as u64;
+        let compressed = match &mut current.deflate {
+            Some(deflate) => {
+                deflate
+                    .write_all(data)
+                    .expect("writing to a Vec doesn't fail");
+                std::mem::take(deflate.get_mut())
+            }
+            None => data.to_vec(),
+        };
+        current.entry.compressed += compressed.len() as u64;
+        self.emit(&compressed);
+    }
+
+    fn finish_entry(&mut self) {
+        let Current {
+            mut entry,
+            crc,
+            deflate,
+        } = self.current.take().expect("an entry is started");
+        if let Some(deflate) = deflate {
+            let rest = deflate.finish().expect("writing to a Vec doesn't fail");
+            entry.compressed += rest.len() as u64;
+            self.emit(&rest);
+        }
+        entry.crc = crc.finalize();
+
+        let mut descriptor = vec![];
+        put_u32(&mut descriptor, 0x08074b50);
+        put_u32(&mut descriptor, entry.crc);
+        if entry.compressed >= MAX_U32 || entry.uncompressed >= MAX_U32 {
+            put_u64(&mut descriptor, entry.compressed);
+            put_u64(&mut descriptor, entry.uncompressed);
+        } else {
+            put_u32(&mut descriptor, entry.compressed as u32);
+            put_u32(&mut descriptor, entry.uncompressed as u32);
+        }
+        self.emit(&descriptor);
+        self.entries.push(entry);
+    }
+
+    /// Write the central directory
+    fn finish(&mut self) {
+        let start = self.offset;
+        let mut directory = vec![];
+        for entry in &self.entries {
+            let zip64 = entry.zip64();
+            let mut extra = vec![];
+            if zip64 {
+                put_u16(&mut extra, 0x0001);
+                put_u16(&mut extra, 24);
+                put_u64(&mut extra, entry.uncompressed);
+                put_u64(&mut extra, entry.compressed);
+                put_u64(&mut extra, entry.offset);
+            }
+            let version = if zip64 {
+                VERSION_ZIP64
+            } else {
+                VERSION_DEFAULT
+            };
+            let capped = |n: u64| if zip64 { u32::MAX } else { n as u32 };
+            put_u32(&mut directory, 0x02014b50);
+            // Made by Unix, so the external attributes hold the permissions
+            put_u16(&mut directory, (3 << 8) | version);
+            put_u16(&mut directory, version);
+            put_u16(&mut directory, FLAGS);
+            put_u16(&mut directory, entry.method);
+            put_u16(&mut directory, entry.time);
+            put_u16(&mut directory, entry.date);
+            put_u32(&mut directory, entry.crc);
+            put_u32(&mut directory, capped(entry.compressed));
+            put_u32(&mut directory, capped(entry.uncompressed));
+            put_u16(&mut directory, entry.name.len() as u16);
+            put_u16(&mut directory, extra.len() as u16);
+            put_u16(&mut directory, 0);
+            put_u16(&mut directory, 0);
+            put_u16(&mut directory, 0);
+            put_u32(&mut directory, 0o100644 << 16);
+            put_u32(&mut directory, capped(entry.offset));
+            directory.extend_from_slice(entry.name.as_bytes());
+            directory.extend_from_slice(&extra);
+        }
+        let size = directory.len() as u64;
+        self.emit(&directory);
+
+        let count = self.entries.len();
+        let mut end = vec![];
+        let zip64 = count >= MAX_U16 || start >= MAX_U32 || size >= MAX_U32;
+        if zip64 {
+            let record = self.offset;
+            put_u32(&mut end, 0x06064b50);
+            put_u64(&mut end, 44);
+            put_u16(&mut end, (3 << 8) | VERSION_ZIP64);
+            put_u16(&mut end, VERSION_ZIP64);
+            put_u32(&mut end, 0);
+            put_u32(&mut end, 0);
+            put_u64(&mut end, count as u64);
+            put_u64(&mut end, count as u64);
+            put_u64(&mut end, size);
+            put_u64(&mut end, start);
+            // Locator of the Zip64 record
+            put_u32(&mut end, 0x07064b50);
+            put_u32(&mut end, 0);
+            put_u64(&mut end, record);
+            put_u32(&mut end, 1);
+        }
+        let count = if zip64 { u16::MAX } else { count as u16 };
+        let capped = |n: u64| if zip64 { u32::MAX } else { n as u32 };
+        put_u32(&mut end, 0x06054b50);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, count);
+        put_u16(&mut end, count);
+        put_u32(&mut end, capped(size));
+        put_u32(&mut end, capped(start));
+        put_u16(&mut end, 0);
+        self.emit(&end);
+    }
+
+    fn emit(&mut self, data: &[u8]) {
+        self.out.extend_from_slice(data);
+        self.offset += data.len() as u64;
+    }
+
+    /// Output produced since the last call
+    fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.out)
+    }
+}
+
+fn put_u16(out: &mut Vec<u8>, n: u16) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, n: u64) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
@@ -0,0 +1,433 @@
+// The `FromValue` derive returns unboxed `ShellError`s
+#![allow(clippy::result_large_err)]
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use http::{HeaderMap, HeaderName, HeaderValue};
+use nu_plugin::EngineInterface;
+use nu_protocol::{Filesize, FromValue, ShellError, Type, Value, engine::Closure};
+use object_store::{Certificate, ClientOptions};
+use reqwest::ClientBuilder;
+use tokio::runtime::{self, Runtime};
+use url::Url;
+
+/// Plugin configuration, read from `$env.config.plugins.cloud`
+#[derive(Debug, Default, Clone, FromValue)]
+pub struct Config {
+    /// PEM file with additional root certificates to trust, e.g. of a TLS intercepting proxy
+    pub ca_file: Option<PathBuf>,
+    /// Accept invalid TLS certificates, e.g. self-signed ones. This is insecure.
+    pub allow_invalid_certificates: Option<bool>,
+    /// Timeout for a whole request, including reading the response body
+    pub timeout: Option<ConfigDuration>,
+    pub connect_timeout: Option<ConfigDuration>,
+    /// How long idle connections are kept for reuse
+    pub pool_idle_timeout: Option<ConfigDuration>,
+    /// Appended to the plugin's User-Agent header
+    pub user_agent: Option<String>,
+    /// Limit on requests per second across all commands, to stay clear of provider throttling
+    /// and shared API quotas. Applies to `s3`, `http`, and `https` urls.
+    pub requests_per_second: Option<f64>,
+    /// Maximum number of parts of a multipart upload in flight at once, bounding the memory
+    /// used when the network is slower than the input (default: 8)
+    pub upload_concurrency: Option<NonZeroUsize>,
+    /// Objects, parts, or HEAD requests worked on at once by commands with a `--concurrency`
+    /// flag, which overrides it (default: 8)
+    pub concurrency: Option<NonZeroUsize>,
+    /// Maximum transfer rate per second of `cloud open`, `cloud save`, `cloud cp`, `cloud mv`,
+    /// and `cloud sync`, e.g. `10MB` or `'10MB/s'`. The `--bwlimit` flag overrides it.
+    pub bwlimit: Option<Value>,
+    /// Fixed size of upload parts, for stores requiring parts of equal size. By default parts
+    /// are sized for the object size and the measured throughput.
+    pub upload_part_size: Option<Filesize>,
+    /// File recording multipart uploads for `cloud save --resume`, by default in the local
+    /// data directory
+    pub transfer_journal: Option<PathBuf>,
+    /// Ranges of large objects fetched ahead while `cloud open --raw` streams them (default: 4),
+    /// 0 reads objects whole
+    pub read_ahead: Option<usize>,
+    /// Keep objects read by `cloud open` on disk, so they aren't downloaded again
+    pub disk_cache: Option<DiskCacheConfig>,
+    /// Retries of throttled requests and transient server errors
+    pub retry: Option<RetryConfig>,
+    /// Export spans of commands and their requests to an OpenTelemetry collector
+    pub tracing: Option<TracingConfig>,
+    /// Append a line of JSON for every request to a file, for investigating intermittent errors
+    pub debug_log: Option<DebugLogConfig>,
+    /// Append a line of JSON for every object removed or overwritten to a file, for change
+    /// tracking on shared buckets
+    pub audit_log: Option<PathBuf>,
+    /// Inject server errors, slow responses, and truncated bodies into requests, for testing
+    /// how commands cope with unreliable providers
+    pub fault_injection: Option<FaultInjectionConfig>,
+    /// Prices applied by `cloud cost estimate`, by provider, the scheme of the urls. `s3`
+    /// defaults to the list prices of S3 Standard in us-east-1.
+    pub pricing: Option<HashMap<String, PricingConfig>>,
+    /// Record the responses of providers to a file, or replay them from it without sending
+    /// requests, for tests that shouldn't need credentials
+    #[cfg(feature = "fixtures")]
+    pub fixtures: Option<FixturesConfig>,
+    /// Disable plugin GC while stores are cached, so connections and credentials are reused
+    /// across commands (default: true)
+    pub keep_resident: Option<bool>,
+    /// Threads running the plugin's transfers, read by the first command after the plugin
+    /// starts
+    pub runtime: Option<RuntimeConfig>,
+    /// Url that relative paths given to commands resolve against, e.g. `s3://bucket/team/`.
+    /// `CLOUD_DEFAULT_URL` takes precedence.
+    pub default_url: Option<String>,
+    /// Urls that a leading `~name` in urls given to commands expands to
+    pub aliases: Option<HashMap<String, String>>,
+    /// Extra headers sent with every request
+    pub headers: Option<HashMap<String, String>>,
+    /// Closures keyed by url scheme, called with a record describing each request and
+    /// returning a record of headers to add, e.g. signatures for a custom gateway
+    pub header_hooks: Option<HashMap<String, Closure>>,
+    /// AWS profile of S3 buckets without one of their own. `AWS_PROFILE` is used without it.
+    pub profile: Option<String>,
+    /// Settings applied to urls starting with the given bucket or prefix,
+    /// e.g. `s3://prod-data` or `s3://shared/team-a/`
+    pub buckets: Option<HashMap<String, BucketConfig>>,
+}
+
+#[derive(Debug, Default, Clone, FromValue)]
+pub struct BucketConfig {
+    /// Named profile from the AWS configuration files
+    pub profile: Option<String>,
+    pub region: Option<String>,
+    /// Custom endpoint for S3 compatible services
+    pub endpoint: Option<String>,
+    /// Use the S3 Transfer Acceleration endpoint, unless `endpoint` is set
+    pub use_accelerate_endpoint: Option<bool>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+    /// Closure called with the url to obtain credentials, returning a record with
+    /// `access_key_id`, `secret_access_key`, and optionally `session_token` and `expiry`
+    pub credentials: Option<Closure>,
+    /// Authenticate with bearer tokens from an OIDC provider instead of the store's own scheme
+    pub oidc: Option<OidcConfig>,
+    /// Prefixes serving the same objects, tried in order when a read fails
+    pub replicas: Option<Vec<String>>,
+    /// Read from all replicas at once and use the first successful response
+    pub race_replicas: Option<bool>,
+    /// Storage class for newly written objects, e.g. `STANDARD_IA`
+    pub storage_class: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, FromValue)]
+pub struct DiskCacheConfig {
+    /// Directory of the cache, by default in the user's cache directory
+    pub dir: Option<PathBuf>,
+    /// Size beyond which the least recently used objects are evicted (default: 1GiB)
+    pub max_size: Option<Filesize>,
+    /// Time cached objects are used before checking whether they changed (default: 1hr)
+    pub ttl: Option<ConfigDuration>,
+}
+
+#[derive(Debug, Default, Clone, FromValue)]
+pub struct TracingConfig {
+    /// OTLP/HTTP endpoint of the collector, e.g. `http://localhost:4318`. Spans are posted as
+    /// JSON to `<endpoint>/v1/traces`.
+    pub endpoint: String,
+    /// `service.name` of the exported spans (default: nu_plugin_cloud)
+    pub service_name: Option<String>,
+    /// Headers sent with exports, e.g. the API key of a hosted collector
+    pub headers: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Default, Clone, FromValue)]
+pub struct DebugLogConfig {
+    pub path: PathBuf,
+    /// Size at which the file is rotated (default: 10MB)
+    pub max_size: Option<Filesize>,
+    /// Rotated files kept as `<path>.1` to `<path>.<n>` (default: 5)
+    pub max_files: Option<usize>,
+}
+
+#[cfg(feature = "fixtures")]
+#[derive(Debug, Clone, FromValue)]
+pub struct FixturesConfig {
+    /// File of JSON lines, appended to when recording
+    pub path: PathBuf,
+    pub mode: FixtureMode,
+}
+
+#[cfg(feature = "fixtures")]
+#[derive(Debug, Clone, PartialEq, Eq, FromValue)]
+pub enum FixtureMode {
+    Record,
+    Replay,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, FromValue)]
+pub struct FaultInjectionConfig {
+    /// Probability of answering an attempt with `500 Internal Server Error` without sending it
+    pub error_rate: Option<f64>,
+    /// Probability of waiting `delay` before an attempt
+    pub delay_rate: Option<f64>,
+    /// (default: 1sec)
+    pub delay: Option<ConfigDuration>,
+    /// Probability of cutting the body of a response off halfway
+    pub truncate_rate: Option<f64>,
+    /// Seed making the faults repeat between runs
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, FromValue)]
+pub struct PricingConfig {
+    /// Price of a GiB downloaded
+    pub egress_per_gib: Option<f64>,
+    /// Price of a GiB uploaded
+    pub ingress_per_gib: Option<f64>,
+    /// Price of 1000 requests by HTTP method, e.g. `{GET: 0.0004, PUT: 0.005}`. Requests with
+    /// other methods are free.
+    pub per_1000_requests: Option<HashMap<String, f64>>,
+}
+
+impl PricingConfig {
+    /// List prices of S3 Standard in us-east-1
+    fn s3() -> Self {
+        let requests = [
+            ("GET", 0.0004),
+            ("HEAD", 0.0004),
+            ("PUT", 0.005),
+            ("POST", 0.005),
+        ];
+        PricingConfig {
+            egress_per_gib: Some(0.09),
+            ingress_per_gib: Some(0.0),
+            per_1000_requests: Some(
+                requests
+                    .into_iter()
+                    .map(|(method, price)| (method.to_string(), price))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, FromValue)]
+pub struct RetryConfig {
+    /// Retries after the first attempt, 0 disables retries (default: 10)
+    pub max_retries: Option<usize>,
+    /// Initial backoff, grown exponentially with jitter unless the server sends a
+    /// `Retry-After` header (default: 100ms)
+    pub init_backoff: Option<ConfigDuration>,
+    pub max_backoff: Option<ConfigDuration>,
+    /// Time after which a request is no longer retried (default: 3min)
+    pub retry_timeout: Option<ConfigDuration>,
+}
+
+#[derive(Debug, Default, Clone, FromValue)]
+pub struct RuntimeConfig {
+    /// Run everything on the calling thread, for constrained environments
+    pub current_thread: Option<bool>,
+    /// Threads of the multi-threaded runtime (default: one per CPU core)
+    pub worker_threads: Option<NonZeroUsize>,
+}
+
+impl RuntimeConfig {
+    pub fn build(&self) -> std::io::Result<Runtime> {
+        let mut builder = if self.current_thread.unwrap_or(false) {
+            runtime::Builder::new_current_thread()
+        } else {
+            runtime::Builder::new_multi_thread()
+        };
+        if let Some(threads) = self.worker_threads {
+            builder.worker_threads(threads.get());
+        }
+        builder.thread_name("nu_plugin_cloud").enable_all().build()
+    }
+}
+
+#[derive(Debug, Default, Clone, FromValue)]
+pub struct OidcConfig {
+    pub token_url: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub scope: Option<String>,
+    pub audience: Option<String>,
+    /// File containing a token, used directly or exchanged at the token url
+    pub token_file: Option<PathBuf>,
+}
+
+const DEFAULT_UPLOAD_CONCURRENCY: usize = 8;
+const DEFAULT_CONCURRENCY: usize = 8;
+
+impl Config {
+    pub fn load(engine: &EngineInterface) -> Result<Self, Box<ShellError>> {
+        match engine.get_plugin_config()? {
+            Some(value) => Ok(Config::from_value(value)?),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn upload_concurrency(&self) -> usize {
+        self.upload_concurrency
+            .map_or(DEFAULT_UPLOAD_CONCURRENCY, NonZeroUsize::get)
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+            .map_or(DEFAULT_CONCURRENCY, NonZeroUsize::get)
+    }
+
+    /// HTTP client options for object_store backends. `headers` are backend specific headers,
+    /// taking precedence over configured ones.
+    pub fn client_options(
+        &self,
+        headers: HeaderMap,
+    ) -> Result<ClientOptions, Box<dyn Error + Send + Sync>> {
+        let mut default_headers = self.default_headers()?;
+        default_headers.extend(headers);
+        let mut options = ClientOptions::new()
+            .with_user_agent(self.user_agent()?)
+            .with_default_headers(default_headers)
+            .with_allow_invalid_certificates(self.allow_invalid_certificates.unwrap_or(false));
+        if let Some(ConfigDuration(timeout)) = self.timeout {
+            options = options.with_timeout(timeout);
+        }
+        if let Some(ConfigDuration(timeout)) = self.connect_timeout {
+            options = options.with_connect_timeout(timeout);
+        }
+        if let Some(ConfigDuration(timeout)) = self.pool_idle_timeout {
+            options = options.with_pool_idle_timeout(timeout);
+        }
+        if let Some(ca_file) = &self.ca_file {
+            for certificate in Certificate::from_pem_bundle(&read_ca_file(ca_file)?)? {
+                options = options.with_root_certificate(certificate);
+            }
+        }
+        Ok(options)
+    }
+
+    /// HTTP client builder with the same settings as [`Config::client_options`], for backends
+    /// using reqwest directly
+    pub fn client_builder(&self) -> Result<ClientBuilder, Box<dyn Error + Send + Sync>> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(self.user_agent()?)
+            .default_headers(self.default_headers()?)
+            .danger_accept_invalid_certs(self.allow_invalid_certificates.unwrap_or(false));
+        if let Some(ConfigDuration(timeout)) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(ConfigDuration(timeout)) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(ConfigDuration(timeout)) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(ca_file) = &self.ca_file {
+            for certificate in reqwest::Certificate::from_pem_bundle(&read_ca_file(ca_file)?)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        Ok(builder)
+    }
+
+    fn user_agent(&self) -> Result<HeaderValue, Box<dyn Error + Send + Sync>> {
+        let user_agent = concat!("nu_plugin_cloud/", env!("CARGO_PKG_VERSION"));
+        Ok(match &self.user_agent {
+            Some(suffix) => HeaderValue::from_str(&format!("{user_agent} {suffix}"))?,
+            None => HeaderValue::from_static(user_agent),
+        })
+    }
+
+    fn default_headers(&self) -> Result<HeaderMap, Box<dyn Error + Send + Sync>> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.headers.iter().flatten() {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        Ok(headers)
+    }
+
+    /// Prices of the provider, the configured ones taking precedence over the defaults
+    pub fn pricing(&self, provider: &str) -> Option<PricingConfig> {
+        let configured = self
+            .pricing
+            .as_ref()
+            .and_then(|pricing| pricing.get(provider));
+        let default = (provider == "s3").then(PricingConfig::s3);
+        match (configured.cloned(), default) {
+            (Some(configured), Some(default)) => Some(PricingConfig {
+                egress_per_gib: configured.egress_per_gib.or(default.egress_per_gib),
+                ingress_per_gib: configured.ingress_per_gib.or(default.ingress_per_gib),
+                per_1000_requests: configured.per_1000_requests.or(default.per_1000_requests),
+            }),
+            (configured, default) => configured.or(default),
+        }
+    }
+
+    /// The header hook for urls with the scheme
+    pub fn header_hook(&self, scheme: &str) -> Option<Closure> {
+        self.header_hooks.as_ref()?.get(scheme).cloned()
+    }
+
+    /// Find the settings for the longest configured prefix matching the url
+    pub fn bucket(&self, url: &Url) -> Option<(&str, &BucketConfig)> {
+        let url = url.as_str();
+        self.buckets
+            .iter()
+            .flatten()
+            .filter(|(prefix, _)| prefix_matches(prefix, url))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, config)| (prefix.as_str(), config))
+    }
+
+    /// Urls to read the object from: the url itself followed by the configured replicas
+    pub fn replica_urls(&self, url: &Url) -> Result<Vec<Url>, url::ParseError> {
+        let mut urls = vec![url.clone()];
+        if let Some((prefix, config)) = self.bucket(url) {
+            let key = &url.as_str()[prefix.len()..];
+            for replica in config.replicas.iter().flatten() {
+                urls.push(Url::parse(&format!("{replica}{key}"))?);
+            }
+        }
+        Ok(urls)
+    }
+}
+
+/// A prefix only matches on a path boundary, so `s3://data` doesn't match `s3://data-dev/`
+fn prefix_matches(prefix: &str, url: &str) -> bool {
+    url.strip_prefix(prefix)
+        .is_some_and(|rest| prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/'))
+}
+
+/// A nushell duration, e.g. `30sec`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConfigDuration(pub Duration);
+
+impl FromValue for ConfigDuration {
+    fn from_value(v: Value) -> Result<Self, ShellError> {
+        match v {
+            Value::Duration { val, .. } if val >= 0 => {
+                Ok(ConfigDuration(Duration::from_nanos(val as u64)))
+            }
+            v => Err(ShellError::CantConvert {
+                to_type: Self::expected_type().to_string(),
+                from_type: v.get_type().to_string(),
+                span: v.span(),
+                help: Some("Use a positive duration, e.g. 30sec".into()),
+            }),
+        }
+    }
+
+    fn expected_type() -> Type {
+        Type::Duration
+    }
+}
+
+fn read_ca_file(path: &Path) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    std::fs::read(path)
+        .map_err(|e| format!("Could not read CA file {}: {e}", path.display()).into())
+}
@@ -0,0 +1,130 @@
+// The `FromValue` derive returns unboxed `ShellError`s
+#![allow(clippy::result_large_err)]
+
+use std::{error::Error, time::Duration};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use keyring::Entry;
+use nu_plugin::EngineInterface;
+use nu_protocol::{FromValue, ShellError, Spanned, Value, engine::Closure};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::cache::CredentialCache;
+
+/// Credentials supplied by the user, either directly in the plugin config or
+/// returned from a credentials closure
+#[derive(Debug, Clone, FromValue)]
+pub struct CloudCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+    /// When the credentials stop being valid, they are cached until then
+    pub expiry: Option<DateTime<FixedOffset>>,
+}
+
+/// Temporary credentials are refreshed this long before they expire, so that requests
+/// in a long running pipeline don't fail with expired credentials
+pub const REFRESH_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+impl CloudCredentials {
+    /// Whether the credentials expire within the [`REFRESH_WINDOW`]
+    pub fn needs_refresh(&self) -> bool {
+        self.expiry
+            .is_some_and(|expiry| expiry <= Utc::now() + REFRESH_WINDOW)
+    }
+}
+
+/// Call the closure configured for `profile` to obtain credentials, unless the last ones
+/// it returned are still valid
+pub async fn from_closure(
+    engine: &EngineInterface,
+    cache: &CredentialCache,
+    profile: &str,
+    closure: &Closure,
+    url: &Spanned<Url>,
+) -> Result<CloudCredentials, ShellError> {
+    if let Some(credentials) = cache.get(profile).await {
+        return Ok(credentials);
+    }
+
+    let closure = Spanned {
+        item: closure.clone(),
+        span: url.span,
+    };
+    let value = engine.eval_closure(
+        &closure,
+        vec![Value::string(url.item.to_string(), url.span)],
+        None,
+    )?;
+    let credentials =
+        CloudCredentials::from_value(value).map_err(|e| ShellError::GenericError {
+            error: format!("Credentials closure for {profile} returned an invalid record"),
+            msg: "".into(),
+            span: Some(url.span),
+            help: Some(
+                "Return a record with access_key_id, secret_access_key and optionally session_token and expiry".into(),
+            ),
+            inner: vec![e],
+        })?;
+
+    cache.put(profile, credentials.clone()).await;
+    Ok(credentials)
+}
+
+const KEYCHAIN_SERVICE: &str = "nu_plugin_cloud";
+
+/// Credentials as stored in the OS keychain by `cloud login`
+#[derive(Serialize, Deserialize)]
+struct StoredCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+/// Save credentials for the profile in the OS keychain
+pub async fn keychain_store(
+    profile: &str,
+    credentials: &CloudCredentials,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let secret = serde_json::to_string(&StoredCredentials {
+        access_key_id: credentials.access_key_id.clone(),
+        secret_access_key: credentials.secret_access_key.clone(),
+        session_token: credentials.session_token.clone(),
+    })?;
+    let profile = profile.to_string();
+    // The keychain backends block, and may run their own async runtime
+    tokio::task::spawn_blocking(move || {
+        Entry::new(KEYCHAIN_SERVICE, &profile)?.set_password(&secret)
+    })
+    .await??;
+    Ok(())
+}
+
+/// Credentials for the profile from the OS keychain, if `cloud login` stored any
+pub async fn keychain_load(
+    profile: &str,
+) -> Result<Option<CloudCredentials>, Box<dyn Error + Send + Sync>> {
+    let profile = profile.to_string();
+    let secret = tokio::task::spawn_blocking(move || {
+        match Entry::new(KEYCHAIN_SERVICE, &profile)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e),
+        }
+    })
+    .await??;
+
+    match secret {
+        Some(secret) => {
+            let stored: StoredCredentials = serde_json::from_str(&secret)?;
+            Ok(Some(CloudCredentials {
+                access_key_id: stored.access_key_id,
+                secret_access_key: stored.secret_access_key,
+                session_token: stored.session_token,
+                expiry: None,
+            }))
+        }
+        None => Ok(None),
+    }
+}
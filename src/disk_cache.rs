@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use log::debug;
+use object_store::{GetOptions, path::Path};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{config::DiskCacheConfig, providers::NuObjectStore};
+
+const DEFAULT_MAX_SIZE: u64 = 1024 * 1024 * 1024;
+const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+const INDEX_FILE: &str = "index.json";
+
+/// Serializes access to the index by the calls of this process
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// Objects cached on disk, keyed by url
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    entries: HashMap<String, IndexEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    /// Name of the data file in the cache directory
+    file: String,
+    size: u64,
+    e_tag: Option<String>,
+    /// Milliseconds since the epoch when the data was last fetched or revalidated
+    validated_at: u64,
+    /// Milliseconds since the epoch when the entry was last read, for LRU eviction
+    accessed_at: u64,
+}
+
+impl Index {
+    fn size(&self) -> u64 {
+        self.entries.values().map(|entry| entry.size).sum()
+    }
+}
+
+/// Content cache kept across plugin restarts, so repeated reads of the same objects don't
+/// download them again.
+///
+/// Entries are used without asking the store until `ttl` has passed, then revalidated by ETag.
+/// The least recently used entries are evicted once the cache grows beyond `max_size`.
+#[derive(Debug)]
+pub struct DiskCache {
+    dir: PathBuf,
+    max_size: u64,
+    ttl: Duration,
+}
+
+impl DiskCache {
+    pub fn new(config: &DiskCacheConfig) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let dir = match &config.dir {
+            Some(dir) => dir.clone(),
+            None => dirs::cache_dir()
+                .ok_or("Could not determine the cache directory")?
+                .join("nu_plugin_cloud"),
+        };
+        Ok(DiskCache {
+            dir,
+            max_size: config
+                .max_size
+                .map_or(DEFAULT_MAX_SIZE, |size| size.get().max(0) as u64),
+            ttl: config.ttl.map_or(DEFAULT_TTL, |ttl| ttl.0),
+        })
+    }
+
+    /// Read the object at `path`, from the cache if it is still valid
+    pub async fn get(
+        &self,
+        store: &NuObjectStore,
+        path: &Path,
+        url: &Url,
+    ) -> object_store::Result<Bytes> {
+        let key = url.to_string();
+        let entry = self.entry(&key);
+
+        if let Some(entry) = &entry {
+            let age = Duration::from_millis(now().saturating_sub(entry.validated_at));
+            let not_modified = if age < self.ttl {
+                true
+            } else if let Some(e_tag) = &entry.e_tag {
+                let opts = GetOptions {
+                    if_none_match: Some(e_tag.clone()),
+                    ..GetOptions::default()
+                };
+                match store.object_store().get_opts(path, opts).await {
+                    Err(object_store::Error::NotModified { .. }) => true,
+                    Err(e) => return Err(e),
+                    Ok(result) => {
+                        let e_tag = result.meta.e_tag.clone();
+                        let data = result.bytes().await?;
+                        self.put(&key, &data, e_tag);
+                        return Ok(data);
+                    }
+                }
+            } else {
+                false
+            };
+
+            if not_modified {
+                match tokio::fs::read(self.dir.join(&entry.file)).await {
+                    Ok(data) => {
+                        self.touch(&key, age >= self.ttl);
+                        return Ok(Bytes::from(data));
+                    }
+                    Err(e) => debug!("Could not read cached {key}: {e}"),
+                }
+            }
+        }
+
+        let result = store.object_store().get(path).await?;
+        let e_tag = result.meta.e_tag.clone();
+        let data = result.bytes().await?;
+        self.put(&key, &data, e_tag);
+        Ok(data)
+    }
+
+    /// Path of an up to date local copy of the object at `path`, for data that is opened by
+    /// path, such as SQLite databases
+    pub async fn get_file(
+        &self,
+        store: &NuObjectStore,
+        path: &Path,
+        url: &Url,
+    ) -> object_store::Result<PathBuf> {
+        self.get(store, path, url).await?;
+        match self.entry(url.as_str()) {
+            Some(entry) => Ok(self.dir.join(entry.file)),
+            None => Err(object_store::Error::Generic {
+                store: "disk cache",
+                source: format!(
+                    "{url} could not be cached, it may be larger than the disk cache's max_size"
+                )
+                .into(),
+            }),
+        }
+    }
+
+    /// Remove all cached objects
+    pub fn clear(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _lock = INDEX_LOCK.lock().expect("Index lock poisoned");
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn entry(&self, key: &str) -> Option<IndexEntry> {
+        let _lock = INDEX_LOCK.lock().expect("Index lock poisoned");
+        self.load().entries.remove(key)
+    }
+
+    /// Record a read of the entry, which was revalidated if `validated`
+    fn touch(&self, key: &str, validated: bool) {
+        let result = self.update(|index| {
+            if let Some(entry) = index.entries.get_mut(key) {
+                entry.accessed_at = now();
+                if validated {
+                    entry.validated_at = now();
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            debug!("Could not update disk cache index: {e}");
+        }
+    }
+
+    /// Cache `data`, evicting the least recently used entries to stay within the size limit.
+    /// Failures are logged, as the data was read anyway.
+    fn put(&self, key: &str, data: &Bytes, e_tag: Option<String>) {
+        let size = data.len() as u64;
+        if size > self.max_size {
+            return;
+        }
+        let result = self.update(|index| {
+            let file = match index.entries.get(key) {
+                Some(entry) => entry.file.clone(),
+                None => format!("{:016x}", fastrand::u64(..)),
+            };
+            let tmp = self.dir.join(format!("{file}.tmp"));
+            std::fs::write(&tmp, data)?;
+            std::fs::rename(&tmp, self.dir.join(&file))?;
+            index.entries.insert(
+                key.to_string(),
+                IndexEntry {
+                    file,
+                    size,
+                    e_tag,
+                    validated_at: now(),
+                    accessed_at: now(),
+                },
+            );
+
+            let mut total = index.size();
+            if total > self.max_size {
+                let mut entries: Vec<_> = index
+                    .entries
+                    .iter()
+                    .map(|(key, entry)| (entry.accessed_at, key.clone()))
+                    .collect();
+                entries.sort();
+                for (_, key) in entries {
+                    if total <= self.max_size {
+                        break;
+                    }
+                    if let Some(entry) = index.entries.remove(&key) {
+                        debug!("Evicting {key} from the disk cache");
+                        let _ = std::fs::remove_file(self.dir.join(&entry.file));
+                        total -= entry.size;
+                    }
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            debug!("Could not cache {key} on disk: {e}");
+        }
+    }
+
+    fn load(&self) -> Index {
+        std::fs::read(self.dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    fn update(
+        &self,
+        f: impl FnOnce(&mut Index) -> Result<(), Box<dyn Error + Send + Sync>>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _lock = INDEX_LOCK.lock().expect("Index lock poisoned");
+        std::fs::create_dir_all(&self.dir)?;
+        let mut index = self.load();
+        f(&mut index)?;
+        let tmp = self.dir.join(format!("{INDEX_FILE}.tmp"));
+        std::fs::write(&tmp, serde_json::to_vec(&index)?)?;
+        std::fs::rename(&tmp, self.dir.join(INDEX_FILE))?;
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
@@ -0,0 +1,89 @@
+use std::fmt::Display;
+
+use nu_protocol::{LabeledError, ShellError, Span};
+
+/// What a command was doing when a store request failed
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    Read,
+    Write,
+    List,
+    Delete,
+    Copy,
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Action::Read => "read",
+            Action::Write => "write",
+            Action::List => "list",
+            Action::Delete => "delete",
+            Action::Copy => "copy",
+        })
+    }
+}
+
+/// Report a failed store request on `target`, labelling `span` with the cause.
+///
+/// Errors get a stable `cloud::` code for scripts to match on with `try`, and help for the
+/// usual causes.
+pub fn store_error(
+    action: Action,
+    target: impl Display,
+    span: Span,
+    e: object_store::Error,
+) -> ShellError {
+    let source = e.to_string();
+    let (code, help) = match &e {
+        object_store::Error::NotFound { .. } if source.contains("NoSuchBucket") => (
+            "not_found",
+            Some("The bucket doesn't exist, check its name, and the region or profile used for it"),
+        ),
+        object_store::Error::NotFound { .. } => (
+            "not_found",
+            Some("Check the url, `cloud ls` lists the objects under a prefix"),
+        ),
+        object_store::Error::PermissionDenied { .. } => (
+            "permission_denied",
+            Some(
+                "The credentials lack permission for this, check the profile or role used for the bucket",
+            ),
+        ),
+        object_store::Error::Unauthenticated { .. } => (
+            "unauthenticated",
+            Some("Check the credentials, e.g. the AWS profile, or store them with `cloud login`"),
+        ),
+        object_store::Error::AlreadyExists { .. } => ("already_exists", None),
+        object_store::Error::Precondition { .. } => (
+            "precondition_failed",
+            Some("The object changed while the command ran, try again"),
+        ),
+        object_store::Error::NotModified { .. } => ("not_modified", None),
+        object_store::Error::NotImplemented | object_store::Error::NotSupported { .. } => (
+            "not_supported",
+            Some("The store of this url doesn't support this operation"),
+        ),
+        object_store::Error::InvalidPath { .. } => ("invalid_path", None),
+        // S3 redirects requests signed for the wrong region
+        _ if source.contains("PermanentRedirect")
+            || source.contains("AuthorizationHeaderMalformed") =>
+        {
+            (
+                "wrong_region",
+                Some(
+                    "The bucket is in another region, set `region` in its bucket config or AWS_REGION",
+                ),
+            )
+        }
+        _ => ("store", None),
+    };
+    let error = LabeledError::new(format!("Could not {action} {target}"))
+        .with_code(format!("cloud::{code}"))
+        .with_label(source, span);
+    let error = match help {
+        Some(help) => error.with_help(help),
+        None => error,
+    };
+    ShellError::LabeledError(Box::new(error))
+}
@@ -0,0 +1,162 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use chrono::{DateTime, Local};
+use log::warn;
+use nu_plugin::EngineInterface;
+use nu_protocol::ShellError;
+use tokio::{runtime::Runtime, task::AbortHandle};
+
+/// Transfers started with `--background`, which run on the plugin's runtime after the commands
+/// that started them return. Finished jobs are kept for their status until the plugin stops.
+#[derive(Debug, Default)]
+pub struct Jobs {
+    jobs: Mutex<BTreeMap<u64, Job>>,
+    /// Whether cached stores keep the plugin resident, which it stays once jobs finish
+    stores_resident: AtomicBool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: u64,
+    /// Source of the command that started the job
+    pub command: String,
+    pub url: String,
+    pub started: DateTime<Local>,
+    pub finished: Option<DateTime<Local>>,
+    pub status: JobStatus,
+    abort: AbortHandle,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Running,
+    /// Completed with the size of the object written
+    Completed(u64),
+    Failed(String),
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobStatus::Running => "running",
+            JobStatus::Completed(_) => "completed",
+            JobStatus::Failed(_) => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl Jobs {
+    /// Run `transfer` on the runtime, returning the id of its job. Plugin GC is disabled until
+    /// it finishes, so the engine doesn't stop the plugin in the middle of it.
+    pub fn spawn(
+        self: &Arc<Self>,
+        runtime: &Runtime,
+        engine: &EngineInterface,
+        command: String,
+        url: String,
+        transfer: impl Future<Output = Result<u64, Box<ShellError>>> + Send + 'static,
+    ) -> Result<u64, Box<ShellError>> {
+        engine.set_gc_disabled(true)?;
+        // Held while spawning, so a job finishing at once finds itself
+        let mut jobs = self.jobs.lock().expect("not poisoned");
+        let id = jobs.keys().next_back().map_or(1, |id| id + 1);
+        let this = Arc::clone(self);
+        let engine = engine.clone();
+        let handle = runtime.spawn(async move {
+            let status = match transfer.await {
+                Ok(size) => JobStatus::Completed(size),
+                Err(e) => JobStatus::Failed(e.to_string()),
+            };
+            this.finish(id, status);
+            if let Err(e) = this.update_gc(&engine) {
+                warn!("Could not update plugin GC after job {id}: {e}");
+            }
+        });
+        jobs.insert(
+            id,
+            Job {
+                id,
+                command,
+                url,
+                started: Local::now(),
+                finished: None,
+                status: JobStatus::Running,
+                abort: handle.abort_handle(),
+            },
+        );
+        Ok(id)
+    }
+
+    pub fn list(&self) -> Vec<Job> {
+        self.jobs
+            .lock()
+            .expect("not poisoned")
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        self.jobs.lock().expect("not poisoned").get(&id).cloned()
+    }
+
+    /// Stop a running job, returning it as it is after the cancel
+    pub fn cancel(
+        &self,
+        id: u64,
+        engine: &EngineInterface,
+    ) -> Result<Option<Job>, Box<ShellError>> {
+        let job = {
+            let mut jobs = self.jobs.lock().expect("not poisoned");
+            let Some(job) = jobs.get_mut(&id) else {
+                return Ok(None);
+            };
+            if job.status == JobStatus::Running {
+                job.abort.abort();
+                job.status = JobStatus::Cancelled;
+                job.finished = Some(Local::now());
+            }
+            job.clone()
+        };
+        self.update_gc(engine)?;
+        Ok(Some(job))
+    }
+
+    pub fn running(&self) -> usize {
+        self.jobs
+            .lock()
+            .expect("not poisoned")
+            .values()
+            .filter(|job| job.status == JobStatus::Running)
+            .count()
+    }
+
+    /// Disable plugin GC while cached stores keep the plugin resident or jobs are running
+    pub fn update_gc(&self, engine: &EngineInterface) -> Result<(), Box<ShellError>> {
+        let disabled = self.stores_resident.load(Ordering::Relaxed) || self.running() > 0;
+        Ok(engine.set_gc_disabled(disabled)?)
+    }
+
+    pub fn set_stores_resident(&self, resident: bool) {
+        self.stores_resident.store(resident, Ordering::Relaxed);
+    }
+
+    fn finish(&self, id: u64, status: JobStatus) {
+        let mut jobs = self.jobs.lock().expect("not poisoned");
+        // Cancelled jobs are marked when they are cancelled
+        if let Some(job) = jobs.get_mut(&id)
+            && job.status == JobStatus::Running
+        {
+            job.status = status;
+            job.finished = Some(Local::now());
+        }
+    }
+}
@@ -1,30 +1,72 @@
+mod audit;
 mod cache;
 mod command;
+mod config;
+mod credentials;
+mod disk_cache;
+mod error;
+mod jobs;
+mod prompt;
 mod providers;
+mod remote;
+mod transfer;
+
+use std::sync::{Arc, Mutex, OnceLock};
 
 use cache::Cache;
+use config::Config;
 use nu_plugin::{EngineInterface, Plugin};
 use nu_protocol::{ShellError, Span, Spanned};
 use object_store::path::Path;
-use providers::NuObjectStore;
+use providers::{NuObjectStore, stats::Stats};
 use tokio::runtime::Runtime;
 use url::Url;
 
 pub struct CloudPlugin {
     pub cache: cache::Cache,
-    pub rt: Runtime,
+    /// Created by the first command, as it is configured in the plugin config
+    rt: OnceLock<Arc<Runtime>>,
+    /// Totals of the last command run, shown by `cloud last-stats`
+    pub last_stats: Mutex<Option<Stats>>,
 }
 
 impl Default for CloudPlugin {
     fn default() -> Self {
         CloudPlugin {
             cache: Cache::default(),
-            rt: Runtime::new().expect("Could not create tokio runtime"),
+            rt: OnceLock::new(),
+            last_stats: Mutex::new(None),
         }
     }
 }
 
 impl CloudPlugin {
+    /// The runtime commands run on. Streams reading after the command returns keep a reference,
+    /// and must use [`Runtime::block_on`] as a current-thread runtime is only driven by it.
+    pub fn runtime(
+        &self,
+        engine: &EngineInterface,
+        span: Span,
+    ) -> Result<&Arc<Runtime>, Box<ShellError>> {
+        if let Some(rt) = self.rt.get() {
+            return Ok(rt);
+        }
+        let config = Config::load(engine)?;
+        let rt =
+            config
+                .runtime
+                .unwrap_or_default()
+                .build()
+                .map_err(|e| ShellError::GenericError {
+                    error: format!("Could not create tokio runtime: {e}"),
+                    msg: "".into(),
+                    span: Some(span),
+                    help: None,
+                    inner: vec![],
+                })?;
+        Ok(self.rt.get_or_init(|| Arc::new(rt)))
+    }
+
     pub async fn parse_url(
         &self,
         engine: &EngineInterface,
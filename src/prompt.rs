@@ -0,0 +1,39 @@
+use nu_plugin::{EngineInterface, EvaluatedCall};
+use nu_protocol::{PipelineData, ShellError, Span, Spanned, Value};
+
+/// Ask the user for a line of input using nushell's `input` command. Secret input isn't echoed.
+pub fn prompt(
+    engine: &EngineInterface,
+    span: Span,
+    message: &str,
+    secret: bool,
+) -> Result<String, Box<ShellError>> {
+    let decl_id = engine
+        .find_decl("input")?
+        .ok_or_else(|| ShellError::GenericError {
+            error: "The input command is required for prompting".into(),
+            msg: "".into(),
+            span: Some(span),
+            help: None,
+            inner: vec![],
+        })?;
+
+    let call = EvaluatedCall::new(span).with_positional(Value::string(message, span));
+    let call = if secret {
+        call.with_flag(Spanned {
+            item: "suppress-output",
+            span,
+        })
+    } else {
+        call
+    };
+
+    let value = engine
+        .call_decl(decl_id, call, PipelineData::Empty, true, false)?
+        .into_value(span)?;
+    if secret {
+        // Without echo the user's enter key doesn't move to the next line
+        eprintln!();
+    }
+    Ok(value.coerce_into_string()?)
+}
@@ -1,23 +1,53 @@
-use std::{error::Error, sync::Arc};
+use std::{borrow::Cow, error::Error, sync::Arc, time::SystemTime};
 
-use aws_config::{BehaviorVersion, SdkConfig};
-use aws_credential_types::{Credentials, provider::ProvideCredentials};
+use async_lock::Mutex;
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region, SdkConfig, profile::Profile};
+use aws_credential_types::provider::{ProvideCredentials, SharedCredentialsProvider};
+use aws_sdk_sts::error::DisplayErrorContext;
+use aws_types::os_shim_internal::{Env, Fs};
+use chrono::{DateTime, Utc};
+use http::{HeaderMap, HeaderValue};
 use itertools::Itertools;
+use log::debug;
 use nu_plugin::EngineInterface;
-use nu_protocol::{ShellError, Spanned};
-use object_store::aws::AmazonS3Builder;
+use nu_protocol::{ShellError, Spanned, engine::Closure};
+use object_store::{
+    CredentialProvider,
+    aws::{AmazonS3Builder, AwsCredential, AwsCredentialProvider},
+    client::HttpConnector,
+};
 use url::Url;
 
-use crate::cache::{Cache, ObjectStoreCacheKey};
+use crate::{
+    cache::{Cache, CredentialCache, ObjectStoreCacheKey},
+    config::{BucketConfig, Config},
+    credentials::{self, CloudCredentials, REFRESH_WINDOW},
+    prompt::prompt,
+};
 
-use super::NuObjectStore;
+use super::{
+    NuObjectStore,
+    connector::PluginConnector,
+    env_string,
+    signed::{BucketClient, SignedClient, StorageClass},
+};
 
 pub async fn build_object_store(
     engine: &EngineInterface,
     cache: &Cache,
     url: &Spanned<Url>,
 ) -> Result<NuObjectStore, ShellError> {
-    let aws_config = aws_load_config().await;
+    let config = Config::load(engine).map_err(|e| *e)?;
+    let (profile, mut bucket_config) = match config.bucket(&url.item) {
+        Some((prefix, bucket_config)) => (Some(prefix.to_string()), bucket_config.clone()),
+        None => (None, BucketConfig::default()),
+    };
+    if bucket_config.profile.is_none() {
+        bucket_config.profile = config.profile.clone();
+    }
+
+    let bucket_profile = aws_profile(cache, bucket_config.profile.clone()).await;
 
     let parsed_info = parse_url_parts(&url.item);
 
@@ -35,9 +65,11 @@ pub async fn build_object_store(
             inner: vec![],
         })?;
 
-    let region = if let Some(region) = aws_config
-        .region()
-        .map(ToString::to_string)
+    let region = if let Some(region) = bucket_config
+        .region
+        .clone()
+        .or(env_string(engine, "NU_CLOUD_S3_REGION").map_err(|e| *e)?)
+        .or_else(|| bucket_profile.config.region().map(ToString::to_string))
         .or(parsed_info.region)
     {
         region
@@ -51,28 +83,193 @@ pub async fn build_object_store(
         });
     };
 
+    let auth = match (&profile, &bucket_config) {
+        (
+            _,
+            BucketConfig {
+                access_key_id: Some(access_key_id),
+                secret_access_key: Some(secret_access_key),
+                session_token,
+                ..
+            },
+        ) => Auth::Static(CloudCredentials {
+            access_key_id: access_key_id.clone(),
+            secret_access_key: secret_access_key.clone(),
+            session_token: session_token.clone(),
+            expiry: None,
+        }),
+        (
+            Some(profile),
+            BucketConfig {
+                credentials: Some(closure),
+                ..
+            },
+        ) => Auth::Temporary(CredentialSource::Closure {
+            profile: profile.clone(),
+            closure: closure.clone(),
+        }),
+        _ => match env_credentials(engine).map_err(|e| *e)? {
+            Some(credentials) => Auth::Static(credentials),
+            None => {
+                let aws_profile_name = match &bucket_config.profile {
+                    Some(profile) => profile.clone(),
+                    None => env_string(engine, "AWS_PROFILE")
+                        .map_err(|e| *e)?
+                        .unwrap_or_else(|| "default".into()),
+                };
+                // The profile files are read once, with the profile
+                let named = Box::pin(aws_profile(cache, Some(aws_profile_name.clone()))).await;
+                match named.mfa_role {
+                    Some(role) => Auth::Temporary(CredentialSource::Mfa {
+                        profile: aws_profile_name,
+                        role,
+                    }),
+                    None => match keychain_credentials(cache, &aws_profile_name).await {
+                        Some(credentials) => Auth::Static(credentials),
+                        None => Auth::Default,
+                    },
+                }
+            }
+        },
+    };
+    let credentials_key = match &auth {
+        Auth::Static(credentials) => Some(credentials.access_key_id.clone()),
+        Auth::Temporary(CredentialSource::Mfa { profile, .. }) => Some(format!("mfa:{profile}")),
+        Auth::Temporary(CredentialSource::Closure { .. }) | Auth::Default => None,
+    };
+
     let cache_key = ObjectStoreCacheKey::AmazonS3 {
         bucket: bucket.clone(),
         region: region.clone(),
+        profile: profile.clone(),
+        credentials: credentials_key.clone(),
     };
 
-    if let Some(object_store) = cache.get_store(&cache_key).await {
+    let cacheable = PluginConnector::is_cacheable(&config, &url.item);
+    let bearer_auth = bucket_config.oidc.is_some();
+
+    if let Some(object_store) = cache.get_store(&cache_key).await
+        && cacheable
+    {
+        if let NuObjectStore::AmazonS3 {
+            temporary: Some(temporary),
+            ..
+        } = &object_store
+        {
+            temporary.set_command(engine, url);
+        }
         Ok(object_store)
     } else {
+        let mut connector = PluginConnector::new(engine, cache, &config, &bucket_config, url)
+            .await
+            .map_err(|e| ShellError::GenericError {
+                error: format!("Could not create Amazon S3 client: {e}"),
+                msg: "".into(),
+                span: Some(url.span),
+                help: None,
+                inner: vec![],
+            })?;
+        if let Some(storage_class) = &bucket_config.storage_class {
+            let value =
+                HeaderValue::from_str(storage_class).map_err(|e| ShellError::GenericError {
+                    error: format!("Invalid storage class {storage_class}: {e}"),
+                    msg: "".into(),
+                    span: Some(url.span),
+                    help: None,
+                    inner: vec![],
+                })?;
+            connector.storage_class = Some(Arc::new(StorageClass::new(value, region.clone())));
+        }
+        let storage_class = connector.storage_class.clone();
+
+        let options =
+            config
+                .client_options(HeaderMap::new())
+                .map_err(|e| ShellError::GenericError {
+                    error: format!("Could not create Amazon S3 client: {e}"),
+                    msg: "".into(),
+                    span: Some(url.span),
+                    help: None,
+                    inner: vec![],
+                })?;
+
+        let options = match &bucket_config.endpoint {
+            Some(endpoint) => options.with_allow_http(endpoint.starts_with("http://")),
+            None => options,
+        };
+        // Its own client for the requests object_store has no API for
+        let signed_client = connector
+            .connect(&options)
+            .map_err(|e| ShellError::GenericError {
+                error: format!("Could not create Amazon S3 client: {e}"),
+                msg: "".into(),
+                span: Some(url.span),
+                help: None,
+                inner: vec![],
+            })?;
         let builder = AmazonS3Builder::new()
             .with_url(url.item.clone())
-            .with_region(region.clone());
+            .with_region(region.clone())
+            .with_http_connector(connector);
+        let (builder, bucket_endpoint) = if let Some(endpoint) = &bucket_config.endpoint {
+            let bucket_endpoint = format!("{}/{bucket}", endpoint.trim_end_matches('/'));
+            (
+                builder.with_endpoint(endpoint).with_client_options(options),
+                bucket_endpoint,
+            )
+        } else if bucket_config.use_accelerate_endpoint.unwrap_or(false) {
+            // Transfer acceleration is only available with virtual hosted style requests
+            let bucket_endpoint = format!("https://{bucket}.s3-accelerate.amazonaws.com");
+            (
+                builder
+                    .with_endpoint(&bucket_endpoint)
+                    .with_virtual_hosted_style_request(true)
+                    .with_client_options(options),
+                bucket_endpoint,
+            )
+        } else {
+            let bucket_endpoint = format!("https://s3.{region}.amazonaws.com/{bucket}");
+            (builder.with_client_options(options), bucket_endpoint)
+        };
 
-        let builder = if let Some(credentials) = aws_creds(&aws_config).await? {
+        let mut temporary_credentials = None;
+        let builder = if bearer_auth {
+            // Requests are authenticated with the bearer token instead of being signed
+            builder.with_skip_signature(true)
+        } else if let Auth::Temporary(source) = auth {
+            let temporary = Arc::new(TemporaryCredentials::new(
+                cache.credentials(),
+                source,
+                engine,
+                url,
+            ));
+            // Resolved now, so closure errors and MFA prompts come before the first request
+            Box::pin(temporary.resolve()).await?;
+            temporary_credentials = Some(temporary.clone());
+            builder.with_credentials(temporary)
+        } else if let Auth::Static(credentials) = &auth {
             let builder = builder
-                .with_access_key_id(credentials.access_key_id())
-                .with_secret_access_key(credentials.secret_access_key());
+                .with_access_key_id(&credentials.access_key_id)
+                .with_secret_access_key(&credentials.secret_access_key);
 
-            if let Some(token) = credentials.session_token() {
+            if let Some(token) = &credentials.session_token {
                 builder.with_token(token)
             } else {
                 builder
             }
+        } else if let Some(provider) = &bucket_profile.credentials {
+            // Fail early when no credentials can be fetched, rather than on first use
+            provider
+                .get_credential()
+                .await
+                .map_err(|e| ShellError::GenericError {
+                    error: format!("Could not fetch AWS credentials: {e}"),
+                    msg: "".into(),
+                    span: Some(url.span),
+                    help: None,
+                    inner: vec![],
+                })?;
+            builder.with_credentials(provider.clone())
         } else {
             return Err(ShellError::GenericError {
                 error: "Could not determine AWS credentials from environment".into(),
@@ -91,42 +288,380 @@ pub async fn build_object_store(
             inner: vec![],
         })?;
 
+        if let Some(storage_class) = storage_class.filter(|_| !bearer_auth) {
+            storage_class.set_credentials(s3.credentials().clone());
+        }
+        let signed = SignedClient::new(
+            signed_client,
+            region.clone(),
+            (!bearer_auth).then(|| s3.credentials().clone()),
+        );
+        let api = BucketClient::new(Arc::new(signed), bucket_endpoint, bucket.clone());
+        let s3 = Arc::new(s3);
         let object_store = NuObjectStore::AmazonS3 {
-            store: Arc::new(s3),
+            store: s3.clone(),
+            multipart: s3,
             bucket,
             region,
+            profile,
+            credentials: credentials_key,
+            temporary: temporary_credentials,
+            api: Arc::new(api),
         };
 
-        cache
-            .put_store(engine, cache_key, object_store.clone())
-            .await?;
+        // Header hooks are evaluated through the engine of this call, so the store can't be reused
+        if cacheable {
+            cache
+                .put_store(engine, cache_key, object_store.clone())
+                .await?;
+        }
         Ok(object_store)
     }
 }
 
-async fn aws_load_config() -> SdkConfig {
-    aws_config::load_defaults(BehaviorVersion::latest()).await
+/// Credentials from the plugin specific `NU_CLOUD_S3_*` variables, which take precedence over
+/// the standard AWS environment
+fn env_credentials(engine: &EngineInterface) -> Result<Option<CloudCredentials>, Box<ShellError>> {
+    let access_key_id = env_string(engine, "NU_CLOUD_S3_ACCESS_KEY_ID")?;
+    let secret_access_key = env_string(engine, "NU_CLOUD_S3_SECRET_ACCESS_KEY")?;
+    match (access_key_id, secret_access_key) {
+        (Some(access_key_id), Some(secret_access_key)) => Ok(Some(CloudCredentials {
+            access_key_id,
+            secret_access_key,
+            session_token: env_string(engine, "NU_CLOUD_S3_SESSION_TOKEN")?,
+            expiry: None,
+        })),
+        _ => Ok(None),
+    }
+}
+
+/// How requests to the bucket are authenticated
+enum Auth {
+    /// Credentials from the config, the environment, or the keychain, which don't expire
+    Static(CloudCredentials),
+    /// Credentials that expire, resolved again while the store is used
+    Temporary(CredentialSource),
+    /// The credential provider of the AWS SDK
+    Default,
+}
+
+/// Where temporary credentials come from
+#[derive(Clone)]
+enum CredentialSource {
+    /// The credentials closure of the configured bucket prefix `profile`
+    Closure { profile: String, closure: Closure },
+    /// The role of the AWS `profile`, assumed with an MFA code
+    Mfa { profile: String, role: MfaRole },
+}
+
+/// Temporary credentials of a store, read from the credential cache and resolved again when
+/// they are about to expire. Closures are called and MFA codes asked for through the engine of
+/// the latest command using the store, so long transfers outlive the session they started with.
+pub struct TemporaryCredentials {
+    cache: Arc<CredentialCache>,
+    source: CredentialSource,
+    command: std::sync::Mutex<(EngineInterface, Spanned<Url>)>,
+    /// Held while resolving, so concurrent requests don't call the closure or prompt at once
+    resolving: Mutex<()>,
+}
+
+impl std::fmt::Debug for TemporaryCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = match &self.source {
+            CredentialSource::Closure { profile, .. } => format!("closure of {profile}"),
+            CredentialSource::Mfa { profile, .. } => format!("MFA session of {profile}"),
+        };
+        f.debug_struct("TemporaryCredentials")
+            .field("source", &source)
+            .finish()
+    }
+}
+
+impl TemporaryCredentials {
+    fn new(
+        cache: Arc<CredentialCache>,
+        source: CredentialSource,
+        engine: &EngineInterface,
+        url: &Spanned<Url>,
+    ) -> Self {
+        TemporaryCredentials {
+            cache,
+            source,
+            command: std::sync::Mutex::new((engine.clone(), url.clone())),
+            resolving: Mutex::new(()),
+        }
+    }
+
+    /// Resolve credentials through the engine of the command now using the store
+    pub fn set_command(&self, engine: &EngineInterface, url: &Spanned<Url>) {
+        if let Ok(mut command) = self.command.lock() {
+            *command = (engine.clone(), url.clone());
+        }
+    }
+
+    /// The cached credentials, or new ones if they are about to expire
+    async fn resolve(&self) -> Result<CloudCredentials, ShellError> {
+        let _resolving = self.resolving.lock().await;
+        let (engine, url) = match self.command.lock() {
+            Ok(command) => command.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        match &self.source {
+            CredentialSource::Closure { profile, closure } => {
+                credentials::from_closure(&engine, &self.cache, profile, closure, &url).await
+            }
+            CredentialSource::Mfa { profile, role } => {
+                // Boxed, as the STS client would make every store's future as large
+                Box::pin(mfa_credentials(&engine, &self.cache, profile, role, &url)).await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for TemporaryCredentials {
+    type Credential = AwsCredential;
+
+    async fn get_credential(&self) -> object_store::Result<Arc<AwsCredential>> {
+        let credentials = self
+            .resolve()
+            .await
+            .map_err(|e| object_store::Error::Generic {
+                store: "S3",
+                source: Box::new(e),
+            })?;
+        Ok(Arc::new(AwsCredential {
+            key_id: credentials.access_key_id,
+            secret_key: credentials.secret_access_key,
+            token: credentials.session_token,
+        }))
+    }
+}
+
+/// Credentials stored by `cloud login` for the profile
+async fn keychain_credentials(cache: &Cache, profile: &str) -> Option<CloudCredentials> {
+    let cache_key = format!("keychain:{profile}");
+    let credentials = cache.credentials();
+    if let Some(credentials) = credentials.get(&cache_key).await {
+        return Some(credentials);
+    }
+    // Most profiles have no entry, which is remembered so the keychain isn't asked every call
+    if credentials.is_missing(&cache_key).await {
+        return None;
+    }
+    // The keychain may be unavailable, e.g. in a headless session
+    match credentials::keychain_load(profile).await {
+        Ok(Some(found)) => {
+            credentials.put(&cache_key, found.clone()).await;
+            Some(found)
+        }
+        Ok(None) => {
+            credentials.put_missing(&cache_key).await;
+            None
+        }
+        Err(e) => {
+            debug!("Could not read credentials from the keychain: {e}");
+            credentials.put_missing(&cache_key).await;
+            None
+        }
+    }
+}
+
+/// The settings of a profile assuming a role with an MFA code
+#[derive(Debug, Clone)]
+struct MfaRole {
+    role_arn: String,
+    mfa_serial: String,
+    source_profile: Option<String>,
+    role_session_name: Option<String>,
+    duration_seconds: Option<String>,
+}
+
+impl MfaRole {
+    fn from_profile(profile: &Profile) -> Option<Self> {
+        let setting = |name: &str| profile.get(name).map(ToString::to_string);
+        Some(MfaRole {
+            role_arn: setting("role_arn")?,
+            mfa_serial: setting("mfa_serial")?,
+            source_profile: setting("source_profile"),
+            role_session_name: setting("role_session_name"),
+            duration_seconds: setting("duration_seconds"),
+        })
+    }
+}
+
+/// Assume the role of a profile with an `mfa_serial`, prompting for the MFA code.
+///
+/// The SDK can't prompt for codes, so profiles requiring MFA are handled here. The session is
+/// cached until shortly before it expires, so the code is only asked for once per session.
+async fn mfa_credentials(
+    engine: &EngineInterface,
+    cache: &CredentialCache,
+    profile_name: &str,
+    role: &MfaRole,
+    url: &Spanned<Url>,
+) -> Result<CloudCredentials, ShellError> {
+    let cache_key = format!("mfa:{profile_name}");
+    if let Some(credentials) = cache.get(&cache_key).await {
+        return Ok(credentials);
+    }
+    let MfaRole {
+        role_arn,
+        mfa_serial,
+        ..
+    } = role;
+
+    let sts_error = |e: String| ShellError::GenericError {
+        error: format!("Could not assume role {role_arn} for profile {profile_name}: {e}"),
+        msg: "".into(),
+        span: Some(url.span),
+        help: None,
+        inner: vec![],
+    };
+
+    // The role is assumed with the credentials of the source profile
+    let loader = aws_config::defaults(BehaviorVersion::latest());
+    let source_config = match &role.source_profile {
+        Some(source_profile) => loader.profile_name(source_profile).load().await,
+        None => loader.load().await,
+    };
+    let mut sts_config = aws_sdk_sts::config::Builder::from(&source_config);
+    if source_config.region().is_none() {
+        sts_config.set_region(Some(Region::from_static("us-east-1")));
+    }
+    let duration_seconds = role
+        .duration_seconds
+        .as_deref()
+        .map(str::parse::<i32>)
+        .transpose()
+        .map_err(|e| sts_error(format!("invalid duration_seconds: {e}")))?;
+
+    let token_code = prompt(
+        engine,
+        url.span,
+        &format!("MFA code for {mfa_serial}: "),
+        false,
+    )
+    .map_err(|e| *e)?;
+
+    let response = aws_sdk_sts::Client::from_conf(sts_config.build())
+        .assume_role()
+        .role_arn(role_arn)
+        .role_session_name(
+            role.role_session_name
+                .as_deref()
+                .unwrap_or("nu_plugin_cloud"),
+        )
+        .serial_number(mfa_serial)
+        .token_code(token_code.trim())
+        .set_duration_seconds(duration_seconds)
+        .send()
+        .await
+        .map_err(|e| sts_error(DisplayErrorContext(e).to_string()))?;
+    let session = response
+        .credentials()
+        .ok_or_else(|| sts_error("no credentials were returned".into()))?;
+
+    let credentials = CloudCredentials {
+        access_key_id: session.access_key_id().to_string(),
+        secret_access_key: session.secret_access_key().to_string(),
+        session_token: Some(session.session_token().to_string()),
+        expiry: SystemTime::try_from(*session.expiration())
+            .ok()
+            .map(|expiry| DateTime::<Utc>::from(expiry).fixed_offset()),
+    };
+    cache.put(&cache_key, credentials.clone()).await;
+    Ok(credentials)
+}
+
+/// AWS configuration loaded for a named profile, or the default one
+#[derive(Clone)]
+pub struct AwsProfile {
+    config: SdkConfig,
+    credentials: Option<AwsCredentialProvider>,
+    /// Role of the profile requiring an MFA code, which the SDK can't assume itself
+    mfa_role: Option<MfaRole>,
+}
+
+/// Load the AWS configuration for the profile once, and reuse it across calls
+async fn aws_profile(cache: &Cache, profile: Option<String>) -> AwsProfile {
+    if let Some(aws_profile) = cache.get_aws_profile(&profile).await {
+        return aws_profile;
+    }
+
+    let loader = aws_config::defaults(BehaviorVersion::latest());
+    let config = match &profile {
+        Some(profile) => loader.profile_name(profile).load().await,
+        None => loader.load().await,
+    };
+    let credentials = config.credentials_provider().map(|provider| {
+        Arc::new(SdkCredentialProvider {
+            provider,
+            cached: Mutex::new(None),
+        }) as AwsCredentialProvider
+    });
+    let selected = profile.clone().map(Cow::Owned);
+    let mfa_role =
+        match aws_config::profile::load(&Fs::real(), &Env::real(), &Default::default(), selected)
+            .await
+        {
+            Ok(profiles) => profiles
+                .get_profile(profiles.selected_profile())
+                .and_then(MfaRole::from_profile),
+            Err(e) => {
+                debug!("Could not load AWS profiles: {e}");
+                None
+            }
+        };
+
+    let aws_profile = AwsProfile {
+        config,
+        credentials,
+        mfa_role,
+    };
+    cache.put_aws_profile(profile, aws_profile.clone()).await;
+    aws_profile
+}
+
+/// Credentials from the AWS SDK, fetched once and refreshed shortly before they expire
+#[derive(Debug)]
+struct SdkCredentialProvider {
+    provider: SharedCredentialsProvider,
+    cached: Mutex<Option<(Arc<AwsCredential>, Option<SystemTime>)>>,
 }
 
-async fn aws_creds(aws_config: &SdkConfig) -> Result<Option<Credentials>, ShellError> {
-    if let Some(provider) = aws_config.credentials_provider() {
-        Ok(Some(provider.provide_credentials().await.map_err(|e| {
-            ShellError::GenericError {
-                error: format!(
-                    "Could not fetch AWS credentials: {} - {}",
+#[async_trait]
+impl CredentialProvider for SdkCredentialProvider {
+    type Credential = AwsCredential;
+
+    async fn get_credential(&self) -> object_store::Result<Arc<AwsCredential>> {
+        let mut cached = self.cached.lock().await;
+        if let Some((credential, expiry)) = cached.as_ref()
+            && expiry.is_none_or(|expiry| expiry > SystemTime::now() + REFRESH_WINDOW)
+        {
+            return Ok(credential.clone());
+        }
+
+        let credentials = self.provider.provide_credentials().await.map_err(|e| {
+            object_store::Error::Generic {
+                store: "S3",
+                source: format!(
+                    "{} - {}",
                     e,
                     e.source()
                         .map(|e| format!("{}", e))
                         .unwrap_or("".to_string())
-                ),
-                msg: "".into(),
-                span: None,
-                help: None,
-                inner: vec![],
+                )
+                .into(),
             }
-        })?))
-    } else {
-        Ok(None)
+        })?;
+        let credential = Arc::new(AwsCredential {
+            key_id: credentials.access_key_id().to_string(),
+            secret_key: credentials.secret_access_key().to_string(),
+            token: credentials.session_token().map(ToString::to_string),
+        });
+        *cached = Some((credential.clone(), credentials.expiry()));
+        Ok(credential)
     }
 }
 
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use http::{
+    HeaderMap, Method,
+    header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, LAST_MODIFIED},
+};
+use object_store::{Result, client::HttpRequestBody, path::Path};
+
+use super::{
+    headers,
+    signed::{self, BucketClient},
+};
+
+const STORE: &str = "S3";
+
+/// Checksum algorithms S3 may store with an object, named as in their `x-amz-checksum-` headers
+pub const ALGORITHMS: [&str; 5] = ["crc32", "crc32c", "crc64nvme", "sha1", "sha256"];
+
+/// Reads the checksums S3 stores with objects, which are only returned by requests in checksum
+/// mode, so aren't available through object_store
+#[derive(Debug)]
+pub struct ChecksumClient {
+    bucket: Arc<BucketClient>,
+}
+
+/// An object with its checksums, from a HEAD request in checksum mode
+#[derive(Debug)]
+pub struct ObjectChecksums {
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub e_tag: Option<String>,
+    pub version: Option<String>,
+    pub content_type: Option<String>,
+    /// Algorithms and base64 values, as S3 gives them. Those of objects uploaded in parts may
+    /// be of the checksums of the parts, with their number after a `-`.
+    pub checksums: Vec<(&'static str, String)>,
+}
+
+impl ChecksumClient {
+    pub fn new(bucket: Arc<BucketClient>) -> Self {
+        ChecksumClient { bucket }
+    }
+
+    pub async fn head(&self, location: &Path) -> Result<ObjectChecksums> {
+        let url = self.bucket.url(Some(location), &[]);
+        let request_headers = [("x-amz-checksum-mode", "ENABLED".to_string())];
+        let (result, headers) = headers::captured(self.bucket.send(
+            Method::HEAD,
+            &url,
+            &request_headers,
+            HttpRequestBody::empty(),
+        ))
+        .await;
+        result?;
+        let header = |name: &str| Some(headers.get(name)?.to_str().ok()?.to_string());
+        let size = header(CONTENT_LENGTH.as_str())
+            .and_then(|length| length.parse().ok())
+            .ok_or_else(|| signed::generic(STORE, "Invalid response: missing content-length"))?;
+        Ok(ObjectChecksums {
+            size,
+            last_modified: header(LAST_MODIFIED.as_str())
+                .and_then(|time| DateTime::parse_from_rfc2822(&time).ok())
+                .map(|time| time.with_timezone(&Utc)),
+            e_tag: header(ETAG.as_str()),
+            version: header("x-amz-version-id"),
+            content_type: header(CONTENT_TYPE.as_str()),
+            checksums: checksums(&headers),
+        })
+    }
+}
+
+fn checksums(headers: &HeaderMap) -> Vec<(&'static str, String)> {
+    ALGORITHMS
+        .into_iter()
+        .filter_map(|algorithm| {
+            let value = headers.get(format!("x-amz-checksum-{algorithm}"))?;
+            Some((algorithm, value.to_str().ok()?.to_string()))
+        })
+        .collect()
+}
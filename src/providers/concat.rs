@@ -0,0 +1,204 @@
+use std::sync::Arc;
+
+use futures::{StreamExt, TryStreamExt, stream};
+use http::Method;
+use object_store::{Attribute, Attributes, Result, client::HttpRequestBody, path::Path};
+
+use super::signed::{self, BucketClient};
+
+const STORE: &str = "S3";
+
+/// Smallest part of a multipart upload, except for the last
+pub const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+/// Largest part of a multipart upload, and largest object S3 copies in one request, so larger
+/// sources are copied in ranges
+pub const MAX_PART_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+pub const MAX_PARTS: usize = 10_000;
+
+/// Joins objects of an S3 bucket into one with a multipart upload copying them as its parts,
+/// without downloading them
+#[derive(Debug)]
+pub struct ConcatClient {
+    bucket: Arc<BucketClient>,
+}
+
+/// A part of the upload, copied from a range of a source
+struct Part<'a> {
+    number: usize,
+    source: &'a Path,
+    /// Inclusive byte range, for sources copied in more than one part
+    range: Option<(u64, u64)>,
+}
+
+impl ConcatClient {
+    pub fn new(bucket: Arc<BucketClient>) -> Self {
+        ConcatClient { bucket }
+    }
+
+    /// Number of parts the sources of these sizes are copied in
+    pub fn parts(sizes: impl IntoIterator<Item = u64>) -> usize {
+        sizes
+            .into_iter()
+            .map(|size| size.div_ceil(MAX_PART_SIZE) as usize)
+            .sum()
+    }
+
+    /// Write the sources, with their sizes, one after the other to `destination`. Sources
+    /// before the last must be at least [`MIN_PART_SIZE`], and empty ones are skipped. The
+    /// upload is aborted if a part fails.
+    pub async fn concat(
+        &self,
+        destination: &Path,
+        sources: &[(Path, u64)],
+        concurrency: usize,
+    ) -> Result<()> {
+        self.upload(destination, sources, &[], concurrency).await
+    }
+
+    /// Copy an object of `size` bytes in parts, for objects too large to copy in one request,
+    /// with the content headers and metadata of its `attributes`
+    pub async fn copy(
+        &self,
+        from: &Path,
+        to: &Path,
+        size: u64,
+        attributes: &Attributes,
+        concurrency: usize,
+    ) -> Result<()> {
+        let headers: Vec<(String, String)> = attributes
+            .iter()
+            .filter_map(|(attribute, value)| {
+                let name = match attribute {
+                    Attribute::ContentDisposition => "content-disposition".into(),
+                    Attribute::ContentEncoding => "content-encoding".into(),
+                    Attribute::ContentLanguage => "content-language".into(),
+                    Attribute::ContentType => "content-type".into(),
+                    Attribute::CacheControl => "cache-control".into(),
+                    Attribute::Metadata(key) => format!("x-amz-meta-{key}"),
+                    _ => return None,
+                };
+                Some((name, value.to_string()))
+            })
+            .collect();
+        let headers: Vec<(&str, String)> = headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.clone()))
+            .collect();
+        let sources = [(from.clone(), size)];
+        self.upload(to, &sources, &headers, concurrency).await
+    }
+
+    async fn upload(
+        &self,
+        destination: &Path,
+        sources: &[(Path, u64)],
+        headers: &[(&str, String)],
+        concurrency: usize,
+    ) -> Result<()> {
+        let mut parts = vec![];
+        for (source, size) in sources.iter().filter(|(_, size)| *size > 0) {
+            let ranges = size.div_ceil(MAX_PART_SIZE);
+            for i in 0..ranges {
+                let start = i * MAX_PART_SIZE;
+                parts.push(Part {
+                    number: parts.len() + 1,
+                    source,
+                    range: (ranges > 1).then(|| (start, (start + MAX_PART_SIZE).min(*size) - 1)),
+                });
+            }
+        }
+
+        let url = self.bucket.url(Some(destination), &[("uploads", "")]);
+        let body = self
+            .bucket
+            .send(Method::POST, &url, headers, HttpRequestBody::empty())
+            .await?;
+        let upload_id = element_text(&body, "UploadId")?;
+        let copies = stream::iter(parts)
+            .map(|part| self.copy_part(destination, &upload_id, part))
+            .buffered(concurrency)
+            .try_collect::<Vec<_>>()
+            .await;
+        let result = match copies {
+            Ok(etags) => self.complete(destination, &upload_id, etags).await,
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            // The parts copied so far would be kept, and billed, until the upload is aborted
+            let url = self
+                .bucket
+                .url(Some(destination), &[("uploadId", &upload_id)]);
+            let _ = self
+                .bucket
+                .send(Method::DELETE, &url, &[], HttpRequestBody::empty())
+                .await;
+        }
+        result
+    }
+
+    async fn copy_part(
+        &self,
+        destination: &Path,
+        upload_id: &str,
+        part: Part<'_>,
+    ) -> Result<String> {
+        let number = part.number.to_string();
+        let url = self.bucket.url(
+            Some(destination),
+            &[("partNumber", &number), ("uploadId", upload_id)],
+        );
+        let mut headers = vec![(
+            "x-amz-copy-source",
+            self.bucket.copy_source(part.source, None),
+        )];
+        if let Some((start, end)) = part.range {
+            headers.push(("x-amz-copy-source-range", format!("bytes={start}-{end}")));
+        }
+        let body = self
+            .bucket
+            .send_checked(Method::PUT, &url, &headers, HttpRequestBody::empty())
+            .await?;
+        element_text(&body, "ETag")
+    }
+
+    async fn complete(
+        &self,
+        destination: &Path,
+        upload_id: &str,
+        etags: Vec<String>,
+    ) -> Result<()> {
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (i, etag) in etags.iter().enumerate() {
+            let etag = etag
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+            body.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{etag}</ETag></Part>",
+                i + 1
+            ));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+        let url = self
+            .bucket
+            .url(Some(destination), &[("uploadId", upload_id)]);
+        self.bucket
+            .send_checked(Method::POST, &url, &[], HttpRequestBody::from(body))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Text of an element of the root of a response, e.g. the `UploadId` of a new upload
+pub(super) fn element_text(body: &[u8], name: &str) -> Result<String> {
+    let generic = |source: String| signed::generic(STORE, source);
+    let body = std::str::from_utf8(body).map_err(|e| generic(e.to_string()))?;
+    let document = roxmltree::Document::parse(body).map_err(|e| generic(e.to_string()))?;
+    document
+        .root_element()
+        .children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+        .map(str::to_string)
+        .ok_or_else(|| generic(format!("Invalid response: missing {name}")))
+}
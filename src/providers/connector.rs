@@ -0,0 +1,264 @@
+use std::{
+    error::Error,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use http::{
+    HeaderValue, Method,
+    header::{AUTHORIZATION, CONTENT_LENGTH},
+};
+use nu_plugin::EngineInterface;
+use nu_protocol::Spanned;
+use object_store::{
+    ClientOptions,
+    client::{
+        HttpClient, HttpConnector, HttpError, HttpErrorKind, HttpRequest, HttpResponse,
+        HttpService, ReqwestConnector,
+    },
+};
+
+use url::Url;
+
+use super::{
+    debug_log::DebugLog,
+    faults::FaultInjector,
+    headers,
+    hook::HeaderHook,
+    limit::{self, RateLimiter},
+    metrics::Metrics,
+    oidc::OidcTokenSource,
+    retry::RetryPolicy,
+    signed::StorageClass,
+    stats, trace, unavailable, verbose,
+};
+use crate::{
+    cache::Cache,
+    config::{BucketConfig, Config},
+};
+
+/// [`HttpConnector`] for object_store backends, applying the plugin's request settings that
+/// can't be expressed with [`ClientOptions`]
+#[derive(Debug)]
+pub struct PluginConnector {
+    pub token: Option<Arc<OidcTokenSource>>,
+    pub hook: Option<HeaderHook>,
+    /// Configured limit, shared by all stores
+    pub limit: Option<Arc<RateLimiter>>,
+    pub retry: RetryPolicy,
+    pub debug_log: Option<Arc<DebugLog>>,
+    pub metrics: Arc<Metrics>,
+    /// Provider the requests are counted for in the metrics, the scheme of the url
+    pub provider: String,
+    pub faults: Option<Arc<FaultInjector>>,
+    /// Storage class of the objects written, for S3
+    pub storage_class: Option<Arc<StorageClass>>,
+    #[cfg(feature = "fixtures")]
+    pub fixtures: Option<Arc<super::fixtures::Fixtures>>,
+}
+
+impl PluginConnector {
+    pub async fn new(
+        engine: &EngineInterface,
+        cache: &Cache,
+        config: &Config,
+        bucket_config: &BucketConfig,
+        url: &Spanned<Url>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let token = match &bucket_config.oidc {
+            Some(oidc) => Some(Arc::new(OidcTokenSource::new(
+                oidc.clone(),
+                config.client_builder()?.build()?,
+            ))),
+            None => None,
+        };
+        let hook = config
+            .header_hook(url.item.scheme())
+            .map(|closure| HeaderHook::new(engine, closure, url.span));
+        let limit = match config.requests_per_second {
+            Some(per_second) if per_second.is_nan() || per_second <= 0.0 => {
+                return Err("requests_per_second must be positive".into());
+            }
+            Some(per_second) => Some(cache.rate_limiter(per_second).await),
+            None => None,
+        };
+        let debug_log = match &config.debug_log {
+            Some(debug_log) => Some(cache.debug_log(debug_log).await),
+            None => None,
+        };
+        let faults = match &config.fault_injection {
+            Some(faults) => Some(cache.fault_injector(faults).await),
+            None => None,
+        };
+        #[cfg(feature = "fixtures")]
+        let fixtures = match &config.fixtures {
+            Some(fixtures) => Some(cache.fixtures(fixtures).await.map_err(|e| {
+                format!(
+                    "Could not open the fixtures {}: {e}",
+                    fixtures.path.display()
+                )
+            })?),
+            None => None,
+        };
+        Ok(PluginConnector {
+            token,
+            hook,
+            limit,
+            retry: RetryPolicy::new(config),
+            debug_log,
+            metrics: cache.metrics(),
+            provider: url.item.scheme().into(),
+            faults,
+            storage_class: None,
+            #[cfg(feature = "fixtures")]
+            fixtures,
+        })
+    }
+
+    /// Whether stores for the url may be cached across calls, checked before building the
+    /// connector so cache hits don't pay for its HTTP client
+    pub fn is_cacheable(config: &Config, url: &Url) -> bool {
+        config.header_hook(url.scheme()).is_none()
+    }
+}
+
+impl HttpConnector for PluginConnector {
+    fn connect(&self, options: &ClientOptions) -> object_store::Result<HttpClient> {
+        // Always wrapped, as a limit for the command run may apply to any request
+        let inner = ReqwestConnector::default().connect(options)?;
+        #[cfg(feature = "fixtures")]
+        let inner = match &self.fixtures {
+            Some(fixtures) => fixtures.client(inner),
+            None => inner,
+        };
+        // Outside of the fixtures, so replayed responses can be made to fail
+        let inner = match &self.faults {
+            Some(faults) => faults.client(inner),
+            None => inner,
+        };
+        Ok(HttpClient::new(PluginService {
+            inner,
+            token: self.token.clone(),
+            hook: self.hook.clone(),
+            limit: self.limit.clone(),
+            storage_class: self.storage_class.clone(),
+            retry: self.retry.clone(),
+            debug_log: self.debug_log.clone(),
+            metrics: self.metrics.clone(),
+            provider: self.provider.clone(),
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct PluginService {
+    inner: HttpClient,
+    token: Option<Arc<OidcTokenSource>>,
+    hook: Option<HeaderHook>,
+    limit: Option<Arc<RateLimiter>>,
+    storage_class: Option<Arc<StorageClass>>,
+    retry: RetryPolicy,
+    debug_log: Option<Arc<DebugLog>>,
+    metrics: Arc<Metrics>,
+    provider: String,
+}
+
+#[async_trait]
+impl HttpService for PluginService {
+    async fn call(&self, mut req: HttpRequest) -> Result<HttpResponse, HttpError> {
+        if let Some(token) = &self.token {
+            let token = token
+                .token()
+                .await
+                .map_err(|e| HttpError::new(HttpErrorKind::Unknown, TokenError(e)))?;
+            let mut value = HeaderValue::from_str(&format!("Bearer {token}"))
+                .map_err(|e| HttpError::new(HttpErrorKind::Unknown, e))?;
+            value.set_sensitive(true);
+            req.headers_mut().insert(AUTHORIZATION, value);
+        }
+        if let Some(hook) = &self.hook {
+            hook.apply(&mut req)?;
+        }
+        if let Some(storage_class) = &self.storage_class {
+            storage_class
+                .apply(&mut req)
+                .await
+                .map_err(|e| HttpError::new(HttpErrorKind::Unknown, e))?;
+        }
+        // Retries count against the limits like any other request
+        let run_limit = limit::current();
+        let (method, uri) = (req.method().clone(), req.uri().clone());
+        let sent = req.body().content_length() as u64;
+        let started_at = SystemTime::now();
+        let started = Instant::now();
+        let result = self
+            .retry
+            .execute(
+                &self.inner,
+                req,
+                || async {
+                    if let Some(limit) = &self.limit {
+                        limit.acquire().await;
+                    }
+                    if let Some(limit) = &run_limit {
+                        limit.acquire().await;
+                    }
+                },
+                |attempt, result, started, elapsed| {
+                    unavailable::record(result);
+                    let outcome = result
+                        .as_ref()
+                        .map(|response| (response.status(), response.headers()));
+                    self.metrics
+                        .attempt(&self.provider, &method, attempt, sent, outcome);
+                    if let Some(debug_log) = &self.debug_log {
+                        let outcome = outcome.map_err(|e| e.to_string());
+                        debug_log.request(&method, &uri, attempt, outcome, started, elapsed);
+                    }
+                },
+            )
+            .await;
+        if verbose::enabled() || trace::enabled() {
+            let elapsed = started.elapsed();
+            let outcome = match &result {
+                Ok(response) => Ok(response.status()),
+                Err(e) => Err(e.to_string()),
+            };
+            if verbose::enabled() {
+                let outcome = match &outcome {
+                    Ok(status) => status.to_string(),
+                    Err(e) => e.clone(),
+                };
+                verbose::request(&method, &uri, &outcome, elapsed);
+            }
+            let outcome = outcome.map(|status| status.as_u16());
+            trace::request(&method, &uri, outcome, started_at, elapsed);
+        }
+        // Responses to HEAD requests give the length of the object they don't have a body for
+        let received = match &result {
+            Ok(response) if method != Method::HEAD => response
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok()?.parse().ok())
+                .unwrap_or(0),
+            _ => 0,
+        };
+        stats::request(sent, received);
+        let response = result?;
+        headers::record(response.headers());
+        Ok(response)
+    }
+}
+
+/// Wraps a failure to obtain a bearer token for [`HttpError`], which needs a sized error
+#[derive(Debug)]
+struct TokenError(Box<dyn Error + Send + Sync>);
+
+impl std::fmt::Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Could not obtain OIDC token: {}", self.0)
+    }
+}
+
+impl Error for TokenError {}
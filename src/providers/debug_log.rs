@@ -0,0 +1,149 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use http::{HeaderMap, Method, StatusCode, Uri};
+use log::warn;
+use serde_json::json;
+
+use crate::config::DebugLogConfig;
+
+const DEFAULT_MAX_SIZE: u64 = 10 * 1000 * 1000;
+const DEFAULT_MAX_FILES: usize = 5;
+
+/// Response headers identifying a request to the provider's support
+const REQUEST_ID_HEADERS: &[&str] = &[
+    "x-amz-request-id",
+    "x-amz-id-2",
+    "x-amz-cf-id",
+    "x-ms-request-id",
+    "x-guploader-uploadid",
+    "x-request-id",
+];
+
+/// File the requests of all stores are appended to as lines of JSON, rotated to `<path>.1`,
+/// `<path>.2`, ... when it grows past its maximum size.
+///
+/// Only the method, the url without its query, the status or error, the timing, and request ids
+/// are written, as headers and presigned urls carry credentials.
+#[derive(Debug)]
+pub struct DebugLog {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    /// Opened by the first request
+    file: Mutex<Option<File>>,
+}
+
+impl DebugLog {
+    pub fn new(config: &DebugLogConfig) -> Self {
+        DebugLog {
+            path: config.path.clone(),
+            max_size: config
+                .max_size
+                .map_or(DEFAULT_MAX_SIZE, |size| size.get().max(1) as u64),
+            max_files: config.max_files.unwrap_or(DEFAULT_MAX_FILES),
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Whether the log was created with the same settings as `config`
+    pub fn matches(&self, config: &DebugLogConfig) -> bool {
+        let other = DebugLog::new(config);
+        (&self.path, self.max_size, self.max_files)
+            == (&other.path, other.max_size, other.max_files)
+    }
+
+    /// Log an attempt of a request, the `attempt`th after the first
+    pub fn request(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        attempt: usize,
+        outcome: Result<(StatusCode, &HeaderMap), String>,
+        started: SystemTime,
+        elapsed: Duration,
+    ) {
+        let scheme = uri.scheme_str().unwrap_or("https");
+        let authority = uri.authority().map(|a| a.as_str()).unwrap_or_default();
+        let mut line = json!({
+            "time": DateTime::<Utc>::from(started).to_rfc3339(),
+            "method": method.as_str(),
+            "url": format!("{scheme}://{authority}{}", uri.path()),
+            "attempt": attempt + 1,
+            "elapsed_ms": elapsed.as_secs_f64() * 1000.0,
+        });
+        match outcome {
+            Ok((status, headers)) => {
+                line["status"] = status.as_u16().into();
+                for name in REQUEST_ID_HEADERS {
+                    if let Some(value) = headers.get(*name).and_then(|v| v.to_str().ok()) {
+                        line[*name] = value.into();
+                    }
+                }
+            }
+            Err(e) => line["error"] = e.into(),
+        }
+        if let Err(e) = self.write(line.to_string()) {
+            warn!("Could not write to {}: {e}", self.path.display());
+        }
+    }
+
+    fn write(&self, mut line: String) -> io::Result<()> {
+        line.push('\n');
+        let mut lock = self.file.lock().expect("not poisoned");
+        let mut file = match lock.take() {
+            Some(file) => file,
+            None => self.open()?,
+        };
+        let len = file.metadata()?.len();
+        if len > 0 && len + line.len() as u64 > self.max_size {
+            drop(file);
+            self.rotate()?;
+            file = self.open()?;
+        }
+        file.write_all(line.as_bytes())?;
+        *lock = Some(file);
+        Ok(())
+    }
+
+    fn open(&self) -> io::Result<File> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+    }
+
+    /// Shift the rotated files up by one, dropping the oldest
+    fn rotate(&self) -> io::Result<()> {
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+        // Renaming doesn't replace files on every platform
+        let oldest = rotated(&self.path, self.max_files);
+        if oldest.exists() {
+            fs::remove_file(oldest)?;
+        }
+        for n in (1..self.max_files).rev() {
+            let from = rotated(&self.path, n);
+            if from.exists() {
+                fs::rename(from, rotated(&self.path, n + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated(&self.path, 1))
+    }
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
@@ -0,0 +1,130 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::StatusCode;
+use http_body::{Body, Frame};
+use log::debug;
+use object_store::client::{
+    HttpClient, HttpError, HttpErrorKind, HttpRequest, HttpResponse, HttpResponseBody, HttpService,
+};
+
+use crate::config::FaultInjectionConfig;
+
+/// Server errors, slow responses, and truncated bodies injected into the requests of all
+/// stores, for testing how retries, resumed uploads, and aborts cope with unreliable providers.
+///
+/// Faults are chosen at random for each attempt, in the same order for the same seed.
+#[derive(Debug)]
+pub struct FaultInjector {
+    config: FaultInjectionConfig,
+    rng: Mutex<fastrand::Rng>,
+}
+
+/// Faults chosen for an attempt
+struct Faults {
+    error: bool,
+    delay: Option<Duration>,
+    truncate: bool,
+}
+
+impl FaultInjector {
+    pub fn new(config: &FaultInjectionConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => fastrand::Rng::with_seed(seed),
+            None => fastrand::Rng::new(),
+        };
+        FaultInjector {
+            config: config.clone(),
+            rng: Mutex::new(rng),
+        }
+    }
+
+    /// Whether the injector was created with the same settings as `config`
+    pub fn matches(&self, config: &FaultInjectionConfig) -> bool {
+        self.config == *config
+    }
+
+    /// Wrap the client making the requests of a store
+    pub fn client(self: &Arc<Self>, inner: HttpClient) -> HttpClient {
+        HttpClient::new(FaultService {
+            inner,
+            injector: Arc::clone(self),
+        })
+    }
+
+    fn roll(&self) -> Faults {
+        let mut rng = self.rng.lock().expect("not poisoned");
+        let mut hit = |rate: Option<f64>| rate.is_some_and(|rate| rng.f64() < rate);
+        Faults {
+            error: hit(self.config.error_rate),
+            delay: hit(self.config.delay_rate)
+                .then(|| self.config.delay.map_or(DEFAULT_DELAY, |d| d.0)),
+            truncate: hit(self.config.truncate_rate),
+        }
+    }
+}
+
+const DEFAULT_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct FaultService {
+    inner: HttpClient,
+    injector: Arc<FaultInjector>,
+}
+
+#[async_trait]
+impl HttpService for FaultService {
+    async fn call(&self, req: HttpRequest) -> Result<HttpResponse, HttpError> {
+        let faults = self.injector.roll();
+        if let Some(delay) = faults.delay {
+            debug!("Delaying {} {} by {delay:?}", req.method(), req.uri());
+            tokio::time::sleep(delay).await;
+        }
+        if faults.error {
+            debug!("Failing {} {}", req.method(), req.uri());
+            let mut response = HttpResponse::new(HttpResponseBody::from(Bytes::new()));
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return Ok(response);
+        }
+        let response = self.inner.execute(req).await?;
+        if !faults.truncate {
+            return Ok(response);
+        }
+        // The headers still give the full length, like a connection dropped mid-transfer
+        let (parts, body) = response.into_parts();
+        let mut body = body.bytes().await?;
+        body.truncate(body.len() / 2);
+        let body = HttpResponseBody::new(Truncated { data: Some(body) });
+        Ok(HttpResponse::from_parts(parts, body))
+    }
+}
+
+/// Body yielding the data it was cut to, then failing
+struct Truncated {
+    data: Option<Bytes>,
+}
+
+impl Body for Truncated {
+    type Data = Bytes;
+    type Error = HttpError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, HttpError>>> {
+        Poll::Ready(Some(match self.data.take() {
+            Some(data) => Ok(Frame::data(data)),
+            None => Err(HttpError::new(
+                HttpErrorKind::Interrupted,
+                io::Error::other("Injected fault: response body truncated"),
+            )),
+        }))
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use http::{HeaderName, HeaderValue, Uri};
+use object_store::client::{
+    HttpClient, HttpError, HttpErrorKind, HttpRequest, HttpResponse, HttpResponseBody, HttpService,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{FixtureMode, FixturesConfig};
+
+/// Provider responses recorded to a file of JSON lines, and replayed from it in place of the
+/// network, so tests of authentication, retries, and pagination run without credentials.
+///
+/// Requests are matched by method and url, without the signature parameters of presigned
+/// urls, and each recorded response is replayed once, in the order it was recorded.
+#[derive(Debug)]
+pub struct Fixtures {
+    config: FixturesConfig,
+    /// Responses not yet replayed, or the file being recorded to
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+enum State {
+    Record(File),
+    Replay(Vec<Interaction>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Interaction {
+    method: String,
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// Base64 encoded, as bodies may be binary
+    body: String,
+}
+
+impl Fixtures {
+    pub fn new(config: &FixturesConfig) -> io::Result<Self> {
+        let state = match config.mode {
+            FixtureMode::Record => {
+                if let Some(dir) = config.path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                State::Record(
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&config.path)?,
+                )
+            }
+            FixtureMode::Replay => State::Replay(
+                BufReader::new(File::open(&config.path)?)
+                    .lines()
+                    .map(|line| Ok(serde_json::from_str(&line?)?))
+                    .collect::<io::Result<_>>()?,
+            ),
+        };
+        Ok(Fixtures {
+            config: config.clone(),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Whether the fixtures were loaded with the same settings as `config`
+    pub fn matches(&self, config: &FixturesConfig) -> bool {
+        (&self.config.path, &self.config.mode) == (&config.path, &config.mode)
+    }
+
+    /// Wrap the client making the requests of a store, replacing it when replaying
+    pub fn client(self: &Arc<Self>, inner: HttpClient) -> HttpClient {
+        HttpClient::new(FixtureService {
+            inner,
+            fixtures: Arc::clone(self),
+        })
+    }
+
+    fn replay(&self, method: &str, url: &str) -> Option<Interaction> {
+        let mut state = self.state.lock().expect("not poisoned");
+        let State::Replay(interactions) = &mut *state else {
+            return None;
+        };
+        let position = interactions
+            .iter()
+            .position(|interaction| interaction.method == method && interaction.url == url)?;
+        Some(interactions.remove(position))
+    }
+
+    fn record(&self, interaction: &Interaction) -> io::Result<()> {
+        let mut state = self.state.lock().expect("not poisoned");
+        if let State::Record(file) = &mut *state {
+            writeln!(file, "{}", serde_json::to_string(interaction)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct FixtureService {
+    inner: HttpClient,
+    fixtures: Arc<Fixtures>,
+}
+
+#[async_trait]
+impl HttpService for FixtureService {
+    async fn call(&self, req: HttpRequest) -> Result<HttpResponse, HttpError> {
+        let method = req.method().to_string();
+        let url = fixture_url(req.uri());
+        if matches!(self.fixtures.config.mode, FixtureMode::Replay) {
+            let interaction = self.fixtures.replay(&method, &url).ok_or_else(|| {
+                HttpError::new(
+                    HttpErrorKind::Unknown,
+                    io::Error::other(format!("No recorded response for {method} {url}")),
+                )
+            })?;
+            return response(interaction);
+        }
+
+        let response = self.inner.execute(req).await?;
+        let (parts, body) = response.into_parts();
+        let body = body.bytes().await?;
+        let interaction = Interaction {
+            method,
+            url,
+            status: parts.status.as_u16(),
+            headers: parts
+                .headers
+                .iter()
+                .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.into())))
+                .collect(),
+            body: STANDARD.encode(&body),
+        };
+        self.fixtures
+            .record(&interaction)
+            .map_err(|e| HttpError::new(HttpErrorKind::Unknown, e))?;
+        Ok(HttpResponse::from_parts(
+            parts,
+            HttpResponseBody::from(body),
+        ))
+    }
+}
+
+fn response(interaction: Interaction) -> Result<HttpResponse, HttpError> {
+    let invalid = |e: Box<dyn std::error::Error + Send + Sync>| {
+        HttpError::new(
+            HttpErrorKind::Unknown,
+            io::Error::other(format!("Invalid recorded response: {e}")),
+        )
+    };
+    let body = STANDARD
+        .decode(interaction.body)
+        .map_err(|e| invalid(e.into()))?;
+    let mut response = HttpResponse::new(HttpResponseBody::from(body));
+    *response.status_mut() = interaction
+        .status
+        .try_into()
+        .map_err(|e: http::status::InvalidStatusCode| invalid(e.into()))?;
+    for (name, value) in interaction.headers {
+        response.headers_mut().append(
+            HeaderName::try_from(name).map_err(|e| invalid(e.into()))?,
+            HeaderValue::try_from(value).map_err(|e| invalid(e.into()))?,
+        );
+    }
+    Ok(response)
+}
+
+/// The url requests are matched by, without the signature parameters of presigned urls,
+/// which change with every request
+fn fixture_url(uri: &Uri) -> String {
+    let scheme = uri.scheme_str().unwrap_or("https");
+    let authority = uri.authority().map(|a| a.as_str()).unwrap_or_default();
+    let mut url = format!("{scheme}://{authority}{}", uri.path());
+    let query = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|param| !param.is_empty() && !param.to_ascii_lowercase().starts_with("x-amz-"))
+        .collect::<Vec<_>>();
+    if !query.is_empty() {
+        url.push('?');
+        url.push_str(&query.join("&"));
+    }
+    url
+}
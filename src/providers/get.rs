@@ -0,0 +1,57 @@
+//! Helpers for implementing [`object_store::ObjectStore::get_opts`] in the stores
+//! provided by this plugin, mirroring the behavior of the object_store implementations.
+use object_store::{Error, GetOptions, GetRange, ObjectMeta, Result};
+use std::ops::Range;
+
+/// Resolve the byte range requested from an object of `len` bytes
+pub fn resolve_range(store: &'static str, range: &GetRange, len: u64) -> Result<Range<u64>> {
+    let range = match range {
+        GetRange::Bounded(r) => r.start..r.end.min(len),
+        GetRange::Offset(o) => *o..len,
+        GetRange::Suffix(n) => len.saturating_sub(*n)..len,
+    };
+    if range.start > range.end || (range.start >= len && len > 0) {
+        return Err(Error::Generic {
+            store,
+            source: format!("Invalid range {range:?} for object of {len} bytes").into(),
+        });
+    }
+    Ok(range)
+}
+
+/// Evaluate the conditional request headers of `options` against `meta`
+pub fn check_preconditions(options: &GetOptions, meta: &ObjectMeta) -> Result<()> {
+    let etag = meta.e_tag.as_deref().unwrap_or("*");
+    if let Some(m) = &options.if_match {
+        if m != "*" && m.split(',').map(str::trim).all(|x| x != etag) {
+            return Err(Error::Precondition {
+                path: meta.location.to_string(),
+                source: format!("{etag} does not match {m}").into(),
+            });
+        }
+    } else if let Some(date) = options.if_unmodified_since
+        && meta.last_modified > date
+    {
+        return Err(Error::Precondition {
+            path: meta.location.to_string(),
+            source: format!("{date} < {}", meta.last_modified).into(),
+        });
+    }
+
+    if let Some(m) = &options.if_none_match {
+        if m == "*" || m.split(',').map(str::trim).any(|x| x == etag) {
+            return Err(Error::NotModified {
+                path: meta.location.to_string(),
+                source: format!("{etag} matches {m}").into(),
+            });
+        }
+    } else if let Some(date) = options.if_modified_since
+        && meta.last_modified <= date
+    {
+        return Err(Error::NotModified {
+            path: meta.location.to_string(),
+            source: format!("{date} >= {}", meta.last_modified).into(),
+        });
+    }
+    Ok(())
+}
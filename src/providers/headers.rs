@@ -0,0 +1,26 @@
+use std::{cell::RefCell, future::Future};
+
+use http::HeaderMap;
+
+tokio::task_local! {
+    /// Headers of the last response received by the future polled in [`captured`]
+    static CAPTURED: RefCell<HeaderMap>;
+}
+
+/// Poll `future`, returning its output with the headers of the last response it received.
+///
+/// Stores don't expose every response header, e.g. the storage class and encryption of S3
+/// objects, but requests through the plugin's connector record them here.
+pub async fn captured<F: Future>(future: F) -> (F::Output, HeaderMap) {
+    CAPTURED
+        .scope(RefCell::default(), async {
+            let output = future.await;
+            (output, CAPTURED.with(|headers| headers.take()))
+        })
+        .await
+}
+
+/// Record the headers of a response for [`captured`], if it's being polled
+pub fn record(headers: &HeaderMap) {
+    let _ = CAPTURED.try_with(|captured| *captured.borrow_mut() = headers.clone());
+}
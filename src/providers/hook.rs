@@ -0,0 +1,81 @@
+use http::{HeaderName, HeaderValue};
+use nu_plugin::EngineInterface;
+use nu_protocol::{Record, Span, Spanned, Value, engine::Closure};
+use object_store::client::{HttpError, HttpErrorKind, HttpRequest};
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+/// Configured closure called for each request, adding the headers it returns.
+///
+/// The closure is evaluated through the engine of the current call, so stores using a hook must
+/// not be cached across calls.
+#[derive(Debug, Clone)]
+pub struct HeaderHook {
+    engine: EngineInterface,
+    closure: Spanned<Closure>,
+}
+
+impl HeaderHook {
+    pub fn new(engine: &EngineInterface, closure: Closure, span: Span) -> Self {
+        HeaderHook {
+            engine: engine.clone(),
+            closure: Spanned {
+                item: closure,
+                span,
+            },
+        }
+    }
+
+    pub fn apply(&self, req: &mut HttpRequest) -> Result<(), HttpError> {
+        let span = self.closure.span;
+        let mut headers = Record::new();
+        for (name, value) in req.headers() {
+            // Don't hand credentials to the closure
+            if !value.is_sensitive()
+                && let Ok(value) = value.to_str()
+            {
+                headers.push(name.as_str(), Value::string(value, span));
+            }
+        }
+        let request = Value::record(
+            [
+                ("method", Value::string(req.method().as_str(), span)),
+                ("url", Value::string(req.uri().to_string(), span)),
+                ("headers", Value::record(headers, span)),
+                (
+                    "content_length",
+                    Value::int(req.body().content_length() as i64, span),
+                ),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+            span,
+        );
+
+        // Engine calls block until the engine responds. A current-thread runtime has no other
+        // workers to hand its tasks to, so it is blocked as a whole.
+        let eval = || {
+            self.engine
+                .eval_closure(&self.closure, vec![request], None)
+                .map_err(|e| HttpError::new(HttpErrorKind::Unknown, e))
+        };
+        let added = match Handle::current().runtime_flavor() {
+            RuntimeFlavor::CurrentThread => eval(),
+            _ => tokio::task::block_in_place(eval),
+        }?
+        .into_record()
+        .map_err(|e| HttpError::new(HttpErrorKind::Unknown, e))?;
+
+        for (name, value) in added {
+            let value = value
+                .coerce_into_string()
+                .map_err(|e| HttpError::new(HttpErrorKind::Unknown, e))?;
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| HttpError::new(HttpErrorKind::Unknown, e))?;
+            let value = HeaderValue::from_str(&value)
+                .map_err(|e| HttpError::new(HttpErrorKind::Unknown, e))?;
+            req.headers_mut().insert(name, value);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use std::{sync::Arc, time::Duration};
+
+use async_lock::Mutex;
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use object_store::GetResult;
+use tokio::time::Instant;
+
+tokio::task_local! {
+    /// Limit of the command run being executed, see [`scoped`]
+    static RUN_LIMIT: Arc<RateLimiter>;
+}
+
+/// Spaces work evenly to stay under a number of units per second, e.g. requests or bytes
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_second: f64,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// `per_second` must be positive
+    pub fn new(per_second: f64) -> Self {
+        RateLimiter {
+            per_second,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn per_second(&self) -> f64 {
+        self.per_second
+    }
+
+    /// Wait for the next free slot
+    pub async fn acquire(&self) {
+        self.acquire_many(1).await
+    }
+
+    /// Wait until `units` may be used. The first use after a pause goes ahead immediately,
+    /// later ones wait for the time the previous ones take at the allowed rate.
+    pub async fn acquire_many(&self, units: u64) {
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + Duration::from_secs_f64(units as f64 / self.per_second);
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// Read the body of `result`, receiving no faster than `limit` bytes per second
+pub async fn read_throttled(result: GetResult, limit: &RateLimiter) -> object_store::Result<Bytes> {
+    let mut body = BytesMut::with_capacity(result.meta.size as usize);
+    let mut stream = result.into_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        limit.acquire_many(chunk.len() as u64).await;
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
+
+/// Apply `limit` to the requests made while polling `future`, in addition to the configured one.
+///
+/// Tasks the future spawns, e.g. for parts of multipart uploads, carry it over with [`current`].
+pub async fn scoped<F: Future>(limit: Option<Arc<RateLimiter>>, future: F) -> F::Output {
+    match limit {
+        Some(limit) => RUN_LIMIT.scope(limit, future).await,
+        None => future.await,
+    }
+}
+
+/// Limit of the current command run, to carry it into work done outside of [`scoped`]
+pub fn current() -> Option<Arc<RateLimiter>> {
+    RUN_LIMIT.try_with(Arc::clone).ok()
+}
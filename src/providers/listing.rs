@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use futures::{
+    StreamExt, TryStreamExt,
+    stream::{self, BoxStream},
+};
+use http::Method;
+use object_store::{Error, ObjectMeta, Result, client::HttpRequestBody, path::Path};
+
+use super::{
+    signed::{self, BucketClient},
+    versions::{child_text, owner},
+};
+
+const STORE: &str = "S3";
+
+/// Lists objects of S3 buckets with ListObjectsV2 itself, for what object_store leaves out of
+/// its listings, like storage classes and owners
+#[derive(Debug)]
+pub struct ListingClient {
+    bucket: Arc<BucketClient>,
+    /// Whether the owners of the objects are listed, which S3 leaves out by default
+    owners: bool,
+}
+
+/// A listed object with its storage class
+#[derive(Debug)]
+pub struct ListedObject {
+    pub meta: ObjectMeta,
+    pub storage_class: String,
+    /// Only listed [`ListingClient::with_owners`]
+    pub owner: Option<String>,
+}
+
+/// A page of a listing, with the token of the next one
+struct Page {
+    objects: Vec<ListedObject>,
+    /// Prefixes of deeper objects, in listings by level
+    prefixes: Vec<Path>,
+    next: Option<String>,
+}
+
+impl ListingClient {
+    pub fn new(bucket: Arc<BucketClient>) -> Self {
+        ListingClient {
+            bucket,
+            owners: false,
+        }
+    }
+
+    /// List the owners of the objects too
+    pub fn with_owners(self) -> Self {
+        ListingClient {
+            owners: true,
+            ..self
+        }
+    }
+
+    /// Objects under `prefix` in key order, like [`object_store::ObjectStore::list`], listed a
+    /// page at a time as the stream is read
+    pub fn list(&self, prefix: &Path) -> BoxStream<'static, Result<ListedObject>> {
+        let bucket = Arc::clone(&self.bucket);
+        let owners = self.owners;
+        let prefix = dir_prefix(prefix);
+        // The token of the page to list, none for the first, and done after the last
+        let first: Option<Option<String>> = Some(None);
+        stream::try_unfold(first, move |token| {
+            let (bucket, prefix) = (Arc::clone(&bucket), prefix.clone());
+            async move {
+                let Some(token) = token else {
+                    return Ok(None);
+                };
+                let options = ListOptions {
+                    prefix: &prefix,
+                    delimiter: false,
+                    owners,
+                };
+                let page = list_page(&bucket, options, token.as_deref()).await?;
+                Ok::<_, Error>(Some((page.objects, page.next.map(Some))))
+            }
+        })
+        .map_ok(|objects| stream::iter(objects.into_iter().map(Ok)))
+        .try_flatten()
+        .boxed()
+    }
+
+    /// The objects directly under `prefix` and the prefixes of deeper ones, like
+    /// [`object_store::ObjectStore::list_with_delimiter`]
+    pub async fn list_with_delimiter(
+        &self,
+        prefix: &Path,
+    ) -> Result<(Vec<Path>, Vec<ListedObject>)> {
+        let prefix = dir_prefix(prefix);
+        let options = ListOptions {
+            prefix: &prefix,
+            delimiter: true,
+            owners: self.owners,
+        };
+        let (mut prefixes, mut objects) = (vec![], vec![]);
+        let mut token = None;
+        loop {
+            let page = list_page(&self.bucket, options, token.as_deref()).await?;
+            prefixes.extend(page.prefixes);
+            objects.extend(page.objects);
+            match page.next {
+                Some(next) => token = Some(next),
+                None => return Ok((prefixes, objects)),
+            }
+        }
+    }
+}
+
+/// The prefix of the keys under a path, only on path boundaries so `a` doesn't match `ab`
+fn dir_prefix(prefix: &Path) -> String {
+    match prefix.as_ref() {
+        "" => String::new(),
+        prefix => format!("{prefix}/"),
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ListOptions<'a> {
+    prefix: &'a str,
+    /// List a single level, with the prefixes of deeper objects
+    delimiter: bool,
+    owners: bool,
+}
+
+/// List the page after `token`, or the first one
+async fn list_page(
+    bucket: &BucketClient,
+    options: ListOptions<'_>,
+    token: Option<&str>,
+) -> Result<Page> {
+    let mut query = vec![("list-type", "2"), ("prefix", options.prefix)];
+    if options.delimiter {
+        query.push(("delimiter", "/"));
+    }
+    if options.owners {
+        query.push(("fetch-owner", "true"));
+    }
+    if let Some(token) = token {
+        query.push(("continuation-token", token));
+    }
+    let url = bucket.url(None, &query);
+    let body = bucket
+        .send(Method::GET, &url, &[], HttpRequestBody::empty())
+        .await?;
+    parse_page(&body)
+}
+
+fn generic(source: impl ToString) -> Error {
+    signed::generic(STORE, source)
+}
+
+/// Parse a `ListBucketResult` of ListObjectsV2
+fn parse_page(body: &[u8]) -> Result<Page> {
+    let body = std::str::from_utf8(body).map_err(generic)?;
+    let document = roxmltree::Document::parse(body).map_err(generic)?;
+    let result = document.root_element();
+    let invalid = |element: &str| generic(format!("Invalid listing: missing {element}"));
+    let mut objects = vec![];
+    for node in result
+        .children()
+        .filter(|node| node.has_tag_name("Contents"))
+    {
+        let text = |name| child_text(node, name).ok_or_else(|| invalid(name));
+        let meta = ObjectMeta {
+            location: Path::parse(text("Key")?).map_err(|source| Error::InvalidPath { source })?,
+            last_modified: text("LastModified")?
+                .parse()
+                .map_err(|_| invalid("LastModified"))?,
+            size: text("Size")?.parse().map_err(|_| invalid("Size"))?,
+            e_tag: child_text(node, "ETag").map(str::to_string),
+            version: None,
+        };
+        objects.push(ListedObject {
+            meta,
+            // Some S3 compatible stores leave out the class of standard objects
+            storage_class: child_text(node, "StorageClass")
+                .unwrap_or("STANDARD")
+                .to_string(),
+            owner: owner(node),
+        });
+    }
+    let mut prefixes = vec![];
+    for node in result
+        .children()
+        .filter(|node| node.has_tag_name("CommonPrefixes"))
+    {
+        let prefix = child_text(node, "Prefix").ok_or_else(|| invalid("Prefix"))?;
+        let prefix = Path::parse(prefix.trim_end_matches('/'))
+            .map_err(|source| Error::InvalidPath { source })?;
+        prefixes.push(prefix);
+    }
+    let next = match child_text(result, "IsTruncated") {
+        Some("true") => Some(
+            child_text(result, "NextContinuationToken")
+                .ok_or_else(|| invalid("NextContinuationToken"))?
+                .to_string(),
+        ),
+        _ => None,
+    };
+    Ok(Page {
+        objects,
+        prefixes,
+        next,
+    })
+}
@@ -1,20 +1,29 @@
 use super::NuObjectStore;
 use crate::cache::{Cache, ObjectStoreCacheKey};
 use nu_plugin::EngineInterface;
-use nu_protocol::ShellError;
+use nu_protocol::{ShellError, Spanned};
 use object_store::memory::InMemory;
 use std::sync::Arc;
+use url::Url;
 
 pub async fn build_object_store(
     engine: &EngineInterface,
     cache: &Cache,
+    url: &Spanned<Url>,
 ) -> Result<NuObjectStore, ShellError> {
-    let key = ObjectStoreCacheKey::Memory;
+    let bucket = url.item.host_str().map(ToString::to_string);
+    let key = ObjectStoreCacheKey::Memory {
+        bucket: bucket.clone(),
+    };
     if let Some(store) = cache.get_store(&key).await {
         Ok(store)
     } else {
-        let store = InMemory::new();
-        let store = NuObjectStore::Memory(Arc::new(store));
+        let store = Arc::new(InMemory::new());
+        let store = NuObjectStore::Memory {
+            store: store.clone(),
+            multipart: store,
+            bucket,
+        };
         cache.put_store(engine, key, store.clone()).await?;
         Ok(store)
     }
@@ -0,0 +1,119 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use chrono::{DateTime, Local};
+use http::{HeaderMap, Method, StatusCode, header::CONTENT_LENGTH};
+use object_store::client::{HttpError, HttpErrorKind};
+
+/// Requests of all stores since the plugin started, counting every attempt, as each is billed
+#[derive(Debug)]
+pub struct Metrics {
+    totals: Mutex<Totals>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Totals {
+    pub since: DateTime<Local>,
+    /// Attempts by HTTP method
+    pub requests: BTreeMap<String, u64>,
+    pub retries: u64,
+    /// Failed attempts by status class or transport error
+    pub errors: BTreeMap<String, u64>,
+    /// Request body bytes
+    pub bytes_uploaded: u64,
+    /// Response body bytes, by their `Content-Length`
+    pub bytes_downloaded: u64,
+    /// Attempts and bytes by provider, the scheme of the urls of the stores
+    pub providers: BTreeMap<String, Usage>,
+}
+
+/// Requests and bytes of a provider, as it bills them
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    /// Attempts by HTTP method
+    pub requests: BTreeMap<String, u64>,
+    pub bytes_uploaded: u64,
+    pub bytes_downloaded: u64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            totals: Mutex::new(Totals::new()),
+        }
+    }
+}
+
+impl Totals {
+    fn new() -> Self {
+        Totals {
+            since: Local::now(),
+            requests: BTreeMap::new(),
+            retries: 0,
+            errors: BTreeMap::new(),
+            bytes_uploaded: 0,
+            bytes_downloaded: 0,
+            providers: BTreeMap::new(),
+        }
+    }
+}
+
+impl Metrics {
+    /// Count an attempt of a request to `provider`, the `attempt`th after the first, that sent
+    /// `sent` bytes
+    pub fn attempt(
+        &self,
+        provider: &str,
+        method: &Method,
+        attempt: usize,
+        sent: u64,
+        outcome: Result<(StatusCode, &HeaderMap), &HttpError>,
+    ) {
+        let mut totals = self.totals.lock().expect("not poisoned");
+        *totals.requests.entry(method.to_string()).or_default() += 1;
+        if attempt > 0 {
+            totals.retries += 1;
+        }
+        // Responses to HEAD requests give the length of the object they don't have a body for
+        let received = match outcome {
+            Ok((_, headers)) if method != Method::HEAD => headers
+                .get(CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok()?.parse::<u64>().ok())
+                .unwrap_or(0),
+            _ => 0,
+        };
+        totals.bytes_uploaded += sent;
+        totals.bytes_downloaded += received;
+        let usage = totals.providers.entry(provider.into()).or_default();
+        *usage.requests.entry(method.to_string()).or_default() += 1;
+        usage.bytes_uploaded += sent;
+        usage.bytes_downloaded += received;
+        let error = match outcome {
+            Ok((status, _)) => match status.as_u16() {
+                400..=499 => Some("4xx"),
+                500.. => Some("5xx"),
+                _ => None,
+            },
+            Err(e) => Some(match e.kind() {
+                HttpErrorKind::Connect => "connect",
+                HttpErrorKind::Timeout => "timeout",
+                HttpErrorKind::Request | HttpErrorKind::Interrupted => "network",
+                _ => "other",
+            }),
+        };
+        if let Some(error) = error {
+            *totals.errors.entry(error.into()).or_default() += 1;
+        }
+    }
+
+    pub fn totals(&self) -> Totals {
+        self.totals.lock().expect("not poisoned").clone()
+    }
+
+    /// Start counting over, returning the totals until now
+    pub fn reset(&self) -> Totals {
+        std::mem::replace(
+            &mut *self.totals.lock().expect("not poisoned"),
+            Totals::new(),
+        )
+    }
+}
@@ -1,66 +1,233 @@
-mod aws;
+pub(crate) mod aws;
+pub(crate) mod checksums;
+pub(crate) mod concat;
+mod connector;
+pub(crate) mod debug_log;
+pub(crate) mod faults;
+#[cfg(feature = "fixtures")]
+pub(crate) mod fixtures;
+mod get;
+pub(crate) mod headers;
+mod hook;
+mod http;
+pub(crate) mod limit;
+pub(crate) mod listing;
 mod local;
 mod mem;
+pub(crate) mod metrics;
+mod oidc;
+pub(crate) mod presign;
+mod retry;
+#[cfg(feature = "sftp")]
+mod sftp;
+pub(crate) mod signed;
+pub(crate) mod sqs;
+pub(crate) mod stats;
+pub(crate) mod trace;
+pub(crate) mod unavailable;
+pub(crate) mod verbose;
+pub(crate) mod versions;
+mod webhdfs;
 
 use crate::cache::Cache;
 use nu_plugin::EngineInterface;
 use nu_protocol::{ShellError, Span, Spanned};
-use object_store::{ObjectStore, ObjectStoreScheme, path::Path};
+use object_store::{ObjectStore, ObjectStoreScheme, multipart::MultipartStore, path::Path};
 use std::sync::Arc;
 use url::Url;
 
 #[derive(Clone)]
 pub enum NuObjectStore {
     Local(Arc<dyn ObjectStore>),
-    Memory(Arc<dyn ObjectStore>),
+    Memory {
+        store: Arc<dyn ObjectStore>,
+        multipart: Arc<dyn MultipartStore>,
+        bucket: Option<String>,
+    },
     AmazonS3 {
         store: Arc<dyn ObjectStore>,
+        multipart: Arc<dyn MultipartStore>,
         bucket: String,
         region: String,
+        /// Configured bucket prefix the store was built for
+        profile: Option<String>,
+        /// Access key of static credentials, or the AWS profile of MFA sessions
+        credentials: Option<String>,
+        /// Temporary credentials refreshed while the store is used, through the engine of the
+        /// latest command using it
+        temporary: Option<Arc<aws::TemporaryCredentials>>,
+        /// Client for the S3 APIs object_store has no support for, signing requests with the
+        /// bucket's credentials
+        api: Arc<signed::BucketClient>,
+    },
+    #[cfg(feature = "sftp")]
+    Sftp {
+        store: Arc<dyn ObjectStore>,
+        host: String,
+        port: u16,
+        user: String,
+    },
+    WebHdfs {
+        store: Arc<dyn ObjectStore>,
+        endpoint: String,
+        user: Option<String>,
     },
     #[allow(dead_code)]
     GoogleCloudStorage(Arc<dyn ObjectStore>),
     #[allow(dead_code)]
     MicrosoftAzure(Arc<dyn ObjectStore>),
-    #[allow(dead_code)]
-    Http(Arc<dyn ObjectStore>),
+    Http {
+        store: Arc<dyn ObjectStore>,
+        origin: String,
+        authorization: Option<String>,
+        /// Configured prefix the store was built for
+        profile: Option<String>,
+    },
 }
 impl NuObjectStore {
     pub fn object_store(&self) -> &dyn ObjectStore {
         match self {
             NuObjectStore::Local(store) => store.as_ref(),
-            NuObjectStore::Memory(store) => store.as_ref(),
+            NuObjectStore::Memory { store, .. } => store.as_ref(),
             NuObjectStore::AmazonS3 { store, .. } => store.as_ref(),
+            #[cfg(feature = "sftp")]
+            NuObjectStore::Sftp { store, .. } => store.as_ref(),
+            NuObjectStore::WebHdfs { store, .. } => store.as_ref(),
             NuObjectStore::GoogleCloudStorage(store) => store.as_ref(),
             NuObjectStore::MicrosoftAzure(store) => store.as_ref(),
-            NuObjectStore::Http(store) => store.as_ref(),
+            NuObjectStore::Http { store, .. } => store.as_ref(),
+        }
+    }
+
+    /// Client signing requests with the credentials of S3 buckets
+    pub fn signed_client(&self) -> Option<&Arc<signed::SignedClient>> {
+        match self {
+            NuObjectStore::AmazonS3 { api, .. } => Some(api.signed()),
+            _ => None,
+        }
+    }
+
+    /// Client joining objects of S3 buckets without downloading them
+    pub fn concat_client(&self) -> Option<concat::ConcatClient> {
+        match self {
+            NuObjectStore::AmazonS3 { api, .. } => Some(concat::ConcatClient::new(Arc::clone(api))),
+            _ => None,
+        }
+    }
+
+    /// Client for the checksums S3 stores with objects
+    pub fn checksum_client(&self) -> Option<checksums::ChecksumClient> {
+        match self {
+            NuObjectStore::AmazonS3 { api, .. } => {
+                Some(checksums::ChecksumClient::new(Arc::clone(api)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Client presigning urls of objects in S3 buckets
+    pub fn presign_client(&self) -> Option<presign::PresignClient> {
+        match self {
+            NuObjectStore::AmazonS3 { api, .. } => {
+                Some(presign::PresignClient::new(Arc::clone(api)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Client listing the objects of S3 buckets with their storage classes
+    pub fn listing_client(&self) -> Option<listing::ListingClient> {
+        match self {
+            NuObjectStore::AmazonS3 { api, .. } => {
+                Some(listing::ListingClient::new(Arc::clone(api)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Client for the object versions of S3 buckets
+    pub fn version_client(&self) -> Option<versions::VersionClient> {
+        match self {
+            NuObjectStore::AmazonS3 { api, .. } => {
+                Some(versions::VersionClient::new(Arc::clone(api)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Lower level multipart API of stores supporting it, for uploads tracked in the
+    /// [`crate::transfer::Journal`]
+    pub fn multipart_store(&self) -> Option<Arc<dyn MultipartStore>> {
+        match self {
+            NuObjectStore::Memory { multipart, .. } | NuObjectStore::AmazonS3 { multipart, .. } => {
+                Some(Arc::clone(multipart))
+            }
+            _ => None,
         }
     }
 }
 
+/// Storage backends, extending [`ObjectStoreScheme`] with the ones implemented by this plugin
+enum Scheme {
+    ObjectStore(ObjectStoreScheme),
+    #[cfg(feature = "sftp")]
+    Sftp,
+    WebHdfs,
+}
+
 pub async fn parse_url(
     engine: &EngineInterface,
     cache: &Cache,
     url: &Spanned<Url>,
     span: Span,
 ) -> Result<(NuObjectStore, Path), ShellError> {
-    let (scheme, path) =
-        ObjectStoreScheme::parse(&url.item).map_err(|e| ShellError::IncorrectValue {
-            msg: format!("Unsupported url: {e}"),
+    let (scheme, path) = match (url.item.scheme(), url.item.host_str()) {
+        // memory://bucket/key selects a separate in-memory store per bucket
+        ("memory", Some(_)) => (
+            Scheme::ObjectStore(ObjectStoreScheme::Memory),
+            Path::from_url_path(url.item.path()),
+        ),
+        #[cfg(feature = "sftp")]
+        ("sftp", Some(_)) => (Scheme::Sftp, Path::from_url_path(url.item.path())),
+        ("hdfs" | "webhdfs" | "swebhdfs", Some(_)) => {
+            (Scheme::WebHdfs, Path::from_url_path(url.item.path()))
+        }
+        _ => {
+            let (scheme, path) =
+                ObjectStoreScheme::parse(&url.item).map_err(|e| ShellError::IncorrectValue {
+                    msg: format!("Unsupported url: {e}"),
+                    val_span: url.span,
+                    call_span: span,
+                })?;
+            (Scheme::ObjectStore(scheme), Ok(path))
+        }
+    };
+
+    let path = path
+        .and_then(Path::parse)
+        .map_err(|e| ShellError::IncorrectValue {
+            msg: format!("Unsupported path: {e}"),
             val_span: url.span,
             call_span: span,
         })?;
 
-    let path = Path::parse(path).map_err(|e| ShellError::IncorrectValue {
-        msg: format!("Unsupported path: {e}"),
-        val_span: url.span,
-        call_span: span,
-    })?;
-
     let object_store = match scheme {
-        ObjectStoreScheme::AmazonS3 => aws::build_object_store(engine, cache, url).await?,
-        ObjectStoreScheme::Local => local::build_object_store(engine, cache).await?,
-        ObjectStoreScheme::Memory => mem::build_object_store(engine, cache).await?,
+        Scheme::ObjectStore(ObjectStoreScheme::AmazonS3) => {
+            // Boxed, as the AWS config loading would make every command's future as large
+            Box::pin(aws::build_object_store(engine, cache, url)).await?
+        }
+        Scheme::ObjectStore(ObjectStoreScheme::Http) => {
+            http::build_object_store(engine, cache, url).await?
+        }
+        Scheme::ObjectStore(ObjectStoreScheme::Local) => {
+            local::build_object_store(engine, cache).await?
+        }
+        Scheme::ObjectStore(ObjectStoreScheme::Memory) => {
+            mem::build_object_store(engine, cache, url).await?
+        }
+        #[cfg(feature = "sftp")]
+        Scheme::Sftp => sftp::build_object_store(engine, cache, url).await?,
+        Scheme::WebHdfs => webhdfs::build_object_store(engine, cache, url).await?,
         _ => {
             return Err(ShellError::IncorrectValue {
                 msg: format!("Unsupported url: {}", url.item),
@@ -72,3 +239,17 @@ pub async fn parse_url(
 
     Ok((object_store, path))
 }
+
+/// Read an environment variable from the engine, ignoring it when empty
+pub(crate) fn env_string(
+    engine: &EngineInterface,
+    name: &str,
+) -> Result<Option<String>, Box<ShellError>> {
+    match engine.get_env_var(name)? {
+        Some(value) => {
+            let value = value.coerce_into_string()?;
+            Ok((!value.is_empty()).then_some(value))
+        }
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use async_lock::Mutex;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::{config::OidcConfig, credentials::REFRESH_WINDOW};
+
+const CLIENT_CREDENTIALS: &str = "client_credentials";
+const TOKEN_EXCHANGE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+const JWT_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:jwt";
+
+/// Bearer tokens from an OIDC token endpoint or a token file.
+///
+/// With a `token_url`, tokens are requested with the client credentials grant, or exchanged for
+/// the token in `token_file` (RFC 8693), and cached until shortly before they expire. Without
+/// one, the token file is used as is.
+#[derive(Debug)]
+pub struct OidcTokenSource {
+    config: OidcConfig,
+    client: Client,
+    cached: Mutex<Option<(String, Option<Instant>)>>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+impl OidcTokenSource {
+    pub fn new(config: OidcConfig, client: Client) -> Self {
+        OidcTokenSource {
+            config,
+            client,
+            cached: Mutex::new(None),
+        }
+    }
+
+    pub async fn token(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let Some(token_url) = &self.config.token_url else {
+            // Token files are rotated in place, e.g. Kubernetes projected tokens
+            return self.read_token_file().await;
+        };
+
+        let mut cached = self.cached.lock().await;
+        if let Some((token, expires_at)) = cached.as_ref()
+            && expires_at.is_none_or(|expires_at| expires_at > Instant::now() + REFRESH_WINDOW)
+        {
+            return Ok(token.clone());
+        }
+
+        let mut form = vec![];
+        if self.config.token_file.is_some() {
+            form.push(("grant_type", TOKEN_EXCHANGE.to_string()));
+            form.push(("subject_token", self.read_token_file().await?));
+            form.push(("subject_token_type", JWT_TOKEN_TYPE.to_string()));
+        } else {
+            form.push(("grant_type", CLIENT_CREDENTIALS.to_string()));
+        }
+        for (name, value) in [
+            ("client_id", &self.config.client_id),
+            ("client_secret", &self.config.client_secret),
+            ("scope", &self.config.scope),
+            ("audience", &self.config.audience),
+        ] {
+            if let Some(value) = value {
+                form.push((name, value.clone()));
+            }
+        }
+
+        let response: TokenResponse = self
+            .client
+            .post(token_url)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let expires_at = response
+            .expires_in
+            .map(|seconds| Instant::now() + Duration::from_secs(seconds));
+        *cached = Some((response.access_token.clone(), expires_at));
+        Ok(response.access_token)
+    }
+
+    async fn read_token_file(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let path = self
+            .config
+            .token_file
+            .as_ref()
+            .ok_or("OIDC configuration needs a token_url or token_file")?;
+        let token = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| format!("Could not read token file {}: {e}", path.display()))?;
+        Ok(token.trim().to_string())
+    }
+}
@@ -0,0 +1,102 @@
+use std::{sync::Arc, time::Duration};
+
+use http::Method;
+use object_store::{Result, client::HttpRequestBody, path::Path};
+
+use super::{concat::element_text, signed::BucketClient};
+
+/// Longest S3 accepts a presigned url for
+pub const MAX_EXPIRY: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Presigns urls of objects in an S3 bucket, which can be requested without credentials until
+/// they expire
+#[derive(Debug)]
+pub struct PresignClient {
+    bucket: Arc<BucketClient>,
+}
+
+/// A multipart upload started for others to upload the parts of, with the urls of its requests
+#[derive(Debug)]
+pub struct PresignedUpload {
+    pub upload_id: String,
+    /// Urls to put the parts to, in order
+    pub parts: Vec<String>,
+    /// Url to post the `CompleteMultipartUpload` document with the ETags of the parts to
+    pub complete: String,
+    /// Url to delete, abandoning the upload and its parts
+    pub abort: String,
+}
+
+impl PresignClient {
+    pub fn new(bucket: Arc<BucketClient>) -> Self {
+        PresignClient { bucket }
+    }
+
+    /// Url to request an object with `method`. With a `content_type`, it must be sent with the
+    /// request.
+    pub async fn url(
+        &self,
+        method: &Method,
+        location: &Path,
+        content_type: Option<&str>,
+        expires_in: Duration,
+    ) -> Result<String> {
+        let url = self.bucket.url(Some(location), &[]);
+        self.presign(method, &url, content_type, expires_in).await
+    }
+
+    /// Start a multipart upload to `location` with the content type of the object, presigning
+    /// the requests of its `parts` and those completing and aborting it
+    pub async fn upload(
+        &self,
+        location: &Path,
+        parts: usize,
+        content_type: Option<&str>,
+        expires_in: Duration,
+    ) -> Result<PresignedUpload> {
+        let url = self.bucket.url(Some(location), &[("uploads", "")]);
+        let headers: Vec<(&str, String)> = content_type
+            .map(|content_type| ("content-type", content_type.to_string()))
+            .into_iter()
+            .collect();
+        let body = self
+            .bucket
+            .send(Method::POST, &url, &headers, HttpRequestBody::empty())
+            .await?;
+        let upload_id = element_text(&body, "UploadId")?;
+
+        let mut urls = vec![];
+        for number in 1..=parts {
+            let number = number.to_string();
+            let query = [("partNumber", number.as_str()), ("uploadId", &upload_id)];
+            let url = self.bucket.url(Some(location), &query);
+            urls.push(self.presign(&Method::PUT, &url, None, expires_in).await?);
+        }
+        let url = self.bucket.url(Some(location), &[("uploadId", &upload_id)]);
+        Ok(PresignedUpload {
+            complete: self.presign(&Method::POST, &url, None, expires_in).await?,
+            abort: self
+                .presign(&Method::DELETE, &url, None, expires_in)
+                .await?,
+            upload_id,
+            parts: urls,
+        })
+    }
+
+    async fn presign(
+        &self,
+        method: &Method,
+        url: &str,
+        content_type: Option<&str>,
+        expires_in: Duration,
+    ) -> Result<String> {
+        let headers: Vec<(&str, &str)> = content_type
+            .map(|content_type| ("content-type", content_type))
+            .into_iter()
+            .collect();
+        self.bucket
+            .signed()
+            .presign(method, url, &headers, expires_in)
+            .await
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    fmt,
+    time::{Duration, Instant, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use http::{HeaderMap, StatusCode, header::RETRY_AFTER};
+use log::info;
+use object_store::client::{HttpClient, HttpError, HttpErrorKind, HttpRequest, HttpResponse};
+
+use crate::config::{Config, ConfigDuration};
+
+/// Retries of throttled requests and transient server errors.
+///
+/// object_store retries server errors itself, but not `429 Too Many Requests`, and ignores
+/// `Retry-After`. Responses that are still failing when the retries are exhausted are turned
+/// into errors, so object_store doesn't retry them again.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_retries: usize,
+    init_backoff: Duration,
+    max_backoff: Duration,
+    retry_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 10,
+            init_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(15),
+            retry_timeout: Duration::from_secs(3 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(config: &Config) -> Self {
+        let default = RetryPolicy::default();
+        let Some(retry) = &config.retry else {
+            return default;
+        };
+        let duration = |d: Option<ConfigDuration>, default| d.map_or(default, |d| d.0);
+        RetryPolicy {
+            max_retries: retry.max_retries.unwrap_or(default.max_retries),
+            init_backoff: duration(retry.init_backoff, default.init_backoff),
+            max_backoff: duration(retry.max_backoff, default.max_backoff),
+            retry_timeout: duration(retry.retry_timeout, default.retry_timeout),
+        }
+    }
+
+    /// Send `req` until it succeeds, fails permanently, or the retries are exhausted.
+    ///
+    /// `prepare` is called before every attempt, e.g. to wait for rate limits, and `observe`
+    /// after it with the number of retries before it, its outcome, start, and duration.
+    pub async fn execute<F: Future<Output = ()>>(
+        &self,
+        client: &HttpClient,
+        req: HttpRequest,
+        prepare: impl Fn() -> F,
+        observe: impl Fn(usize, &Result<HttpResponse, HttpError>, SystemTime, Duration),
+    ) -> Result<HttpResponse, HttpError> {
+        let started = Instant::now();
+        let mut backoff = Backoff::new(self);
+        let mut retries = 0;
+        loop {
+            prepare().await;
+            let (attempt_started, attempt_start) = (SystemTime::now(), Instant::now());
+            let response = client.execute(req.clone()).await;
+            observe(retries, &response, attempt_started, attempt_start.elapsed());
+            let response = response?;
+            let status = response.status();
+            if !is_retryable(status) {
+                if retries > 0 {
+                    info!(
+                        "{} {} succeeded after {retries} retries",
+                        req.method(),
+                        req.uri()
+                    );
+                }
+                return Ok(response);
+            }
+
+            let remaining = self.retry_timeout.saturating_sub(started.elapsed());
+            if retries == self.max_retries || remaining.is_zero() {
+                return Err(HttpError::new(
+                    HttpErrorKind::Unknown,
+                    RetriesExhausted {
+                        status,
+                        attempts: retries + 1,
+                    },
+                ));
+            }
+
+            let wait = retry_after(response.headers())
+                .unwrap_or_else(|| backoff.next())
+                .min(remaining);
+            retries += 1;
+            info!(
+                "{} {} returned {status}, retry {retries} of {} in {:.1}s",
+                req.method(),
+                req.uri(),
+                self.max_retries,
+                wait.as_secs_f32()
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Throttling and server errors that usually go away when retried
+fn is_retryable(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// The delay requested with a `Retry-After` header, in seconds or as an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Exponential backoff with decorrelated jitter, see
+/// <https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/>
+struct Backoff {
+    init: f64,
+    max: f64,
+    next: f64,
+}
+
+impl Backoff {
+    const BASE: f64 = 2.0;
+
+    fn new(policy: &RetryPolicy) -> Self {
+        let init = policy.init_backoff.as_secs_f64();
+        Backoff {
+            init,
+            max: policy.max_backoff.as_secs_f64(),
+            next: init,
+        }
+    }
+
+    fn next(&mut self) -> Duration {
+        let upper = (self.next * Self::BASE).max(self.init);
+        let backoff = self.init + fastrand::f64() * (upper - self.init);
+        self.next = backoff.min(self.max);
+        Duration::from_secs_f64(self.next)
+    }
+}
+
+#[derive(Debug)]
+struct RetriesExhausted {
+    status: StatusCode,
+    attempts: usize,
+}
+
+impl fmt::Display for RetriesExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Server responded with {} after {} attempts",
+            self.status, self.attempts
+        )
+    }
+}
+
+impl std::error::Error for RetriesExhausted {}
@@ -0,0 +1,719 @@
+use super::{
+    NuObjectStore, env_string,
+    get::{check_preconditions, resolve_range},
+};
+use crate::cache::{Cache, ObjectStoreCacheKey};
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, TimeZone, Utc};
+use futures::{StreamExt, TryStreamExt, stream::BoxStream};
+use nu_plugin::EngineInterface;
+use nu_protocol::{ShellError, Span, Spanned};
+use object_store::{
+    Attributes, Error, GetOptions, GetResult, GetResultPayload, ListResult, MultipartUpload,
+    ObjectMeta, ObjectStore, PutMode, PutMultipartOpts, PutOptions, PutPayload, PutResult, Result,
+    UploadPart, path::Path,
+};
+use percent_encoding::percent_decode_str;
+use ssh2::{CheckResult, ErrorCode, FileStat, KnownHostFileKind, RenameFlags, Session, Sftp};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    io::{Read, Seek, SeekFrom, Write},
+    net::TcpStream,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+use url::Url;
+
+const STORE: &str = "SFTP";
+const CHUNK_SIZE: usize = 64 * 1024;
+const DEFAULT_IDENTITIES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+pub async fn build_object_store(
+    engine: &EngineInterface,
+    cache: &Cache,
+    url: &Spanned<Url>,
+) -> Result<NuObjectStore, ShellError> {
+    let target = SshTarget::resolve(engine, &url.item, url.span).map_err(|e| *e)?;
+
+    let key = ObjectStoreCacheKey::Sftp {
+        host: target.host.clone(),
+        port: target.port,
+        user: target.user.clone(),
+    };
+    if let Some(store) = cache.get_store(&key).await {
+        Ok(store)
+    } else {
+        let host = target.host.clone();
+        let port = target.port;
+        let user = target.user.clone();
+        let store = tokio::task::spawn_blocking(move || SftpStore::connect(target))
+            .await
+            .map_err(|e| ShellError::GenericError {
+                error: format!("Could not connect to {host}: {e}"),
+                msg: "".into(),
+                span: Some(url.span),
+                help: None,
+                inner: vec![],
+            })?
+            .map_err(|e| ShellError::GenericError {
+                error: format!("Could not connect to {host}: {e}"),
+                msg: "".into(),
+                span: Some(url.span),
+                help: Some("Make sure the host is in known_hosts and an ssh key is available in the ssh agent or ~/.ssh".into()),
+                inner: vec![],
+            })?;
+
+        let store = NuObjectStore::Sftp {
+            store: Arc::new(store),
+            host,
+            port,
+            user,
+        };
+        cache.put_store(engine, key, store.clone()).await?;
+        Ok(store)
+    }
+}
+
+/// Connection details for a host, combining the url with ~/.ssh/config
+struct SshTarget {
+    host: String,
+    port: u16,
+    user: String,
+    password: Option<String>,
+    identity_files: Vec<PathBuf>,
+    ssh_dir: Option<PathBuf>,
+}
+
+impl SshTarget {
+    fn resolve(engine: &EngineInterface, url: &Url, span: Span) -> Result<Self, Box<ShellError>> {
+        let alias = url.host_str().unwrap_or_default();
+        let ssh_dir = env_string(engine, "HOME")?
+            .or(env_string(engine, "USERPROFILE")?)
+            .map(|home| PathBuf::from(home).join(".ssh"));
+        let config = ssh_dir
+            .as_ref()
+            .and_then(|dir| std::fs::read_to_string(dir.join("config")).ok())
+            .map(|contents| HostConfig::parse(&contents, alias))
+            .unwrap_or_default();
+
+        let user = if !url.username().is_empty() {
+            percent_decode_str(url.username())
+                .decode_utf8_lossy()
+                .to_string()
+        } else if let Some(user) = config.user {
+            user
+        } else if let Some(user) = env_string(engine, "USER")?.or(env_string(engine, "USERNAME")?) {
+            user
+        } else {
+            return Err(Box::new(ShellError::GenericError {
+                error: format!("Could not determine the user to connect to {alias} as"),
+                msg: "".into(),
+                span: Some(span),
+                help: Some("Specify the user in the url: sftp://user@host/path".into()),
+                inner: vec![],
+            }));
+        };
+
+        let identity_files = if config.identity_files.is_empty() {
+            ssh_dir
+                .iter()
+                .flat_map(|dir| DEFAULT_IDENTITIES.iter().map(|name| dir.join(name)))
+                .filter(|path| path.exists())
+                .collect()
+        } else {
+            config
+                .identity_files
+                .iter()
+                .map(|file| match (file.strip_prefix("~/"), &ssh_dir) {
+                    (Some(rest), Some(dir)) => dir
+                        .parent()
+                        .map(|home| home.join(rest))
+                        .unwrap_or_else(|| PathBuf::from(file)),
+                    _ => PathBuf::from(file),
+                })
+                .collect()
+        };
+
+        Ok(SshTarget {
+            host: config.hostname.unwrap_or_else(|| alias.to_string()),
+            port: url.port().or(config.port).unwrap_or(22),
+            user,
+            password: url
+                .password()
+                .map(|p| percent_decode_str(p).decode_utf8_lossy().to_string()),
+            identity_files,
+            ssh_dir,
+        })
+    }
+}
+
+/// The subset of an ssh_config(5) host entry needed to connect
+#[derive(Default)]
+struct HostConfig {
+    hostname: Option<String>,
+    port: Option<u16>,
+    user: Option<String>,
+    identity_files: Vec<String>,
+}
+
+impl HostConfig {
+    /// Collect the settings for `alias`. As with ssh, the first value found for a key wins.
+    fn parse(contents: &str, alias: &str) -> Self {
+        let mut config = HostConfig::default();
+        let mut matches = true;
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line
+                .split_once(|c: char| c.is_whitespace() || c == '=')
+                .map(|(k, v)| (k.to_lowercase(), v.trim_start_matches('=').trim()))
+            else {
+                continue;
+            };
+
+            match key.as_str() {
+                "host" => matches = host_matches(value, alias),
+                "match" => matches = false,
+                _ if !matches => {}
+                "hostname" if config.hostname.is_none() => {
+                    config.hostname = Some(value.replace("%h", alias))
+                }
+                "port" if config.port.is_none() => config.port = value.parse().ok(),
+                "user" if config.user.is_none() => config.user = Some(value.to_string()),
+                "identityfile" => config.identity_files.push(value.to_string()),
+                _ => {}
+            }
+        }
+        config
+    }
+}
+
+/// Match a `Host` line: any pattern must match and no negated (`!`) pattern may
+fn host_matches(patterns: &str, host: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns.split_whitespace() {
+        match pattern.strip_prefix('!') {
+            Some(negated) if wildcard_matches(negated, host) => return false,
+            Some(_) => {}
+            None => matched |= wildcard_matches(pattern, host),
+        }
+    }
+    matched
+}
+
+fn wildcard_matches(pattern: &str, host: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, rest)) => {
+            host.starts_with(prefix)
+                && host[prefix.len()..]
+                    .char_indices()
+                    .map(|(i, _)| i)
+                    .chain([host.len() - prefix.len()])
+                    .any(|i| wildcard_matches(rest, &host[prefix.len() + i..]))
+        }
+        None => pattern == host,
+    }
+}
+
+/// [`ObjectStore`] backed by a remote filesystem accessed over SFTP
+///
+/// Object paths are absolute paths on the server.
+pub struct SftpStore {
+    sftp: Arc<Sftp>,
+    name: String,
+    // The session must outlive the sftp channel
+    _session: Session,
+}
+
+impl SftpStore {
+    fn connect(target: SshTarget) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let tcp = TcpStream::connect((target.host.as_str(), target.port))?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+
+        verify_host_key(&session, &target)?;
+
+        if let Some(password) = &target.password {
+            session.userauth_password(&target.user, password)?;
+        }
+        if !session.authenticated() {
+            let _ = session.userauth_agent(&target.user);
+        }
+        for identity in &target.identity_files {
+            if session.authenticated() {
+                break;
+            }
+            let _ = session.userauth_pubkey_file(&target.user, None, identity, None);
+        }
+        if !session.authenticated() {
+            return Err("no authentication method succeeded".into());
+        }
+
+        let sftp = session.sftp()?;
+        Ok(SftpStore {
+            sftp: Arc::new(sftp),
+            name: format!("{}@{}:{}", target.user, target.host, target.port),
+            _session: session,
+        })
+    }
+
+    /// Run a blocking sftp operation on the blocking thread pool
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Sftp) -> Result<T> + Send + 'static,
+    {
+        let sftp = Arc::clone(&self.sftp);
+        tokio::task::spawn_blocking(move || f(&sftp)).await?
+    }
+}
+
+fn verify_host_key(
+    session: &Session,
+    target: &SshTarget,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (key, _) = session.host_key().ok_or("server sent no host key")?;
+    let mut known_hosts = session.known_hosts()?;
+    if let Some(dir) = &target.ssh_dir {
+        let _ = known_hosts.read_file(&dir.join("known_hosts"), KnownHostFileKind::OpenSSH);
+    }
+    match known_hosts.check_port(&target.host, target.port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err("host key does not match known_hosts".into()),
+        CheckResult::NotFound | CheckResult::Failure => Err("host is not in known_hosts".into()),
+    }
+}
+
+impl Display for SftpStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SftpStore({})", self.name)
+    }
+}
+
+impl Debug for SftpStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+fn remote_path(location: &Path) -> PathBuf {
+    PathBuf::from(format!("/{location}"))
+}
+
+fn location(path: &std::path::Path) -> Path {
+    Path::from(path.to_string_lossy().trim_start_matches('/'))
+}
+
+fn object_meta(location: Path, stat: &FileStat) -> ObjectMeta {
+    let mtime = stat.mtime.unwrap_or_default();
+    let size = stat.size.unwrap_or_default();
+    ObjectMeta {
+        location,
+        last_modified: Utc
+            .timestamp_opt(mtime as i64, 0)
+            .single()
+            .unwrap_or(DateTime::UNIX_EPOCH),
+        size,
+        e_tag: Some(format!("{mtime:x}-{size:x}")),
+        version: None,
+    }
+}
+
+fn map_err(e: ssh2::Error, location: &Path) -> Error {
+    match e.code() {
+        // LIBSSH2_FX_NO_SUCH_FILE
+        ErrorCode::SFTP(2) => Error::NotFound {
+            path: location.to_string(),
+            source: Box::new(e),
+        },
+        // LIBSSH2_FX_PERMISSION_DENIED
+        ErrorCode::SFTP(3) => Error::PermissionDenied {
+            path: location.to_string(),
+            source: Box::new(e),
+        },
+        _ => Error::Generic {
+            store: STORE,
+            source: Box::new(e),
+        },
+    }
+}
+
+fn io_err(e: std::io::Error) -> Error {
+    Error::Generic {
+        store: STORE,
+        source: Box::new(e),
+    }
+}
+
+fn stat_file(sftp: &Sftp, location: &Path) -> Result<ObjectMeta> {
+    let stat = sftp
+        .stat(&remote_path(location))
+        .map_err(|e| map_err(e, location))?;
+    if stat.is_dir() {
+        return Err(Error::NotFound {
+            path: location.to_string(),
+            source: "is a directory".into(),
+        });
+    }
+    Ok(object_meta(location.clone(), &stat))
+}
+
+/// Create all missing parent directories of `location`
+fn create_parent_dirs(sftp: &Sftp, location: &Path) -> Result<()> {
+    let mut dir = PathBuf::from("/");
+    let parts: Vec<_> = location.parts().collect();
+    for part in parts.iter().take(parts.len().saturating_sub(1)) {
+        dir.push(part.as_ref());
+        if sftp.stat(&dir).is_err() {
+            sftp.mkdir(&dir, 0o755)
+                .map_err(|e| map_err(e, &location.clone()))?;
+        }
+    }
+    Ok(())
+}
+
+fn temp_location(location: &Path) -> Path {
+    let nonce = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    Path::from(format!("{location}#{nonce:x}"))
+}
+
+/// Move a fully written temporary file over its destination
+fn commit(sftp: &Sftp, temp: &Path, location: &Path, mode: &PutMode) -> Result<()> {
+    let (from, to) = (remote_path(temp), remote_path(location));
+    let overwrite = matches!(mode, PutMode::Overwrite);
+    let flags = if overwrite {
+        RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE
+    } else {
+        RenameFlags::ATOMIC | RenameFlags::NATIVE
+    };
+    if let Err(e) = sftp.rename(&from, &to, Some(flags)) {
+        // SFTPv3 servers refuse to rename over an existing file
+        if overwrite && sftp.stat(&to).is_ok() {
+            sftp.unlink(&to).map_err(|e| map_err(e, location))?;
+            sftp.rename(&from, &to, None)
+                .map_err(|e| map_err(e, location))?;
+        } else {
+            let _ = sftp.unlink(&from);
+            return Err(map_err(e, location));
+        }
+    }
+    Ok(())
+}
+
+fn write_file(sftp: &Sftp, location: &Path, payload: PutPayload, mode: PutMode) -> Result<()> {
+    if matches!(mode, PutMode::Create) && sftp.stat(&remote_path(location)).is_ok() {
+        return Err(Error::AlreadyExists {
+            path: location.to_string(),
+            source: "file exists".into(),
+        });
+    }
+    create_parent_dirs(sftp, location)?;
+    let temp = temp_location(location);
+    let mut file = sftp
+        .create(&remote_path(&temp))
+        .map_err(|e| map_err(e, location))?;
+    for chunk in payload.iter() {
+        file.write_all(chunk).map_err(io_err)?;
+    }
+    drop(file);
+    commit(sftp, &temp, location, &mode)
+}
+
+fn walk(sftp: &Sftp, dir: &std::path::Path, objects: &mut Vec<ObjectMeta>) -> Result<()> {
+    let entries = match sftp.readdir(dir) {
+        Ok(entries) => entries,
+        Err(e) if matches!(e.code(), ErrorCode::SFTP(2)) => return Ok(()),
+        Err(e) => return Err(map_err(e, &location(dir))),
+    };
+    for (path, stat) in entries {
+        if stat.is_dir() {
+            walk(sftp, &path, objects)?;
+        } else if stat.is_file() {
+            objects.push(object_meta(location(&path), &stat));
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl ObjectStore for SftpStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        if matches!(opts.mode, PutMode::Update(_)) {
+            return Err(Error::NotImplemented);
+        }
+        let location = location.clone();
+        self.blocking(move |sftp| {
+            write_file(sftp, &location, payload, opts.mode)?;
+            let meta = stat_file(sftp, &location)?;
+            Ok(PutResult {
+                e_tag: meta.e_tag,
+                version: None,
+            })
+        })
+        .await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        _opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        let temp = temp_location(location);
+        let file = {
+            let (location, temp) = (location.clone(), temp.clone());
+            self.blocking(move |sftp| {
+                create_parent_dirs(sftp, &location)?;
+                sftp.create(&remote_path(&temp))
+                    .map_err(|e| map_err(e, &location))
+            })
+            .await?
+        };
+
+        Ok(Box::new(SftpUpload {
+            sftp: Arc::clone(&self.sftp),
+            location: location.clone(),
+            temp,
+            file: Arc::new(Mutex::new(Some(file))),
+            offset: Arc::new(AtomicU64::new(0)),
+        }))
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let location = location.clone();
+        let (meta, range, file) = self
+            .blocking(move |sftp| {
+                let meta = stat_file(sftp, &location)?;
+                check_preconditions(&options, &meta)?;
+                let range = match &options.range {
+                    Some(range) => resolve_range(STORE, range, meta.size)?,
+                    None => 0..meta.size,
+                };
+                let file = if options.head {
+                    None
+                } else {
+                    let mut file = sftp
+                        .open(remote_path(&location))
+                        .map_err(|e| map_err(e, &location))?;
+                    file.seek(SeekFrom::Start(range.start)).map_err(io_err)?;
+                    Some(file)
+                };
+                Ok((meta, range, file))
+            })
+            .await?;
+
+        let payload = match file {
+            None => futures::stream::empty().boxed(),
+            Some(file) => {
+                let remaining = range.end - range.start;
+                futures::stream::try_unfold((file, remaining), |(mut file, remaining)| async move {
+                    if remaining == 0 {
+                        return Ok(None);
+                    }
+                    tokio::task::spawn_blocking(move || {
+                        let mut buf = vec![0; CHUNK_SIZE.min(remaining as usize)];
+                        let n = file.read(&mut buf).map_err(io_err)?;
+                        if n == 0 {
+                            return Ok(None);
+                        }
+                        buf.truncate(n);
+                        Ok(Some((Bytes::from(buf), (file, remaining - n as u64))))
+                    })
+                    .await?
+                })
+                .boxed()
+            }
+        };
+
+        Ok(GetResult {
+            payload: GetResultPayload::Stream(payload),
+            meta,
+            range,
+            attributes: Attributes::default(),
+        })
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        let location = location.clone();
+        self.blocking(move |sftp| {
+            sftp.unlink(&remote_path(&location))
+                .map_err(|e| map_err(e, &location))
+        })
+        .await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, Result<ObjectMeta>> {
+        let sftp = Arc::clone(&self.sftp);
+        let dir = prefix
+            .map(remote_path)
+            .unwrap_or_else(|| PathBuf::from("/"));
+        futures::stream::once(async move {
+            let objects = tokio::task::spawn_blocking(move || {
+                let mut objects = vec![];
+                walk(&sftp, &dir, &mut objects)?;
+                Ok::<_, Error>(objects)
+            })
+            .await??;
+            Ok::<_, Error>(futures::stream::iter(objects.into_iter().map(Ok)))
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        let dir = prefix
+            .map(remote_path)
+            .unwrap_or_else(|| PathBuf::from("/"));
+        self.blocking(move |sftp| {
+            let entries = match sftp.readdir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if matches!(e.code(), ErrorCode::SFTP(2)) => vec![],
+                Err(e) => return Err(map_err(e, &location(&dir))),
+            };
+            let mut result = ListResult {
+                common_prefixes: vec![],
+                objects: vec![],
+            };
+            for (path, stat) in entries {
+                if stat.is_dir() {
+                    result.common_prefixes.push(location(&path));
+                } else if stat.is_file() {
+                    result.objects.push(object_meta(location(&path), &stat));
+                }
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        let (from, to) = (from.clone(), to.clone());
+        self.blocking(move |sftp| copy_file(sftp, &from, &to, PutMode::Overwrite))
+            .await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        let (from, to) = (from.clone(), to.clone());
+        self.blocking(move |sftp| copy_file(sftp, &from, &to, PutMode::Create))
+            .await
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let (from, to) = (from.clone(), to.clone());
+        self.blocking(move |sftp| {
+            create_parent_dirs(sftp, &to)?;
+            commit(sftp, &from, &to, &PutMode::Overwrite)
+        })
+        .await
+    }
+}
+
+/// SFTP has no server side copy, so data is streamed through the plugin
+fn copy_file(sftp: &Sftp, from: &Path, to: &Path, mode: PutMode) -> Result<()> {
+    if matches!(mode, PutMode::Create) && sftp.stat(&remote_path(to)).is_ok() {
+        return Err(Error::AlreadyExists {
+            path: to.to_string(),
+            source: "file exists".into(),
+        });
+    }
+    let mut source = sftp.open(remote_path(from)).map_err(|e| map_err(e, from))?;
+    create_parent_dirs(sftp, to)?;
+    let temp = temp_location(to);
+    let mut dest = sftp
+        .create(&remote_path(&temp))
+        .map_err(|e| map_err(e, to))?;
+    std::io::copy(&mut source, &mut dest).map_err(io_err)?;
+    drop(dest);
+    commit(sftp, &temp, to, &mode)
+}
+
+/// Multipart upload writing parts at their offset in a temporary file, which
+/// replaces the destination when the upload completes
+struct SftpUpload {
+    sftp: Arc<Sftp>,
+    location: Path,
+    temp: Path,
+    file: Arc<Mutex<Option<ssh2::File>>>,
+    offset: Arc<AtomicU64>,
+}
+
+impl Debug for SftpUpload {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SftpUpload({})", self.location)
+    }
+}
+
+#[async_trait]
+impl MultipartUpload for SftpUpload {
+    fn put_part(&mut self, data: PutPayload) -> UploadPart {
+        let offset = self
+            .offset
+            .fetch_add(data.content_length() as u64, Ordering::SeqCst);
+        let file = Arc::clone(&self.file);
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let mut file = file.lock().map_err(|e| Error::Generic {
+                    store: STORE,
+                    source: e.to_string().into(),
+                })?;
+                let file = file.as_mut().ok_or_else(|| Error::Generic {
+                    store: STORE,
+                    source: "upload already finished".into(),
+                })?;
+                file.seek(SeekFrom::Start(offset)).map_err(io_err)?;
+                for chunk in data.iter() {
+                    file.write_all(chunk).map_err(io_err)?;
+                }
+                Ok(())
+            })
+            .await?
+        })
+    }
+
+    async fn complete(&mut self) -> Result<PutResult> {
+        let sftp = Arc::clone(&self.sftp);
+        let file = Arc::clone(&self.file);
+        let (temp, location) = (self.temp.clone(), self.location.clone());
+        tokio::task::spawn_blocking(move || {
+            if let Ok(mut file) = file.lock() {
+                file.take();
+            }
+            commit(&sftp, &temp, &location, &PutMode::Overwrite)?;
+            let meta = stat_file(&sftp, &location)?;
+            Ok(PutResult {
+                e_tag: meta.e_tag,
+                version: None,
+            })
+        })
+        .await?
+    }
+
+    async fn abort(&mut self) -> Result<()> {
+        let sftp = Arc::clone(&self.sftp);
+        let file = Arc::clone(&self.file);
+        let temp = self.temp.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Ok(mut file) = file.lock() {
+                file.take();
+            }
+            sftp.unlink(&remote_path(&temp))
+                .map_err(|e| map_err(e, &temp))
+        })
+        .await?
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use http::{HeaderValue, Method, Request, StatusCode};
+use object_store::{
+    Error, Result,
+    aws::{AwsAuthorizer, AwsCredential, AwsCredentialProvider},
+    client::{HttpClient, HttpRequest, HttpRequestBody},
+    path::Path,
+};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use sha2::{Digest, Sha256};
+
+/// Characters left as they are in query values, as in SigV4 canonical requests
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+const KEY: &AsciiSet = &UNRESERVED.remove(b'/');
+
+const S3: &str = "S3";
+
+/// Client for the AWS APIs object_store has no support for, signing requests like the store
+/// signs its own. They are sent with a client from the same connector, so retries, limits, and
+/// metrics apply to them too.
+#[derive(Debug)]
+pub struct SignedClient {
+    client: HttpClient,
+    /// Region of the bucket the client was created for
+    region: String,
+    /// Credentials signing the requests, none when they carry a bearer token instead
+    credentials: Option<AwsCredentialProvider>,
+}
+
+impl SignedClient {
+    pub fn new(
+        client: HttpClient,
+        region: String,
+        credentials: Option<AwsCredentialProvider>,
+    ) -> Self {
+        SignedClient {
+            client,
+            region,
+            credentials,
+        }
+    }
+
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    /// Sign `url` for `method` in its query, so it can be requested without credentials for
+    /// `expires_in`, with the `headers` given, which must be sent as they are
+    pub async fn presign(
+        &self,
+        method: &Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        expires_in: Duration,
+    ) -> Result<String> {
+        let credentials = self
+            .credentials
+            .as_ref()
+            .ok_or_else(|| generic(S3, "Urls are only presigned with AWS credentials"))?;
+        let credential = credentials.get_credential().await?;
+        let url = url::Url::parse(url).map_err(|e| generic(S3, e))?;
+        Ok(presign_url(
+            method,
+            url,
+            headers,
+            expires_in,
+            &credential,
+            &self.region,
+            Utc::now(),
+        ))
+    }
+
+    /// Sign `request` for `service` in `region` and send it, returning the body of a successful
+    /// response. Errors are reported for `store`.
+    pub async fn send(
+        &self,
+        mut request: HttpRequest,
+        store: &'static str,
+        service: &str,
+        region: &str,
+    ) -> Result<Bytes> {
+        if let Some(credentials) = &self.credentials {
+            let credential = credentials.get_credential().await?;
+            AwsAuthorizer::new(&credential, service, region).authorize(&mut request, None);
+        }
+        let url = request.uri().to_string();
+        let response = self
+            .client
+            .execute(request)
+            .await
+            .map_err(|e| generic(store, e))?;
+        let status = response.status();
+        let body = response
+            .into_body()
+            .bytes()
+            .await
+            .map_err(|e| generic(store, e))?;
+        if !status.is_success() {
+            return Err(status_error(store, status, url, &body));
+        }
+        Ok(body)
+    }
+}
+
+/// Requests to the S3 API of a bucket, for the operations object_store has no API for
+#[derive(Debug)]
+pub struct BucketClient {
+    client: Arc<SignedClient>,
+    /// Url of the bucket, e.g. `https://s3.us-east-1.amazonaws.com/mybucket`
+    endpoint: String,
+    bucket: String,
+}
+
+impl BucketClient {
+    pub fn new(client: Arc<SignedClient>, endpoint: String, bucket: String) -> Self {
+        BucketClient {
+            client,
+            endpoint,
+            bucket,
+        }
+    }
+
+    pub fn signed(&self) -> &Arc<SignedClient> {
+        &self.client
+    }
+
+    /// Url of the bucket, or of an object in it, with a query
+    pub fn url(&self, location: Option<&Path>, query: &[(&str, &str)]) -> String {
+        let mut url = self.endpoint.clone();
+        if let Some(location) = location {
+            url.push('/');
+            url.extend(utf8_percent_encode(location.as_ref(), KEY));
+        }
+        for (i, (name, value)) in query.iter().enumerate() {
+            url.push(if i == 0 { '?' } else { '&' });
+            url.push_str(name);
+            url.push('=');
+            url.extend(utf8_percent_encode(value, UNRESERVED));
+        }
+        url
+    }
+
+    /// Value of the `x-amz-copy-source` header for an object of the bucket, or a version of it
+    pub fn copy_source(&self, location: &Path, version_id: Option<&str>) -> String {
+        let mut source = format!(
+            "/{}/{}",
+            self.bucket,
+            utf8_percent_encode(location.as_ref(), KEY)
+        );
+        if let Some(version_id) = version_id {
+            source.push_str("?versionId=");
+            source.extend(utf8_percent_encode(version_id, UNRESERVED));
+        }
+        source
+    }
+
+    pub async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, String)],
+        body: HttpRequestBody,
+    ) -> Result<Bytes> {
+        let mut request = Request::builder().method(method).uri(url);
+        for (name, value) in headers {
+            request = request.header(*name, value);
+        }
+        let request = request.body(body).map_err(|e| generic(S3, e))?;
+        let region = self.client.region();
+        self.client.send(request, S3, "s3", region).await
+    }
+
+    /// Send a request that still responds with 200 when it fails after it started, with an
+    /// error document instead of the result, like copies and completing multipart uploads
+    pub async fn send_checked(
+        &self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, String)],
+        body: HttpRequestBody,
+    ) -> Result<Bytes> {
+        let body = self.send(method, url, headers, body).await?;
+        let failed = std::str::from_utf8(&body)
+            .ok()
+            .and_then(|body| roxmltree::Document::parse(body).ok())
+            .is_some_and(|document| document.root_element().has_tag_name("Error"));
+        if failed {
+            return Err(status_error(S3, StatusCode::OK, url.to_string(), &body));
+        }
+        Ok(body)
+    }
+}
+
+/// Storage class of the objects a bucket's store writes, which object_store has no option for.
+///
+/// The `x-amz-storage-class` header is added to uploads and the creation of multipart uploads
+/// once the store has signed them, so they are signed again to cover it.
+#[derive(Debug)]
+pub struct StorageClass {
+    value: HeaderValue,
+    region: String,
+    /// Credentials of the store, none when its requests carry a bearer token instead
+    credentials: OnceLock<AwsCredentialProvider>,
+}
+
+impl StorageClass {
+    pub fn new(value: HeaderValue, region: String) -> Self {
+        StorageClass {
+            value,
+            region,
+            credentials: OnceLock::new(),
+        }
+    }
+
+    /// Sign the requests with `credentials`, those of the store built with the connector
+    pub fn set_credentials(&self, credentials: AwsCredentialProvider) {
+        let _ = self.credentials.set(credentials);
+    }
+
+    /// Add the storage class to `request` if it writes an object
+    pub async fn apply(&self, request: &mut HttpRequest) -> Result<()> {
+        if !is_object_write(request) {
+            return Ok(());
+        }
+        request
+            .headers_mut()
+            .insert("x-amz-storage-class", self.value.clone());
+        let Some(credentials) = self.credentials.get() else {
+            return Ok(());
+        };
+        let credential = credentials.get_credential().await?;
+        // The payload is signed as it was by the store
+        let digest = request
+            .headers()
+            .get("x-amz-content-sha256")
+            .and_then(|digest| unhex(digest.to_str().ok()?));
+        AwsAuthorizer::new(&credential, "s3", &self.region)
+            .with_sign_payload(digest.is_some())
+            .authorize(request, digest.as_deref());
+        Ok(())
+    }
+}
+
+/// Whether `request` is a PutObject, CopyObject, or CreateMultipartUpload, rather than e.g. an
+/// UploadPart or a PUT of a subresource like `?tagging`
+fn is_object_write(request: &HttpRequest) -> bool {
+    let query = request.uri().query();
+    match *request.method() {
+        Method::PUT => query.is_none_or(str::is_empty),
+        Method::POST => query.is_some_and(|query| {
+            query
+                .split('&')
+                .any(|pair| pair.split('=').next() == Some("uploads"))
+        }),
+        _ => false,
+    }
+}
+
+/// Presign `url` as of `now`, see [`SignedClient::presign`]
+fn presign_url(
+    method: &Method,
+    url: url::Url,
+    headers: &[(&str, &str)],
+    expires_in: Duration,
+    credential: &AwsCredential,
+    region: &str,
+    now: DateTime<Utc>,
+) -> String {
+    let host = match url.port() {
+        Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let date = now.format("%Y%m%d").to_string();
+    let scope = format!("{date}/{region}/s3/aws4_request");
+
+    // https://docs.aws.amazon.com/AmazonS3/latest/API/sigv4-query-string-auth.html
+    let mut headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.trim().to_string()))
+        .chain([("host".to_string(), host)])
+        .collect();
+    headers.sort();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let mut query: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    query.extend([
+        ("X-Amz-Algorithm".into(), "AWS4-HMAC-SHA256".into()),
+        (
+            "X-Amz-Credential".into(),
+            format!("{}/{scope}", credential.key_id),
+        ),
+        (
+            "X-Amz-Date".into(),
+            now.format("%Y%m%dT%H%M%SZ").to_string(),
+        ),
+        ("X-Amz-Expires".into(), expires_in.as_secs().to_string()),
+        ("X-Amz-SignedHeaders".into(), signed_headers.clone()),
+    ]);
+    if let Some(token) = &credential.token {
+        query.push(("X-Amz-Security-Token".into(), token.clone()));
+    }
+    let mut query: Vec<String> = query
+        .iter()
+        .map(|(name, value)| {
+            let name = utf8_percent_encode(name, UNRESERVED);
+            format!("{name}={}", utf8_percent_encode(value, UNRESERVED))
+        })
+        .collect();
+    query.sort();
+    let query = query.join("&");
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{value}\n"))
+        .collect();
+    let canonical_request = format!(
+        "{method}\n{}\n{query}\n{canonical_headers}\n{signed_headers}\nUNSIGNED-PAYLOAD",
+        url.path()
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{scope}\n{}",
+        now.format("%Y%m%dT%H%M%SZ"),
+        hex(&Sha256::digest(canonical_request))
+    );
+    let key = [date.as_str(), region, "s3", "aws4_request"]
+        .into_iter()
+        .fold(
+            format!("AWS4{}", credential.secret_key).into_bytes(),
+            |key, part| hmac_sha256(&key, part.as_bytes()),
+        );
+    let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+    let mut url = url;
+    url.set_query(Some(&format!("{query}&X-Amz-Signature={signature}")));
+    url.to_string()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Bytes of a SHA-256 in hex, none for other values like `UNSIGNED-PAYLOAD`
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() != 64 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub fn generic(store: &'static str, source: impl ToString) -> Error {
+    Error::Generic {
+        store,
+        source: source.to_string().into(),
+    }
+}
+
+/// Error for a failed response, with the code and message of the XML error documents of S3 or
+/// the JSON errors of newer APIs
+pub fn status_error(store: &'static str, status: StatusCode, url: String, body: &[u8]) -> Error {
+    let body = String::from_utf8_lossy(body);
+    let xml = || {
+        let document = roxmltree::Document::parse(&body).ok()?;
+        let text = |name| {
+            let element = document.root_element();
+            let child = element.children().find(|child| child.has_tag_name(name));
+            child.and_then(|child| child.text()).unwrap_or_default()
+        };
+        Some(format!("{}: {}", text("Code"), text("Message")))
+    };
+    let json = || {
+        let error: serde_json::Value = serde_json::from_str(&body).ok()?;
+        let code = error["__type"].as_str()?;
+        // Codes are qualified by the namespace of the API, e.g. `com.amazonaws.sqs#`
+        let code = code.rsplit('#').next().unwrap_or(code);
+        let message = error["message"].as_str().or(error["Message"].as_str());
+        Some(format!("{code}: {}", message.unwrap_or_default()))
+    };
+    let message = xml()
+        .or_else(json)
+        .unwrap_or_else(|| format!("{status}: {body}"));
+    let source = message.into();
+    match status {
+        StatusCode::NOT_FOUND => Error::NotFound { path: url, source },
+        StatusCode::FORBIDDEN => Error::PermissionDenied { path: url, source },
+        StatusCode::UNAUTHORIZED => Error::Unauthenticated { path: url, source },
+        _ => Error::Generic { store, source },
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http::{Method, Request, header::CONTENT_TYPE};
+use object_store::{Result, client::HttpRequestBody};
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+use super::signed::{self, SignedClient};
+
+const STORE: &str = "SQS";
+
+/// Longest an SQS receive can wait for messages
+pub const MAX_WAIT_SECONDS: u64 = 20;
+
+/// Receives and deletes the messages of an SQS queue, with the JSON protocol
+#[derive(Debug)]
+pub struct SqsClient {
+    client: Arc<SignedClient>,
+    queue: Url,
+    /// Region of the queue, from its url or else the one of the bucket the client signs for
+    region: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Message {
+    pub receipt_handle: String,
+    pub body: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Received {
+    #[serde(default)]
+    messages: Vec<Message>,
+}
+
+impl SqsClient {
+    pub fn new(client: Arc<SignedClient>, queue: Url) -> Self {
+        let region = queue_region(&queue).unwrap_or_else(|| client.region().to_string());
+        SqsClient {
+            client,
+            queue,
+            region,
+        }
+    }
+
+    /// Up to 10 messages of the queue, waiting up to `wait_seconds` for the first to arrive
+    pub async fn receive(&self, wait_seconds: u64) -> Result<Vec<Message>> {
+        let body = json!({
+            "QueueUrl": self.queue.as_str(),
+            "MaxNumberOfMessages": 10,
+            "WaitTimeSeconds": wait_seconds.min(MAX_WAIT_SECONDS),
+        });
+        let body = self.send("ReceiveMessage", body).await?;
+        let received: Received =
+            serde_json::from_slice(&body).map_err(|e| signed::generic(STORE, e))?;
+        Ok(received.messages)
+    }
+
+    /// Delete a received message, so it isn't received again
+    pub async fn delete(&self, receipt_handle: &str) -> Result<()> {
+        let body = json!({
+            "QueueUrl": self.queue.as_str(),
+            "ReceiptHandle": receipt_handle,
+        });
+        self.send("DeleteMessage", body).await?;
+        Ok(())
+    }
+
+    async fn send(&self, action: &str, body: serde_json::Value) -> Result<Bytes> {
+        // Actions are posted to the endpoint of the queue, which is named in the body
+        let mut endpoint = self.queue.clone();
+        endpoint.set_path("/");
+        endpoint.set_query(None);
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(endpoint.as_str())
+            .header(CONTENT_TYPE, "application/x-amz-json-1.0")
+            .header("x-amz-target", format!("AmazonSQS.{action}"))
+            .body(HttpRequestBody::from(body.to_string()))
+            .map_err(|e| signed::generic(STORE, e))?;
+        self.client.send(request, STORE, "sqs", &self.region).await
+    }
+}
+
+/// Region in the host of an AWS queue url, `sqs.<region>.amazonaws.com` or the legacy
+/// `<region>.queue.amazonaws.com`
+fn queue_region(queue: &Url) -> Option<String> {
+    let host = queue.host_str()?.strip_suffix(".amazonaws.com")?;
+    let region = match host.strip_prefix("sqs.") {
+        Some(region) => region,
+        None => host.strip_suffix(".queue")?,
+    };
+    (!region.is_empty() && !region.contains('.')).then(|| region.to_string())
+}
@@ -0,0 +1,85 @@
+use std::{
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+
+tokio::task_local! {
+    /// Counters of the command run being executed, see [`scoped`]
+    static COUNTERS: Arc<Counters>;
+}
+
+#[derive(Debug, Default)]
+pub struct Counters {
+    requests: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+/// Totals of a finished command run
+#[derive(Debug, Clone)]
+pub struct Stats {
+    pub command: String,
+    pub started: DateTime<Local>,
+    pub duration: Duration,
+    pub success: bool,
+    /// HTTP requests, counting retried ones once
+    pub requests: u64,
+    /// Request body bytes
+    pub bytes_sent: u64,
+    /// Response body bytes, by their `Content-Length`
+    pub bytes_received: u64,
+}
+
+/// Count the requests made while polling `future`, returning its output and the totals.
+///
+/// Tasks the future spawns are counted too when they carry the counters over with [`current`]
+/// and [`in_run`].
+pub async fn scoped<T, E, F: Future<Output = Result<T, E>>>(
+    command: String,
+    future: F,
+) -> (F::Output, Stats) {
+    let counters = Arc::new(Counters::default());
+    let started = Local::now();
+    let start = Instant::now();
+    let result = COUNTERS.scope(Arc::clone(&counters), future).await;
+    let stats = Stats {
+        command,
+        started,
+        duration: start.elapsed(),
+        success: result.is_ok(),
+        requests: counters.requests.load(Ordering::Relaxed),
+        bytes_sent: counters.bytes_sent.load(Ordering::Relaxed),
+        bytes_received: counters.bytes_received.load(Ordering::Relaxed),
+    };
+    (result, stats)
+}
+
+/// Counters of the current command run, to carry them into work done outside of [`scoped`]
+pub fn current() -> Option<Arc<Counters>> {
+    COUNTERS.try_with(Arc::clone).ok()
+}
+
+/// Count the requests made while polling `future` with the `counters` of a run, see [`current`]
+pub async fn in_run<F: Future>(counters: Option<Arc<Counters>>, future: F) -> F::Output {
+    match counters {
+        Some(counters) => COUNTERS.scope(counters, future).await,
+        None => future.await,
+    }
+}
+
+/// Count a finished request
+pub fn request(sent: u64, received: u64) {
+    let _ = COUNTERS.try_with(|counters| {
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+        counters.bytes_sent.fetch_add(sent, Ordering::Relaxed);
+        counters
+            .bytes_received
+            .fetch_add(received, Ordering::Relaxed);
+    });
+}
@@ -0,0 +1,213 @@
+use std::{
+    fmt::Display,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use http::{Method, Uri};
+use log::warn;
+use serde_json::{Value, json};
+
+use crate::config::{Config, TracingConfig};
+
+/// Time an export may take before it is given up, so an unreachable collector doesn't hold up
+/// commands
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SPAN_KIND_INTERNAL: u8 = 1;
+const SPAN_KIND_CLIENT: u8 = 3;
+const STATUS_OK: u8 = 1;
+const STATUS_ERROR: u8 = 2;
+
+tokio::task_local! {
+    /// Spans of the command run being executed, see [`scoped`]
+    static TRACE: Arc<Trace>;
+}
+
+/// Spans recorded during a command run, exported together when it finishes
+pub struct Trace {
+    trace_id: String,
+    /// Span of the command, the parent of its requests
+    span_id: String,
+    spans: Mutex<Vec<Value>>,
+}
+
+/// Trace `future` as a span named `name`, with a span for each request it makes, and export
+/// them to the configured collector once it finishes. `traceparent` is a W3C trace context,
+/// e.g. from the `TRACEPARENT` environment variable, continuing the trace of the caller.
+///
+/// Tasks the future spawns are traced too when they carry the trace over with [`current`] and
+/// [`in_run`]. Failed exports are only logged, as they shouldn't fail the command.
+pub async fn scoped<T, E: Display, F: Future<Output = Result<T, E>>>(
+    config: &Config,
+    traceparent: Option<String>,
+    name: String,
+    future: F,
+) -> F::Output {
+    let Some(tracing) = &config.tracing else {
+        return future.await;
+    };
+    let parent = traceparent.as_deref().and_then(parse_traceparent);
+    let trace = Arc::new(Trace {
+        trace_id: match &parent {
+            Some((trace_id, _)) => trace_id.clone(),
+            None => format!("{:032x}", fastrand::u128(1..)),
+        },
+        span_id: span_id(),
+        spans: Mutex::new(vec![]),
+    });
+
+    let started = SystemTime::now();
+    let result = TRACE.scope(Arc::clone(&trace), future).await;
+    let status = match &result {
+        Ok(_) => json!({ "code": STATUS_OK }),
+        Err(e) => json!({ "code": STATUS_ERROR, "message": e.to_string() }),
+    };
+    let mut span = json!({
+        "traceId": trace.trace_id,
+        "spanId": trace.span_id,
+        "name": name,
+        "kind": SPAN_KIND_INTERNAL,
+        "startTimeUnixNano": unix_nanos(started),
+        "endTimeUnixNano": unix_nanos(SystemTime::now()),
+        "attributes": [attribute("cloud.command", name.as_str())],
+        "status": status,
+    });
+    if let Some((_, parent_span_id)) = parent {
+        span["parentSpanId"] = parent_span_id.into();
+    }
+    let mut spans = std::mem::take(&mut *trace.spans.lock().expect("not poisoned"));
+    spans.push(span);
+
+    if let Err(e) = export(config, tracing, spans).await {
+        warn!("Could not export traces to {}: {e}", tracing.endpoint);
+    }
+    result
+}
+
+/// Whether the current command run is traced
+pub fn enabled() -> bool {
+    TRACE.try_with(|_| ()).is_ok()
+}
+
+/// Trace of the current command run, to carry it into work done outside of [`scoped`]
+pub fn current() -> Option<Arc<Trace>> {
+    TRACE.try_with(Arc::clone).ok()
+}
+
+/// Trace the requests made while polling `future` in the `trace` of a run, see [`current`]
+pub async fn in_run<F: Future>(trace: Option<Arc<Trace>>, future: F) -> F::Output {
+    match trace {
+        Some(trace) => TRACE.scope(trace, future).await,
+        None => future.await,
+    }
+}
+
+/// Record a finished request with its status code or error
+pub fn request(
+    method: &Method,
+    uri: &Uri,
+    outcome: Result<u16, String>,
+    started: SystemTime,
+    elapsed: Duration,
+) {
+    let _ = TRACE.try_with(|trace| {
+        // Queries are left out, as presigned urls carry credentials in them
+        let scheme = uri.scheme_str().unwrap_or("https");
+        let host = uri.host().unwrap_or_default();
+        let authority = uri.authority().map(|a| a.as_str()).unwrap_or_default();
+        let mut attributes = vec![
+            attribute("http.request.method", method.as_str()),
+            attribute("url.full", &format!("{scheme}://{authority}{}", uri.path())),
+            attribute("server.address", host),
+        ];
+        let status = match outcome {
+            Ok(code) => {
+                attributes.push(json!({
+                    "key": "http.response.status_code",
+                    "value": { "intValue": code.to_string() },
+                }));
+                match code {
+                    400.. => {
+                        attributes.push(attribute("error.type", &code.to_string()));
+                        json!({ "code": STATUS_ERROR })
+                    }
+                    _ => json!({}),
+                }
+            }
+            Err(e) => json!({ "code": STATUS_ERROR, "message": e }),
+        };
+        let span = json!({
+            "traceId": trace.trace_id,
+            "spanId": span_id(),
+            "parentSpanId": trace.span_id,
+            "name": method.as_str(),
+            "kind": SPAN_KIND_CLIENT,
+            "startTimeUnixNano": unix_nanos(started),
+            "endTimeUnixNano": unix_nanos(started + elapsed),
+            "attributes": attributes,
+            "status": status,
+        });
+        trace.spans.lock().expect("not poisoned").push(span);
+    });
+}
+
+/// Post spans to the collector with the OTLP/HTTP JSON encoding
+async fn export(
+    config: &Config,
+    tracing: &TracingConfig,
+    spans: Vec<Value>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let service_name = tracing.service_name.as_deref().unwrap_or("nu_plugin_cloud");
+    let body = json!({
+        "resourceSpans": [{
+            "resource": { "attributes": [attribute("service.name", service_name)] },
+            "scopeSpans": [{
+                "scope": { "name": "nu_plugin_cloud", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    });
+    let client = config.client_builder()?.timeout(EXPORT_TIMEOUT).build()?;
+    let mut request = client
+        .post(format!(
+            "{}/v1/traces",
+            tracing.endpoint.trim_end_matches('/')
+        ))
+        .json(&body);
+    for (name, value) in tracing.headers.iter().flatten() {
+        request = request.header(name, value);
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// Trace and parent span ids of a `traceparent` header value, `00-<trace id>-<span id>-<flags>`
+fn parse_traceparent(traceparent: &str) -> Option<(String, String)> {
+    let mut parts = traceparent.trim().split('-');
+    let (_version, trace_id, span_id) = (parts.next()?, parts.next()?, parts.next()?);
+    let valid = |id: &str, len: usize| {
+        id.len() == len
+            && id.bytes().all(|b| b.is_ascii_hexdigit())
+            && id.bytes().any(|b| b != b'0')
+    };
+    (valid(trace_id, 32) && valid(span_id, 16))
+        .then(|| (trace_id.to_ascii_lowercase(), span_id.to_ascii_lowercase()))
+}
+
+fn span_id() -> String {
+    format!("{:016x}", fastrand::u64(1..))
+}
+
+/// Times are strings in the JSON encoding, as they exceed the integers of JavaScript
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
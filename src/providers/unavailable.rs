@@ -0,0 +1,31 @@
+use std::{cell::Cell, future::Future};
+
+use object_store::client::{HttpError, HttpErrorKind, HttpResponse};
+
+tokio::task_local! {
+    /// Whether the last request of the future polled in [`captured`] failed on the server's side
+    static UNAVAILABLE: Cell<bool>;
+}
+
+/// Poll `future`, returning its output with whether the last request it made failed with a
+/// server error or timed out, rather than for a problem with the request or the object.
+///
+/// Store errors don't expose the status of the response, but requests through the plugin's
+/// connector record their outcome here.
+pub async fn captured<F: Future>(future: F) -> (F::Output, bool) {
+    UNAVAILABLE
+        .scope(Cell::default(), async {
+            let output = future.await;
+            (output, UNAVAILABLE.with(Cell::get))
+        })
+        .await
+}
+
+/// Record the outcome of an attempt of a request for [`captured`], if it's being polled
+pub fn record(result: &Result<HttpResponse, HttpError>) {
+    let unavailable = match result {
+        Ok(response) => response.status().is_server_error(),
+        Err(e) => matches!(e.kind(), HttpErrorKind::Timeout),
+    };
+    let _ = UNAVAILABLE.try_with(|captured| captured.set(unavailable));
+}
@@ -0,0 +1,33 @@
+use std::{future::Future, time::Duration};
+
+use http::{Method, Uri};
+
+tokio::task_local! {
+    /// Set while the requests of the command run being executed are logged, see [`scoped`]
+    static VERBOSE: ();
+}
+
+/// Log the requests made while polling `future` to stderr if `verbose`
+pub async fn scoped<F: Future>(verbose: bool, future: F) -> F::Output {
+    match verbose {
+        true => VERBOSE.scope((), future).await,
+        false => future.await,
+    }
+}
+
+/// Whether the current command run logs its requests, to carry it into work done outside of
+/// [`scoped`]
+pub fn enabled() -> bool {
+    VERBOSE.try_with(|_| ()).is_ok()
+}
+
+/// Log a finished request with its status or error
+pub fn request(method: &Method, uri: &Uri, outcome: &str, elapsed: Duration) {
+    // Queries are left out, as presigned urls carry credentials in them
+    let scheme = uri.scheme_str().unwrap_or("https");
+    let authority = uri.authority().map(|a| a.as_str()).unwrap_or_default();
+    eprintln!(
+        "cloud: {method} {scheme}://{authority}{} {outcome} in {elapsed:.1?}",
+        uri.path()
+    );
+}
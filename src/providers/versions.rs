@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use http::Method;
+use object_store::{Error, Result, client::HttpRequestBody, path::Path};
+
+use super::signed::{self, BucketClient};
+
+const STORE: &str = "S3";
+
+/// Requests for the versions of objects in a versioned S3 bucket, which object_store has no API
+/// for
+#[derive(Debug)]
+pub struct VersionClient {
+    bucket: Arc<BucketClient>,
+}
+
+/// A version of an object, or a delete marker
+#[derive(Debug, Clone)]
+pub struct ObjectVersion {
+    pub location: Path,
+    pub version_id: String,
+    pub is_latest: bool,
+    pub delete_marker: bool,
+    pub last_modified: DateTime<Utc>,
+    pub size: u64,
+    pub owner: Option<String>,
+}
+
+/// A page of a version listing, with the markers of the next one
+struct Page {
+    versions: Vec<ObjectVersion>,
+    next: Option<(String, String)>,
+}
+
+impl VersionClient {
+    pub fn new(bucket: Arc<BucketClient>) -> Self {
+        VersionClient { bucket }
+    }
+
+    /// Versions and delete markers of the object at `prefix` or the objects under it, in key
+    /// order and newest first
+    pub async fn list(&self, prefix: &Path) -> Result<Vec<ObjectVersion>> {
+        let mut versions = vec![];
+        let mut next: Option<(String, String)> = None;
+        loop {
+            let mut query = vec![("versions", ""), ("prefix", prefix.as_ref())];
+            if let Some((key, version_id)) = &next {
+                query.extend([
+                    ("key-marker", key.as_str()),
+                    ("version-id-marker", version_id),
+                ]);
+            }
+            let url = self.bucket.url(None, &query);
+            let body = self
+                .bucket
+                .send(Method::GET, &url, &[], HttpRequestBody::empty())
+                .await?;
+            let page = parse_page(&body)?;
+            // Only on path boundaries, so a prefix `a` doesn't match `ab`
+            versions.extend(
+                page.versions
+                    .into_iter()
+                    .filter(|version| version.location.prefix_match(prefix).is_some()),
+            );
+            next = page.next;
+            if next.is_none() {
+                return Ok(versions);
+            }
+        }
+    }
+
+    /// Copy a version of the object over its current version, which the store keeps as an older
+    /// version
+    pub async fn restore(&self, location: &Path, version_id: &str) -> Result<()> {
+        let source = self.bucket.copy_source(location, Some(version_id));
+        let url = self.bucket.url(Some(location), &[]);
+        let headers = [("x-amz-copy-source", source)];
+        self.bucket
+            .send_checked(Method::PUT, &url, &headers, HttpRequestBody::empty())
+            .await?;
+        Ok(())
+    }
+
+    /// Delete a version of the object for good, or remove a delete marker
+    pub async fn delete(&self, location: &Path, version_id: &str) -> Result<()> {
+        let url = self
+            .bucket
+            .url(Some(location), &[("versionId", version_id)]);
+        self.bucket
+            .send(Method::DELETE, &url, &[], HttpRequestBody::empty())
+            .await?;
+        Ok(())
+    }
+}
+
+fn generic(source: impl ToString) -> Error {
+    signed::generic(STORE, source)
+}
+
+pub(super) fn child_text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+}
+
+/// Owner of a listed object, by its display name where the provider still gives one
+pub(super) fn owner(node: roxmltree::Node) -> Option<String> {
+    let owner = node.children().find(|child| child.has_tag_name("Owner"))?;
+    let name = child_text(owner, "DisplayName").or_else(|| child_text(owner, "ID"));
+    name.map(str::to_string)
+}
+
+/// Parse a `ListVersionsResult`, where versions and delete markers are listed in order
+fn parse_page(body: &[u8]) -> Result<Page> {
+    let body = std::str::from_utf8(body).map_err(generic)?;
+    let document = roxmltree::Document::parse(body).map_err(generic)?;
+    let result = document.root_element();
+    let invalid = |element: &str| generic(format!("Invalid version listing: missing {element}"));
+    let mut versions = vec![];
+    for node in result
+        .children()
+        .filter(|node| node.has_tag_name("Version") || node.has_tag_name("DeleteMarker"))
+    {
+        let text = |name| child_text(node, name).ok_or_else(|| invalid(name));
+        versions.push(ObjectVersion {
+            location: Path::parse(text("Key")?).map_err(|source| Error::InvalidPath { source })?,
+            version_id: text("VersionId")?.to_string(),
+            is_latest: text("IsLatest")? == "true",
+            delete_marker: node.has_tag_name("DeleteMarker"),
+            last_modified: text("LastModified")?
+                .parse()
+                .map_err(|_| invalid("LastModified"))?,
+            size: child_text(node, "Size")
+                .map_or(Ok(0), |size| size.parse().map_err(|_| invalid("Size")))?,
+            owner: owner(node),
+        });
+    }
+    let next = match child_text(result, "IsTruncated") {
+        Some("true") => Some((
+            child_text(result, "NextKeyMarker")
+                .ok_or_else(|| invalid("NextKeyMarker"))?
+                .to_string(),
+            child_text(result, "NextVersionIdMarker")
+                .ok_or_else(|| invalid("NextVersionIdMarker"))?
+                .to_string(),
+        )),
+        _ => None,
+    };
+    Ok(Page { versions, next })
+}
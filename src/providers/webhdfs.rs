@@ -0,0 +1,627 @@
+use super::{
+    NuObjectStore, env_string,
+    get::{check_preconditions, resolve_range},
+};
+use crate::{
+    cache::{Cache, ObjectStoreCacheKey},
+    config::Config,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, TryStreamExt, stream::BoxStream};
+use nu_plugin::EngineInterface;
+use nu_protocol::{ShellError, Spanned};
+use object_store::{
+    Attributes, Error, GetOptions, GetResult, GetResultPayload, ListResult, MultipartUpload,
+    ObjectMeta, ObjectStore, PutMode, PutMultipartOpts, PutOptions, PutPayload, PutResult, Result,
+    UploadPart, path::Path,
+};
+use percent_encoding::percent_decode_str;
+use reqwest::{Body, Client, Method, Response, StatusCode, header::LOCATION, redirect::Policy};
+use serde::Deserialize;
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Notify;
+use url::Url;
+
+const STORE: &str = "WebHDFS";
+const DEFAULT_PORT: u16 = 9870;
+const USER_ENV: &str = "HADOOP_USER_NAME";
+
+pub async fn build_object_store(
+    engine: &EngineInterface,
+    cache: &Cache,
+    url: &Spanned<Url>,
+) -> Result<NuObjectStore, ShellError> {
+    let scheme = match url.item.scheme() {
+        "swebhdfs" => "https",
+        _ => "http",
+    };
+    let host = url.item.host_str().unwrap_or_default();
+    let port = url.item.port().unwrap_or(DEFAULT_PORT);
+    let endpoint = format!("{scheme}://{host}:{port}/webhdfs/v1");
+
+    let user = if !url.item.username().is_empty() {
+        Some(
+            percent_decode_str(url.item.username())
+                .decode_utf8_lossy()
+                .to_string(),
+        )
+    } else {
+        env_string(engine, USER_ENV).map_err(|e| *e)?
+    };
+
+    let key = ObjectStoreCacheKey::WebHdfs {
+        endpoint: endpoint.clone(),
+        user: user.clone(),
+    };
+    if let Some(store) = cache.get_store(&key).await {
+        Ok(store)
+    } else {
+        let config = Config::load(engine).map_err(|e| *e)?;
+        let store = WebHdfsStore::new(&endpoint, user.clone(), &config).map_err(|e| {
+            ShellError::GenericError {
+                error: format!("Could not create WebHDFS client: {e}"),
+                msg: "".into(),
+                span: Some(url.span),
+                help: None,
+                inner: vec![],
+            }
+        })?;
+        let store = NuObjectStore::WebHdfs {
+            store: Arc::new(store),
+            endpoint,
+            user,
+        };
+        cache.put_store(engine, key, store.clone()).await?;
+        Ok(store)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct FileStatusResponse {
+    file_status: FileStatus,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListStatusResponse {
+    file_statuses: FileStatuses,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct FileStatuses {
+    file_status: Vec<FileStatus>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileStatus {
+    path_suffix: String,
+    #[serde(rename = "type")]
+    kind: String,
+    length: u64,
+    modification_time: i64,
+}
+
+impl FileStatus {
+    fn is_dir(&self) -> bool {
+        self.kind == "DIRECTORY"
+    }
+
+    fn object_meta(&self, location: Path) -> ObjectMeta {
+        ObjectMeta {
+            location,
+            last_modified: DateTime::<Utc>::from_timestamp_millis(self.modification_time)
+                .unwrap_or(DateTime::UNIX_EPOCH),
+            size: self.length,
+            e_tag: Some(format!("{:x}-{:x}", self.modification_time, self.length)),
+            version: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BooleanResponse {
+    boolean: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RemoteExceptionResponse {
+    remote_exception: RemoteException,
+}
+
+#[derive(Deserialize)]
+struct RemoteException {
+    exception: String,
+    message: String,
+}
+
+/// [`ObjectStore`] for HDFS using the WebHDFS REST API
+///
+/// Object paths are absolute paths in the HDFS namespace.
+#[derive(Debug)]
+pub struct WebHdfsStore {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// Client following the namenode's redirects to datanodes
+    client: Client,
+    /// Client for writes, where the redirect has to be followed with the data
+    write_client: Client,
+    endpoint: Url,
+    user: Option<String>,
+}
+
+impl WebHdfsStore {
+    fn new(
+        endpoint: &str,
+        user: Option<String>,
+        config: &Config,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(WebHdfsStore {
+            inner: Arc::new(Inner {
+                client: config.client_builder()?.build()?,
+                write_client: config.client_builder()?.redirect(Policy::none()).build()?,
+                endpoint: Url::parse(endpoint)?,
+                user,
+            }),
+        })
+    }
+}
+
+impl Display for WebHdfsStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WebHdfsStore({})", self.inner.endpoint)
+    }
+}
+
+fn generic(e: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::Generic {
+        store: STORE,
+        source: Box::new(e),
+    }
+}
+
+fn remote_path(location: &Path) -> String {
+    format!("/{location}")
+}
+
+impl Inner {
+    fn url(&self, location: &Path, op: &str, params: &[(&str, &str)]) -> Url {
+        let mut url = self.endpoint.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().extend(location.parts());
+        }
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("op", op);
+            if let Some(user) = &self.user {
+                query.append_pair("user.name", user);
+            }
+            for (key, value) in params {
+                query.append_pair(key, value);
+            }
+        }
+        url
+    }
+
+    /// Turn error responses into the matching object_store error
+    async fn check(&self, response: Response, location: &Path) -> Result<Response> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        let (exception, message) = serde_json::from_str::<RemoteExceptionResponse>(&body)
+            .map(|r| (r.remote_exception.exception, r.remote_exception.message))
+            .unwrap_or_else(|_| (status.to_string(), body));
+        let source = format!("{exception}: {message}").into();
+        let path = location.to_string();
+        Err(match (status, exception.as_str()) {
+            (StatusCode::NOT_FOUND, _) | (_, "FileNotFoundException") => {
+                Error::NotFound { path, source }
+            }
+            (StatusCode::FORBIDDEN, _) | (_, "AccessControlException") => {
+                Error::PermissionDenied { path, source }
+            }
+            (_, "FileAlreadyExistsException") => Error::AlreadyExists { path, source },
+            _ => Error::Generic {
+                store: STORE,
+                source,
+            },
+        })
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        location: &Path,
+        op: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Response> {
+        let response = self
+            .client
+            .request(method, self.url(location, op, params))
+            .send()
+            .await
+            .map_err(generic)?;
+        self.check(response, location).await
+    }
+
+    async fn boolean(
+        &self,
+        method: Method,
+        location: &Path,
+        op: &str,
+        params: &[(&str, &str)],
+    ) -> Result<bool> {
+        let response = self.request(method, location, op, params).await?;
+        let result: BooleanResponse = response.json().await.map_err(generic)?;
+        Ok(result.boolean)
+    }
+
+    async fn file_status(&self, location: &Path) -> Result<FileStatus> {
+        let response = self
+            .request(Method::GET, location, "GETFILESTATUS", &[])
+            .await?;
+        let status: FileStatusResponse = response.json().await.map_err(generic)?;
+        Ok(status.file_status)
+    }
+
+    async fn list_status(&self, location: &Path) -> Result<Vec<FileStatus>> {
+        match self.request(Method::GET, location, "LISTSTATUS", &[]).await {
+            Ok(response) => {
+                let list: ListStatusResponse = response.json().await.map_err(generic)?;
+                Ok(list.file_statuses.file_status)
+            }
+            Err(Error::NotFound { .. }) => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes go to the namenode first, which redirects to the datanode receiving the data
+    async fn write(
+        &self,
+        method: Method,
+        location: &Path,
+        op: &str,
+        params: &[(&str, &str)],
+        body: impl Into<Body>,
+    ) -> Result<()> {
+        let response = self
+            .write_client
+            .request(method.clone(), self.url(location, op, params))
+            .send()
+            .await
+            .map_err(generic)?;
+        if !response.status().is_redirection() {
+            self.check(response, location).await?;
+            return Err(Error::Generic {
+                store: STORE,
+                source: format!("Namenode did not redirect the {op} request to a datanode").into(),
+            });
+        }
+        let datanode = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .ok_or_else(|| Error::Generic {
+                store: STORE,
+                source: "Redirect is missing a location".into(),
+            })?
+            .to_string();
+
+        let response = self
+            .write_client
+            .request(method, datanode)
+            .header("content-type", "application/octet-stream")
+            .body(body)
+            .send()
+            .await
+            .map_err(generic)?;
+        self.check(response, location).await?;
+        Ok(())
+    }
+
+    async fn create(&self, location: &Path, body: impl Into<Body>, overwrite: bool) -> Result<()> {
+        let overwrite = if overwrite { "true" } else { "false" };
+        self.write(
+            Method::PUT,
+            location,
+            "CREATE",
+            &[("overwrite", overwrite)],
+            body,
+        )
+        .await
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let parent = Path::from_iter(to.parts().take(to.parts().count().saturating_sub(1)));
+        self.boolean(Method::PUT, &parent, "MKDIRS", &[]).await?;
+        // HDFS refuses to rename over an existing file
+        match self.boolean(Method::DELETE, to, "DELETE", &[]).await {
+            Ok(_) | Err(Error::NotFound { .. }) => {}
+            Err(e) => return Err(e),
+        }
+        let destination = remote_path(to);
+        match self
+            .boolean(
+                Method::PUT,
+                from,
+                "RENAME",
+                &[("destination", &destination)],
+            )
+            .await?
+        {
+            true => Ok(()),
+            false => Err(Error::Generic {
+                store: STORE,
+                source: format!("Could not rename {from} to {to}").into(),
+            }),
+        }
+    }
+
+    async fn put_result(&self, location: &Path) -> Result<PutResult> {
+        let status = self.file_status(location).await?;
+        Ok(PutResult {
+            e_tag: status.object_meta(location.clone()).e_tag,
+            version: None,
+        })
+    }
+}
+
+fn child(parent: &Path, status: &FileStatus) -> Path {
+    parent.child(status.path_suffix.as_str())
+}
+
+#[async_trait]
+impl ObjectStore for WebHdfsStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> Result<PutResult> {
+        let overwrite = match opts.mode {
+            PutMode::Overwrite => true,
+            PutMode::Create => false,
+            PutMode::Update(_) => return Err(Error::NotImplemented),
+        };
+        let body: bytes::Bytes = payload.into();
+        self.inner.create(location, body, overwrite).await?;
+        self.inner.put_result(location).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        _opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        let nonce = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let temp = Path::from(format!("{location}#{nonce:x}"));
+        self.inner.create(&temp, Vec::<u8>::new(), true).await?;
+        Ok(Box::new(WebHdfsUpload {
+            inner: Arc::clone(&self.inner),
+            location: location.clone(),
+            temp,
+            parts: 0,
+            queue: Arc::new(AppendQueue::default()),
+        }))
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let status = self.inner.file_status(location).await?;
+        if status.is_dir() {
+            return Err(Error::NotFound {
+                path: location.to_string(),
+                source: "is a directory".into(),
+            });
+        }
+        let meta = status.object_meta(location.clone());
+        check_preconditions(&options, &meta)?;
+        let range = match &options.range {
+            Some(range) => resolve_range(STORE, range, meta.size)?,
+            None => 0..meta.size,
+        };
+
+        let payload = if options.head || range.is_empty() {
+            futures::stream::empty().boxed()
+        } else {
+            let offset = range.start.to_string();
+            let length = (range.end - range.start).to_string();
+            self.inner
+                .request(
+                    Method::GET,
+                    location,
+                    "OPEN",
+                    &[("offset", &offset), ("length", &length)],
+                )
+                .await?
+                .bytes_stream()
+                .map_err(generic)
+                .boxed()
+        };
+
+        Ok(GetResult {
+            payload: GetResultPayload::Stream(payload),
+            meta,
+            range,
+            attributes: Attributes::default(),
+        })
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        match self
+            .inner
+            .boolean(Method::DELETE, location, "DELETE", &[])
+            .await?
+        {
+            true => Ok(()),
+            false => Err(Error::NotFound {
+                path: location.to_string(),
+                source: "file does not exist".into(),
+            }),
+        }
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'static, Result<ObjectMeta>> {
+        let inner = Arc::clone(&self.inner);
+        let prefix = prefix.cloned().unwrap_or_default();
+        futures::stream::once(async move {
+            let mut objects = vec![];
+            let mut dirs = vec![prefix];
+            while let Some(dir) = dirs.pop() {
+                for status in inner.list_status(&dir).await? {
+                    // Listing a file returns the file itself
+                    if status.path_suffix.is_empty() {
+                        continue;
+                    }
+                    let location = child(&dir, &status);
+                    if status.is_dir() {
+                        dirs.push(location);
+                    } else {
+                        objects.push(status.object_meta(location));
+                    }
+                }
+            }
+            Ok::<_, Error>(futures::stream::iter(objects.into_iter().map(Ok)))
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        let prefix = prefix.cloned().unwrap_or_default();
+        let mut result = ListResult {
+            common_prefixes: vec![],
+            objects: vec![],
+        };
+        for status in self.inner.list_status(&prefix).await? {
+            if status.path_suffix.is_empty() {
+                continue;
+            }
+            let location = child(&prefix, &status);
+            if status.is_dir() {
+                result.common_prefixes.push(location);
+            } else {
+                result.objects.push(status.object_meta(location));
+            }
+        }
+        Ok(result)
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        copy(&self.inner, from, to, true).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        copy(&self.inner, from, to, false).await
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.rename(from, to).await
+    }
+}
+
+/// HDFS has no server side copy, so the data is streamed through the plugin
+async fn copy(inner: &Inner, from: &Path, to: &Path, overwrite: bool) -> Result<()> {
+    let source = inner.request(Method::GET, from, "OPEN", &[]).await?;
+    let body = Body::wrap_stream(source.bytes_stream());
+    inner.create(to, body, overwrite).await
+}
+
+/// Orders concurrently uploaded parts, as appends must happen in sequence
+#[derive(Default)]
+struct AppendQueue {
+    /// Index of the next part to append, and whether an append failed
+    state: Mutex<(usize, bool)>,
+    notify: Notify,
+}
+
+impl AppendQueue {
+    async fn wait_for_turn(&self, part: usize) -> Result<()> {
+        loop {
+            let notified = self.notify.notified();
+            {
+                let (next, failed) = *self.state.lock().map_err(|e| Error::Generic {
+                    store: STORE,
+                    source: e.to_string().into(),
+                })?;
+                if failed {
+                    return Err(Error::Generic {
+                        store: STORE,
+                        source: "a previous part failed to upload".into(),
+                    });
+                }
+                if next == part {
+                    return Ok(());
+                }
+            }
+            notified.await;
+        }
+    }
+
+    fn finished(&self, success: bool) {
+        if let Ok(mut state) = self.state.lock() {
+            match success {
+                true => state.0 += 1,
+                false => state.1 = true,
+            }
+        }
+        self.notify.notify_waiters();
+    }
+}
+
+/// Multipart upload appending parts to a temporary file, which replaces the
+/// destination when the upload completes
+struct WebHdfsUpload {
+    inner: Arc<Inner>,
+    location: Path,
+    temp: Path,
+    parts: usize,
+    queue: Arc<AppendQueue>,
+}
+
+impl Debug for WebHdfsUpload {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WebHdfsUpload({})", self.location)
+    }
+}
+
+#[async_trait]
+impl MultipartUpload for WebHdfsUpload {
+    fn put_part(&mut self, data: PutPayload) -> UploadPart {
+        let part = self.parts;
+        self.parts += 1;
+        let inner = Arc::clone(&self.inner);
+        let queue = Arc::clone(&self.queue);
+        let temp = self.temp.clone();
+        Box::pin(async move {
+            queue.wait_for_turn(part).await?;
+            let body: bytes::Bytes = data.into();
+            let result = inner.write(Method::POST, &temp, "APPEND", &[], body).await;
+            queue.finished(result.is_ok());
+            result
+        })
+    }
+
+    async fn complete(&mut self) -> Result<PutResult> {
+        self.inner.rename(&self.temp, &self.location).await?;
+        self.inner.put_result(&self.location).await
+    }
+
+    async fn abort(&mut self) -> Result<()> {
+        self.inner
+            .boolean(Method::DELETE, &self.temp, "DELETE", &[])
+            .await
+            .map(|_| ())
+    }
+}
@@ -0,0 +1,289 @@
+use std::{ffi::OsStr, fmt, path::PathBuf};
+
+use nu_glob::{MatchOptions, Pattern};
+use nu_plugin::{EngineInterface, EvaluatedCall};
+use nu_protocol::{ShellError, Span, Spanned};
+use object_store::path::Path;
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+use crate::{config::Config, providers::env_string};
+
+/// Environment variable with the url relative paths resolve against, taking precedence over
+/// the `default_url` config
+const DEFAULT_URL_ENV: &str = "CLOUD_DEFAULT_URL";
+
+/// Start of environment variable references in urls
+const ENV_PREFIX: &str = "$env.";
+
+/// Characters that make a path segment a glob pattern
+const GLOB_CHARS: &[char] = &['*', '?', '['];
+
+/// Url schemes handled by [`crate::providers::parse_url`]
+const SCHEMES: &[&str] = &[
+    "file",
+    "hdfs",
+    "http",
+    "https",
+    "memory",
+    "s3",
+    "s3a",
+    #[cfg(feature = "sftp")]
+    "sftp",
+    "swebhdfs",
+    "webhdfs",
+];
+
+/// A url argument of a command, validated and normalized so every command reports
+/// malformed urls the same way
+#[derive(Debug, Clone)]
+pub struct RemotePath {
+    pub url: Spanned<Url>,
+}
+
+impl RemotePath {
+    /// Parse the positional argument at `pos`. `$env.NAME` references and a leading `~alias` are
+    /// expanded, then relative paths resolve against the default url from `CLOUD_DEFAULT_URL` or
+    /// the `default_url` config.
+    pub fn from_call(
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        pos: usize,
+    ) -> Result<Self, Box<ShellError>> {
+        let arg: Spanned<PathBuf> = call.req(pos)?;
+        let url = arg
+            .item
+            .to_str()
+            .expect("The path should already be unicode");
+        let url = &expand(engine, url, arg.span, call.head)?;
+        if Url::parse(url) == Err(url::ParseError::RelativeUrlWithoutBase)
+            && let Some(base) = default_url(engine)?
+        {
+            let mut base = Self::parse(&base, arg.span, call.head)?.url.item;
+            // Resolve under the base rather than next to its last segment
+            if !base.path().ends_with('/') {
+                base.set_path(&format!("{}/", base.path()));
+            }
+            let url = base.join(url).map_err(|e| ShellError::IncorrectValue {
+                msg: format!("Invalid url: {e}"),
+                val_span: arg.span,
+                call_span: call.head,
+            })?;
+            return Self::parse(url.as_str(), arg.span, call.head);
+        }
+        Self::parse(url, arg.span, call.head)
+    }
+
+    /// Parse a url, resolving `.` and `..` segments and collapsing empty ones
+    pub fn parse(url: &str, span: Span, call_span: Span) -> Result<Self, Box<ShellError>> {
+        let invalid = |msg: String| ShellError::IncorrectValue {
+            msg,
+            val_span: span,
+            call_span,
+        };
+
+        // Dot segments are resolved by the parser
+        let mut url = Url::parse(url).map_err(|e| invalid(format!("Invalid url: {e}")))?;
+        if !SCHEMES.contains(&url.scheme()) {
+            return Err(Box::new(ShellError::GenericError {
+                error: format!("Unsupported url scheme: {}", url.scheme()),
+                msg: "".into(),
+                span: Some(span),
+                help: Some(format!("Supported schemes are {}", SCHEMES.join(", "))),
+                inner: vec![],
+            }));
+        }
+
+        // Only http urls have queries, a `?` in other paths is part of the path or a glob
+        if !matches!(url.scheme(), "http" | "https")
+            && let Some(query) = url.query().map(ToString::to_string)
+        {
+            url.set_path(&format!("{}%3F{query}", url.path()));
+            url.set_query(None);
+        }
+
+        for segment in url.path().split('/') {
+            percent_decode_str(segment)
+                .decode_utf8()
+                .map_err(|e| invalid(format!("Invalid path segment {segment}: {e}")))?;
+        }
+
+        // Object stores reject empty path segments, keep a trailing slash for listings
+        if url.path().contains("//") {
+            let trailing = url.path().ends_with('/');
+            let mut path = url
+                .path()
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .collect::<Vec<_>>()
+                .join("/");
+            if trailing {
+                path.push('/');
+            }
+            url.set_path(&format!("/{path}"));
+        }
+
+        Ok(RemotePath {
+            url: Spanned { item: url, span },
+        })
+    }
+
+    /// The glob pattern in the path, if any segment has glob characters
+    pub fn glob(&self) -> Result<Option<Glob>, Box<ShellError>> {
+        let Some(segments) = self.url.item.path_segments() else {
+            return Ok(None);
+        };
+        let segments: Vec<_> = segments
+            .map(|segment| (segment, percent_decode_str(segment).decode_utf8_lossy()))
+            .collect();
+        let Some(first) = segments
+            .iter()
+            .position(|(_, decoded)| decoded.contains(GLOB_CHARS))
+        else {
+            return Ok(None);
+        };
+
+        let mut url = self.url.item.clone();
+        let prefix: String = segments[..first]
+            .iter()
+            .map(|(segment, _)| format!("{segment}/"))
+            .collect();
+        url.set_path(&format!("/{prefix}"));
+        let pattern = segments[first..]
+            .iter()
+            .map(|(_, decoded)| decoded.as_ref())
+            .collect::<Vec<_>>()
+            .join("/");
+        let pattern = Pattern::new(&pattern).map_err(|e| {
+            Box::new(ShellError::GenericError {
+                error: format!("Invalid glob pattern {pattern}: {}", e.msg),
+                msg: "".into(),
+                span: Some(self.url.span),
+                help: Some(
+                    "Wrap glob characters in brackets to match them literally, e.g. [*]".into(),
+                ),
+                inner: vec![],
+            })
+        })?;
+        Ok(Some(Glob {
+            url: Spanned {
+                item: url,
+                span: self.url.span,
+            },
+            pattern,
+        }))
+    }
+
+    /// Extension of the last path segment
+    pub fn extension(&self) -> Option<String> {
+        let name = self.url.item.path_segments()?.next_back()?;
+        let name = percent_decode_str(name).decode_utf8_lossy();
+        std::path::Path::new(name.as_ref())
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(ToString::to_string)
+    }
+}
+
+/// A url with a glob pattern, split at the first segment with glob characters
+#[derive(Debug, Clone)]
+pub struct Glob {
+    /// The url of the prefix before the pattern, which is listed to expand it
+    pub url: Spanned<Url>,
+    pattern: Pattern,
+}
+
+impl Glob {
+    /// Whether an object listed under `prefix` matches, relative to the prefix. `*` and `?` stay
+    /// within a segment while `**` matches any number of them. With `recursive`, objects under a
+    /// matching prefix match too.
+    pub fn matches(&self, prefix: &Path, location: &Path, recursive: bool) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::default()
+        };
+        let parts: Vec<_> = location
+            .prefix_match(prefix)
+            .into_iter()
+            .flatten()
+            .collect();
+        let mut relative = String::new();
+        for (i, part) in parts.iter().enumerate() {
+            if i > 0 {
+                relative.push('/');
+            }
+            relative.push_str(part.as_ref());
+            if (recursive || i == parts.len() - 1) && self.pattern.matches_with(&relative, options)
+            {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.url.item, self.pattern)
+    }
+}
+
+/// The url relative paths resolve against, if one is set
+pub(crate) fn default_url(engine: &EngineInterface) -> Result<Option<String>, Box<ShellError>> {
+    if let Some(url) = env_string(engine, DEFAULT_URL_ENV)? {
+        return Ok(Some(url));
+    }
+    Ok(Config::load(engine)?.default_url)
+}
+
+/// Expand a leading `~alias` from the `aliases` config, and `$env.NAME` references to the values
+/// of environment variables
+fn expand(
+    engine: &EngineInterface,
+    url: &str,
+    span: Span,
+    call_span: Span,
+) -> Result<String, Box<ShellError>> {
+    let invalid = |msg: String| ShellError::IncorrectValue {
+        msg,
+        val_span: span,
+        call_span,
+    };
+
+    let mut expanded = String::with_capacity(url.len());
+    let mut rest = url;
+    if let Some(aliased) = url.strip_prefix('~') {
+        let (alias, path) = aliased.split_at(aliased.find('/').unwrap_or(aliased.len()));
+        let aliases = Config::load(engine)?.aliases.unwrap_or_default();
+        let Some(base) = aliases.get(alias) else {
+            return Err(Box::new(invalid(format!("Unknown url alias: ~{alias}"))));
+        };
+        expanded.push_str(base.strip_suffix('/').unwrap_or(base));
+        rest = path;
+    }
+
+    while let Some(start) = rest.find(ENV_PREFIX) {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + ENV_PREFIX.len()..];
+        let end = after
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(after.len());
+        let name = &after[..end];
+        if name.is_empty() {
+            return Err(Box::new(invalid(format!(
+                "Missing environment variable name after {ENV_PREFIX}"
+            ))));
+        }
+        match env_string(engine, name)? {
+            Some(value) => expanded.push_str(&value),
+            None => {
+                return Err(Box::new(invalid(format!(
+                    "Environment variable {name} is not set"
+                ))));
+            }
+        }
+        rest = &after[end..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
@@ -0,0 +1,338 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    error::Error,
+    fmt,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use futures::FutureExt;
+use object_store::{
+    MultipartId, MultipartUpload, PutPayload, PutResult, Result, UploadPart,
+    multipart::{MultipartStore, PartId},
+    path::Path,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Serializes access to journal files by the calls of this process
+static JOURNAL_LOCK: Mutex<()> = Mutex::new(());
+
+/// Multipart uploads in progress, keyed by destination url
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Entries {
+    uploads: BTreeMap<String, UploadRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadRecord {
+    pub upload_id: MultipartId,
+    /// Uploaded parts by index
+    pub parts: BTreeMap<usize, PartRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartRecord {
+    pub len: u64,
+    /// Id returned by the store, e.g. the ETag of an S3 part
+    pub content_id: String,
+    /// CRC32 of the part's data, to check the input is the same when resuming
+    pub crc32: u32,
+}
+
+impl UploadRecord {
+    /// Parts from the start of the upload without gaps, which a resumed upload can skip.
+    /// Parts after a gap are uploaded again.
+    pub fn completed_prefix(&self) -> Vec<PartRecord> {
+        (0..)
+            .map_while(|idx| self.parts.get(&idx).cloned())
+            .collect()
+    }
+}
+
+/// Local record of multipart uploads, kept until they complete so `cloud save --resume` can
+/// continue an upload interrupted by a crash
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn open(config: &Config) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let path = match &config.transfer_journal {
+            Some(path) => path.clone(),
+            None => dirs::data_local_dir()
+                .ok_or("Could not determine the local data directory")?
+                .join("nu_plugin_cloud")
+                .join("transfers.json"),
+        };
+        Ok(Journal { path })
+    }
+
+    pub fn get(&self, url: &str) -> Result<Option<UploadRecord>, JournalError> {
+        let _lock = JOURNAL_LOCK.lock().expect("Journal lock poisoned");
+        Ok(self.load()?.uploads.remove(url))
+    }
+
+    pub fn insert(&self, url: &str, record: UploadRecord) -> Result<(), JournalError> {
+        self.update(|entries| {
+            entries.uploads.insert(url.to_string(), record);
+        })
+    }
+
+    pub fn remove(&self, url: &str) -> Result<(), JournalError> {
+        self.update(|entries| {
+            entries.uploads.remove(url);
+        })
+    }
+
+    fn part_uploaded(&self, url: &str, idx: usize, part: PartRecord) -> Result<(), JournalError> {
+        self.update(|entries| {
+            if let Some(record) = entries.uploads.get_mut(url) {
+                record.parts.insert(idx, part);
+            }
+        })
+    }
+
+    fn load(&self) -> Result<Entries, JournalError> {
+        match std::fs::read(&self.path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| self.error(e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Entries::default()),
+            Err(e) => Err(self.error(e)),
+        }
+    }
+
+    /// Apply `f` to the entries and replace the file, so a crash leaves the old or new version
+    fn update(&self, f: impl FnOnce(&mut Entries)) -> Result<(), JournalError> {
+        let _lock = JOURNAL_LOCK.lock().expect("Journal lock poisoned");
+        let mut entries = self.load()?;
+        f(&mut entries);
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| self.error(e))?;
+        }
+        let data = serde_json::to_vec_pretty(&entries).map_err(|e| self.error(e))?;
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, data).map_err(|e| self.error(e))?;
+        std::fs::rename(&tmp, &self.path).map_err(|e| self.error(e))
+    }
+
+    fn error(&self, e: impl Error + Send + Sync + 'static) -> JournalError {
+        JournalError {
+            path: self.path.clone(),
+            source: Box::new(e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct JournalError {
+    path: PathBuf,
+    source: Box<dyn Error + Send + Sync>,
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Could not update transfer journal {}: {}",
+            self.path.display(),
+            self.source
+        )
+    }
+}
+
+impl Error for JournalError {}
+
+impl From<JournalError> for object_store::Error {
+    fn from(e: JournalError) -> Self {
+        object_store::Error::Generic {
+            store: "journal",
+            source: Box::new(e),
+        }
+    }
+}
+
+/// [`MultipartUpload`] recording its id and uploaded parts in the [`Journal`]
+pub struct JournaledUpload {
+    store: Arc<dyn MultipartStore>,
+    path: Path,
+    url: String,
+    upload_id: MultipartId,
+    journal: Arc<Journal>,
+    next_part: usize,
+}
+
+impl JournaledUpload {
+    /// Continue the journaled upload to `url`, or start a new one. Returns the parts
+    /// already uploaded, which the caller must skip in its input.
+    pub async fn resume(
+        store: Arc<dyn MultipartStore>,
+        path: Path,
+        url: String,
+        journal: Arc<Journal>,
+    ) -> Result<(Self, Vec<PartRecord>)> {
+        let Some(mut record) = journal.get(&url)? else {
+            return Ok((Self::start(store, path, url, journal).await?, vec![]));
+        };
+
+        let completed = record.completed_prefix();
+        record.parts.retain(|idx, _| *idx < completed.len());
+        let upload_id = record.upload_id.clone();
+        journal.insert(&url, record)?;
+        let upload = JournaledUpload {
+            store,
+            path,
+            url,
+            upload_id,
+            journal,
+            next_part: completed.len(),
+        };
+        Ok((upload, completed))
+    }
+
+    async fn start(
+        store: Arc<dyn MultipartStore>,
+        path: Path,
+        url: String,
+        journal: Arc<Journal>,
+    ) -> Result<Self> {
+        let upload_id = store.create_multipart(&path).await?;
+        journal.insert(
+            &url,
+            UploadRecord {
+                upload_id: upload_id.clone(),
+                parts: BTreeMap::new(),
+            },
+        )?;
+        Ok(JournaledUpload {
+            store,
+            path,
+            url,
+            upload_id,
+            journal,
+            next_part: 0,
+        })
+    }
+}
+
+impl fmt::Debug for JournaledUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JournaledUpload")
+            .field("path", &self.path)
+            .field("upload_id", &self.upload_id)
+            .field("next_part", &self.next_part)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl MultipartUpload for JournaledUpload {
+    fn put_part(&mut self, data: PutPayload) -> UploadPart {
+        let idx = self.next_part;
+        self.next_part += 1;
+        let store = Arc::clone(&self.store);
+        let journal = Arc::clone(&self.journal);
+        let (path, url, upload_id) = (self.path.clone(), self.url.clone(), self.upload_id.clone());
+        async move {
+            let mut crc = crc32fast::Hasher::new();
+            for chunk in &data {
+                crc.update(chunk);
+            }
+            let part = PartRecord {
+                len: data.content_length() as u64,
+                content_id: String::new(),
+                crc32: crc.finalize(),
+            };
+            let id = store.put_part(&path, &upload_id, idx, data).await?;
+            journal.part_uploaded(
+                &url,
+                idx,
+                PartRecord {
+                    content_id: id.content_id,
+                    ..part
+                },
+            )?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    async fn complete(&mut self) -> Result<PutResult> {
+        let record = self.journal.get(&self.url)?;
+        let parts = (0..self.next_part)
+            .map(|idx| {
+                record
+                    .as_ref()
+                    .and_then(|record| record.parts.get(&idx))
+                    .map(|part| PartId {
+                        content_id: part.content_id.clone(),
+                    })
+                    .ok_or_else(|| object_store::Error::Generic {
+                        store: "journal",
+                        source: format!("Part {idx} is missing from the transfer journal").into(),
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let result = self
+            .store
+            .complete_multipart(&self.path, &self.upload_id, parts)
+            .await?;
+        self.journal.remove(&self.url)?;
+        Ok(result)
+    }
+
+    async fn abort(&mut self) -> Result<()> {
+        self.store
+            .abort_multipart(&self.path, &self.upload_id)
+            .await?;
+        self.journal.remove(&self.url)?;
+        Ok(())
+    }
+}
+
+/// Checks the input of a resumed upload against the parts uploaded before, which are skipped
+#[derive(Default)]
+pub struct Replay {
+    parts: VecDeque<PartRecord>,
+    /// Bytes of the first part read so far, and their checksum
+    read: u64,
+    crc: crc32fast::Hasher,
+}
+
+impl Replay {
+    pub fn new(parts: Vec<PartRecord>) -> Self {
+        Replay {
+            parts: parts.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether all uploaded parts were skipped
+    pub fn is_done(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    /// Skip the data of uploaded parts at the start of `buf`, returning its length
+    pub fn skip(&mut self, buf: &[u8]) -> Result<usize, String> {
+        let mut skipped = 0;
+        while let Some(part) = self.parts.front()
+            && skipped < buf.len()
+        {
+            let len = ((part.len - self.read) as usize).min(buf.len() - skipped);
+            self.crc.update(&buf[skipped..skipped + len]);
+            self.read += len as u64;
+            skipped += len;
+            if self.read == part.len {
+                let crc = std::mem::take(&mut self.crc).finalize();
+                if crc != part.crc32 {
+                    return Err("the input differs from the data uploaded before".into());
+                }
+                self.parts.pop_front();
+                self.read = 0;
+            }
+        }
+        Ok(skipped)
+    }
+}